@@ -0,0 +1,50 @@
+//! Clone cost of a deep expression tree: `Expr::clone` vs `SharedExpr::clone`.
+//!
+//! Run with `cargo run --release -p chim-ast --example shared_expr_benchmark`.
+
+use chim_ast::{BinOp, BinaryExpr, Expr, ExprKind, Literal, LiteralKind, SharedExpr};
+use chim_span::{FileId, Span};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const DEPTH: usize = 16;
+const ITERATIONS: usize = 100;
+
+fn main() {
+    let tree = build_tree(DEPTH);
+    println!("tree depth {} ({} nodes), {} clones each", DEPTH, (1usize << (DEPTH + 1)) - 1, ITERATIONS);
+
+    let deep = time(|| {
+        black_box(tree.clone());
+    });
+    println!("  Expr::clone       {:>12?} per clone", deep / ITERATIONS as u32);
+
+    let shared = tree.into_shared();
+    let cheap = time(|| {
+        black_box(SharedExpr::clone(&shared));
+    });
+    println!("  SharedExpr::clone {:>12?} per clone", cheap / ITERATIONS as u32);
+}
+
+fn time(mut f: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    start.elapsed()
+}
+
+/// A full binary tree of `+` nodes with integer leaves.
+fn build_tree(depth: usize) -> Expr {
+    let span = Span::new(FileId::new(0), 0, 0, 0, 0);
+    let kind = if depth == 0 {
+        ExprKind::Literal(Literal { kind: LiteralKind::Int(1), span })
+    } else {
+        ExprKind::Binary(BinaryExpr {
+            left: Box::new(build_tree(depth - 1)),
+            op: BinOp::Add,
+            right: Box::new(build_tree(depth - 1)),
+        })
+    };
+    Expr { kind: Box::new(kind), span, ty: None }
+}
@@ -1,5 +1,6 @@
-use chim_span::{FileId, Span};
+use chim_span::{HasSpan, Span};
 use smallvec::SmallVec;
+use std::fmt;
 use std::sync::Arc;
 
 pub type Ident = Arc<str>;
@@ -10,6 +11,49 @@ pub struct Program {
     pub span: Span,
 }
 
+impl Program {
+    /// Functions marked `@test`, including those in inline modules, in source order.
+    pub fn test_functions(&self) -> Vec<&Function> {
+        fn collect<'p>(items: &'p [Item], out: &mut Vec<&'p Function>) {
+            for item in items {
+                match item {
+                    Item::Function(func) if func.has_attribute("test") => out.push(func),
+                    Item::Mod(module) => collect(&module.items, out),
+                    _ => {}
+                }
+            }
+        }
+
+        let mut tests = Vec::new();
+        collect(&self.items, &mut tests);
+        tests
+    }
+
+    /// The documentation of `item`: its `///` comments and `@doc("...")`
+    /// attributes in source order, joined with newlines. `None` when it has
+    /// neither, or is a kind of item that takes no attributes.
+    pub fn docs_for(&self, item: &Item) -> Option<String> {
+        let attributes = match item {
+            Item::Function(func) => &func.attributes,
+            Item::Struct(struct_def) => &struct_def.attributes,
+            Item::Enum(enum_def) => &enum_def.attributes,
+            Item::Trait(trait_def) => &trait_def.attributes,
+            Item::Impl(imp) => &imp.attributes,
+            Item::Mod(module) => &module.attributes,
+            _ => return None,
+        };
+        let docs: Vec<&str> = attributes.iter()
+            .filter(|attr| &*attr.name == "doc")
+            .flat_map(|attr| &attr.args)
+            .filter_map(|arg| match &*arg.expr.kind {
+                ExprKind::Literal(Literal { kind: LiteralKind::String(text), .. }) => Some(&**text),
+                _ => None,
+            })
+            .collect();
+        (!docs.is_empty()).then(|| docs.join("\n"))
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Item {
     Function(Function),
@@ -23,6 +67,7 @@ pub enum Item {
     Constant(Constant),
     Static(Static),
     Macro(Macro),
+    MacroCall(MacroCall),
     ForAll(ForAll),
     Default(Default),
     Sync(Sync),
@@ -40,7 +85,15 @@ pub struct Function {
     pub is_pub: bool,
     pub is_async: bool,
     pub lifetimes: Vec<LifetimeParam>,
+    pub generics: Vec<GenericParam>,
     pub where_clauses: Vec<WhereClause>,
+    pub attributes: Vec<Attribute>,
+}
+
+impl Function {
+    pub fn has_attribute(&self, name: &str) -> bool {
+        self.attributes.iter().any(|attr| &*attr.name == name)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -60,6 +113,8 @@ pub struct Struct {
     pub is_pub: bool,
     pub generics: Vec<GenericParam>,
     pub where_clauses: Vec<WhereClause>,
+    /// Attributes such as `@derive(Clone)`; see `chim_semantic::expand_derives`.
+    pub attributes: Vec<Attribute>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -79,6 +134,8 @@ pub struct Enum {
     pub is_pub: bool,
     pub generics: Vec<GenericParam>,
     pub where_clauses: Vec<WhereClause>,
+    /// Attributes such as `@repr(u8)`, which fixes the discriminant type.
+    pub attributes: Vec<Attribute>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -87,6 +144,8 @@ pub struct Variant {
     pub fields: Vec<Field>,
     pub span: Span,
     pub attributes: Vec<Attribute>,
+    /// The explicit `= value` discriminant, if any.
+    pub discriminant: Option<Expr>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -98,6 +157,7 @@ pub struct Trait {
     pub generics: Vec<GenericParam>,
     pub super_traits: Vec<Type>,
     pub where_clauses: Vec<WhereClause>,
+    pub attributes: Vec<Attribute>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -113,6 +173,8 @@ pub struct FunctionSig {
     pub params: Vec<Param>,
     pub return_type: Option<Type>,
     pub span: Span,
+    /// Whether the signature ends in a C-style `...` parameter.
+    pub is_variadic: bool,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -131,6 +193,18 @@ pub struct Impl {
     pub span: Span,
     pub generics: Vec<GenericParam>,
     pub where_clauses: Vec<WhereClause>,
+    pub attributes: Vec<Attribute>,
+}
+
+impl Impl {
+    /// The implemented trait with its generic arguments, e.g. `From<int>`
+    /// in `impl From<int> for Celsius`.
+    pub fn trait_path(&self) -> Option<&Path> {
+        match self.trait_name.as_ref().map(|ty| &*ty.kind) {
+            Some(TypeKind::Path(path)) => Some(path),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -145,6 +219,7 @@ pub struct TypeBinding {
     pub name: Ident,
     pub ty: Type,
     pub span: Span,
+    pub is_pub: bool,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -162,6 +237,9 @@ pub struct Mod {
     pub span: Span,
     pub is_pub: bool,
     pub file_path: Option<String>,
+    /// `true` for `mod name { ... }`, `false` for `mod name;` whose items live in another file.
+    pub is_inline: bool,
+    pub attributes: Vec<Attribute>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -177,6 +255,10 @@ pub struct ExternItem {
     pub ty: Type,
     pub span: Span,
     pub is_pub: bool,
+    /// Whether the binding was declared `static mut`.
+    pub is_mut: bool,
+    /// The declared signature when the item is an `fn` rather than a `name: Type` binding.
+    pub sig: Option<FunctionSig>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -231,8 +313,14 @@ pub struct MacroRule {
 pub enum MacroPattern {
     Token(MacroToken),
     Sequence(Vec<MacroPattern>),
-    Repeat(Box<MacroPattern>, MacroRepeatKind),
+    /// `$( ... ) sep *`: the patterns, the optional separator between
+    /// repetitions, and how often they may repeat.
+    Repeat(Vec<MacroPattern>, Option<MacroToken>, MacroRepeatKind),
     Choice(Vec<MacroPattern>),
+    /// `$name:fragment` in a matcher, or `$name` in an expansion.
+    Var(Ident, Option<MacroFragment>),
+    /// A delimited group whose contents may hold variables and repetitions.
+    Group(MacroDelimiter, Vec<MacroPattern>),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -240,7 +328,17 @@ pub enum MacroRepeatKind {
     ZeroOrMore,
     OneOrMore,
     ZeroOrOne,
-    Sep(Option<char>),
+}
+
+/// What a `$name:fragment` variable matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MacroFragment {
+    /// An expression, up to the next `,`, `;` or `=>`.
+    Expr,
+    Ident,
+    Literal,
+    /// Any single token or delimited group.
+    Tt,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -248,13 +346,35 @@ pub enum MacroToken {
     Ident(Ident),
     Literal(String),
     Punctuation(char),
-    Group(Vec<MacroToken>),
+    /// A punctuation token longer than one character, such as `==` or `->`.
+    Operator(Ident),
+    Group(MacroDelimiter, Vec<MacroToken>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MacroDelimiter {
+    Paren,
+    Bracket,
+    Brace,
+}
+
+/// An invocation `name!(...)`, `name![...]` or `name!{...}`, holding the
+/// unparsed tokens between the delimiters until it is expanded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MacroCall {
+    pub name: Ident,
+    pub delimiter: MacroDelimiter,
+    pub tokens: Vec<MacroToken>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum MacroExpansion {
     Expr(Expr),
     Tokens(Vec<MacroToken>),
+    /// A delimited expansion, with its variables and repetitions left to
+    /// be substituted.
+    Template(Vec<MacroPattern>),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -315,10 +435,14 @@ pub enum StmtKind {
     Var(VarStmt),
     Return(Option<Expr>),
     Break(Option<Expr>),
-    Continue,
+    Continue(Option<Label>),
     Loop(LoopStmt),
     While(WhileStmt),
     For(ForStmt),
+    Guard(GuardStmt),
+    /// `defer { ... }`: a block run when control leaves the enclosing block,
+    /// after any blocks deferred later in it.
+    Defer(Vec<Stmt>),
     Empty,
 }
 
@@ -362,6 +486,42 @@ pub struct ForStmt {
     pub span: Span,
 }
 
+/// `guard cond else { ... }`: an early exit whose `else` block runs when
+/// `cond` is false and is expected to leave the enclosing scope.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GuardStmt {
+    pub condition: Expr,
+    pub else_branch: Vec<Stmt>,
+    pub span: Span,
+}
+
+impl GuardStmt {
+    /// The equivalent `if !cond { else-block }`.
+    pub fn to_if_expr(&self) -> Expr {
+        let condition = Expr {
+            kind: Box::new(ExprKind::Unary(UnaryExpr {
+                op: UnOp::Not,
+                expr: Box::new(self.condition.clone()),
+            })),
+            span: self.condition.span,
+            ty: None,
+        };
+        Expr {
+            kind: Box::new(ExprKind::If(IfExpr {
+                condition: Box::new(condition),
+                then_branch: BlockExpr {
+                    label: None,
+                    stmts: self.else_branch.clone(),
+                    ty: None,
+                },
+                else_branch: None,
+            })),
+            span: self.span,
+            ty: None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Expr {
     pub kind: Box<ExprKind>,
@@ -384,14 +544,16 @@ pub enum ExprKind {
     Index(IndexExpr),
     Slice(SliceExpr),
     Cast(CastExpr),
+    Ternary(TernaryExpr),
     If(IfExpr),
     Match(MatchExpr),
     Block(BlockExpr),
     Closure(ClosureExpr),
     AsyncBlock(AsyncBlockExpr),
-    Continue,
+    Continue(Option<Label>),
     Break(Option<Label>, Option<Box<Expr>>),
     Return(Option<Box<Expr>>),
+    MacroCall(MacroCall),
     Loop(LoopExpr),
     While(WhileExpr),
     For(ForExpr),
@@ -501,7 +663,8 @@ pub enum LiteralKind {
     Char(char),
     String(Ident),
     Unit,
-    Byte,
+    Null,
+    Byte(u8),
     ByteString(Ident),
     Atomic(AtomicLiteral),
 }
@@ -640,6 +803,14 @@ pub struct CastExpr {
     pub ty: Type,
 }
 
+/// `condition ? then_branch : else_branch`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TernaryExpr {
+    pub condition: Box<Expr>,
+    pub then_branch: Box<Expr>,
+    pub else_branch: Box<Expr>,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct IfExpr {
     pub condition: Box<Expr>,
@@ -1066,6 +1237,12 @@ pub struct CaseExpr {
     pub cases: Vec<MatchCase>,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchCase {
+    pub pattern: Pattern,
+    pub body: Expr,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct ReflExpr {
     pub ty: Type,
@@ -1113,6 +1290,8 @@ pub struct ClassExpr {
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct InstanceExpr {
+    /// The `<T: Show>` in `instance<T: Show> Show Vec<T> { .. }`.
+    pub generics: Vec<GenericParam>,
     pub class_name: Ident,
     pub ty: Type,
     pub methods: Vec<Function>,
@@ -1228,17 +1407,32 @@ pub struct Pattern {
 #[derive(Debug, Clone, PartialEq)]
 pub enum PatternKind {
     Wildcard,
-    Identifier(Ident),
+    Identifier(Ident, BindingMode),
     Literal(Literal),
     Tuple(Vec<Pattern>),
     Struct(Path, Vec<PatternField>),
     Enum(Path, Ident, Vec<PatternField>),
     Range(Option<Box<Pattern>>, Option<Box<Pattern>>),
-    Slice(Vec<Pattern>),
+    /// `[before.., ..rest, after..]`; the rest element is optional.
+    Slice(Vec<Pattern>, Option<SliceRest>, Vec<Pattern>),
     Or(Vec<Pattern>),
     Error,
 }
 
+/// How an identifier pattern binds: `x`, `mut x`, `ref x` or `ref mut x`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BindingMode {
+    pub is_mut: bool,
+    pub by_ref: bool,
+}
+
+/// The `..` or `..name` element of a slice pattern.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SliceRest {
+    pub binding: Option<Ident>,
+    pub span: Span,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct PatternField {
     pub name: Ident,
@@ -1255,21 +1449,151 @@ pub struct Type {
 pub enum TypeKind {
     Path(Path),
     Tuple(Vec<Type>),
-    Array(Box<Type>, usize),
+    Array(Box<Type>, ArrayLen),
     Slice(Box<Type>),
     Pointer(Box<Type>, Mutability),
     Reference(Option<Lifetime>, Box<Type>, Mutability),
     Function(FunctionType),
+    /// `Self` inside an impl or trait; see `chim_semantic::resolve_self_types`.
+    SelfType,
     Never,
     Infer,
     Error,
 }
 
+/// The length in `[T; N]`. Lengths are arbitrary const expressions and are
+/// resolved to a `usize` by const evaluation, not by the parser.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArrayLen {
+    Const(Expr),
+    /// `[T; _]`, taken from the initializer.
+    Inferred,
+}
+
+impl ArrayLen {
+    /// The length when it is written as a plain integer literal.
+    pub fn as_literal(&self) -> Option<usize> {
+        match self {
+            ArrayLen::Const(expr) => match &*expr.kind {
+                ExprKind::Literal(Literal { kind: LiteralKind::Int(n), .. }) => usize::try_from(*n).ok(),
+                _ => None,
+            },
+            ArrayLen::Inferred => None,
+        }
+    }
+}
+
+/// Renders a type the way it is written in source, for diagnostics.
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &*self.kind {
+            TypeKind::Path(path) => write!(f, "{}", path),
+            TypeKind::Tuple(elements) => match elements.as_slice() {
+                [single] => write!(f, "({},)", single),
+                _ => {
+                    write!(f, "(")?;
+                    write_list(f, elements)?;
+                    write!(f, ")")
+                }
+            },
+            TypeKind::Array(element, len) => match len {
+                ArrayLen::Inferred => write!(f, "[{}; _]", element),
+                ArrayLen::Const(expr) => match (len.as_literal(), &*expr.kind) {
+                    (Some(n), _) => write!(f, "[{}; {}]", element, n),
+                    (None, ExprKind::Identifier(name)) => write!(f, "[{}; {}]", element, name),
+                    (None, _) => write!(f, "[{}; {{ .. }}]", element),
+                },
+            },
+            TypeKind::Slice(element) => write!(f, "[{}]", element),
+            TypeKind::Pointer(pointee, Mutability::Immutable) => write!(f, "*{}", pointee),
+            TypeKind::Pointer(pointee, Mutability::Mutable) => write!(f, "*mut {}", pointee),
+            TypeKind::Reference(lifetime, referent, mutability) => {
+                write!(f, "&")?;
+                if let Some(lifetime) = lifetime {
+                    write!(f, "{} ", lifetime)?;
+                }
+                if *mutability == Mutability::Mutable {
+                    write!(f, "mut ")?;
+                }
+                write!(f, "{}", referent)
+            }
+            TypeKind::Function(func) => {
+                if func.is_async {
+                    write!(f, "async ")?;
+                }
+                if func.is_unsafe {
+                    write!(f, "unsafe ")?;
+                }
+                if let Some(abi) = &func.abi {
+                    write!(f, "extern {:?} ", abi)?;
+                }
+                write!(f, "fn(")?;
+                write_list(f, &func.params)?;
+                write!(f, ")")?;
+                match &*func.return_type.kind {
+                    TypeKind::Tuple(elements) if elements.is_empty() => Ok(()),
+                    _ => write!(f, " -> {}", func.return_type),
+                }
+            }
+            TypeKind::SelfType => write!(f, "Self"),
+            TypeKind::Never => write!(f, "!"),
+            TypeKind::Infer => write!(f, "_"),
+            TypeKind::Error => write!(f, "{{error}}"),
+        }
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, segment) in self.segments.iter().enumerate() {
+            if i > 0 {
+                write!(f, "::")?;
+            }
+            write!(f, "{}", segment.ident)?;
+            if !segment.args.is_empty() {
+                write!(f, "<")?;
+                write_list(f, &segment.args)?;
+                write!(f, ">")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for GenericArg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            GenericArgKind::Type(ty) => write!(f, "{}", ty),
+            GenericArgKind::Lifetime(lifetime) => write!(f, "{}", lifetime),
+        }
+    }
+}
+
+impl fmt::Display for Lifetime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "'{}", self.name)
+    }
+}
+
+fn write_list<T: fmt::Display>(f: &mut fmt::Formatter<'_>, items: &[T]) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct FunctionType {
     pub params: Vec<Type>,
     pub return_type: Box<Type>,
     pub is_async: bool,
+    pub is_unsafe: bool,
+    /// The calling convention of an `extern "ABI" fn`, `None` for the
+    /// native one.
+    pub abi: Option<String>,
 }
 
 impl Eq for FunctionType {}
@@ -1325,16 +1649,52 @@ pub struct WhereClause {
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub struct WherePredicate {
+pub enum WherePredicate {
+    /// `T: Trait + 'a`
+    Type(TypePredicate),
+    /// `'a: 'b + 'c`: `'a` outlives each of the bounds.
+    Lifetime(LifetimePredicate),
+}
+
+impl WherePredicate {
+    /// The trait bounds of a type predicate; none for a lifetime predicate.
+    pub fn bounds(&self) -> &[WhereBound] {
+        match self {
+            WherePredicate::Type(predicate) => &predicate.bounds,
+            WherePredicate::Lifetime(_) => &[],
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            WherePredicate::Type(predicate) => predicate.span,
+            WherePredicate::Lifetime(predicate) => predicate.span,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypePredicate {
     pub bounded_type: Type,
     pub bounds: Vec<WhereBound>,
     pub span: Span,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct LifetimePredicate {
+    pub lifetime: Lifetime,
+    pub bounds: Vec<Lifetime>,
+    pub span: Span,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct WhereBound {
-    pub trait_ref: Type,
+    /// `None` for a bound made only of lifetimes, as in `T: 'a`.
+    pub trait_ref: Option<Type>,
+    /// Lifetimes the bounded type must outlive, as in `T: Trait + 'a`.
     pub lifetime_bounds: Vec<Lifetime>,
+    /// `?Sized`: lifts the implicit bound instead of adding one.
+    pub relaxed: bool,
     pub span: Span,
 }
 
@@ -1360,19 +1720,603 @@ pub struct AttributeArg {
 #[derive(Debug, Clone, PartialEq)]
 pub struct GenericParam {
     pub name: Ident,
-    pub bounds: Vec<Type>,
+    pub bounds: Vec<WhereBound>,
+    /// The type of a `const N: usize` parameter; `None` for a type parameter.
+    pub const_ty: Option<Type>,
     pub span: Span,
 }
 
+impl HasSpan for Item {
+    fn span(&self) -> Span {
+        match self {
+            Item::Function(item) => item.span,
+            Item::Struct(item) => item.span,
+            Item::Enum(item) => item.span,
+            Item::Trait(item) => item.span,
+            Item::Impl(item) => item.span,
+            Item::Use(item) => item.span,
+            Item::Mod(item) => item.span,
+            Item::Extern(item) => item.span,
+            Item::Constant(item) => item.span,
+            Item::Static(item) => item.span,
+            Item::Macro(item) => item.span,
+            Item::MacroCall(item) => item.span,
+            Item::ForAll(item) => item.span,
+            Item::Default(item) => item.span,
+            Item::Sync(item) => item.span,
+            Item::Sized(item) => item.span,
+            Item::IntoIterator(item) => item.span,
+        }
+    }
+}
+
+macro_rules! impl_has_span {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl HasSpan for $ty {
+                fn span(&self) -> Span {
+                    self.span
+                }
+            }
+        )*
+    };
+}
+
+impl_has_span!(
+    Program, Function, Param, Struct, Field, Enum, Variant, Trait, Impl, Use, Mod,
+    ExternBlock, ExternItem, Constant, Static, Stmt, Expr, Literal, Pattern, SliceRest, Type,
+    Path, PathSegment, Lifetime, Label, Attribute, GenericParam,
+);
+
+/// An expression tree behind an `Arc`, for passes that hand the same subtree to
+/// many owners without mutating it (macro expansion, incremental re-analysis).
+/// Cloning only bumps a reference count; `into_expr` gives back an owned tree,
+/// deep-cloning only if the tree is still shared.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SharedExpr(Arc<Expr>);
+
+impl SharedExpr {
+    pub fn new(expr: Expr) -> Self {
+        SharedExpr(Arc::new(expr))
+    }
+
+    pub fn into_expr(self) -> Expr {
+        Arc::try_unwrap(self.0).unwrap_or_else(|shared| (*shared).clone())
+    }
+
+    /// Whether both handles point at the same tree, not merely equal ones.
+    pub fn ptr_eq(&self, other: &SharedExpr) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl std::ops::Deref for SharedExpr {
+    type Target = Expr;
+
+    fn deref(&self) -> &Expr {
+        &self.0
+    }
+}
+
+impl From<Expr> for SharedExpr {
+    fn from(expr: Expr) -> Self {
+        SharedExpr::new(expr)
+    }
+}
+
+impl Expr {
+    pub fn into_shared(self) -> SharedExpr {
+        SharedExpr::new(self)
+    }
+
+    /// Whether the expression ends in a block (`if`, `match`, loops, `{ ... }`)
+    /// and so can stand as a statement without a trailing `;`.
+    pub fn is_block_like(&self) -> bool {
+        matches!(
+            &*self.kind,
+            ExprKind::If(_)
+                | ExprKind::Match(_)
+                | ExprKind::Loop(_)
+                | ExprKind::While(_)
+                | ExprKind::For(_)
+                | ExprKind::Block(_)
+                | ExprKind::Unsafe(_)
+                | ExprKind::AsyncBlock(_)
+        )
+    }
+
+    /// Whether the expression names a memory location that can be assigned
+    /// to: a variable, a field, an index or a dereference.
+    pub fn is_place(&self) -> bool {
+        match &*self.kind {
+            ExprKind::Identifier(_) | ExprKind::FieldAccess(_) | ExprKind::Index(_) => true,
+            ExprKind::Unary(unary) => unary.op == UnOp::Deref,
+            _ => false,
+        }
+    }
+}
+
+impl HasSpan for SharedExpr {
+    fn span(&self) -> Span {
+        self.0.span
+    }
+}
+
+/// Structural equality that treats every span as equal, so trees built by
+/// hand or parsed from differently formatted source can be compared.
+pub trait EqIgnoringSpans {
+    fn eq_ignoring_spans(&self, other: &Self) -> bool;
+}
+
+pub fn eq_ignoring_spans<T: EqIgnoringSpans>(a: &T, b: &T) -> bool {
+    a.eq_ignoring_spans(b)
+}
+
+impl EqIgnoringSpans for Span {
+    fn eq_ignoring_spans(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+/// Types that hold no spans compare with `==`.
+macro_rules! impl_eq_by_value {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl EqIgnoringSpans for $ty {
+                fn eq_ignoring_spans(&self, other: &Self) -> bool {
+                    self == other
+                }
+            }
+        )*
+    };
+}
+
+impl_eq_by_value!(str, String, bool, char, f64, i32, i64, i128, isize, u8, u32, u64, usize);
+
+impl<T: EqIgnoringSpans + ?std::marker::Sized> EqIgnoringSpans for &T {
+    fn eq_ignoring_spans(&self, other: &Self) -> bool {
+        (**self).eq_ignoring_spans(*other)
+    }
+}
+
+impl<T: EqIgnoringSpans + ?std::marker::Sized> EqIgnoringSpans for Box<T> {
+    fn eq_ignoring_spans(&self, other: &Self) -> bool {
+        (**self).eq_ignoring_spans(other)
+    }
+}
+
+impl<T: EqIgnoringSpans + ?std::marker::Sized> EqIgnoringSpans for Arc<T> {
+    fn eq_ignoring_spans(&self, other: &Self) -> bool {
+        (**self).eq_ignoring_spans(other)
+    }
+}
+
+impl<T: EqIgnoringSpans> EqIgnoringSpans for Option<T> {
+    fn eq_ignoring_spans(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Some(a), Some(b)) => a.eq_ignoring_spans(b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<T: EqIgnoringSpans> EqIgnoringSpans for [T] {
+    fn eq_ignoring_spans(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().zip(other).all(|(a, b)| a.eq_ignoring_spans(b))
+    }
+}
+
+impl<T: EqIgnoringSpans> EqIgnoringSpans for Vec<T> {
+    fn eq_ignoring_spans(&self, other: &Self) -> bool {
+        self.as_slice().eq_ignoring_spans(other.as_slice())
+    }
+}
+
+impl<A: smallvec::Array> EqIgnoringSpans for SmallVec<A>
+where
+    A::Item: EqIgnoringSpans,
+{
+    fn eq_ignoring_spans(&self, other: &Self) -> bool {
+        self.as_slice().eq_ignoring_spans(other.as_slice())
+    }
+}
+
+macro_rules! impl_eq_for_tuples {
+    ($(($($name:ident $index:tt),*))*) => {
+        $(
+            impl<$($name: EqIgnoringSpans),*> EqIgnoringSpans for ($($name,)*) {
+                #[allow(unused_variables)]
+                fn eq_ignoring_spans(&self, other: &Self) -> bool {
+                    true $(&& self.$index.eq_ignoring_spans(&other.$index))*
+                }
+            }
+        )*
+    };
+}
+
+impl_eq_for_tuples! {
+    ()
+    (A 0)
+    (A 0, B 1)
+    (A 0, B 1, C 2)
+}
+
+impl EqIgnoringSpans for SharedExpr {
+    fn eq_ignoring_spans(&self, other: &Self) -> bool {
+        self.0.eq_ignoring_spans(&other.0)
+    }
+}
+
+/// Rewrites every span in a tree in place, e.g. to point nodes parsed from
+/// generated text back at the code that generated it.
+pub trait MapSpans {
+    fn map_spans(&mut self, f: &mut dyn FnMut(&mut Span));
+}
+
+impl MapSpans for Span {
+    fn map_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        f(self)
+    }
+}
+
+/// Types that hold no spans have nothing to map.
+macro_rules! impl_map_spans_noop {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl MapSpans for $ty {
+                fn map_spans(&mut self, _f: &mut dyn FnMut(&mut Span)) {}
+            }
+        )*
+    };
+}
+
+impl_map_spans_noop!(Arc<str>, String, bool, char, f64, i32, i64, i128, isize, u8, u32, u64, usize);
+
+impl<T: MapSpans + ?std::marker::Sized> MapSpans for Box<T> {
+    fn map_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        (**self).map_spans(f)
+    }
+}
+
+impl<T: MapSpans> MapSpans for Option<T> {
+    fn map_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        if let Some(value) = self {
+            value.map_spans(f);
+        }
+    }
+}
+
+impl<T: MapSpans> MapSpans for [T] {
+    fn map_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        for value in self {
+            value.map_spans(f);
+        }
+    }
+}
+
+impl<T: MapSpans> MapSpans for Vec<T> {
+    fn map_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        self.as_mut_slice().map_spans(f)
+    }
+}
+
+impl<A: smallvec::Array> MapSpans for SmallVec<A>
+where
+    A::Item: MapSpans,
+{
+    fn map_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        self.as_mut_slice().map_spans(f)
+    }
+}
+
+macro_rules! impl_map_spans_for_tuples {
+    ($(($($name:ident $index:tt),*))*) => {
+        $(
+            impl<$($name: MapSpans),*> MapSpans for ($($name,)*) {
+                fn map_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+                    $(self.$index.map_spans(f);)*
+                }
+            }
+        )*
+    };
+}
+
+impl_map_spans_for_tuples! {
+    (A 0, B 1)
+    (A 0, B 1, C 2)
+}
+
+/// Copies the expression first if it is shared, so other holders keep
+/// their spans.
+impl MapSpans for SharedExpr {
+    fn map_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        Arc::make_mut(&mut self.0).map_spans(f)
+    }
+}
+
+/// Implements `EqIgnoringSpans` and `MapSpans` field by field for the
+/// structs, and variant by variant for the enums, listed with their fields.
+/// Enum variants name a binding per field; for equality the bindings of both
+/// sides are gathered into tuples and compared.
+macro_rules! derive_span_traits {
+    ($($kind:ident $name:ident { $($body:tt)* })*) => {
+        $(derive_span_traits!(@$kind $name { $($body)* });)*
+    };
+    (@struct $name:ident { $($field:ident),* $(,)? }) => {
+        impl EqIgnoringSpans for $name {
+            fn eq_ignoring_spans(&self, other: &Self) -> bool {
+                true $(&& self.$field.eq_ignoring_spans(&other.$field))*
+            }
+        }
+
+        impl MapSpans for $name {
+            #[allow(unused_variables)]
+            fn map_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+                $(self.$field.map_spans(f);)*
+            }
+        }
+    };
+    (@enum $name:ident { $($variant:ident $(($($binding:ident),*))?),* $(,)? }) => {
+        impl MapSpans for $name {
+            #[allow(unused_variables)]
+            fn map_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+                match self {
+                    $($name::$variant $(($($binding),*))? => { $($($binding.map_spans(f);)*)? })*
+                }
+            }
+        }
+
+        impl EqIgnoringSpans for $name {
+            fn eq_ignoring_spans(&self, other: &Self) -> bool {
+                match self {
+                    $(
+                        $name::$variant $(($($binding),*))? => {
+                            let lhs = ($($($binding,)*)?);
+                            #[allow(unreachable_patterns)]
+                            match other {
+                                $name::$variant $(($($binding),*))? => lhs.eq_ignoring_spans(&($($($binding,)*)?)),
+                                _ => false,
+                            }
+                        }
+                    )*
+                }
+            }
+        }
+    };
+}
+
+derive_span_traits! {
+    struct Program { items, span }
+    struct Function {
+        name, params, return_type, body, span, is_pub, is_async, lifetimes, generics, where_clauses, attributes
+    }
+    struct Param { name, ty, span, is_mut, is_ref }
+    struct Struct { name, fields, span, is_pub, generics, where_clauses, attributes }
+    struct Field { name, ty, span, is_pub, attributes }
+    struct Enum { name, variants, span, is_pub, generics, where_clauses, attributes }
+    struct Variant { name, fields, span, attributes, discriminant }
+    struct Trait { name, items, span, is_pub, generics, super_traits, where_clauses, attributes }
+    struct FunctionSig { name, params, return_type, span, is_variadic }
+    struct TraitConst { name, ty, default, span }
+    struct Impl { trait_name, type_name, items, span, generics, where_clauses, attributes }
+    struct TypeBinding { name, ty, span, is_pub }
+    struct Use { path, alias, span, is_pub }
+    struct Mod { name, items, span, is_pub, file_path, is_inline, attributes }
+    struct ExternBlock { abi, items, span }
+    struct ExternItem { name, ty, span, is_pub, is_mut, sig }
+    struct Constant { name, ty, value, span, is_pub }
+    struct Static { name, ty, value, span, is_pub, is_mut }
+    struct Macro { name, params, body, span, is_pub, is_procedural }
+    struct MacroParam { name, ty, span }
+    struct MacroRule { pattern, expansion }
+    struct MacroCall { name, delimiter, tokens, span }
+    struct ForAll { name, params, return_type, body, span, is_pub, generics, where_clauses }
+    struct Default { name, ty, value, span, is_pub }
+    struct Sync { name, ty, span, is_pub }
+    struct Sized { name, ty, span, is_pub }
+    struct IntoIterator { name, ty, span, is_pub }
+    struct Stmt { kind, span }
+    struct LetStmt { pattern, ty, initializer, span }
+    struct VarStmt { pattern, ty, initializer, span }
+    struct LoopStmt { label, body, span }
+    struct WhileStmt { label, condition, body, span }
+    struct ForStmt { label, pattern, iterable, body, span }
+    struct GuardStmt { condition, else_branch, span }
+    struct Expr { kind, span, ty }
+    struct Literal { kind, span }
+    struct Ability { name, effects }
+    struct BinaryExpr { left, op, right }
+    struct UnaryExpr { op, expr }
+    struct CallExpr { func, args }
+    struct MethodCallExpr { expr, method, args, generics }
+    struct FieldAccessExpr { expr, field }
+    struct TupleExpr { elements }
+    struct ArrayExpr { elements }
+    struct IndexExpr { expr, index }
+    struct SliceExpr { expr, start, end }
+    struct CastExpr { expr, ty }
+    struct TernaryExpr { condition, then_branch, else_branch }
+    struct IfExpr { condition, then_branch, else_branch }
+    struct MatchExpr { expr, arms }
+    struct MatchArm { pattern, guard, body, span }
+    struct BlockExpr { label, stmts, ty }
+    struct ClosureExpr { params, body, is_async, is_move, captures }
+    struct AsyncBlockExpr { body, is_move }
+    struct AtomicLoadExpr { atomic, order }
+    struct AtomicStoreExpr { atomic, value, order }
+    struct AtomicFetchExpr { atomic, value, order }
+    struct AtomicCompareExchangeExpr { atomic, expected, desired, success_order, failure_order }
+    struct AtomicExchangeExpr { atomic, value, order }
+    struct AtomicFenceExpr { order }
+    struct EffectBlockExpr { effects, body }
+    struct AbilityBlockExpr { ability, body }
+    struct LinkedListExpr { ty, span }
+    struct ListNodeExpr { ty, span }
+    struct PushFrontExpr { list, value }
+    struct PushBackExpr { list, value }
+    struct PopFrontExpr { list }
+    struct PopBackExpr { list }
+    struct FrontExpr { list }
+    struct BackExpr { list }
+    struct InsertExpr { list, position, value }
+    struct EraseExpr { list, value }
+    struct ClearExpr { list }
+    struct SpliceExpr { list1, list2 }
+    struct MergeExpr { list1, list2 }
+    struct ReverseExpr { list }
+    struct SortExpr { list }
+    struct UniqueExpr { list }
+    struct RemoveExpr { list, value }
+    struct WaitExpr { atomic, timeout }
+    struct NotifyExpr { atomic }
+    struct NotifyAllExpr { atomic }
+    struct IteratorExpr { iterable }
+    struct NextExpr { iterator }
+    struct ItemExpr { iterator }
+    struct CollectExpr { iterator }
+    struct ChainExpr { iterator1, iterator2 }
+    struct FilterExpr { iterator, predicate }
+    struct FoldExpr { iterator, init, accumulator, body }
+    struct MapExpr { iterator, mapper }
+    struct ResultExpr { ok_type, err_type }
+    struct OkExpr { value }
+    struct ErrExpr { error }
+    struct TryExpr { expr }
+    struct CatchExpr { try_expr, error_var, catch_expr }
+    struct ErrorExpr { message }
+    struct ContextExpr { context }
+    struct ThrowExpr { error }
+    struct FutureExpr { body }
+    struct YieldExpr { value }
+    struct StreamExpr { body }
+    struct UnsafeExpr { body }
+    struct AllocExpr { ty, size }
+    struct AllocAlignedExpr { ty, size, alignment }
+    struct FreeExpr { ptr }
+    struct PtrExpr { ty }
+    struct PtrAddExpr { ptr, offset }
+    struct PtrSubExpr { ptr1, ptr2 }
+    struct PtrLoadExpr { ptr, ty }
+    struct PtrStoreExpr { ptr, value }
+    struct PtrCastExpr { ptr, target_ty }
+    struct PtrOffsetOfExpr { ptr, field }
+    struct PtrSizeOfExpr { ty }
+    struct AlignOfExpr { ty }
+    struct ProofExpr { proposition, proof }
+    struct TheoremExpr { name, params, proposition, proof }
+    struct LemmaExpr { name, params, proposition, proof }
+    struct InductionExpr { variable, base_case, inductive_step }
+    struct CaseExpr { value, cases }
+    struct MatchCase { pattern, body }
+    struct ReflExpr { ty }
+    struct CongExpr { ty, expr1, expr2 }
+    struct SymExpr { ty, expr }
+    struct TransExpr { ty, expr1, expr2, expr3 }
+    struct RecExpr { ty, body }
+    struct FixExpr { ty, body }
+    struct ClassExpr { name, params, methods }
+    struct InstanceExpr { generics, class_name, ty, methods }
+    struct WhereExpr { expr, constraints }
+    struct EqPropExpr { ty, left, right }
+    struct ReflPropExpr { ty, expr }
+    struct JMeqExpr { ty, expr1, expr2 }
+    struct RewriteExpr { ty, expr, rule }
+    struct WithExpr { expr, bindings }
+    struct LoopExpr { label, body }
+    struct WhileExpr { label, condition, body }
+    struct ForExpr { label, pattern, iterable, body }
+    struct AssignExpr { left, right }
+    struct AssignOpExpr { left, op, right }
+    struct RangeExpr { start, end, inclusive }
+    struct StructExpr { path, fields, base }
+    struct StructField { name, expr, span }
+    struct EnumExpr { path, variant, fields }
+    struct Pattern { kind, span }
+    struct BindingMode { is_mut, by_ref }
+    struct SliceRest { binding, span }
+    struct PatternField { name, pattern }
+    struct Type { kind, span }
+    struct FunctionType { params, return_type, is_async, is_unsafe, abi }
+    struct Path { segments, span }
+    struct PathSegment { ident, args, span }
+    struct GenericArg { kind, span }
+    struct Lifetime { name, span }
+    struct LifetimeParam { name, bounds, span }
+    struct WhereClause { predicates, span }
+    struct TypePredicate { bounded_type, bounds, span }
+    struct LifetimePredicate { lifetime, bounds, span }
+    struct WhereBound { trait_ref, lifetime_bounds, relaxed, span }
+    struct Label { name, span }
+    struct Attribute { name, args, span }
+    struct AttributeArg { expr, span }
+    struct GenericParam { name, bounds, const_ty, span }
+    enum Item {
+        Function(a), Struct(a), Enum(a), Trait(a), Impl(a), Use(a), Mod(a), Extern(a), Constant(a), Static(a),
+        Macro(a), MacroCall(a), ForAll(a), Default(a), Sync(a), Sized(a), IntoIterator(a)
+    }
+    enum TraitItem { Function(a), Const(a), Type(a) }
+    enum ImplItem { Function(a), Const(a), Type(a) }
+    enum MacroBody { Rules(a), Procedural(a) }
+    enum MacroPattern { Token(a), Sequence(a), Repeat(a, b, c), Choice(a), Var(a, b), Group(a, b) }
+    enum MacroRepeatKind { ZeroOrMore, OneOrMore, ZeroOrOne }
+    enum MacroFragment { Expr, Ident, Literal, Tt }
+    enum MacroToken { Ident(a), Literal(a), Punctuation(a), Operator(a), Group(a, b) }
+    enum MacroDelimiter { Paren, Bracket, Brace }
+    enum MacroExpansion { Expr(a), Tokens(a), Template(a) }
+    enum StmtKind {
+        Expr(a), Let(a), Var(a), Return(a), Break(a), Continue(a), Loop(a), While(a), For(a), Guard(a), Defer(a),
+        Empty
+    }
+    enum ExprKind {
+        Literal(a), Identifier(a), Path(a), Binary(a), Unary(a), Call(a), MethodCall(a), FieldAccess(a), Tuple(a),
+        Array(a), Index(a), Slice(a), Cast(a), Ternary(a), If(a), Match(a), Block(a), Closure(a), AsyncBlock(a),
+        Continue(a), Break(a, b), Return(a), MacroCall(a), Loop(a), While(a), For(a), Assign(a), AssignOp(a),
+        Range(a), Struct(a), Enum(a), Field(a), AtomicLoad(a), AtomicStore(a), AtomicFetchAdd(a), AtomicFetchSub(a),
+        AtomicFetchAnd(a), AtomicFetchOr(a), AtomicFetchXor(a), AtomicCompareExchange(a), AtomicExchange(a),
+        AtomicFence(a), Wait(a), Notify(a), NotifyAll(a), EffectBlock(a), AbilityBlock(a), LinkedList(a),
+        ListNode(a), PushFront(a), PushBack(a), PopFront(a), PopBack(a), Front(a), Back(a), Insert(a), Erase(a),
+        Clear(a), Splice(a), Merge(a), Reverse(a), Sort(a), Unique(a), Remove(a), Error, Iterator(a), Next(a),
+        Item(a), Collect(a), Chain(a), Filter(a), Fold(a), Map(a), Result(a), Ok(a), Err(a), Try(a), Catch(a),
+        ErrorExpr(a), Context(a), Throw(a), Future(a), Yield(a), Stream(a), Unsafe(a), Alloc(a), AllocAligned(a),
+        Free(a), Ptr(a), PtrAdd(a), PtrSub(a), PtrLoad(a), PtrStore(a), PtrCast(a), PtrOffsetOf(a), PtrSizeOf(a),
+        AlignOf(a), Proof(a), Theorem(a), Lemma(a), Induction(a), Case(a), Refl(a), Cong(a), Sym(a), Trans(a),
+        Rec(a), Fix(a), Class(a), Instance(a), Where(a), EqProp(a), ReflProp(a), JMeq(a), Rewrite(a), With(a)
+    }
+    enum LiteralKind { Int(a), Float(a), Bool(a), Char(a), String(a), Unit, Null, Byte(a), ByteString(a), Atomic(a) }
+    enum AtomicLiteral { AtomicI32(a), AtomicI64(a), AtomicU32(a), AtomicU64(a), AtomicIsize(a), AtomicUsize(a) }
+    enum MemoryOrder {
+        Relaxed, Consume, Acquire, Release, AcqRel, SeqCst, HappensBefore, Volatile, MemoryBarrier, Wait, Notify,
+        NotifyAll, DataDependency
+    }
+    enum Effect { IO, Exception, State, Async }
+    enum BinOp { Add, Sub, Mul, Div, Mod, And, Or, BitAnd, BitOr, BitXor, Shl, Shr, Eq, Ne, Lt, Le, Gt, Ge }
+    enum UnOp { Neg, Not, Deref, Ref, RefMut }
+    enum Capture { CaptureRef(a), CaptureValue(a) }
+    enum PatternKind {
+        Wildcard, Identifier(a, b), Literal(a), Tuple(a), Struct(a, b), Enum(a, b, c), Range(a, b), Slice(a, b, c),
+        Or(a), Error
+    }
+    enum TypeKind {
+        Path(a), Tuple(a), Array(a, b), Slice(a), Pointer(a, b), Reference(a, b, c), Function(a), SelfType, Never,
+        Infer, Error
+    }
+    enum ArrayLen { Const(a), Inferred }
+    enum Mutability { Mutable, Immutable }
+    enum GenericArgKind { Type(a), Lifetime(a) }
+    enum WherePredicate { Type(a), Lifetime(a) }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chim_span::FileId;
 
     #[test]
     fn test_program_creation() {
         let program = Program {
             items: Vec::new(),
-            span: Span::new(FileId(0), 0, 0, 0, 0),
+            span: Span::new(FileId::new(0), 0, 0, 0, 0),
         };
         assert!(program.items.is_empty());
     }
@@ -1384,11 +2328,13 @@ mod tests {
             params: Vec::new(),
             return_type: None,
             body: Vec::new(),
-            span: Span::new(FileId(0), 0, 0, 0, 0),
+            span: Span::new(FileId::new(0), 0, 0, 0, 0),
             is_pub: false,
             is_async: false,
             lifetimes: Vec::new(),
+            generics: Vec::new(),
             where_clauses: Vec::new(),
+            attributes: Vec::new(),
         };
         assert_eq!(func.name.as_ref(), "main");
     }
@@ -1396,22 +2342,167 @@ mod tests {
     #[test]
     fn test_type_creation() {
         let ty = Type {
-            kind: TypeKind::Infer,
-            span: Span::new(FileId(0), 0, 0, 0, 0),
+            kind: Box::new(TypeKind::Infer),
+            span: Span::new(FileId::new(0), 0, 0, 0, 0),
         };
-        matches!(ty.kind, TypeKind::Infer);
+        assert!(matches!(*ty.kind, TypeKind::Infer));
     }
 
     #[test]
     fn test_expr_creation() {
         let expr = Expr {
-            kind: ExprKind::Literal(Literal {
+            kind: Box::new(ExprKind::Literal(Literal {
                 kind: LiteralKind::Int(42),
-                span: Span::new(FileId(0), 0, 2, 0, 0),
-            }),
-            span: Span::new(FileId(0), 0, 2, 0, 0),
+                span: Span::new(FileId::new(0), 0, 2, 0, 0),
+            })),
+            span: Span::new(FileId::new(0), 0, 2, 0, 0),
+            ty: None,
+        };
+        assert!(matches!(*expr.kind, ExprKind::Literal(Literal { kind: LiteralKind::Int(42), .. })));
+    }
+
+    #[test]
+    fn test_has_span() {
+        let span = Span::new(FileId::new(0), 4, 9, 1, 2);
+        let expr = Expr {
+            kind: Box::new(ExprKind::Literal(Literal {
+                kind: LiteralKind::Int(1),
+                span,
+            })),
+            span,
+            ty: None,
+        };
+        let stmt = Stmt {
+            kind: StmtKind::Expr(expr.clone()),
+            span,
+        };
+        let ty = Type {
+            kind: Box::new(TypeKind::Infer),
+            span,
+        };
+        let pattern = Pattern {
+            kind: PatternKind::Wildcard,
+            span,
+        };
+        let item = Item::Use(Use {
+            path: Path { segments: Vec::new(), span },
+            alias: None,
+            span,
+            is_pub: false,
+        });
+
+        fn span_of(node: &dyn HasSpan) -> Span {
+            node.span()
+        }
+        assert_eq!(span_of(&expr), span);
+        assert_eq!(span_of(&stmt), span);
+        assert_eq!(span_of(&ty), span);
+        assert_eq!(span_of(&pattern), span);
+        assert_eq!(span_of(&item), span);
+    }
+
+    #[test]
+    fn test_shared_expr_clone_shares_tree() {
+        let span = Span::new(FileId::new(0), 0, 1, 0, 0);
+        let literal = Expr {
+            kind: Box::new(ExprKind::Literal(Literal { kind: LiteralKind::Int(1), span })),
+            span,
             ty: None,
         };
-        matches!(expr.kind, ExprKind::Literal(Literal { kind: LiteralKind::Int(42), .. }));
+        let shared = literal.clone().into_shared();
+        let copy = shared.clone();
+        assert!(shared.ptr_eq(&copy));
+        assert!(!shared.ptr_eq(&SharedExpr::new(literal.clone())));
+        assert_eq!(copy.span(), span);
+
+        drop(shared);
+        assert_eq!(copy.into_expr(), literal);
+    }
+
+    #[test]
+    fn test_eq_ignoring_spans() {
+        let literal = |value, start| {
+            let span = Span::new(FileId::new(0), start, start + 1, 0, start);
+            Expr {
+                kind: Box::new(ExprKind::Literal(Literal { kind: LiteralKind::Int(value), span })),
+                span,
+                ty: None,
+            }
+        };
+        let sum = |start| Expr {
+            kind: Box::new(ExprKind::Binary(BinaryExpr {
+                left: Box::new(literal(1, start)),
+                op: BinOp::Add,
+                right: Box::new(literal(2, start + 4)),
+            })),
+            span: Span::new(FileId::new(0), start, start + 5, 0, start),
+            ty: None,
+        };
+
+        assert_ne!(sum(0), sum(3));
+        assert!(eq_ignoring_spans(&sum(0), &sum(3)));
+        assert!(!eq_ignoring_spans(&sum(0), &literal(1, 0)));
+
+        let stmt = |start| Stmt { kind: StmtKind::Expr(sum(start)), span: sum(start).span };
+        assert!(eq_ignoring_spans(&stmt(0), &stmt(7)));
+    }
+
+    #[test]
+    fn test_type_display() {
+        let span = Span::new(FileId::new(0), 0, 0, 0, 0);
+        let ty = |kind: TypeKind| Type { kind: Box::new(kind), span };
+        let segment = |name: &str, args: Vec<GenericArg>| PathSegment { ident: Arc::from(name), args, span };
+        let named = |name: &str| ty(TypeKind::Path(Path { segments: vec![segment(name, Vec::new())], span }));
+        let lifetime = Lifetime { name: Arc::from("a"), span };
+        let len = |n: i128| ArrayLen::Const(Expr {
+            kind: Box::new(ExprKind::Literal(Literal { kind: LiteralKind::Int(n), span })),
+            span,
+            ty: None,
+        });
+
+        let path = ty(TypeKind::Path(Path {
+            segments: vec![
+                segment("std", Vec::new()),
+                segment("Map", vec![
+                    GenericArg { kind: GenericArgKind::Lifetime(lifetime.clone()), span },
+                    GenericArg { kind: GenericArgKind::Type(named("K")), span },
+                ]),
+            ],
+            span,
+        }));
+        assert_eq!(path.to_string(), "std::Map<'a, K>");
+        assert_eq!(ty(TypeKind::Tuple(vec![named("A"), named("B")])).to_string(), "(A, B)");
+        assert_eq!(ty(TypeKind::Tuple(vec![named("A")])).to_string(), "(A,)");
+        assert_eq!(ty(TypeKind::Tuple(Vec::new())).to_string(), "()");
+        assert_eq!(ty(TypeKind::Array(Box::new(named("T")), len(4))).to_string(), "[T; 4]");
+        assert_eq!(ty(TypeKind::Array(Box::new(named("T")), ArrayLen::Inferred)).to_string(), "[T; _]");
+        assert_eq!(ty(TypeKind::Slice(Box::new(named("T")))).to_string(), "[T]");
+        assert_eq!(ty(TypeKind::Pointer(Box::new(named("T")), Mutability::Immutable)).to_string(), "*T");
+        assert_eq!(ty(TypeKind::Pointer(Box::new(named("T")), Mutability::Mutable)).to_string(), "*mut T");
+        assert_eq!(
+            ty(TypeKind::Reference(Some(lifetime), Box::new(named("T")), Mutability::Mutable)).to_string(),
+            "&'a mut T"
+        );
+        assert_eq!(ty(TypeKind::Reference(None, Box::new(named("T")), Mutability::Immutable)).to_string(), "&T");
+        let func = |return_type: Type, is_async: bool| ty(TypeKind::Function(FunctionType {
+            params: vec![named("A"), named("C")],
+            return_type: Box::new(return_type),
+            is_async,
+            is_unsafe: false,
+            abi: None,
+        }));
+        assert_eq!(func(named("B"), false).to_string(), "fn(A, C) -> B");
+        assert_eq!(func(ty(TypeKind::Tuple(Vec::new())), true).to_string(), "async fn(A, C)");
+        let foreign = ty(TypeKind::Function(FunctionType {
+            params: Vec::new(),
+            return_type: Box::new(ty(TypeKind::Tuple(Vec::new()))),
+            is_async: false,
+            is_unsafe: true,
+            abi: Some("C".to_string()),
+        }));
+        assert_eq!(foreign.to_string(), "unsafe extern \"C\" fn()");
+        assert_eq!(ty(TypeKind::Never).to_string(), "!");
+        assert_eq!(ty(TypeKind::Infer).to_string(), "_");
+        assert_eq!(ty(TypeKind::Error).to_string(), "{error}");
     }
 }
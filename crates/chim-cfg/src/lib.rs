@@ -1,15 +1,15 @@
-use chim_ir::{IRModule, IRFunction, BasicBlock, BlockId, IRInst, Terminator};
+use chim_ir::{IRModule, IRFunction, IRFunctionId, BasicBlock, BlockId, IRInst, Terminator, ValueId};
 use chim_semantic::TypeId;
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::{BTreeMap, BTreeSet};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct ControlFlowGraph {
-    pub function_id: usize,
+    pub function_id: IRFunctionId,
     pub blocks: Vec<CFGBlock>,
     pub entry: BlockId,
     pub exit: Option<BlockId>,
-    pub predecessors: HashMap<BlockId, Vec<BlockId>>,
-    pub successors: HashMap<BlockId, Vec<BlockId>>,
+    pub predecessors: BTreeMap<BlockId, Vec<BlockId>>,
+    pub successors: BTreeMap<BlockId, Vec<BlockId>>,
     pub dom_tree: DominatorTree,
     pub post_dom_tree: PostDominatorTree,
     pub loops: Vec<NaturalLoop>,
@@ -22,56 +22,49 @@ pub struct CFGBlock {
     pub label: String,
     pub instructions: Vec<IRInst>,
     pub terminator: Terminator,
-    pub phis: Vec<PhiNode>,
     pub is_entry: bool,
     pub is_exit: bool,
     pub is_loop_header: bool,
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub struct PhiNode {
-    pub result: TypeId,
-    pub incoming: Vec<(BlockId, TypeId)>,
-}
-
 #[derive(Debug, Clone, PartialEq)]
 pub struct DominatorTree {
-    pub tree: HashMap<BlockId, Vec<BlockId>>,
-    pub idom: HashMap<BlockId, BlockId>,
-    pub depth: HashMap<BlockId, usize>,
+    pub tree: BTreeMap<BlockId, Vec<BlockId>>,
+    pub idom: BTreeMap<BlockId, BlockId>,
+    pub depth: BTreeMap<BlockId, usize>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct PostDominatorTree {
-    pub tree: HashMap<BlockId, Vec<BlockId>>,
-    pub idom: HashMap<BlockId, BlockId>,
+    pub tree: BTreeMap<BlockId, Vec<BlockId>>,
+    pub idom: BTreeMap<BlockId, BlockId>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct NaturalLoop {
     pub header: BlockId,
-    pub blocks: HashSet<BlockId>,
+    pub blocks: BTreeSet<BlockId>,
     pub preheader: Option<BlockId>,
     pub exits: Vec<BlockId>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct DataFlowResults {
-    pub reaching_defs: HashMap<BlockId, Vec<TypeId>>,
-    pub live_vars: HashMap<BlockId, HashSet<TypeId>>,
-    pub available_exprs: HashMap<BlockId, HashSet<TypeId>>,
-    pub very_busy_exprs: HashMap<BlockId, HashSet<TypeId>>,
+    pub reaching_defs: BTreeMap<BlockId, BTreeSet<TypeId>>,
+    pub live_vars: BTreeMap<BlockId, BTreeSet<ValueId>>,
+    pub available_exprs: BTreeMap<BlockId, BTreeSet<TypeId>>,
+    pub very_busy_exprs: BTreeMap<BlockId, BTreeSet<TypeId>>,
 }
 
 impl ControlFlowGraph {
     pub fn build_from_function(module: &IRModule, func: &IRFunction) -> Self {
         let mut cfg = ControlFlowGraph {
-            function_id: func.id.0,
+            function_id: func.id,
             blocks: Vec::new(),
             entry: BlockId(0),
             exit: None,
-            predecessors: HashMap::new(),
-            successors: HashMap::new(),
+            predecessors: BTreeMap::new(),
+            successors: BTreeMap::new(),
             dom_tree: DominatorTree::new(),
             post_dom_tree: PostDominatorTree::new(),
             loops: Vec::new(),
@@ -84,7 +77,6 @@ impl ControlFlowGraph {
                 label: format!(".L{}", block.id.0),
                 instructions: block.instructions.clone(),
                 terminator: block.terminator.clone(),
-                phis: Vec::new(),
                 is_entry: block.id.0 == 0,
                 is_exit: matches!(block.terminator, Terminator::Return(_)),
                 is_loop_header: false,
@@ -97,28 +89,94 @@ impl ControlFlowGraph {
             .find(|b| b.is_exit)
             .map(|b| b.id);
 
-        cfg.compute_dominators();
-        cfg.compute_post_dominators();
-        cfg.find_natural_loops();
-        cfg.compute_dataflow();
-
+        cfg.analyze();
         cfg
     }
 
-    fn add_edges(&mut self, block: &BasicBlock) {
-        let successors: Vec<BlockId> = match &block.terminator {
-            Terminator::Branch(target) => vec![*target],
-            Terminator::ConditionalBranch { true_block, false_block, .. } => vec![*true_block, *false_block],
-            Terminator::Return(_) | Terminator::Unreachable => Vec::new(),
-            Terminator::Switch { default_block, cases, .. } => {
-                let mut succs = vec![*default_block];
-                for (_, target) in cases {
-                    succs.push(*target);
+    /// (Re)computes dominators, loops and dataflow from the current edges.
+    fn analyze(&mut self) {
+        self.loops.clear();
+        for block in &mut self.blocks {
+            block.is_loop_header = false;
+        }
+        self.compute_dominators();
+        self.compute_post_dominators();
+        self.find_natural_loops();
+        self.compute_dataflow();
+    }
+
+    /// Inserts an empty block on every critical edge, one leading from a
+    /// block with several successors to a block with several predecessors,
+    /// so that copies placed "on the edge" when leaving SSA have a block of
+    /// their own. Phis in the target take the new block as their incoming
+    /// block. Returns the number of edges split.
+    pub fn split_critical_edges(&mut self) -> usize {
+        let distinct = |blocks: Option<&Vec<BlockId>>| blocks.map_or(0, |blocks| blocks.iter().collect::<BTreeSet<_>>().len());
+        let mut critical = Vec::new();
+        for (&from, succs) in &self.successors {
+            if distinct(Some(succs)) < 2 {
+                continue;
+            }
+            for &to in succs.iter().collect::<BTreeSet<_>>() {
+                if distinct(self.predecessors.get(&to)) > 1 {
+                    critical.push((from, to));
                 }
-                succs
             }
-            Terminator::Invoke { normal_block, unwind_block, .. } => vec![*normal_block, *unwind_block],
-        };
+        }
+
+        let mut next_id = self.blocks.iter().map(|b| b.id.0 + 1).max().unwrap_or(0);
+        for &(from, to) in &critical {
+            let middle = BlockId(next_id);
+            next_id += 1;
+
+            if let Some(block) = self.blocks.iter_mut().find(|b| b.id == from) {
+                for target in block.terminator.successors_mut() {
+                    if *target == to {
+                        *target = middle;
+                    }
+                }
+            }
+            if let Some(block) = self.blocks.iter_mut().find(|b| b.id == to) {
+                for inst in block.instructions.iter_mut().take_while(|inst| matches!(inst, IRInst::Phi { .. })) {
+                    for incoming in inst.blocks_mut() {
+                        if *incoming == from {
+                            *incoming = middle;
+                        }
+                    }
+                }
+            }
+            self.blocks.push(CFGBlock {
+                id: middle,
+                label: format!(".L{}", middle.0),
+                instructions: Vec::new(),
+                terminator: Terminator::Branch(to),
+                is_entry: false,
+                is_exit: false,
+                is_loop_header: false,
+            });
+
+            for succ in self.successors.entry(from).or_default() {
+                if *succ == to {
+                    *succ = middle;
+                }
+            }
+            for pred in self.predecessors.entry(to).or_default() {
+                if *pred == from {
+                    *pred = middle;
+                }
+            }
+            self.successors.insert(middle, vec![to]);
+            self.predecessors.insert(middle, vec![from]);
+        }
+
+        if !critical.is_empty() {
+            self.analyze();
+        }
+        critical.len()
+    }
+
+    fn add_edges(&mut self, block: &BasicBlock) {
+        let successors: Vec<BlockId> = block.terminator.successors().into_vec();
 
         self.successors.insert(block.id, successors.clone());
 
@@ -128,14 +186,14 @@ impl ControlFlowGraph {
     }
 
     fn compute_dominators(&mut self) {
-        let all_blocks: HashSet<BlockId> = self.blocks.iter().map(|b| b.id).collect();
-        let mut dom: HashMap<BlockId, HashSet<BlockId>> = HashMap::new();
+        let all_blocks: BTreeSet<BlockId> = self.blocks.iter().map(|b| b.id).collect();
+        let mut dom: BTreeMap<BlockId, BTreeSet<BlockId>> = BTreeMap::new();
 
         for &block_id in &all_blocks {
             dom.insert(block_id, all_blocks.clone());
         }
 
-        dom.insert(self.entry, HashSet::from([self.entry]));
+        dom.insert(self.entry, BTreeSet::from([self.entry]));
 
         let mut changed = true;
         while changed {
@@ -145,7 +203,7 @@ impl ControlFlowGraph {
                     continue;
                 }
 
-                let mut new_dom = HashSet::from([block_id]);
+                let mut new_dom = BTreeSet::from([block_id]);
                 if let Some(preds) = self.predecessors.get(&block_id) {
                     if !preds.is_empty() {
                         let mut intersection = all_blocks.clone();
@@ -163,40 +221,16 @@ impl ControlFlowGraph {
             }
         }
 
-        let mut idom: HashMap<BlockId, BlockId> = HashMap::new();
-        for &block_id in &all_blocks {
-            if block_id == self.entry {
-                continue;
-            }
-
-            let dom_set = &dom[&block_id];
-            let mut candidates: HashSet<BlockId> = dom_set.iter().filter(|&&b| b != block_id).cloned().collect();
-
-            if let Some(preds) = self.predecessors.get(&block_id) {
-                for &pred in preds {
-                    let pred_dom = &dom[&pred];
-                    candidates.retain(|b| pred_dom.contains(b));
-                }
-            }
-
-            if let Some(&immediate) = candidates.iter().next() {
-                idom.insert(block_id, immediate);
-            }
-        }
-
-        let mut depth: HashMap<BlockId, usize> = HashMap::new();
-        depth.insert(self.entry, 0);
+        let idom = immediate_dominators(&dom, self.entry);
 
-        for &block_id in &all_blocks {
-            if block_id == self.entry {
-                continue;
-            }
-            if let Some(&parent) = idom.get(&block_id) {
-                depth.insert(block_id, depth[&parent] + 1);
-            }
-        }
+        // The dominators of a block form a chain from the entry down to it.
+        let depth: BTreeMap<BlockId, usize> = dom
+            .iter()
+            .filter(|(block_id, _)| **block_id == self.entry || idom.contains_key(block_id))
+            .map(|(&block_id, dominators)| (block_id, dominators.len() - 1))
+            .collect();
 
-        let mut tree: HashMap<BlockId, Vec<BlockId>> = HashMap::new();
+        let mut tree: BTreeMap<BlockId, Vec<BlockId>> = BTreeMap::new();
         for (&child, &parent) in &idom {
             tree.entry(parent).or_default().push(child);
         }
@@ -205,16 +239,16 @@ impl ControlFlowGraph {
     }
 
     fn compute_post_dominators(&mut self) {
-        let all_blocks: HashSet<BlockId> = self.blocks.iter().map(|b| b.id).collect();
+        let all_blocks: BTreeSet<BlockId> = self.blocks.iter().map(|b| b.id).collect();
 
-        let mut post_dom: HashMap<BlockId, HashSet<BlockId>> = HashMap::new();
+        let mut post_dom: BTreeMap<BlockId, BTreeSet<BlockId>> = BTreeMap::new();
 
         for &block_id in &all_blocks {
             post_dom.insert(block_id, all_blocks.clone());
         }
 
         if let Some(exit) = self.exit {
-            post_dom.insert(exit, HashSet::from([exit]));
+            post_dom.insert(exit, BTreeSet::from([exit]));
         }
 
         let mut changed = true;
@@ -230,7 +264,7 @@ impl ControlFlowGraph {
                     continue;
                 }
 
-                let mut new_post_dom = HashSet::from([block_id]);
+                let mut new_post_dom = BTreeSet::from([block_id]);
                 let mut first = true;
                 for &succ in &successors {
                     let succ_post = &post_dom[&succ];
@@ -250,28 +284,12 @@ impl ControlFlowGraph {
             }
         }
 
-        let mut idom: HashMap<BlockId, BlockId> = HashMap::new();
-
-        for &block_id in &all_blocks {
-            if Some(block_id) == self.exit {
-                continue;
-            }
-
-            let post_dom_set = &post_dom[&block_id];
-            let mut candidates: HashSet<BlockId> = post_dom_set.iter().filter(|&&b| b != block_id).cloned().collect();
-
-            let successors = self.successors.get(&block_id).cloned().unwrap_or_default();
-            for &succ in &successors {
-                let succ_post = &post_dom[&succ];
-                candidates.retain(|b| succ_post.contains(b));
-            }
-
-            if let Some(&immediate) = candidates.iter().next() {
-                idom.insert(block_id, immediate);
-            }
-        }
+        let idom = match self.exit {
+            Some(exit) => immediate_dominators(&post_dom, exit),
+            None => BTreeMap::new(),
+        };
 
-        let mut tree: HashMap<BlockId, Vec<BlockId>> = HashMap::new();
+        let mut tree: BTreeMap<BlockId, Vec<BlockId>> = BTreeMap::new();
         for (&child, &parent) in &idom {
             tree.entry(parent).or_default().push(child);
         }
@@ -282,7 +300,7 @@ impl ControlFlowGraph {
     fn find_natural_loops(&mut self) {
         for &block_id in self.blocks.iter().map(|b| b.id) {
             if let Some(preds) = self.predecessors.get(&block_id) {
-                let mut loop_blocks = HashSet::new();
+                let mut loop_blocks = BTreeSet::new();
                 loop_blocks.insert(block_id);
 
                 let mut stack = Vec::new();
@@ -356,26 +374,26 @@ impl ControlFlowGraph {
     }
 
     fn compute_reaching_defs(&mut self) {
-        let mut in_sets: HashMap<BlockId, HashSet<TypeId>> = HashMap::new();
-        let mut out_sets: HashMap<BlockId, HashSet<TypeId>> = HashMap::new();
+        let mut in_sets: BTreeMap<BlockId, BTreeSet<TypeId>> = BTreeMap::new();
+        let mut out_sets: BTreeMap<BlockId, BTreeSet<TypeId>> = BTreeMap::new();
 
         for &block_id in self.blocks.iter().map(|b| b.id) {
-            in_sets.insert(block_id, HashSet::new());
-            out_sets.insert(block_id, HashSet::new());
+            in_sets.insert(block_id, BTreeSet::new());
+            out_sets.insert(block_id, BTreeSet::new());
         }
 
         let mut changed = true;
         while changed {
             changed = false;
             for &block_id in self.blocks.iter().map(|b| b.id) {
-                let mut new_in = HashSet::new();
+                let mut new_in = BTreeSet::new();
                 if let Some(preds) = self.predecessors.get(&block_id) {
                     for &pred in preds {
                         new_in.extend(out_sets.get(&pred).cloned().unwrap_or_default());
                     }
                 }
 
-                if new_in != *in_sets.get(&block_id).unwrap_or(&HashSet::new()) {
+                if new_in != *in_sets.get(&block_id).unwrap_or(&BTreeSet::new()) {
                     in_sets.insert(block_id, new_in.clone());
                     changed = true;
                 }
@@ -387,7 +405,7 @@ impl ControlFlowGraph {
                     }
                 }
 
-                if new_out != *out_sets.get(&block_id).unwrap_or(&HashSet::new()) {
+                if new_out != *out_sets.get(&block_id).unwrap_or(&BTreeSet::new()) {
                     out_sets.insert(block_id, new_out);
                     changed = true;
                 }
@@ -397,66 +415,58 @@ impl ControlFlowGraph {
         self.dataflow.reaching_defs = in_sets;
     }
 
+    /// Values live on entry to each block. A phi reads its operand on the
+    /// edge from the matching predecessor, so the operand is live out of that
+    /// predecessor only, not live into the phi's own block.
     fn compute_live_vars(&mut self) {
-        let mut use_sets: HashMap<BlockId, HashSet<TypeId>> = HashMap::new();
-        let mut def_sets: HashMap<BlockId, HashSet<TypeId>> = HashMap::new();
-
-        for &block_id in self.blocks.iter().map(|b| b.id) {
-            let mut use_set = HashSet::new();
-            let mut def_set = HashSet::new();
-
-            for inst in &self.blocks.iter().find(|b| b.id == block_id).map(|b| &b.instructions).unwrap_or(&Vec::new()) {
-                match inst {
-                    chim_ir::IRInst::Load { src, .. } => {
-                        use_set.insert(*src);
+        let mut use_sets: BTreeMap<BlockId, BTreeSet<ValueId>> = BTreeMap::new();
+        let mut def_sets: BTreeMap<BlockId, BTreeSet<ValueId>> = BTreeMap::new();
+        let mut phi_uses: BTreeMap<BlockId, BTreeSet<ValueId>> = BTreeMap::new();
+
+        for block in &self.blocks {
+            let mut use_set = BTreeSet::new();
+            let mut def_set = BTreeSet::new();
+
+            for inst in &block.instructions {
+                if let IRInst::Phi { incoming, .. } = inst {
+                    for &(pred, value) in incoming {
+                        phi_uses.entry(pred).or_default().insert(value);
                     }
-                    chim_ir::IRInst::Store { dest, .. } => {
-                        def_set.insert(*dest);
-                    }
-                    _ => {}
+                } else {
+                    use_set.extend(inst.used_values().into_iter().filter(|value| !def_set.contains(value)));
+                }
+                if let Some(dest) = inst.defined_value() {
+                    def_set.insert(ValueId(dest.0));
                 }
             }
+            use_set.extend(block.terminator.used_values().into_iter().filter(|value| !def_set.contains(value)));
 
-            use_sets.insert(block_id, use_set);
-            def_sets.insert(block_id, def_set);
+            use_sets.insert(block.id, use_set);
+            def_sets.insert(block.id, def_set);
         }
 
-        let mut in_sets: HashMap<BlockId, HashSet<TypeId>> = HashMap::new();
-        let mut out_sets: HashMap<BlockId, HashSet<TypeId>> = HashMap::new();
-
+        let mut in_sets: BTreeMap<BlockId, BTreeSet<ValueId>> = BTreeMap::new();
         for &block_id in self.blocks.iter().map(|b| b.id) {
-            in_sets.insert(block_id, HashSet::new());
-            out_sets.insert(block_id, HashSet::new());
+            in_sets.insert(block_id, BTreeSet::new());
         }
 
         let mut changed = true;
         while changed {
             changed = false;
             for &block_id in self.blocks.iter().map(|b| b.id).rev() {
-                let mut new_out = HashSet::new();
+                let mut live_out = phi_uses.get(&block_id).cloned().unwrap_or_default();
                 if let Some(succs) = self.successors.get(&block_id) {
                     for &succ in succs {
-                        new_out.extend(in_sets.get(&succ).cloned().unwrap_or_default());
+                        live_out.extend(in_sets.get(&succ).cloned().unwrap_or_default());
                     }
                 }
 
-                let use_set = use_sets.get(&block_id).cloned().unwrap_or_default();
-                let def_set = def_sets.get(&block_id).cloned().unwrap_or_default();
-
-                let mut new_in = use_set.clone();
-                for &var in &new_out {
-                    if !def_set.contains(&var) {
-                        new_in.insert(var);
-                    }
-                }
-
-                if new_in != *in_sets.get(&block_id).unwrap_or(&HashSet::new()) {
-                    in_sets.insert(block_id, new_in.clone());
-                    changed = true;
-                }
+                let def_set = &def_sets[&block_id];
+                let mut new_in = use_sets[&block_id].clone();
+                new_in.extend(live_out.into_iter().filter(|value| !def_set.contains(value)));
 
-                if new_out != *out_sets.get(&block_id).unwrap_or(&HashSet::new()) {
-                    out_sets.insert(block_id, new_out);
+                if new_in != in_sets[&block_id] {
+                    in_sets.insert(block_id, new_in);
                     changed = true;
                 }
             }
@@ -466,19 +476,19 @@ impl ControlFlowGraph {
     }
 
     fn compute_available_exprs(&mut self) {
-        let mut expr_sets: HashMap<BlockId, HashSet<String>> = HashMap::new();
+        let mut expr_sets: BTreeMap<BlockId, BTreeSet<String>> = BTreeMap::new();
 
         for &block_id in self.blocks.iter().map(|b| b.id) {
-            expr_sets.insert(block_id, HashSet::new());
+            expr_sets.insert(block_id, BTreeSet::new());
         }
 
-        expr_sets.insert(self.entry, HashSet::new());
+        expr_sets.insert(self.entry, BTreeSet::new());
 
         let mut changed = true;
         while changed {
             changed = false;
             for &block_id in self.blocks.iter().map(|b| b.id) {
-                let mut new_in = HashSet::new();
+                let mut new_in = BTreeSet::new();
                 if let Some(preds) = self.predecessors.get(&block_id) {
                     for &pred in preds {
                         new_in.extend(expr_sets.get(&pred).cloned().unwrap_or_default());
@@ -495,7 +505,7 @@ impl ControlFlowGraph {
                     }
                 }
 
-                if new_out != *expr_sets.get(&block_id).unwrap_or(&HashSet::new()) {
+                if new_out != *expr_sets.get(&block_id).unwrap_or(&BTreeSet::new()) {
                     expr_sets.insert(block_id, new_out);
                     changed = true;
                 }
@@ -509,17 +519,17 @@ impl ControlFlowGraph {
     }
 
     fn compute_very_busy_exprs(&mut self) {
-        let mut expr_sets: HashMap<BlockId, HashSet<String>> = HashMap::new();
+        let mut expr_sets: BTreeMap<BlockId, BTreeSet<String>> = BTreeMap::new();
 
         for &block_id in self.blocks.iter().map(|b| b.id) {
-            expr_sets.insert(block_id, HashSet::new());
+            expr_sets.insert(block_id, BTreeSet::new());
         }
 
         let mut changed = true;
         while changed {
             changed = false;
             for &block_id in self.blocks.iter().map(|b| b.id).rev() {
-                let mut new_out = HashSet::new();
+                let mut new_out = BTreeSet::new();
                 if let Some(succs) = self.successors.get(&block_id) {
                     for &succ in succs {
                         new_out.extend(expr_sets.get(&succ).cloned().unwrap_or_default());
@@ -538,7 +548,7 @@ impl ControlFlowGraph {
                     }
                 }
 
-                if new_in != *expr_sets.get(&block_id).unwrap_or(&HashSet::new()) {
+                if new_in != *expr_sets.get(&block_id).unwrap_or(&BTreeSet::new()) {
                     expr_sets.insert(block_id, new_in);
                     changed = true;
                 }
@@ -568,12 +578,40 @@ impl ControlFlowGraph {
     }
 }
 
+/// The immediate dominator of every block with one, given each block's full
+/// dominator set. Dominators of a block are totally ordered, so the nearest
+/// one is the strict dominator that itself has the most dominators.
+fn immediate_dominators(dom: &BTreeMap<BlockId, BTreeSet<BlockId>>, root: BlockId) -> BTreeMap<BlockId, BlockId> {
+    let mut idom = BTreeMap::new();
+    for (&block_id, dominators) in dom {
+        if block_id == root {
+            continue;
+        }
+        let nearest = dominators
+            .iter()
+            .filter(|&&candidate| candidate != block_id)
+            .max_by_key(|candidate| dom[candidate].len());
+        if let Some(&nearest) = nearest {
+            idom.insert(block_id, nearest);
+        }
+    }
+    idom
+}
+
+impl CFGBlock {
+    /// The phis at the top of the block.
+    pub fn phis(&self) -> &[IRInst] {
+        let count = self.instructions.iter().take_while(|inst| matches!(inst, IRInst::Phi { .. })).count();
+        &self.instructions[..count]
+    }
+}
+
 impl DominatorTree {
     fn new() -> Self {
         DominatorTree {
-            tree: HashMap::new(),
-            idom: HashMap::new(),
-            depth: HashMap::new(),
+            tree: BTreeMap::new(),
+            idom: BTreeMap::new(),
+            depth: BTreeMap::new(),
         }
     }
 
@@ -599,8 +637,8 @@ impl DominatorTree {
 impl PostDominatorTree {
     fn new() -> Self {
         PostDominatorTree {
-            tree: HashMap::new(),
-            idom: HashMap::new(),
+            tree: BTreeMap::new(),
+            idom: BTreeMap::new(),
         }
     }
 }
@@ -608,10 +646,10 @@ impl PostDominatorTree {
 impl DataFlowResults {
     fn new() -> Self {
         DataFlowResults {
-            reaching_defs: HashMap::new(),
-            live_vars: HashMap::new(),
-            available_exprs: HashMap::new(),
-            very_busy_exprs: HashMap::new(),
+            reaching_defs: BTreeMap::new(),
+            live_vars: BTreeMap::new(),
+            available_exprs: BTreeMap::new(),
+            very_busy_exprs: BTreeMap::new(),
         }
     }
 }
@@ -630,7 +668,7 @@ mod tests {
         };
 
         let func = chim_ir::IRFunction {
-            id: chim_ir::FunctionId(0),
+            id: chim_ir::IRFunctionId(0),
             name: "test".to_string(),
             params: Vec::new(),
             return_type: chim_semantic::TypeId(0),
@@ -645,7 +683,7 @@ mod tests {
                 chim_ir::BasicBlock {
                     id: chim_ir::BlockId(1),
                     instructions: Vec::new(),
-                    terminator: chim_ir::Terminator::Ret(Some(chim_ir::ValueId(0))),
+                    terminator: chim_ir::Terminator::Return(Some(chim_ir::ValueId(0))),
                     predecessors: vec![chim_ir::BlockId(0)],
                     successors: Vec::new(),
                 },
@@ -654,10 +692,13 @@ mod tests {
             is_pub: false,
             is_extern: false,
             is_unsafe: false,
+            inline_hint: chim_ir::InlineHint::None,
+            is_cold: false,
         };
 
         let cfg = ControlFlowGraph::build_from_function(&module, &func);
         assert_eq!(cfg.block_count(), 2);
+        assert_eq!(cfg.function_id, func.id);
     }
 
     #[test]
@@ -665,4 +706,169 @@ mod tests {
         let tree = DominatorTree::new();
         assert_eq!(tree.depth(chim_ir::BlockId(0)), 0);
     }
+
+    #[test]
+    fn test_liveness_through_phis() {
+        use chim_ir::{BasicBlock, BinaryOp, IRConstant, Terminator, ValueId};
+        use chim_semantic::{TypeId, VarId};
+
+        let span = chim_span::Span::new(chim_span::FileId(0), 0, 0, 0, 0);
+        let ty = TypeId(0);
+        let constant = |dest| IRInst::Const { dest: VarId(dest), value: IRConstant::Int(dest as i128), ty, span };
+        let block = |id, instructions, terminator, predecessors: Vec<usize>, successors: Vec<usize>| BasicBlock {
+            id: BlockId(id),
+            instructions,
+            terminator,
+            predecessors: predecessors.into_iter().map(BlockId).collect(),
+            successors: successors.into_iter().map(BlockId).collect(),
+        };
+        // b0: v0 = 0; br v0, b1, b2
+        // b1: v1 = 1; br b3
+        // b2: v2 = 2; br b3
+        // b3: v3 = phi [b1: v1, b2: v2]; v4 = v3 + v0; ret v4
+        let func = IRFunction {
+            id: IRFunctionId(0),
+            name: "select".to_string(),
+            params: Vec::new(),
+            return_type: ty,
+            body: vec![
+                block(0, vec![constant(0)], Terminator::ConditionalBranch {
+                    condition: ValueId(0),
+                    true_block: BlockId(1),
+                    false_block: BlockId(2),
+                }, vec![], vec![1, 2]),
+                block(1, vec![constant(1)], Terminator::Branch(BlockId(3)), vec![0], vec![3]),
+                block(2, vec![constant(2)], Terminator::Branch(BlockId(3)), vec![0], vec![3]),
+                block(3, vec![
+                    IRInst::Phi {
+                        dest: VarId(3),
+                        incoming: vec![(BlockId(1), ValueId(1)), (BlockId(2), ValueId(2))].into(),
+                        ty,
+                        span,
+                    },
+                    IRInst::Binary { dest: VarId(4), op: BinaryOp::Add, left: ValueId(3), right: ValueId(0), ty, span },
+                ], Terminator::Return(Some(ValueId(4))), vec![1, 2], vec![]),
+            ],
+            span,
+            is_pub: false,
+            is_extern: false,
+            is_unsafe: false,
+            inline_hint: chim_ir::InlineHint::None,
+            is_cold: false,
+        };
+        let module = IRModule { functions: Vec::new(), globals: Vec::new(), structs: Vec::new(), enums: Vec::new() };
+
+        let cfg = ControlFlowGraph::build_from_function(&module, &func);
+        assert_eq!(cfg.blocks[3].phis().len(), 1);
+        assert!(cfg.blocks[1].phis().is_empty());
+
+        let live = |block: usize| {
+            let mut values: Vec<usize> = cfg.dataflow.live_vars[&BlockId(block)].iter().map(|value| value.0).collect();
+            values.sort();
+            values
+        };
+        assert_eq!(live(0), Vec::<usize>::new());
+        assert_eq!(live(1), vec![0]);
+        assert_eq!(live(2), vec![0]);
+        assert_eq!(live(3), vec![0]);
+    }
+
+    /// A function whose block `i` branches to `successors[i]`, returning when
+    /// it has none.
+    fn branching_function(successors: &[&[usize]]) -> IRFunction {
+        use chim_ir::ValueId;
+
+        let body = successors
+            .iter()
+            .enumerate()
+            .map(|(id, succs)| {
+                let terminator = match *succs {
+                    [] => Terminator::Return(None),
+                    [target] => Terminator::Branch(BlockId(*target)),
+                    [then, otherwise] => Terminator::ConditionalBranch {
+                        condition: ValueId(0),
+                        true_block: BlockId(*then),
+                        false_block: BlockId(*otherwise),
+                    },
+                    _ => unreachable!("at most two successors"),
+                };
+                let predecessors = (0..successors.len())
+                    .filter(|pred| successors[*pred].contains(&id))
+                    .map(BlockId)
+                    .collect();
+                BasicBlock {
+                    id: BlockId(id),
+                    instructions: Vec::new(),
+                    terminator,
+                    predecessors,
+                    successors: succs.iter().copied().map(BlockId).collect(),
+                }
+            })
+            .collect();
+        IRFunction {
+            id: IRFunctionId(0),
+            name: "branching".to_string(),
+            params: Vec::new(),
+            return_type: chim_semantic::TypeId(0),
+            body,
+            span: chim_span::Span::new(chim_span::FileId(0), 0, 0, 0, 0),
+            is_pub: false,
+            is_extern: false,
+            is_unsafe: false,
+            inline_hint: chim_ir::InlineHint::None,
+            is_cold: false,
+        }
+    }
+
+    #[test]
+    fn test_dominators_are_deterministic() {
+        // 0 -> 1 -> {2, 3} -> 4 -> {1, 5}
+        let func = branching_function(&[&[1], &[2, 3], &[4], &[4], &[1, 5], &[]]);
+        let module = IRModule { functions: Vec::new(), globals: Vec::new(), structs: Vec::new(), enums: Vec::new() };
+
+        let first = ControlFlowGraph::build_from_function(&module, &func);
+        let second = ControlFlowGraph::build_from_function(&module, &func);
+        assert_eq!(format!("{:?}", first.dom_tree), format!("{:?}", second.dom_tree));
+        assert_eq!(format!("{:?}", first.post_dom_tree), format!("{:?}", second.post_dom_tree));
+        assert_eq!(format!("{:?}", first.loops), format!("{:?}", second.loops));
+
+        let idom: Vec<(usize, usize)> = first.dom_tree.idom.iter().map(|(block, parent)| (block.0, parent.0)).collect();
+        assert_eq!(idom, vec![(1, 0), (2, 1), (3, 1), (4, 1), (5, 4)]);
+        let depth: Vec<usize> = first.dom_tree.depth.values().copied().collect();
+        assert_eq!(depth, vec![0, 1, 2, 2, 2, 3]);
+        assert!(first.is_loop_header(BlockId(1)));
+    }
+
+    #[test]
+    fn test_split_critical_edges() {
+        use chim_ir::ValueId;
+        use chim_semantic::{TypeId, VarId};
+
+        // 0 -> {1, 2}, 1 -> 2: the edge 0 -> 2 is critical.
+        let mut func = branching_function(&[&[1, 2], &[2], &[]]);
+        func.body[2].instructions.push(IRInst::Phi {
+            dest: VarId(1),
+            incoming: vec![(BlockId(0), ValueId(0)), (BlockId(1), ValueId(0))].into(),
+            ty: TypeId(0),
+            span: func.span,
+        });
+        let module = IRModule { functions: Vec::new(), globals: Vec::new(), structs: Vec::new(), enums: Vec::new() };
+        let mut cfg = ControlFlowGraph::build_from_function(&module, &func);
+
+        assert_eq!(cfg.split_critical_edges(), 1);
+        assert_eq!(cfg.block_count(), 4);
+        let middle = BlockId(3);
+        assert_eq!(cfg.blocks[3].terminator, Terminator::Branch(BlockId(2)));
+        assert_eq!(cfg.blocks[0].terminator.successors().into_vec(), vec![BlockId(1), middle]);
+        assert_eq!(cfg.successors[&BlockId(0)], vec![BlockId(1), middle]);
+        assert_eq!(cfg.predecessors[&BlockId(2)], vec![BlockId(1), middle]);
+        assert_eq!(cfg.predecessors[&middle], vec![BlockId(0)]);
+        match &cfg.blocks[2].instructions[0] {
+            IRInst::Phi { incoming, .. } => assert_eq!(incoming[0].0, middle),
+            other => panic!("expected phi, got {:?}", other),
+        }
+        assert_eq!(cfg.dom_tree.immediate_dominator(middle), Some(BlockId(0)));
+
+        assert_eq!(cfg.split_critical_edges(), 0);
+    }
 }
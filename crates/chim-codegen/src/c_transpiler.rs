@@ -164,6 +164,7 @@ impl CTranspiler {
                     BinaryOp::FMul => "*",
                     BinaryOp::FDiv => "/",
                     BinaryOp::FRem => "%",
+                    BinaryOp::ULt => "<",
                 };
                 format!("    {} {} = {} {} {};\n", c_type, dest_name, left_name, op_str, right_name)
             }
@@ -1,4 +1,4 @@
-use chim_span::{FileId, Span};
+use chim_span::Span;
 use std::fmt;
 use std::sync::Arc;
 
@@ -18,6 +18,15 @@ pub enum ErrorKind {
     Codegen,
     Io,
     Internal,
+    UnexpectedToken,
+    UnexpectedEof,
+    UnterminatedString,
+    InvalidLiteral,
+    RecursionLimit,
+    InvalidAttribute,
+    CannotInfer,
+    /// A warning from a lint pass, e.g. an unused variable.
+    Lint,
 }
 
 #[derive(Debug, Clone)]
@@ -96,8 +105,12 @@ impl ChimError {
         }
     }
 
+    /// Points the error at `span`. A dummy span points nowhere, so it leaves
+    /// any span the error already has in place.
     pub fn with_span(mut self, span: Span) -> Self {
-        self.span = Some(span);
+        if !span.is_dummy() {
+            self.span = Some(span);
+        }
         self
     }
 
@@ -138,6 +151,11 @@ impl ChimError {
         &self.kind
     }
 
+    /// See [`ErrorKind::is_fatal`]; recoverable errors let the caller keep going.
+    pub fn is_fatal(&self) -> bool {
+        self.kind.is_fatal()
+    }
+
     pub fn message(&self) -> &str {
         &self.message
     }
@@ -149,6 +167,54 @@ impl ChimError {
     pub fn notes(&self) -> &[String] {
         &self.notes
     }
+
+    /// The error as one JSON object, for editors and language servers:
+    ///
+    /// ```text
+    /// {"message": "...", "kind": "type", "code": "E0003", "severity": "error",
+    ///  "span": {"file": 0, "start": 10, "end": 20, "line": 1, "column": 10},
+    ///  "labels": [{"message": "...", "style": "primary", "span": {...}}],
+    ///  "notes": ["..."]}
+    /// ```
+    ///
+    /// `start`/`end` are byte offsets; `line` and `column` are zero-based, as in
+    /// LSP positions. `span` is `null` for errors without a location.
+    pub fn to_json(&self) -> String {
+        let code = self.code.clone().unwrap_or_else(|| self.kind.to_string());
+        let labels: Vec<String> = self.labels.iter().map(|label| {
+            let style = match label.style {
+                LabelStyle::Primary => "primary",
+                LabelStyle::Secondary => "secondary",
+                LabelStyle::Note => "note",
+                LabelStyle::Help => "help",
+            };
+            format!(
+                "{{\"message\":{},\"style\":\"{}\",\"span\":{}}}",
+                json_string(&label.message),
+                style,
+                span_json(Some(&label.span))
+            )
+        }).collect();
+        let notes: Vec<String> = self.notes.iter().map(|note| json_string(note)).collect();
+        format!(
+            "{{\"message\":{},\"kind\":\"{}\",\"code\":{},\"severity\":\"error\",\"span\":{},\"labels\":[{}],\"notes\":[{}]}}",
+            json_string(&self.message),
+            self.kind.as_str(),
+            json_string(&code),
+            span_json(self.span.as_ref()),
+            labels.join(","),
+            notes.join(",")
+        )
+    }
+
+    /// The smallest span covering the primary span and every label in the same file.
+    pub fn merge_spans(&self) -> Option<Span> {
+        let mut spans = self.span.iter().chain(self.labels.iter().map(|l| &l.span));
+        let first = *spans.next()?;
+        Some(spans
+            .filter(|span| span.file_id == first.file_id)
+            .fold(first, |acc, span| acc.merge(span)))
+    }
 }
 
 impl fmt::Display for ChimError {
@@ -163,6 +229,46 @@ impl fmt::Display for ChimError {
 
 impl std::error::Error for ChimError {}
 
+fn span_json(span: Option<&Span>) -> String {
+    match span {
+        Some(span) => format!(
+            "{{\"file\":{},\"start\":{},\"end\":{},\"line\":{},\"column\":{}}}",
+            span.file_id.index(), span.start, span.end, span.line, span.column
+        ),
+        None => "null".to_string(),
+    }
+}
+
+/// `text` as a quoted JSON string.
+fn json_string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Sorts diagnostics into source order. Errors without a span keep their
+/// relative order and go last.
+pub fn sort_diagnostics(errors: &mut [ChimError]) {
+    errors.sort_by(|a, b| match (a.span, b.span) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    });
+}
+
 #[derive(Debug, Default)]
 pub struct ErrorReporter {
     source_map: Option<Arc<chim_span::SourceMap>>,
@@ -228,21 +334,11 @@ impl ErrorReporter {
         let mut output = String::new();
 
         let severity = match diag.kind {
-            ErrorKind::Lexer => "lexer error",
-            ErrorKind::Parser => "syntax error",
-            ErrorKind::TypeMismatch => "type error",
-            ErrorKind::UndefinedIdentifier => "undefined identifier",
-            ErrorKind::Redefinition => "redefinition error",
-            ErrorKind::LifetimeError => "lifetime error",
-            ErrorKind::BorrowError => "borrow check error",
-            ErrorKind::EcsError => "ECS error",
-            ErrorKind::ActorError => "Actor error",
-            ErrorKind::Codegen => "code generation error",
-            ErrorKind::Io => "I/O error",
-            ErrorKind::Internal => "internal compiler error",
+            ErrorKind::Lint => "warning",
+            _ => "error",
         };
 
-        output.push_str(&format!("error[{}]: {}\n", diag.kind.as_str(), diag.message));
+        output.push_str(&format!("{}[{}]: {}\n", severity, diag.kind.as_str(), diag.message));
 
         if let Some(span) = &diag.span {
             output.push_str(&format!("  --> {}\n", span));
@@ -287,6 +383,14 @@ impl fmt::Display for ErrorKind {
             ErrorKind::ActorError => write!(f, "E0009"),
             ErrorKind::Codegen => write!(f, "E0010"),
             ErrorKind::Io => write!(f, "E0011"),
+            ErrorKind::UnexpectedToken => write!(f, "E0012"),
+            ErrorKind::UnexpectedEof => write!(f, "E0013"),
+            ErrorKind::UnterminatedString => write!(f, "E0014"),
+            ErrorKind::InvalidLiteral => write!(f, "E0015"),
+            ErrorKind::RecursionLimit => write!(f, "E0016"),
+            ErrorKind::InvalidAttribute => write!(f, "E0017"),
+            ErrorKind::CannotInfer => write!(f, "E0018"),
+            ErrorKind::Lint => write!(f, "W0001"),
             ErrorKind::Internal => write!(f, "E0999"),
         }
     }
@@ -307,8 +411,30 @@ impl ErrorKind {
             ErrorKind::Codegen => "codegen",
             ErrorKind::Io => "io",
             ErrorKind::Internal => "internal",
+            ErrorKind::UnexpectedToken => "unexpected_token",
+            ErrorKind::UnexpectedEof => "unexpected_eof",
+            ErrorKind::UnterminatedString => "unterminated_string",
+            ErrorKind::InvalidLiteral => "invalid_literal",
+            ErrorKind::RecursionLimit => "recursion_limit",
+            ErrorKind::InvalidAttribute => "invalid_attribute",
+            ErrorKind::CannotInfer => "cannot_infer",
+            ErrorKind::Lint => "lint",
         }
     }
+
+    /// Whether an error of this kind leaves nothing sensible to continue
+    /// with: the input ran out, nesting blew the recursion limit, or the
+    /// compiler itself failed. Everything else is local to one construct.
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            ErrorKind::UnexpectedEof
+                | ErrorKind::UnterminatedString
+                | ErrorKind::RecursionLimit
+                | ErrorKind::Io
+                | ErrorKind::Internal
+        )
+    }
 }
 
 impl fmt::Display for LabelStyle {
@@ -348,14 +474,14 @@ mod tests {
     #[test]
     fn test_error_with_span() {
         let error = ChimError::new(ErrorKind::TypeMismatch, "mismatched types".to_string())
-            .with_span(Span::new(FileId(0), 10, 20, 1, 10));
+            .with_span(Span::new(FileId::new(0), 10, 20, 1, 10));
         assert!(error.span.is_some());
     }
 
     #[test]
     fn test_error_with_label() {
         let error = ChimError::new(ErrorKind::TypeMismatch, "mismatched types".to_string())
-            .with_label(Span::new(FileId(0), 10, 20, 1, 10), "expected i32, found str".to_string());
+            .with_label(Span::new(FileId::new(0), 10, 20, 1, 10), "expected i32, found str".to_string());
         assert_eq!(error.labels.len(), 1);
     }
 
@@ -366,5 +492,82 @@ mod tests {
         reporter.report_error(error);
         assert!(reporter.has_errors());
         assert_eq!(reporter.error_count(), 1);
+        assert!(reporter.format().starts_with("error[type]: mismatched types\n"));
+    }
+
+    #[test]
+    fn test_error_reporter_formats_lints_as_warnings() {
+        let mut reporter = ErrorReporter::new();
+        reporter.report_warning(
+            ChimError::new(ErrorKind::Lint, "unused variable: `x`".to_string())
+                .with_note("if this is intentional, prefix it with an underscore: `_x`".to_string()),
+        );
+        assert!(!reporter.has_errors());
+        assert_eq!(
+            reporter.format(),
+            "warning[lint]: unused variable: `x`\n  note: if this is intentional, prefix it with an underscore: `_x`\n\n",
+        );
+    }
+
+    #[test]
+    fn test_structured_parse_error_codes() {
+        assert_eq!(ErrorKind::UnexpectedToken.to_string(), "E0012");
+        assert_eq!(ErrorKind::UnexpectedEof.to_string(), "E0013");
+        assert_eq!(ErrorKind::UnterminatedString.as_str(), "unterminated_string");
+        assert_eq!(ErrorKind::RecursionLimit.as_str(), "recursion_limit");
+    }
+
+    #[test]
+    fn test_fatal_error_kinds() {
+        assert!(ChimError::new(ErrorKind::UnexpectedEof, "eof".to_string()).is_fatal());
+        assert!(ErrorKind::RecursionLimit.is_fatal());
+        assert!(!ChimError::new(ErrorKind::UnexpectedToken, "token".to_string()).is_fatal());
+        assert!(!ErrorKind::TypeMismatch.is_fatal());
+    }
+
+    #[test]
+    fn test_sort_diagnostics() {
+        let at = |file, start| Span::new(FileId::new(file), start, start + 1, 0, start);
+        let mut errors = vec![
+            ChimError::new(ErrorKind::Parser, "no span".to_string()),
+            ChimError::new(ErrorKind::Parser, "second file".to_string()).with_span(at(1, 0)),
+            ChimError::new(ErrorKind::Parser, "late".to_string()).with_span(at(0, 40)),
+            ChimError::new(ErrorKind::Parser, "early".to_string()).with_span(at(0, 5)),
+        ];
+        sort_diagnostics(&mut errors);
+        let messages: Vec<&str> = errors.iter().map(|e| e.message()).collect();
+        assert_eq!(messages, ["early", "late", "second file", "no span"]);
+    }
+
+    #[test]
+    fn test_dummy_span_is_not_reported() {
+        let real = Span::new(FileId::new(0), 0, 3, 0, 0);
+        let error = ChimError::new(ErrorKind::Parser, "oops".to_string()).with_span(Span::dummy());
+        assert_eq!(error.span, None);
+        let error = error.with_span(real).with_span(Span::dummy());
+        assert_eq!(error.span, Some(real));
+    }
+
+    #[test]
+    fn test_error_to_json() {
+        let error = ChimError::new(ErrorKind::TypeMismatch, "expected `i32`, found \"str\"".to_string())
+            .with_span(Span::new(FileId::new(0), 10, 20, 1, 4))
+            .with_label(Span::new(FileId::new(0), 12, 15, 1, 6), "this".to_string())
+            .with_note("types must match\nexactly".to_string());
+        assert_eq!(
+            error.to_json(),
+            concat!(
+                r#"{"message":"expected `i32`, found \"str\"","kind":"type","code":"E0003","severity":"error","#,
+                r#""span":{"file":0,"start":10,"end":20,"line":1,"column":4},"#,
+                r#""labels":[{"message":"this","style":"primary","span":{"file":0,"start":12,"end":15,"line":1,"column":6}}],"#,
+                r#""notes":["types must match\nexactly"]}"#,
+            )
+        );
+
+        let error = ChimError::new(ErrorKind::Io, "cannot read".to_string()).with_code("E9000".to_string());
+        assert_eq!(
+            error.to_json(),
+            r#"{"message":"cannot read","kind":"io","code":"E9000","severity":"error","span":null,"labels":[],"notes":[]}"#
+        );
     }
 }
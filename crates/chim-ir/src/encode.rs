@@ -0,0 +1,669 @@
+use crate::*;
+use chim_span::FileId;
+
+/// Leading bytes of every encoded module.
+const MAGIC: &[u8; 4] = b"CHIR";
+
+/// Bumped whenever the encoding of any IR type changes.
+pub const FORMAT_VERSION: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum DecodeError {
+    #[error("input is not an encoded IR module")]
+    BadMagic,
+    #[error("unsupported IR format version {0}")]
+    UnsupportedVersion(u8),
+    #[error("unexpected end of input")]
+    UnexpectedEnd,
+    #[error("invalid {what} tag {tag}")]
+    InvalidTag { what: &'static str, tag: u8 },
+    #[error("integer out of range")]
+    IntegerOverflow,
+    #[error("invalid UTF-8 in string")]
+    InvalidUtf8,
+    #[error("invalid char {0:#x}")]
+    InvalidChar(u32),
+    #[error("{0} trailing bytes after module")]
+    TrailingBytes(usize),
+}
+
+impl IRModule {
+    /// Encodes the module in a compact, length-prefixed binary format.
+    /// Integers are LEB128 varints, so typical ids take a single byte.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = Writer { buf: MAGIC.to_vec() };
+        writer.u8(FORMAT_VERSION);
+        self.encode_to(&mut writer);
+        writer.buf
+    }
+
+    /// Decodes a module produced by [`IRModule::encode`]. The whole input
+    /// must be consumed.
+    pub fn decode(bytes: &[u8]) -> Result<IRModule, DecodeError> {
+        let mut reader = Reader { bytes, pos: 0 };
+        if reader.take(MAGIC.len()).map_err(|_| DecodeError::BadMagic)? != MAGIC {
+            return Err(DecodeError::BadMagic);
+        }
+        let version = reader.u8()?;
+        if version != FORMAT_VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let module = IRModule::decode_from(&mut reader)?;
+        match reader.remaining() {
+            0 => Ok(module),
+            extra => Err(DecodeError::TrailingBytes(extra)),
+        }
+    }
+}
+
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    fn uleb(&mut self, mut value: u64) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                self.buf.push(byte);
+                return;
+            }
+            self.buf.push(byte | 0x80);
+        }
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.uleb(bytes.len() as u64);
+        self.buf.extend_from_slice(bytes);
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.remaining() < len {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let bytes = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn uleb(&mut self) -> Result<u64, DecodeError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            let bits = u64::from(byte & 0x7f);
+            if shift == 63 && bits > 1 {
+                return Err(DecodeError::IntegerOverflow);
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(DecodeError::IntegerOverflow)
+    }
+
+    /// A length prefix, checked against the input left so corrupt data can't
+    /// request a huge allocation.
+    fn len(&mut self) -> Result<usize, DecodeError> {
+        let len = usize::decode_from(self)?;
+        if len > self.remaining() {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        Ok(len)
+    }
+}
+
+trait Encode {
+    fn encode_to(&self, writer: &mut Writer);
+}
+
+trait Decode: Sized {
+    fn decode_from(reader: &mut Reader) -> Result<Self, DecodeError>;
+}
+
+impl Encode for bool {
+    fn encode_to(&self, writer: &mut Writer) {
+        writer.u8(*self as u8);
+    }
+}
+
+impl Decode for bool {
+    fn decode_from(reader: &mut Reader) -> Result<Self, DecodeError> {
+        match reader.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            tag => Err(DecodeError::InvalidTag { what: "bool", tag }),
+        }
+    }
+}
+
+impl Encode for usize {
+    fn encode_to(&self, writer: &mut Writer) {
+        writer.uleb(*self as u64);
+    }
+}
+
+impl Decode for usize {
+    fn decode_from(reader: &mut Reader) -> Result<Self, DecodeError> {
+        usize::try_from(reader.uleb()?).map_err(|_| DecodeError::IntegerOverflow)
+    }
+}
+
+impl Encode for u32 {
+    fn encode_to(&self, writer: &mut Writer) {
+        writer.uleb(u64::from(*self));
+    }
+}
+
+impl Decode for u32 {
+    fn decode_from(reader: &mut Reader) -> Result<Self, DecodeError> {
+        u32::try_from(reader.uleb()?).map_err(|_| DecodeError::IntegerOverflow)
+    }
+}
+
+/// Zigzag-encoded so small negative values stay short.
+impl Encode for i128 {
+    fn encode_to(&self, writer: &mut Writer) {
+        let mut value = ((*self << 1) ^ (*self >> 127)) as u128;
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                writer.u8(byte);
+                return;
+            }
+            writer.u8(byte | 0x80);
+        }
+    }
+}
+
+impl Decode for i128 {
+    fn decode_from(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let mut value = 0u128;
+        for shift in (0..128).step_by(7) {
+            let byte = reader.u8()?;
+            let bits = u128::from(byte & 0x7f);
+            if shift == 126 && bits > 3 {
+                return Err(DecodeError::IntegerOverflow);
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok((value >> 1) as i128 ^ -((value & 1) as i128));
+            }
+        }
+        Err(DecodeError::IntegerOverflow)
+    }
+}
+
+impl Encode for f64 {
+    fn encode_to(&self, writer: &mut Writer) {
+        writer.buf.extend_from_slice(&self.to_bits().to_le_bytes());
+    }
+}
+
+impl Decode for f64 {
+    fn decode_from(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let bytes = reader.take(8)?;
+        Ok(f64::from_bits(u64::from_le_bytes(bytes.try_into().expect("took 8 bytes"))))
+    }
+}
+
+impl Encode for char {
+    fn encode_to(&self, writer: &mut Writer) {
+        (*self as u32).encode_to(writer);
+    }
+}
+
+impl Decode for char {
+    fn decode_from(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let code = u32::decode_from(reader)?;
+        char::from_u32(code).ok_or(DecodeError::InvalidChar(code))
+    }
+}
+
+impl Encode for String {
+    fn encode_to(&self, writer: &mut Writer) {
+        writer.bytes(self.as_bytes());
+    }
+}
+
+impl Decode for String {
+    fn decode_from(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let len = reader.len()?;
+        let bytes = reader.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidUtf8)
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode_to(&self, writer: &mut Writer) {
+        match self {
+            None => writer.u8(0),
+            Some(value) => {
+                writer.u8(1);
+                value.encode_to(writer);
+            }
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode_from(reader: &mut Reader) -> Result<Self, DecodeError> {
+        match reader.u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(T::decode_from(reader)?)),
+            tag => Err(DecodeError::InvalidTag { what: "option", tag }),
+        }
+    }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    fn encode_to(&self, writer: &mut Writer) {
+        self.0.encode_to(writer);
+        self.1.encode_to(writer);
+    }
+}
+
+impl<A: Decode, B: Decode> Decode for (A, B) {
+    fn decode_from(reader: &mut Reader) -> Result<Self, DecodeError> {
+        Ok((A::decode_from(reader)?, B::decode_from(reader)?))
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode_to(&self, writer: &mut Writer) {
+        self.len().encode_to(writer);
+        for item in self {
+            item.encode_to(writer);
+        }
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode_from(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let len = reader.len()?;
+        (0..len).map(|_| T::decode_from(reader)).collect()
+    }
+}
+
+impl<A: smallvec::Array> Encode for SmallVec<A>
+where
+    A::Item: Encode,
+{
+    fn encode_to(&self, writer: &mut Writer) {
+        self.len().encode_to(writer);
+        for item in self {
+            item.encode_to(writer);
+        }
+    }
+}
+
+impl<A: smallvec::Array> Decode for SmallVec<A>
+where
+    A::Item: Decode,
+{
+    fn decode_from(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let len = reader.len()?;
+        (0..len).map(|_| A::Item::decode_from(reader)).collect()
+    }
+}
+
+macro_rules! impl_id_codec {
+    ($($ty:ident),* $(,)?) => {
+        $(
+            impl Encode for $ty {
+                fn encode_to(&self, writer: &mut Writer) {
+                    self.0.encode_to(writer);
+                }
+            }
+
+            impl Decode for $ty {
+                fn decode_from(reader: &mut Reader) -> Result<Self, DecodeError> {
+                    Ok($ty(usize::decode_from(reader)?))
+                }
+            }
+        )*
+    };
+}
+
+impl_id_codec!(ValueId, BlockId, IRFunctionId, VarId, TypeId, StructId, EnumId);
+
+impl Encode for FileId {
+    fn encode_to(&self, writer: &mut Writer) {
+        self.index().encode_to(writer);
+    }
+}
+
+impl Decode for FileId {
+    fn decode_from(reader: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(FileId::new(usize::decode_from(reader)?))
+    }
+}
+
+macro_rules! impl_struct_codec {
+    ($($ty:ident { $($field:ident),* $(,)? })*) => {
+        $(
+            impl Encode for $ty {
+                fn encode_to(&self, writer: &mut Writer) {
+                    $(self.$field.encode_to(writer);)*
+                }
+            }
+
+            impl Decode for $ty {
+                fn decode_from(reader: &mut Reader) -> Result<Self, DecodeError> {
+                    Ok($ty { $($field: Decode::decode_from(reader)?),* })
+                }
+            }
+        )*
+    };
+}
+
+impl_struct_codec! {
+    Span { file_id, start, end, line, column }
+    IRModule { functions, globals, structs, enums }
+    IRFunction { id, name, params, return_type, body, span, is_pub, is_extern, is_unsafe, inline_hint, is_cold }
+    IRParam { id, name, ty }
+    BasicBlock { id, instructions, terminator, predecessors, successors }
+    Global { name, ty, initializer, is_pub, is_const, align }
+    IRStruct { id, name, fields, size, align, is_packed }
+    IRStructField { name, ty, offset, size }
+    IREnum { id, name, variants, size, align, tag_repr }
+    IREnumVariant { name, discriminant, fields, size, align }
+}
+
+/// Tags are spelled out rather than derived from declaration order so that
+/// adding a variant doesn't silently change the meaning of existing bytes.
+macro_rules! impl_enum_codec {
+    ($ty:ident, $what:literal, { $($tag:literal => $variant:ident { $($field:ident),* $(,)? }),* $(,)? }) => {
+        impl Encode for $ty {
+            fn encode_to(&self, writer: &mut Writer) {
+                match self {
+                    $($ty::$variant { $($field),* } => {
+                        writer.u8($tag);
+                        $($field.encode_to(writer);)*
+                    })*
+                }
+            }
+        }
+
+        impl Decode for $ty {
+            fn decode_from(reader: &mut Reader) -> Result<Self, DecodeError> {
+                match reader.u8()? {
+                    $($tag => Ok($ty::$variant { $($field: Decode::decode_from(reader)?),* }),)*
+                    tag => Err(DecodeError::InvalidTag { what: $what, tag }),
+                }
+            }
+        }
+    };
+}
+
+impl_enum_codec!(InlineHint, "inline hint", { 0 => None {}, 1 => Inline {}, 2 => Never {} });
+
+impl_enum_codec!(CaptureKind, "capture kind", { 0 => CaptureRef {}, 1 => CaptureValue {} });
+
+impl_enum_codec!(MemoryOrder, "memory order", {
+    0 => Relaxed {},
+    1 => Consume {},
+    2 => Acquire {},
+    3 => Release {},
+    4 => AcqRel {},
+    5 => SeqCst {},
+    6 => HappensBefore {},
+    7 => Volatile {},
+    8 => MemoryBarrier {},
+});
+
+impl_enum_codec!(BinaryOp, "binary op", {
+    0 => Add {},
+    1 => Sub {},
+    2 => Mul {},
+    3 => Div {},
+    4 => Rem {},
+    5 => Shl {},
+    6 => Shr {},
+    7 => And {},
+    8 => Or {},
+    9 => Xor {},
+    10 => FAdd {},
+    11 => FSub {},
+    12 => FMul {},
+    13 => FDiv {},
+    14 => FRem {},
+    15 => ULt {},
+});
+
+impl_enum_codec!(UnaryOp, "unary op", { 0 => Neg {}, 1 => Not {}, 2 => FNeg {} });
+
+impl_enum_codec!(CastOp, "cast op", {
+    0 => Trunc {},
+    1 => ZExt {},
+    2 => SExt {},
+    3 => IntToPtr {},
+    4 => PtrToInt {},
+    5 => BitCast {},
+    6 => FToUi {},
+    7 => FToSi {},
+    8 => UiToF {},
+    9 => SiToF {},
+});
+
+impl_enum_codec!(TagRepresentation, "tag representation", {
+    0 => U8 {},
+    1 => U16 {},
+    2 => U32 {},
+    3 => U64 {},
+    4 => Usize {},
+});
+
+impl_enum_codec!(IRInst, "instruction", {
+    0 => Alloca { dest, ty, span },
+    1 => Const { dest, value, ty, span },
+    2 => GlobalAddr { dest, global, ty, span },
+    3 => Load { dest, src, ty, span },
+    4 => Store { dest, src, ty, span },
+    5 => GetElementPtr { dest, ptr, indices, ty, span },
+    6 => Binary { dest, op, left, right, ty, span },
+    7 => Unary { dest, op, operand, ty, span },
+    8 => Call { dest, func, args, ty, span },
+    9 => Invoke { dest, func, args, normal_block, unwind_block, ty, span },
+    10 => Br { target, span },
+    11 => CondBr { condition, true_block, false_block, span },
+    12 => Ret { value, span },
+    13 => RetVoid { span },
+    14 => Switch { value, default_block, cases, span },
+    15 => Select { dest, condition, true_val, false_val, ty, span },
+    16 => ExtractValue { dest, aggregate, indices, ty, span },
+    17 => InsertValue { dest, aggregate, value, indices, ty, span },
+    18 => Cast { dest, value, to_ty, op, span },
+    19 => Phi { dest, incoming, ty, span },
+    20 => Skip { span },
+    21 => Debug { location, span },
+    22 => AtomicLoad { dest, src, order, ty, span },
+    23 => AtomicStore { dest, src, order, ty, span },
+    24 => AtomicFetchAdd { dest, src, value, order, ty, span },
+    25 => AtomicFetchSub { dest, src, value, order, ty, span },
+    26 => AtomicFetchAnd { dest, src, value, order, ty, span },
+    27 => AtomicFetchOr { dest, src, value, order, ty, span },
+    28 => AtomicFetchXor { dest, src, value, order, ty, span },
+    29 => AtomicCompareExchange { dest, src, expected, desired, success_order, failure_order, ty, span },
+    30 => AtomicExchange { dest, src, value, order, ty, span },
+    31 => AtomicFence { order, span },
+    32 => Wait { atomic, timeout, span },
+    33 => Notify { atomic, span },
+    34 => NotifyAll { atomic, span },
+    35 => MemoryBarrier { span },
+    36 => DataDependency { src, dest, span },
+    37 => MacroExpand { dest, macro_name, args, span },
+    38 => ClosureCreate { dest, params, body, captures, span },
+    39 => IteratorNext { dest, iterator, span },
+    40 => IteratorCollect { dest, iterator, span },
+    41 => IteratorChain { dest, iterator1, iterator2, span },
+    42 => IteratorFilter { dest, iterator, predicate, span },
+    43 => IteratorFold { dest, iterator, init, accumulator, body, span },
+    44 => IteratorMap { dest, iterator, mapper, span },
+    45 => ResultOk { dest, value, ok_type, err_type, span },
+    46 => ResultErr { dest, error, ok_type, err_type, span },
+    47 => TryCatch { dest, try_expr, catch_block, error_var, span },
+    48 => Throw { error, span },
+    49 => FutureAwait { dest, future, span },
+    50 => Yield { value, span },
+    51 => StreamYield { value, span },
+    52 => FunctionAddr { dest, function, span },
+});
+
+impl Encode for Terminator {
+    fn encode_to(&self, writer: &mut Writer) {
+        match self {
+            Terminator::Return(value) => {
+                writer.u8(0);
+                value.encode_to(writer);
+            }
+            Terminator::Branch(target) => {
+                writer.u8(1);
+                target.encode_to(writer);
+            }
+            Terminator::ConditionalBranch { condition, true_block, false_block } => {
+                writer.u8(2);
+                condition.encode_to(writer);
+                true_block.encode_to(writer);
+                false_block.encode_to(writer);
+            }
+            Terminator::Unreachable => writer.u8(3),
+            Terminator::Invoke { func, args, normal_block, unwind_block } => {
+                writer.u8(4);
+                func.encode_to(writer);
+                args.encode_to(writer);
+                normal_block.encode_to(writer);
+                unwind_block.encode_to(writer);
+            }
+            Terminator::Switch { value, default_block, cases } => {
+                writer.u8(5);
+                value.encode_to(writer);
+                default_block.encode_to(writer);
+                cases.encode_to(writer);
+            }
+        }
+    }
+}
+
+impl Decode for Terminator {
+    fn decode_from(reader: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(match reader.u8()? {
+            0 => Terminator::Return(Decode::decode_from(reader)?),
+            1 => Terminator::Branch(Decode::decode_from(reader)?),
+            2 => Terminator::ConditionalBranch {
+                condition: Decode::decode_from(reader)?,
+                true_block: Decode::decode_from(reader)?,
+                false_block: Decode::decode_from(reader)?,
+            },
+            3 => Terminator::Unreachable,
+            4 => Terminator::Invoke {
+                func: Decode::decode_from(reader)?,
+                args: Decode::decode_from(reader)?,
+                normal_block: Decode::decode_from(reader)?,
+                unwind_block: Decode::decode_from(reader)?,
+            },
+            5 => Terminator::Switch {
+                value: Decode::decode_from(reader)?,
+                default_block: Decode::decode_from(reader)?,
+                cases: Decode::decode_from(reader)?,
+            },
+            tag => return Err(DecodeError::InvalidTag { what: "terminator", tag }),
+        })
+    }
+}
+
+impl Encode for IRConstant {
+    fn encode_to(&self, writer: &mut Writer) {
+        match self {
+            IRConstant::Int(value) => {
+                writer.u8(0);
+                value.encode_to(writer);
+            }
+            IRConstant::Float(value) => {
+                writer.u8(1);
+                value.encode_to(writer);
+            }
+            IRConstant::Bool(value) => {
+                writer.u8(2);
+                value.encode_to(writer);
+            }
+            IRConstant::Char(value) => {
+                writer.u8(3);
+                value.encode_to(writer);
+            }
+            IRConstant::String(value) => {
+                writer.u8(4);
+                value.encode_to(writer);
+            }
+            IRConstant::Null => writer.u8(5),
+            IRConstant::Aggregate(aggregate) => {
+                writer.u8(6);
+                aggregate.encode_to(writer);
+            }
+            IRConstant::GlobalReference(ty) => {
+                writer.u8(7);
+                ty.encode_to(writer);
+            }
+        }
+    }
+}
+
+impl Decode for IRConstant {
+    fn decode_from(reader: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(match reader.u8()? {
+            0 => IRConstant::Int(Decode::decode_from(reader)?),
+            1 => IRConstant::Float(Decode::decode_from(reader)?),
+            2 => IRConstant::Bool(Decode::decode_from(reader)?),
+            3 => IRConstant::Char(Decode::decode_from(reader)?),
+            4 => IRConstant::String(Decode::decode_from(reader)?),
+            5 => IRConstant::Null,
+            6 => IRConstant::Aggregate(Decode::decode_from(reader)?),
+            7 => IRConstant::GlobalReference(Decode::decode_from(reader)?),
+            tag => return Err(DecodeError::InvalidTag { what: "constant", tag }),
+        })
+    }
+}
+
+impl Encode for AggregateConstant {
+    fn encode_to(&self, writer: &mut Writer) {
+        let (tag, elements) = match self {
+            AggregateConstant::Vector(elements) => (0, elements),
+            AggregateConstant::Array(elements) => (1, elements),
+            AggregateConstant::Struct(elements) => (2, elements),
+        };
+        writer.u8(tag);
+        elements.encode_to(writer);
+    }
+}
+
+impl Decode for AggregateConstant {
+    fn decode_from(reader: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(match reader.u8()? {
+            0 => AggregateConstant::Vector(Decode::decode_from(reader)?),
+            1 => AggregateConstant::Array(Decode::decode_from(reader)?),
+            2 => AggregateConstant::Struct(Decode::decode_from(reader)?),
+            tag => return Err(DecodeError::InvalidTag { what: "aggregate constant", tag }),
+        })
+    }
+}
@@ -1,42 +1,101 @@
 use crate::*;
 use chim_ast::*;
-use chim_semantic::{AnalyzedProgram, TypeId};
+use chim_error::{ChimError, ErrorKind};
+use chim_semantic::{
+    enum_discriminants, enum_repr, AnalyzedProgram, EnumRepr, MethodIndex, TypeData, TypeId, VariantIndex, VariantInfo,
+};
 use chim_span::Span;
-use std::collections::HashMap;
+use smallvec::SmallVec;
+use std::collections::{HashMap, HashSet};
 
 pub struct IRGenerator<'a> {
     module: IRModule,
     program: &'a AnalyzedProgram,
-    builder: IRBuilder<'a>,
+    /// Index into `module.functions` of the function being lowered.
+    current_function: usize,
+    /// The block instructions are appended to; `None` right after a terminator,
+    /// until the next block is started.
+    current_block: Option<BlockId>,
     value_map: HashMap<Ident, ValueId>,
     block_map: HashMap<Ident, BlockId>,
-    next_value_id: usize,
-    next_block_id: usize,
+    value_structs: HashMap<ValueId, usize>,
+    ids: IdAllocator,
+    variants: VariantIndex,
+    /// The `defer` blocks registered in each enclosing scope, innermost scope
+    /// last and each scope's blocks in source order.
+    defer_scopes: Vec<Vec<Vec<Stmt>>>,
+    /// How many `unsafe` blocks enclose the code being lowered, plus one in
+    /// an `@unchecked` function; indexing is bounds-checked only at zero.
+    unchecked_depth: usize,
+    /// The loops enclosing the code being lowered, innermost last.
+    loop_targets: Vec<LoopTarget>,
+    /// How integer `+`, `-` and `*` handle overflow.
+    overflow_mode: OverflowMode,
+    /// Constructs found while lowering that cannot be lowered.
+    errors: Vec<ChimError>,
+    /// The program's impls, for resolving `Trait::method(x)` calls.
+    methods: MethodIndex,
+    /// The functions the program declares, which calls resolve to before
+    /// any intrinsic of the same name.
+    function_names: HashSet<Ident>,
+}
+
+/// Where `continue` and `break` jump for one enclosing loop.
+struct LoopTarget {
+    label: Option<Ident>,
+    continue_block: BlockId,
+    break_block: BlockId,
+    /// The number of defer scopes outside the loop; jumping out of the loop
+    /// runs the ones opened since.
+    defer_depth: usize,
+    /// Each `break` out of the loop: the block it jumps from and the value
+    /// it carries, if any.
+    breaks: SmallVec<[(BlockId, Option<ValueId>); 4]>,
 }
 
 impl<'a> IRGenerator<'a> {
     pub fn new(program: &'a AnalyzedProgram) -> Self {
-        let mut module = IRModule {
+        let module = IRModule {
             functions: Vec::new(),
             globals: Vec::new(),
             structs: Vec::new(),
             enums: Vec::new(),
         };
-        
-        let builder = IRBuilder::new(&mut module);
-        
+
         IRGenerator {
             module,
             program,
-            builder,
+            current_function: 0,
+            current_block: None,
             value_map: HashMap::new(),
             block_map: HashMap::new(),
-            next_value_id: 0,
-            next_block_id: 0,
+            value_structs: HashMap::new(),
+            ids: IdAllocator::new(),
+            variants: VariantIndex::default(),
+            defer_scopes: Vec::new(),
+            unchecked_depth: 0,
+            loop_targets: Vec::new(),
+            overflow_mode: OverflowMode::default(),
+            errors: Vec::new(),
+            methods: MethodIndex::default(),
+            function_names: HashSet::new(),
         }
     }
 
+    pub fn with_overflow_mode(mut self, mode: OverflowMode) -> Self {
+        self.overflow_mode = mode;
+        self
+    }
+
+    /// The errors found by `generate_module`, leaving none behind.
+    pub fn take_errors(&mut self) -> Vec<ChimError> {
+        std::mem::take(&mut self.errors)
+    }
+
     pub fn generate_module(&mut self, ast_program: &Program) -> IRModule {
+        self.variants = VariantIndex::new(ast_program);
+        self.methods = MethodIndex::new(ast_program);
+        self.function_names = Self::declared_functions(&ast_program.items);
         for item in &ast_program.items {
             self.generate_item(item);
         }
@@ -44,6 +103,15 @@ impl<'a> IRGenerator<'a> {
         self.module.clone()
     }
 
+    /// The names of the functions in `items` and their modules.
+    fn declared_functions(items: &[Item]) -> HashSet<Ident> {
+        items.iter().flat_map(|item| match item {
+            Item::Function(func) => HashSet::from([func.name.clone()]),
+            Item::Mod(module) => Self::declared_functions(&module.items),
+            _ => HashSet::new(),
+        }).collect()
+    }
+
     fn generate_item(&mut self, item: &Item) {
         match item {
             Item::Function(func) => self.generate_function(func),
@@ -60,8 +128,21 @@ impl<'a> IRGenerator<'a> {
     }
 
     fn generate_function(&mut self, func: &Function) {
-        let ir_func_id = IRFunctionId(self.module.functions.len());
-        
+        self.lower_function(func.name.to_string(), func);
+    }
+
+    /// The function `name` with `func`'s signature, declared now if it
+    /// hasn't been seen yet; lowering it later fills in the body.
+    fn declare_function(&mut self, name: String, func: &Function) -> IRFunctionId {
+        match self.module.functions.iter().find(|f| !f.is_extern && f.name == name) {
+            Some(existing) => existing.id,
+            None => self.push_declaration(name, func),
+        }
+    }
+
+    /// Adds the function `name` with `func`'s signature and no body.
+    fn push_declaration(&mut self, name: String, func: &Function) -> IRFunctionId {
+        let id = IRFunctionId(self.module.functions.len());
         let params: Vec<IRParam> = func.params.iter().enumerate().map(|(i, param)| {
             IRParam {
                 id: VarId(i),
@@ -74,9 +155,9 @@ impl<'a> IRGenerator<'a> {
             .map(|ty| self.get_type_id(ty))
             .unwrap_or(self.program.pool.builtin_types.unit);
 
-        let ir_func = IRFunction {
-            id: ir_func_id,
-            name: func.name.to_string(),
+        self.module.functions.push(IRFunction {
+            id,
+            name,
             params,
             return_type,
             body: Vec::new(),
@@ -84,27 +165,78 @@ impl<'a> IRGenerator<'a> {
             is_pub: func.is_pub,
             is_extern: false,
             is_unsafe: false,
+            inline_hint: Self::inline_hint(func),
+            is_cold: func.has_attribute("cold"),
+        });
+        id
+    }
+
+    /// Lowers the body of `func` into the function `name`.
+    fn lower_function(&mut self, name: String, func: &Function) {
+        // Reuse a declaration made by an earlier call, but not a function of
+        // the same name that already has a body.
+        let declared = self.module.functions.iter()
+            .find(|f| !f.is_extern && f.name == name && f.body.is_empty())
+            .map(|f| f.id);
+        let ir_func_id = match declared {
+            Some(id) => id,
+            None => self.push_declaration(name, func),
         };
+        self.current_function = ir_func_id.0;
+        self.value_map.clear();
+        self.ids = IdAllocator::with_params(func.params.len());
 
-        self.module.functions.push(ir_func);
-        self.builder = IRBuilder::new(&mut self.module);
-        
         let entry_block = self.create_block();
-        self.builder.set_current_block(&mut self.module.functions[ir_func_id.0].body[0]);
-        
+        self.switch_to_block(entry_block);
+
         for (i, param) in func.params.iter().enumerate() {
             let value_id = ValueId(i);
             self.value_map.insert(param.name.clone(), value_id);
+            if let Some(struct_index) = self.struct_index_of_type(&param.ty) {
+                self.value_structs.insert(value_id, struct_index);
+            }
         }
 
-        for stmt in &func.body {
+        self.unchecked_depth = usize::from(func.has_attribute("unchecked"));
+        self.defer_scopes.clear();
+        self.loop_targets.clear();
+        self.defer_scopes.push(Vec::new());
+
+        // In a function returning a value, a trailing expression is that value.
+        let returns_value = func.return_type.as_ref().is_some_and(|ty| match &*ty.kind {
+            TypeKind::Tuple(elements) => !elements.is_empty(),
+            TypeKind::Never => false,
+            _ => true,
+        });
+        let (tail, rest) = match func.body.split_last() {
+            Some((Stmt { kind: StmtKind::Expr(tail), .. }, rest)) if returns_value => (Some(tail), rest),
+            _ => (None, func.body.as_slice()),
+        };
+        for stmt in rest {
             self.generate_stmt(stmt);
         }
+        // The value is computed before the deferred blocks run, as in a block.
+        let value = tail.map(|expr| self.generate_expr(expr));
+        self.exit_defer_scope();
 
-        if self.module.functions[ir_func_id.0].body.last()
-            .and_then(|block| block.terminator.is_return())
-            .unwrap_or(false) == false {
-            self.builder.emit_ret_void(func.span);
+        if self.current_block.is_some() {
+            self.terminate(Terminator::Return(value));
+        }
+    }
+
+    /// `@inline` asks for inlining, `@inline(never)` forbids it.
+    fn inline_hint(func: &Function) -> InlineHint {
+        let attr = match func.attributes.iter().find(|attr| &*attr.name == "inline") {
+            Some(attr) => attr,
+            None => return InlineHint::None,
+        };
+        let never = attr.args.iter().any(|arg| {
+            matches!(&*arg.expr.kind, ExprKind::Identifier(name) if &**name == "never")
+        });
+        if never {
+            InlineHint::Never
+        } else {
+            InlineHint::Inline
         }
     }
 
@@ -154,7 +286,12 @@ impl<'a> IRGenerator<'a> {
         let mut max_size = 0usize;
         let mut max_align = 1usize;
         
-        let variants: Vec<IREnumVariant> = enum_def.variants.iter().enumerate().map(|(i, variant)| {
+        // Invalid discriminants were already reported by semantic analysis.
+        let discriminants = enum_discriminants(enum_def)
+            .unwrap_or_else(|_| (0..enum_def.variants.len() as i128).collect());
+        let repr = enum_repr(enum_def).ok().flatten();
+
+        let variants: Vec<IREnumVariant> = enum_def.variants.iter().zip(&discriminants).map(|(variant, &discriminant)| {
             let mut offset = 0usize;
             let fields: Vec<IRStructField> = variant.fields.iter().map(|field| {
                 let ty_id = self.get_type_id(&field.ty);
@@ -179,14 +316,19 @@ impl<'a> IRGenerator<'a> {
 
             IREnumVariant {
                 name: variant.name.to_string(),
-                discriminant: i as i128,
+                discriminant,
                 fields,
                 size: offset,
                 align: max_align,
             }
         }).collect();
 
-        let tag_size = 4;
+        let (tag_repr, tag_size) = match repr.map(EnumRepr::size) {
+            Some(1) => (TagRepresentation::U8, 1),
+            Some(2) => (TagRepresentation::U16, 2),
+            Some(8) => (TagRepresentation::U64, 8),
+            _ => (TagRepresentation::U32, 4),
+        };
         let size = max_size.max(tag_size);
         let align = max_align.max(tag_size);
 
@@ -196,7 +338,7 @@ impl<'a> IRGenerator<'a> {
             variants,
             size,
             align,
-            tag_repr: TagRepresentation::U32,
+            tag_repr,
         };
 
         self.module.enums.push(ir_enum);
@@ -205,7 +347,24 @@ impl<'a> IRGenerator<'a> {
     fn generate_trait(&mut self, _trait_def: &Trait) {
     }
 
-    fn generate_impl(&mut self, _impl_def: &Impl) {
+    /// Lowers the methods of an impl as functions named after the type, and
+    /// the trait for a trait impl: `Point::norm`, `<Point as Clone>::clone`.
+    fn generate_impl(&mut self, impl_def: &Impl) {
+        let Some(type_name) = MethodIndex::type_key(&impl_def.type_name) else { return };
+        let trait_name = impl_def.trait_name.as_ref().and_then(MethodIndex::type_key);
+        for item in &impl_def.items {
+            if let ImplItem::Function(method) = item {
+                let symbol = Self::method_symbol(&type_name, trait_name.as_deref(), &method.name);
+                self.lower_function(symbol, method);
+            }
+        }
+    }
+
+    fn method_symbol(type_name: &str, trait_name: Option<&str>, method: &str) -> String {
+        match trait_name {
+            Some(trait_name) => format!("<{} as {}>::{}", type_name, trait_name, method),
+            None => format!("{}::{}", type_name, method),
+        }
     }
 
     fn generate_mod(&mut self, mod_def: &Mod) {
@@ -231,22 +390,46 @@ impl<'a> IRGenerator<'a> {
                 self.generate_expr(expr);
             }
             StmtKind::Return(return_stmt) => self.generate_return_stmt(return_stmt, stmt.span),
-            StmtKind::Break(break_stmt) => self.generate_break_stmt(break_stmt, stmt.span),
-            StmtKind::Continue => self.generate_continue_stmt(stmt.span),
-            StmtKind::Loop(loop_stmt) => self.generate_loop_stmt(loop_stmt),
-            StmtKind::While(while_stmt) => self.generate_while_stmt(while_stmt),
-            StmtKind::For(for_stmt) => self.generate_for_stmt(for_stmt),
+            StmtKind::Break(value) => self.generate_break(None, value.as_ref()),
+            StmtKind::Continue(label) => self.generate_continue(label.as_ref()),
+            StmtKind::Loop(loop_stmt) => {
+                self.generate_loop(loop_stmt.label.as_ref(), &loop_stmt.body, None, loop_stmt.span);
+            }
+            StmtKind::While(while_stmt) => {
+                self.generate_while(while_stmt.label.as_ref(), &while_stmt.condition, &while_stmt.body);
+            }
+            StmtKind::For(for_stmt) => {
+                self.generate_for(for_stmt.label.as_ref(), &for_stmt.pattern, &for_stmt.iterable, &for_stmt.body, for_stmt.span);
+            }
+            StmtKind::Guard(guard) => {
+                self.generate_expr(&guard.to_if_expr());
+            }
+            StmtKind::Defer(body) => {
+                if let Some(scope) = self.defer_scopes.last_mut() {
+                    scope.push(body.clone());
+                }
+            }
             StmtKind::Match(match_stmt) => self.generate_match_stmt(match_stmt),
         }
     }
 
     fn generate_let_stmt(&mut self, let_stmt: &LetStmt) {
-        let value_id = if let Some(init) = &let_stmt.initializer {
-            self.generate_expr(init)
-        } else {
-            self.create_value()
+        let value_id = match &let_stmt.initializer {
+            // `let v: List = collect it` collects into the declared type.
+            Some(init) => match &*init.kind {
+                ExprKind::Collect(collect) => {
+                    let target = init.ty.as_ref().or(let_stmt.ty.as_ref());
+                    self.generate_collect_expr(collect, target, init.span)
+                }
+                _ => self.generate_expr(init),
+            },
+            None => self.create_value(),
         };
 
+        if let Some(struct_index) = let_stmt.ty.as_ref().and_then(|ty| self.struct_index_of_type(ty)) {
+            self.value_structs.insert(value_id, struct_index);
+        }
+
         if let Some(name) = self.get_identifier_from_pattern(&let_stmt.pattern) {
             self.value_map.insert(name, value_id);
         }
@@ -270,59 +453,289 @@ impl<'a> IRGenerator<'a> {
         } else {
             None
         };
-        self.builder.emit_ret(value, span);
+        // Returning leaves every enclosing scope.
+        self.generate_deferred_to(0);
+        self.terminate(Terminator::Return(value));
     }
 
-    fn generate_break_stmt(&mut self, break_stmt: &Option<Box<Expr>>, span: Span) {
-        let value = if let Some(expr) = break_stmt {
-            Some(self.generate_expr(expr))
-        } else {
-            None
+    /// Lowers the blocks deferred in every scope deeper than `depth`,
+    /// innermost first, for a jump out of those scopes. The scopes are set
+    /// aside meanwhile so that a jump inside a deferred block does not run
+    /// the same blocks again.
+    fn generate_deferred_to(&mut self, depth: usize) {
+        let scopes = self.defer_scopes.split_off(depth.min(self.defer_scopes.len()));
+        for deferred in scopes.iter().rev() {
+            self.generate_deferred(deferred);
+        }
+        self.defer_scopes.extend(scopes);
+    }
+
+    /// Leaves the innermost scope, lowering the blocks deferred in it unless
+    /// control already left it through a terminator.
+    fn exit_defer_scope(&mut self) {
+        let deferred = self.defer_scopes.pop().unwrap_or_default();
+        if self.current_block.is_some() {
+            self.generate_deferred(&deferred);
+        }
+    }
+
+    /// Lowers deferred blocks last first, each as a scope of its own.
+    fn generate_deferred(&mut self, deferred: &[Vec<Stmt>]) {
+        for body in deferred.iter().rev() {
+            if self.current_block.is_none() {
+                break;
+            }
+            self.defer_scopes.push(Vec::new());
+            for stmt in body {
+                self.generate_stmt(stmt);
+            }
+            self.exit_defer_scope();
+        }
+    }
+
+    /// The loop a `break` or `continue` with `label` leaves: the innermost
+    /// one without a label, otherwise the innermost one declaring it.
+    fn loop_target(&self, label: Option<&Label>) -> Option<usize> {
+        match label {
+            None => self.loop_targets.len().checked_sub(1),
+            Some(label) => self.loop_targets.iter().rposition(|target| target.label.as_ref() == Some(&label.name)),
+        }
+    }
+
+    fn generate_break(&mut self, label: Option<&Label>, value: Option<&Expr>) {
+        let value = value.map(|expr| self.generate_expr(expr));
+        // A `break` outside any loop was already reported by the parser.
+        let Some(index) = self.loop_target(label) else {
+            return;
         };
-        self.builder.emit_br(BlockId(0), span);
+        self.generate_deferred_to(self.loop_targets[index].defer_depth);
+        if let Some(block) = self.current_block {
+            self.loop_targets[index].breaks.push((block, value));
+        }
+        let target = self.loop_targets[index].break_block;
+        self.terminate(Terminator::Branch(target));
     }
 
-    fn generate_continue_stmt(&mut self, span: Span) {
-        self.builder.emit_br(BlockId(0), span);
+    fn generate_continue(&mut self, label: Option<&Label>) {
+        let Some(index) = self.loop_target(label) else {
+            return;
+        };
+        self.generate_deferred_to(self.loop_targets[index].defer_depth);
+        let target = self.loop_targets[index].continue_block;
+        self.terminate(Terminator::Branch(target));
     }
 
-    fn generate_loop_stmt(&mut self, loop_stmt: &LoopStmt) {
-        let loop_block = self.create_block();
-        let body_block = self.create_block();
-        
-        self.builder.emit_br(loop_block, loop_stmt.span);
-        
-        for stmt in &loop_stmt.body {
+    /// Lowers `body` into the current block as the body of a loop whose
+    /// `continue` jumps to `continue_block` and `break` to `break_block`,
+    /// falling through to `continue_block`. Returns the loop's `break`s.
+    fn generate_loop_body(
+        &mut self,
+        label: Option<&Ident>,
+        body: &[Stmt],
+        continue_block: BlockId,
+        break_block: BlockId,
+    ) -> SmallVec<[(BlockId, Option<ValueId>); 4]> {
+        self.loop_targets.push(LoopTarget {
+            label: label.cloned(),
+            continue_block,
+            break_block,
+            defer_depth: self.defer_scopes.len(),
+            breaks: SmallVec::new(),
+        });
+        self.defer_scopes.push(Vec::new());
+        for stmt in body {
             self.generate_stmt(stmt);
         }
-        
-        self.builder.emit_br(loop_block, loop_stmt.span);
+        self.exit_defer_scope();
+        if self.current_block.is_some() {
+            self.terminate(Terminator::Branch(continue_block));
+        }
+        self.loop_targets.pop().map(|target| target.breaks).unwrap_or_default()
     }
 
-    fn generate_while_stmt(&mut self, while_stmt: &WhileStmt) {
-        let cond_block = self.create_block();
+    /// Lowers `loop` to a body block that branches back to itself. Its value
+    /// is what the `break`s carry, joined by a phi of type `ty` in the exit
+    /// block when every `break` carries one.
+    ///
+    /// ```text
+    ///   br body
+    /// body:
+    ///   ...
+    ///   br body
+    /// exit:
+    /// ```
+    fn generate_loop(&mut self, label: Option<&Ident>, body: &[Stmt], ty: Option<&Type>, span: Span) -> ValueId {
         let body_block = self.create_block();
-        
-        self.builder.emit_br(cond_block, while_stmt.span);
-        
-        let cond_value = self.generate_expr(&while_stmt.condition);
-        
-        for stmt in &while_stmt.body {
-            self.generate_stmt(stmt);
+        let exit = self.create_block();
+        self.terminate(Terminator::Branch(body_block));
+
+        self.switch_to_block(body_block);
+        let breaks = self.generate_loop_body(label, body, body_block, exit);
+
+        self.switch_to_block(exit);
+        let dest = self.create_value();
+        let incoming: Option<SmallVec<[(BlockId, ValueId); 4]>> = breaks.iter()
+            .map(|&(block, value)| value.map(|value| (block, value)))
+            .collect();
+        if let Some(incoming) = incoming.filter(|incoming| !incoming.is_empty()) {
+            let ty = ty.map_or(self.program.pool.builtin_types.unit, |ty| self.get_type_id(ty));
+            self.emit(IRInst::Phi { dest, incoming, ty, span });
         }
-        
-        self.builder.emit_cond_br(cond_value, body_block, BlockId(0), while_stmt.span);
+        dest
     }
 
-    fn generate_for_stmt(&mut self, for_stmt: &ForStmt) {
-        let iterable_value = self.generate_expr(&for_stmt.iterable);
+    /// Lowers `while` to a header that tests the condition before each
+    /// iteration:
+    ///
+    /// ```text
+    ///   br header
+    /// header:
+    ///   cond_br condition, body, exit
+    /// body:
+    ///   ...
+    ///   br header
+    /// exit:
+    /// ```
+    fn generate_while(&mut self, label: Option<&Ident>, condition: &Expr, body: &[Stmt]) -> ValueId {
+        let header = self.create_block();
         let body_block = self.create_block();
-        
-        for stmt in &for_stmt.body {
-            self.generate_stmt(stmt);
+        let exit = self.create_block();
+        self.terminate(Terminator::Branch(header));
+
+        self.switch_to_block(header);
+        let condition = self.generate_expr(condition);
+        self.terminate(Terminator::ConditionalBranch { condition, true_block: body_block, false_block: exit });
+
+        self.switch_to_block(body_block);
+        self.generate_loop_body(label, body, header, exit);
+
+        self.switch_to_block(exit);
+        self.create_value()
+    }
+
+    /// Lowers `for pattern in iterable` to a `while` over the iterator's
+    /// `next`, binding each item to `pattern` (see `bind_pattern`):
+    ///
+    /// ```text
+    ///   br header
+    /// header:
+    ///   next = iterator.next it        ; (has_value, value)
+    ///   cond_br next.0, body, exit
+    /// body:
+    ///   item = next.1
+    ///   ...
+    ///   br header
+    /// exit:
+    /// ```
+    fn generate_for(
+        &mut self,
+        label: Option<&Ident>,
+        pattern: &Pattern,
+        iterable: &Expr,
+        body: &[Stmt],
+        span: Span,
+    ) -> ValueId {
+        let iterator = self.generate_expr(iterable);
+        let bool_ty = self.program.pool.builtin_types.bool;
+        let item_ast_ty = Self::iterator_item_type(iterable);
+        let item_ty = item_ast_ty.as_ref().map_or(self.program.pool.builtin_types.unit, |ty| self.get_type_id(ty));
+
+        let header = self.create_block();
+        let body_block = self.create_block();
+        let exit = self.create_block();
+        self.terminate(Terminator::Branch(header));
+
+        self.switch_to_block(header);
+        let next = self.create_value();
+        self.emit(IRInst::IteratorNext { dest: next, iterator, span });
+        let has_value = self.create_value();
+        self.emit(IRInst::ExtractValue {
+            dest: has_value,
+            aggregate: next,
+            indices: smallvec::smallvec![0],
+            ty: bool_ty,
+            span,
+        });
+        self.terminate(Terminator::ConditionalBranch { condition: has_value, true_block: body_block, false_block: exit });
+
+        self.switch_to_block(body_block);
+        let item = self.create_value();
+        self.emit(IRInst::ExtractValue {
+            dest: item,
+            aggregate: next,
+            indices: smallvec::smallvec![1],
+            ty: item_ty,
+            span,
+        });
+        self.bind_pattern(pattern, item, item_ast_ty.as_ref(), span);
+        self.generate_loop_body(label, body, header, exit);
+
+        self.switch_to_block(exit);
+        self.create_value()
+    }
+
+    /// The type of the items `iterable` yields (see `element_type`). A range
+    /// without a type yields what its bounds are.
+    fn iterator_item_type(iterable: &Expr) -> Option<Type> {
+        match (&iterable.ty, &*iterable.kind) {
+            (Some(ty), _) => Self::element_type(ty),
+            (None, ExprKind::Range(range)) => {
+                range.start.iter().chain(&range.end).find_map(|bound| bound.ty.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// The type of the elements of a collection of type `ty`: the element
+    /// type of an array, slice or reference to one, or the argument of a
+    /// single-argument type such as `Range<int>` or `Vec<T>`.
+    fn element_type(ty: &Type) -> Option<Type> {
+        match &*ty.kind {
+            TypeKind::Array(element, _) | TypeKind::Slice(element) => Some((**element).clone()),
+            TypeKind::Reference(_, referent, _) => Self::element_type(referent),
+            TypeKind::Path(path) => match path.segments.last().map(|segment| segment.args.as_slice()) {
+                Some([GenericArg { kind: GenericArgKind::Type(element), .. }]) => Some(element.clone()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Binds the names in `pattern` to the parts of `value`, whose type is
+    /// `ty`: an identifier takes the whole value and a tuple pattern takes
+    /// each element. Other patterns are reported, since lowering them needs
+    /// a match.
+    fn bind_pattern(&mut self, pattern: &Pattern, value: ValueId, ty: Option<&Type>, span: Span) {
+        match &pattern.kind {
+            PatternKind::Identifier(name, _) => {
+                self.value_map.insert(name.clone(), value);
+            }
+            PatternKind::Wildcard => {}
+            PatternKind::Tuple(patterns) => {
+                let element_types = match ty.map(|ty| &*ty.kind) {
+                    Some(TypeKind::Tuple(elements)) if elements.len() == patterns.len() => Some(elements),
+                    _ => None,
+                };
+                for (index, pattern) in patterns.iter().enumerate() {
+                    let element_ty = element_types.map(|elements| &elements[index]);
+                    let ty = element_ty.map_or(self.program.pool.builtin_types.unit, |ty| self.get_type_id(ty));
+                    let element = self.create_value();
+                    self.emit(IRInst::ExtractValue {
+                        dest: element,
+                        aggregate: value,
+                        indices: smallvec::smallvec![index as u32],
+                        ty,
+                        span,
+                    });
+                    self.bind_pattern(pattern, element, element_ty, span);
+                }
+            }
+            _ => self.errors.push(
+                ChimError::new(ErrorKind::Codegen, "this pattern cannot be used in a `for` loop yet".to_string())
+                    .with_span(pattern.span)
+                    .with_note("bind the item to a name and `match` on it in the loop body".to_string()),
+            ),
         }
-        
-        self.builder.emit_br(body_block, for_stmt.span);
     }
 
     fn generate_match_stmt(&mut self, match_stmt: &MatchStmt) {
@@ -337,7 +750,7 @@ impl<'a> IRGenerator<'a> {
             
             let body_value = self.generate_expr(&arm.body);
             
-            self.builder.emit_br(BlockId(0), match_stmt.span);
+            self.emit(IRInst::Br { target: BlockId(0), span: match_stmt.span });
         }
     }
 
@@ -356,17 +769,71 @@ impl<'a> IRGenerator<'a> {
             ExprKind::Closure(closure_expr) => self.generate_closure_expr(closure_expr, expr.span),
             ExprKind::FieldAccess(field_access) => self.generate_field_access(field_access, expr.span),
             ExprKind::Index(index_expr) => self.generate_index_expr(index_expr, expr.span),
+            ExprKind::Unsafe(unsafe_expr) => {
+                self.unchecked_depth += 1;
+                let value = self.generate_expr(&unsafe_expr.body);
+                self.unchecked_depth -= 1;
+                value
+            }
             ExprKind::Cast(cast_expr) => self.generate_cast_expr(cast_expr, expr.span),
             ExprKind::Assign(assign_expr) => self.generate_assign_expr(assign_expr, expr.span),
             ExprKind::AssignOp(assign_op_expr) => self.generate_assign_op_expr(assign_op_expr, expr.span),
             ExprKind::Range(range_expr) => self.generate_range_expr(range_expr, expr.span),
             ExprKind::Array(array_expr) => self.generate_array_expr(array_expr),
             ExprKind::Ternary(ternary_expr) => self.generate_ternary_expr(ternary_expr, expr.span),
+            ExprKind::Map(map_expr) => self.generate_map_expr(map_expr, expr.span),
+            ExprKind::Path(path) => match self.variants.resolve(path).cloned() {
+                Some(variant) => self.generate_variant_value(&variant, &[], expr.span),
+                None => self.create_value(),
+            },
+            ExprKind::Collect(collect_expr) => self.generate_collect_expr(collect_expr, expr.ty.as_ref(), expr.span),
+            // These have type `!`: control leaves here, so nothing reads the
+            // value they return.
+            ExprKind::Return(value) => {
+                self.generate_return_stmt(value, expr.span);
+                self.create_value()
+            }
+            ExprKind::Break(label, value) => {
+                self.generate_break(label.as_ref(), value.as_deref());
+                self.create_value()
+            }
+            ExprKind::Continue(label) => {
+                self.generate_continue(label.as_ref());
+                self.create_value()
+            }
+            ExprKind::Loop(loop_expr) => {
+                self.generate_loop(loop_expr.label.as_ref(), &loop_expr.body.stmts, expr.ty.as_ref(), expr.span)
+            }
+            ExprKind::While(while_expr) => {
+                self.generate_while(while_expr.label.as_ref(), &while_expr.condition, &while_expr.body.stmts)
+            }
+            ExprKind::For(for_expr) => self.generate_for(
+                for_expr.label.as_ref(),
+                &for_expr.pattern,
+                &for_expr.iterable,
+                &for_expr.body.stmts,
+                expr.span,
+            ),
         }
     }
 
     fn generate_literal(&mut self, lit: &Literal, span: Span) -> ValueId {
         let dest = self.create_value();
+        match &lit.kind {
+            LiteralKind::String(value) => {
+                let ty = self.program.pool.builtin_types.str;
+                let global = self.module.intern_string(value, ty);
+                self.emit(IRInst::GlobalAddr { dest, global, ty, span });
+                return dest;
+            }
+            LiteralKind::ByteString(value) => {
+                let ty = self.program.pool.builtin_types.bytes;
+                let global = self.module.intern_string(value, ty);
+                self.emit(IRInst::GlobalAddr { dest, global, ty, span });
+                return dest;
+            }
+            _ => {}
+        }
         let ty = match lit.kind {
             LiteralKind::Int(_) => self.program.pool.builtin_types.i32,
             LiteralKind::Float(_) => self.program.pool.builtin_types.f64,
@@ -382,18 +849,20 @@ impl<'a> IRGenerator<'a> {
     }
 
     fn generate_binary_expr(&mut self, bin_expr: &BinaryExpr, span: Span) -> ValueId {
+        if matches!(bin_expr.op, BinOp::And | BinOp::Or) {
+            return self.generate_logical_expr(bin_expr, span);
+        }
+
         let left = self.generate_expr(&bin_expr.left);
         let right = self.generate_expr(&bin_expr.right);
-        let dest = self.create_value();
-        
+
         let op = match bin_expr.op {
             BinOp::Add => BinaryOp::Add,
             BinOp::Sub => BinaryOp::Sub,
             BinOp::Mul => BinaryOp::Mul,
             BinOp::Div => BinaryOp::Div,
             BinOp::Mod => BinaryOp::Rem,
-            BinOp::And => BinaryOp::And,
-            BinOp::Or => BinaryOp::Or,
+            BinOp::And | BinOp::Or => unreachable!("logical operators are lowered by generate_logical_expr"),
             BinOp::BitAnd => BinaryOp::And,
             BinOp::BitOr => BinaryOp::Or,
             BinOp::BitXor => BinaryOp::Xor,
@@ -407,8 +876,173 @@ impl<'a> IRGenerator<'a> {
             BinOp::Ge => BinaryOp::Shl,
         };
 
-        let ty = self.program.pool.builtin_types.i32;
-        self.builder.emit_binary(dest, op, left, right, ty, span);
+        let ty = self.operand_type(&bin_expr.left, &bin_expr.right);
+        self.generate_arithmetic(op, left, right, ty, self.overflow_mode, span)
+    }
+
+    /// The type of a binary expression's operands, as inferred for either
+    /// side; `int` when neither is known.
+    fn operand_type(&self, left: &Expr, right: &Expr) -> TypeId {
+        left.ty.as_ref()
+            .or(right.ty.as_ref())
+            .map_or(self.program.pool.builtin_types.i32, |ty| self.get_type_id(ty))
+    }
+
+    /// Emits `left op right` on operands of type `ty`. Float operands
+    /// use the float instructions and never overflow; for integers `Add`,
+    /// `Sub` and `Mul` follow `mode`, shown here for `i64`:
+    ///
+    /// ```text
+    ///   ; Wrapping
+    ///   dest = add left, right
+    ///   ; Saturating
+    ///   dest = call i64::saturating_add(left, right)
+    ///   ; Checked
+    ///   dest = add left, right
+    ///   overflowed = call i64::add_overflows(left, right)
+    ///   cond_br overflowed, fail, ok
+    /// fail:
+    ///   call panic_overflow(left, right)
+    ///   unreachable
+    /// ok:
+    /// ```
+    fn generate_arithmetic(
+        &mut self,
+        op: BinaryOp,
+        left: ValueId,
+        right: ValueId,
+        ty: TypeId,
+        mode: OverflowMode,
+        span: Span,
+    ) -> ValueId {
+        let dest = self.create_value();
+        let data = self.program.pool.get_type(ty);
+        let is_integer = matches!(data, TypeData::Int(_) | TypeData::Uint(_));
+        let integer = data.to_string();
+        if matches!(data, TypeData::Float(_)) {
+            let op = match op {
+                BinaryOp::Add => BinaryOp::FAdd,
+                BinaryOp::Sub => BinaryOp::FSub,
+                BinaryOp::Mul => BinaryOp::FMul,
+                BinaryOp::Div => BinaryOp::FDiv,
+                BinaryOp::Rem => BinaryOp::FRem,
+                op => op,
+            };
+            self.emit(IRInst::Binary { dest, op, left, right, ty, span });
+            return dest;
+        }
+        let name = match op {
+            BinaryOp::Add if is_integer => "add",
+            BinaryOp::Sub if is_integer => "sub",
+            BinaryOp::Mul if is_integer => "mul",
+            _ => {
+                self.emit(IRInst::Binary { dest, op, left, right, ty, span });
+                return dest;
+            }
+        };
+        match mode {
+            OverflowMode::Wrapping => self.emit(IRInst::Binary { dest, op, left, right, ty, span }),
+            OverflowMode::Saturating => {
+                let func = self.runtime_function(&format!("{}::saturating_{}", integer, name), &[ty, ty], ty, span);
+                self.emit(IRInst::Call { dest: Some(dest), func, args: smallvec::smallvec![left, right], ty, span });
+            }
+            OverflowMode::Checked => {
+                self.emit(IRInst::Binary { dest, op, left, right, ty, span });
+                self.generate_overflow_check(&integer, name, left, right, ty, span);
+            }
+        }
+        dest
+    }
+
+    /// Continues in a block reached only when `left name right` didn't
+    /// overflow; see `generate_arithmetic`.
+    fn generate_overflow_check(&mut self, integer: &str, name: &str, left: ValueId, right: ValueId, ty: TypeId, span: Span) {
+        let bool_ty = self.program.pool.builtin_types.bool;
+        let never = self.program.pool.builtin_types.never;
+
+        let check_fn = self.runtime_function(&format!("{}::{}_overflows", integer, name), &[ty, ty], bool_ty, span);
+        let overflowed = self.create_value();
+        self.emit(IRInst::Call {
+            dest: Some(overflowed),
+            func: check_fn,
+            args: smallvec::smallvec![left, right],
+            ty: bool_ty,
+            span,
+        });
+
+        let ok = self.create_block();
+        let fail = self.create_block();
+        self.terminate(Terminator::ConditionalBranch { condition: overflowed, true_block: fail, false_block: ok });
+
+        self.switch_to_block(fail);
+        let panic_fn = self.runtime_function("panic_overflow", &[ty, ty], never, span);
+        self.emit(IRInst::Call { dest: None, func: panic_fn, args: smallvec::smallvec![left, right], ty: never, span });
+        self.terminate(Terminator::Unreachable);
+
+        self.switch_to_block(ok);
+    }
+
+    /// `wrapping_add(a, b)`, `saturating_mul(a, b)` and the like: arithmetic
+    /// with an explicit overflow mode, whatever the build's. A local or a
+    /// function of the program with that name is called instead.
+    fn overflow_intrinsic(&self, func: &Expr) -> Option<(BinaryOp, OverflowMode)> {
+        let ExprKind::Identifier(name) = &*func.kind else { return None };
+        if self.value_map.contains_key(name) || self.function_names.contains(name) {
+            return None;
+        }
+        let (mode, op) = name.split_once('_')?;
+        let mode = match mode {
+            "wrapping" => OverflowMode::Wrapping,
+            "saturating" => OverflowMode::Saturating,
+            _ => return None,
+        };
+        let op = match op {
+            "add" => BinaryOp::Add,
+            "sub" => BinaryOp::Sub,
+            "mul" => BinaryOp::Mul,
+            _ => return None,
+        };
+        Some((op, mode))
+    }
+
+    /// Lowers `&&`/`||` so the right operand is only evaluated in its own block,
+    /// reached when the left operand doesn't already decide the result.
+    fn generate_logical_expr(&mut self, bin_expr: &BinaryExpr, span: Span) -> ValueId {
+        let is_and = matches!(bin_expr.op, BinOp::And);
+        let ty = self.program.pool.builtin_types.bool;
+
+        let left = self.generate_expr(&bin_expr.left);
+        let short_circuit = self.create_value();
+        self.emit(IRInst::Const {
+            dest: short_circuit,
+            value: IRConstant::Bool(!is_and),
+            ty,
+            span,
+        });
+        let left_end = self.current_block.expect("instructions were just emitted");
+
+        let rhs_block = self.create_block();
+        let merge_block = self.create_block();
+        let (true_block, false_block) = if is_and {
+            (rhs_block, merge_block)
+        } else {
+            (merge_block, rhs_block)
+        };
+        self.terminate(Terminator::ConditionalBranch { condition: left, true_block, false_block });
+
+        let mut values: SmallVec<[(BlockId, ValueId); 4]> = SmallVec::new();
+        values.push((left_end, short_circuit));
+
+        self.switch_to_block(rhs_block);
+        let right = self.generate_expr(&bin_expr.right);
+        if let Some(rhs_end) = self.current_block {
+            values.push((rhs_end, right));
+            self.terminate(Terminator::Branch(merge_block));
+        }
+
+        self.switch_to_block(merge_block);
+        let dest = self.create_value();
+        self.emit(IRInst::Phi { dest, incoming: values, ty, span });
         dest
     }
 
@@ -424,11 +1058,30 @@ impl<'a> IRGenerator<'a> {
         };
 
         let ty = self.program.pool.builtin_types.i32;
-        self.builder.emit_unary(dest, op, operand, ty, span);
+        self.emit(IRInst::Unary { dest, op, operand, ty, span });
         dest
     }
 
     fn generate_call_expr(&mut self, call_expr: &CallExpr, span: Span) -> ValueId {
+        if let ExprKind::Path(path) = &*call_expr.func.kind {
+            if let Some(variant) = self.variants.resolve(path).cloned() {
+                return self.generate_variant_value(&variant, &call_expr.args, span);
+            }
+            if let Some((func, ty)) = call_expr.args.first().and_then(|receiver| self.path_call_target(path, receiver, span)) {
+                let args: smallvec::SmallVec<[ValueId; 4]> = call_expr.args.iter()
+                    .map(|arg| self.generate_expr(arg))
+                    .collect();
+                let dest = self.create_value();
+                self.emit(IRInst::Call { dest: Some(dest), func, args, ty, span });
+                return dest;
+            }
+        }
+        if let (Some((op, mode)), [left, right]) = (self.overflow_intrinsic(&call_expr.func), call_expr.args.as_slice()) {
+            let ty = self.operand_type(left, right);
+            let left = self.generate_expr(left);
+            let right = self.generate_expr(right);
+            return self.generate_arithmetic(op, left, right, ty, mode, span);
+        }
         let func = self.generate_expr(&call_expr.func);
         let args: smallvec::SmallVec<[ValueId; 4]> = call_expr.args.iter()
             .map(|arg| self.generate_expr(arg))
@@ -436,35 +1089,151 @@ impl<'a> IRGenerator<'a> {
         
         let dest = self.create_value();
         let ty = self.program.pool.builtin_types.i32;
-        self.builder.emit_call(Some(dest), func, args, ty, span);
+        self.emit(IRInst::Call { dest: Some(dest), func, args, ty, span });
         dest
     }
 
+    /// The address and return type of the method `Trait::method(receiver, ..)`
+    /// calls: the one in `Trait`'s impl for the receiver's type.
+    fn path_call_target(&mut self, path: &Path, receiver: &Expr, span: Span) -> Option<(ValueId, TypeId)> {
+        let receiver_ty = receiver.ty.as_ref()?;
+        let method = self.methods.resolve_path_call(path, receiver_ty)?.clone();
+        let [.., trait_segment, _] = path.segments.as_slice() else { return None };
+        let type_name = MethodIndex::type_key(receiver_ty)?;
+        let symbol = Self::method_symbol(&type_name, Some(&*trait_segment.ident), &method.name);
+        let function = self.declare_function(symbol, &method);
+        let dest = self.create_value();
+        self.emit(IRInst::FunctionAddr { dest, function, span });
+        Some((dest, self.module.functions[function.0].return_type))
+    }
+
+    /// Builds an enum value as an aggregate of its discriminant followed by
+    /// the variant's fields, typed as the enum. The fields start out null
+    /// and are filled in one at a time.
+    fn generate_variant_value(&mut self, variant: &VariantInfo, args: &[Box<Expr>], span: Span) -> ValueId {
+        let ty = self.program.pool.named_type(&variant.enum_name).unwrap_or(self.program.pool.builtin_types.unit);
+        let mut elements = vec![IRConstant::Null; variant.field_count + 1];
+        elements[0] = IRConstant::Int(variant.discriminant);
+        let mut value = self.create_value();
+        self.emit(IRInst::Const {
+            dest: value,
+            value: IRConstant::Aggregate(AggregateConstant::Struct(elements)),
+            ty,
+            span,
+        });
+        for (index, arg) in args.iter().enumerate() {
+            let field = self.generate_expr(arg);
+            let dest = self.create_value();
+            self.emit(IRInst::InsertValue {
+                dest,
+                aggregate: value,
+                value: field,
+                indices: smallvec::smallvec![index as u32 + 1],
+                ty,
+                span,
+            });
+            value = dest;
+        }
+        value
+    }
+
     fn generate_block_expr(&mut self, block_expr: &BlockExpr) -> ValueId {
-        for stmt in &block_expr.stmts {
+        let (last, rest) = match block_expr.stmts.split_last() {
+            Some(split) => split,
+            None => return self.create_value(),
+        };
+        self.defer_scopes.push(Vec::new());
+        for stmt in rest {
             self.generate_stmt(stmt);
         }
-        self.create_value()
+        let value = match &last.kind {
+            StmtKind::Expr(expr) => self.generate_expr(expr),
+            _ => {
+                self.generate_stmt(last);
+                self.create_value()
+            }
+        };
+        // The block's value is computed before its deferred blocks run.
+        self.exit_defer_scope();
+        value
     }
 
+    /// Lowers `if` to a `Select` when both arms are side-effect free single values,
+    /// and otherwise to a conditional branch into then/else blocks that rejoin in
+    /// a merge block, where a phi picks the arm's value. Without `else` the
+    /// result is unit.
     fn generate_if_expr(&mut self, if_expr: &IfExpr, span: Span) -> ValueId {
         let cond = self.generate_expr(&if_expr.condition);
+        let ty = self.program.pool.builtin_types.i32;
+
+        let simple_else = if_expr.else_branch.as_deref().filter(|expr| Self::is_simple_expr(expr));
+        if let (Some(then_expr), Some(else_expr)) = (Self::simple_block_value(&if_expr.then_branch), simple_else) {
+            let then_value = self.generate_expr(then_expr);
+            let else_value = self.generate_expr(else_expr);
+            let dest = self.create_value();
+            self.emit(IRInst::Select {
+                dest,
+                condition: cond,
+                true_val: then_value,
+                false_val: else_value,
+                ty,
+                span,
+            });
+            return dest;
+        }
+
         let then_block = self.create_block();
-        let else_block = self.create_block();
-        
-        let then_value = self.generate_expr(&if_expr.then_branch);
-        let else_value = if let Some(else_branch) = &if_expr.else_branch {
-            self.generate_expr(else_branch)
-        } else {
-            self.create_value()
-        };
-        
+        let else_block = if if_expr.else_branch.is_some() { Some(self.create_block()) } else { None };
+        let merge_block = self.create_block();
+        self.terminate(Terminator::ConditionalBranch {
+            condition: cond,
+            true_block: then_block,
+            false_block: else_block.unwrap_or(merge_block),
+        });
+
+        let mut incoming: SmallVec<[(BlockId, ValueId); 4]> = SmallVec::new();
+
+        self.switch_to_block(then_block);
+        let then_value = self.generate_block_expr(&if_expr.then_branch);
+        if let Some(end) = self.current_block {
+            incoming.push((end, then_value));
+            self.terminate(Terminator::Branch(merge_block));
+        }
+
+        if let (Some(else_block), Some(else_branch)) = (else_block, &if_expr.else_branch) {
+            self.switch_to_block(else_block);
+            let else_value = self.generate_expr(else_branch);
+            if let Some(end) = self.current_block {
+                incoming.push((end, else_value));
+                self.terminate(Terminator::Branch(merge_block));
+            }
+        }
+
+        self.switch_to_block(merge_block);
         let dest = self.create_value();
-        let ty = self.program.pool.builtin_types.i32;
-        self.builder.emit_select(dest, cond, then_value, else_value, ty, span);
+        if if_expr.else_branch.is_some() && !incoming.is_empty() {
+            self.emit(IRInst::Phi { dest, incoming, ty, span });
+        }
         dest
     }
 
+    /// The trailing expression of a block consisting of nothing else.
+    fn simple_block_value(block: &BlockExpr) -> Option<&Expr> {
+        match block.stmts.as_slice() {
+            [Stmt { kind: StmtKind::Expr(expr), .. }] if Self::is_simple_expr(expr) => Some(expr),
+            _ => None,
+        }
+    }
+
+    /// Literals and variables can be evaluated eagerly without changing behavior.
+    fn is_simple_expr(expr: &Expr) -> bool {
+        match &*expr.kind {
+            ExprKind::Literal(_) | ExprKind::Identifier(_) => true,
+            ExprKind::Block(block) => Self::simple_block_value(block).is_some(),
+            _ => false,
+        }
+    }
+
     fn generate_match_expr(&mut self, match_expr: &MatchExpr, span: Span) -> ValueId {
         let value = self.generate_expr(&match_expr.expr);
         let dest = self.create_value();
@@ -482,39 +1251,256 @@ impl<'a> IRGenerator<'a> {
         dest
     }
 
+    fn generate_map_expr(&mut self, map_expr: &MapExpr, span: Span) -> ValueId {
+        let iterator = self.generate_expr(&map_expr.iterator);
+        let mapper = self.generate_expr(&map_expr.mapper);
+        let dest = self.create_value();
+        self.emit(IRInst::IteratorMap { dest, iterator, mapper, span });
+        dest
+    }
+
+    /// Lowers `collect it` to a loop that drains the iterator into a new
+    /// collection of the `target` type (a `Vec` when the context doesn't say):
+    ///
+    /// ```text
+    ///   buf = call T::new()
+    ///   br header
+    /// header:
+    ///   next = iterator.next it        ; (has_value, value)
+    ///   cond_br next.0, body, exit
+    /// body:
+    ///   call T::push(buf, next.1)
+    ///   br header
+    /// exit:
+    /// ```
+    fn generate_collect_expr(&mut self, collect_expr: &CollectExpr, target: Option<&Type>, span: Span) -> ValueId {
+        let iterator = self.generate_expr(&collect_expr.iterator);
+        let collection = target
+            .and_then(|ty| match &*ty.kind {
+                TypeKind::Path(path) => path.segments.last().map(|segment| segment.ident.to_string()),
+                _ => None,
+            })
+            .unwrap_or_else(|| "Vec".to_string());
+        let unit = self.program.pool.builtin_types.unit;
+        let buffer_ty = match target {
+            Some(ty) => self.get_type_id(ty),
+            None => {
+                self.errors.push(
+                    ChimError::new(ErrorKind::CannotInfer, "cannot tell what collection `collect` builds")
+                        .with_span(span)
+                        .with_note("annotate the type of the result, e.g. `let v: Vec<int> = ...`"),
+                );
+                unit
+            }
+        };
+        let item_ty = target
+            .and_then(Self::element_type)
+            .or_else(|| Self::iterator_item_type(&collect_expr.iterator))
+            .map_or(unit, |ty| self.get_type_id(&ty));
+        let bool_ty = self.program.pool.builtin_types.bool;
+
+        let new_fn = self.runtime_function(&format!("{}::new", collection), &[], buffer_ty, span);
+        let buffer = self.create_value();
+        self.emit(IRInst::Call { dest: Some(buffer), func: new_fn, args: SmallVec::new(), ty: buffer_ty, span });
+        let push_fn = self.runtime_function(&format!("{}::push", collection), &[buffer_ty, item_ty], unit, span);
+
+        let header = self.create_block();
+        let body = self.create_block();
+        let exit = self.create_block();
+        self.terminate(Terminator::Branch(header));
+
+        self.switch_to_block(header);
+        let next = self.create_value();
+        self.emit(IRInst::IteratorNext { dest: next, iterator, span });
+        let has_value = self.create_value();
+        self.emit(IRInst::ExtractValue {
+            dest: has_value,
+            aggregate: next,
+            indices: smallvec::smallvec![0],
+            ty: bool_ty,
+            span,
+        });
+        self.terminate(Terminator::ConditionalBranch { condition: has_value, true_block: body, false_block: exit });
+
+        self.switch_to_block(body);
+        let item = self.create_value();
+        self.emit(IRInst::ExtractValue {
+            dest: item,
+            aggregate: next,
+            indices: smallvec::smallvec![1],
+            ty: item_ty,
+            span,
+        });
+        self.emit(IRInst::Call { dest: None, func: push_fn, args: smallvec::smallvec![buffer, item], ty: unit, span });
+        self.terminate(Terminator::Branch(header));
+
+        self.switch_to_block(exit);
+        buffer
+    }
+
+    /// The address of a runtime-provided function, declared as an external
+    /// function taking `params` and returning `return_type` the first time
+    /// it's referenced.
+    fn runtime_function(&mut self, name: &str, params: &[TypeId], return_type: TypeId, span: Span) -> ValueId {
+        let function = match self.module.functions.iter().find(|function| function.is_extern && function.name == name) {
+            Some(function) => function.id,
+            None => {
+                let id = IRFunctionId(self.module.functions.len());
+                self.module.functions.push(IRFunction {
+                    id,
+                    name: name.to_string(),
+                    params: params
+                        .iter()
+                        .enumerate()
+                        .map(|(i, &ty)| IRParam { id: VarId(i), name: format!("arg{}", i), ty })
+                        .collect(),
+                    return_type,
+                    body: Vec::new(),
+                    span: Span::dummy(),
+                    is_pub: false,
+                    is_extern: true,
+                    is_unsafe: false,
+                    inline_hint: InlineHint::None,
+                    is_cold: false,
+                });
+                id
+            }
+        };
+        let dest = self.create_value();
+        self.emit(IRInst::FunctionAddr { dest, function, span });
+        dest
+    }
+
     fn generate_closure_expr(&mut self, closure_expr: &ClosureExpr, span: Span) -> ValueId {
         self.create_value()
     }
 
     fn generate_field_access(&mut self, field_access: &FieldAccessExpr, span: Span) -> ValueId {
         let obj = self.generate_expr(&field_access.expr);
-        let dest = self.create_value();
-        
+        let (field_index, ty) = match self.resolve_field(obj, &field_access.field, span) {
+            Ok(field) => field,
+            Err(error) => {
+                self.errors.push(error);
+                return self.create_value();
+            }
+        };
+
+        let index = self.create_value();
+        let index_ty = self.program.pool.builtin_types.i32;
+        self.emit(IRInst::Const {
+            dest: index,
+            value: IRConstant::Int(field_index as i128),
+            ty: index_ty,
+            span,
+        });
+
         let indices: smallvec::SmallVec<[ValueId; 4]> = smallvec::smallvec![
-            ValueId(0),
+            index,
         ];
-        
-        let ty = self.program.pool.builtin_types.i32;
-        self.builder.emit_get_element_ptr(dest, obj, indices, ty, span);
-        self.builder.emit_load(dest, dest, ty, span);
+
+        let ptr = self.create_value();
+        self.emit(IRInst::GetElementPtr { dest: ptr, ptr: obj, indices, ty, span });
+        let dest = self.create_value();
+        self.emit(IRInst::Load { dest, src: ptr, ty, span });
         dest
     }
 
     fn generate_index_expr(&mut self, index_expr: &IndexExpr, span: Span) -> ValueId {
         let array = self.generate_expr(&index_expr.expr);
         let index = self.generate_expr(&index_expr.index);
-        let dest = self.create_value();
+        if self.unchecked_depth == 0 {
+            let array_ty = index_expr.expr.ty.as_ref().map_or(self.program.pool.builtin_types.unit, |ty| self.get_type_id(ty));
+            self.generate_bounds_check(array, array_ty, index, span);
+        }
         
         let indices: smallvec::SmallVec<[ValueId; 4]> = smallvec::smallvec![
             index,
         ];
         
         let ty = self.program.pool.builtin_types.i32;
-        self.builder.emit_get_element_ptr(dest, array, indices, ty, span);
-        self.builder.emit_load(dest, dest, ty, span);
+        let ptr = self.create_value();
+        self.emit(IRInst::GetElementPtr { dest: ptr, ptr: array, indices, ty, span });
+        let dest = self.create_value();
+        self.emit(IRInst::Load { dest, src: ptr, ty, span });
         dest
     }
 
+    /// Continues in a block reached only when `index` is within the length
+    /// of `array`:
+    ///
+    /// ```text
+    ///   len = call slice::len(array)
+    ///   in_bounds = ult index, len
+    ///   cond_br in_bounds, ok, fail
+    /// fail:
+    ///   call panic_bounds_check(index, len)
+    ///   unreachable
+    /// ok:
+    /// ```
+    fn generate_bounds_check(&mut self, array: ValueId, array_ty: TypeId, index: ValueId, span: Span) {
+        let usize_ty = self.program.pool.builtin_types.usize;
+        let bool_ty = self.program.pool.builtin_types.bool;
+        let never = self.program.pool.builtin_types.never;
+
+        let len_fn = self.runtime_function("slice::len", &[array_ty], usize_ty, span);
+        let len = self.create_value();
+        self.emit(IRInst::Call { dest: Some(len), func: len_fn, args: smallvec::smallvec![array], ty: usize_ty, span });
+        let in_bounds = self.create_value();
+        self.emit(IRInst::Binary { dest: in_bounds, op: BinaryOp::ULt, left: index, right: len, ty: bool_ty, span });
+
+        let ok = self.create_block();
+        let fail = self.create_block();
+        self.terminate(Terminator::ConditionalBranch { condition: in_bounds, true_block: ok, false_block: fail });
+
+        self.switch_to_block(fail);
+        let panic_fn = self.runtime_function("panic_bounds_check", &[usize_ty, usize_ty], never, span);
+        self.emit(IRInst::Call { dest: None, func: panic_fn, args: smallvec::smallvec![index, len], ty: never, span });
+        self.terminate(Terminator::Unreachable);
+
+        self.switch_to_block(ok);
+    }
+
+    /// Finds the position and type of `field` in the layout of the struct `obj` is known
+    /// to hold, falling back to the only struct declaring a field of that name.
+    fn resolve_field(&self, obj: ValueId, field: &Ident, span: Span) -> Result<(usize, TypeId), ChimError> {
+        let find = |ir_struct: &IRStruct| {
+            ir_struct.fields.iter()
+                .position(|f| f.name == **field)
+                .map(|i| (i, ir_struct.fields[i].ty))
+        };
+
+        if let Some(&struct_index) = self.value_structs.get(&obj) {
+            let ir_struct = &self.module.structs[struct_index];
+            return find(ir_struct).ok_or_else(|| {
+                ChimError::new(
+                    ErrorKind::UndefinedIdentifier,
+                    format!("no field `{}` on struct `{}`", field, ir_struct.name),
+                ).with_span(span)
+            });
+        }
+
+        let mut candidates = self.module.structs.iter().filter_map(find);
+        match (candidates.next(), candidates.next()) {
+            (Some(found), None) => Ok(found),
+            (None, _) => Err(ChimError::new(ErrorKind::UndefinedIdentifier, format!("no field `{}` on any struct", field))
+                .with_span(span)),
+            (Some(_), Some(_)) => Err(ChimError::new(
+                ErrorKind::CannotInfer,
+                format!("cannot tell which struct's field `{}` this is", field),
+            ).with_span(span).with_note("annotate the type of the value the field is read from".to_string())),
+        }
+    }
+
+    fn struct_index_of_type(&self, ty: &Type) -> Option<usize> {
+        match &*ty.kind {
+            TypeKind::Path(path) => {
+                let name = &path.segments.last()?.ident;
+                self.module.structs.iter().position(|s| s.name == **name)
+            }
+            _ => None,
+        }
+    }
+
     fn generate_cast_expr(&mut self, cast_expr: &CastExpr, span: Span) -> ValueId {
         let value = self.generate_expr(&cast_expr.expr);
         let dest = self.create_value();
@@ -522,7 +1508,7 @@ impl<'a> IRGenerator<'a> {
         let to_ty = self.get_type_id(&cast_expr.ty);
         let op = CastOp::BitCast;
         
-        self.builder.emit_cast(dest, value, to_ty, op, span);
+        self.emit(IRInst::Cast { dest, value, to_ty, op, span });
         dest
     }
 
@@ -531,7 +1517,7 @@ impl<'a> IRGenerator<'a> {
         let right = self.generate_expr(&assign_expr.right);
         
         let ty = self.program.pool.builtin_types.i32;
-        self.builder.emit_store(left, right, ty, span);
+        self.emit(IRInst::Store { dest: left, src: right, ty, span });
         right
     }
 
@@ -546,8 +1532,7 @@ impl<'a> IRGenerator<'a> {
             BinOp::Mul => BinaryOp::Mul,
             BinOp::Div => BinaryOp::Div,
             BinOp::Mod => BinaryOp::Rem,
-            BinOp::And => BinaryOp::And,
-            BinOp::Or => BinaryOp::Or,
+            BinOp::And | BinOp::Or => unreachable!("logical operators are lowered by generate_logical_expr"),
             BinOp::BitAnd => BinaryOp::And,
             BinOp::BitOr => BinaryOp::Or,
             BinOp::BitXor => BinaryOp::Xor,
@@ -562,8 +1547,8 @@ impl<'a> IRGenerator<'a> {
         };
 
         let ty = self.program.pool.builtin_types.i32;
-        self.builder.emit_binary(dest, op, left, right, ty, span);
-        self.builder.emit_store(left, dest, ty, span);
+        self.generate_arithmetic(dest, op, left, right, self.overflow_mode, span);
+        self.emit(IRInst::Store { dest: left, src: dest, ty, span });
         dest
     }
 
@@ -588,7 +1573,14 @@ impl<'a> IRGenerator<'a> {
         let dest = self.create_value();
         
         let ty = self.program.pool.builtin_types.i32;
-        self.builder.emit_select(dest, cond, then_value, else_value, ty, span);
+        self.emit(IRInst::Select {
+            dest,
+            condition: cond,
+            true_val: then_value,
+            false_val: else_value,
+            ty,
+            span,
+        });
         dest
     }
 
@@ -599,12 +1591,22 @@ impl<'a> IRGenerator<'a> {
                 if let Some(segment) = path.segments.first() {
                     match segment.ident.as_ref() {
                         "i32" | "int" => self.program.pool.builtin_types.i32,
+                        "i8" => self.program.pool.builtin_types.i8,
+                        "i16" => self.program.pool.builtin_types.i16,
                         "i64" => self.program.pool.builtin_types.i64,
+                        "i128" => self.program.pool.builtin_types.i128,
+                        "isize" => self.program.pool.builtin_types.isize,
+                        "u8" => self.program.pool.builtin_types.u8,
+                        "u16" => self.program.pool.builtin_types.u16,
+                        "u32" => self.program.pool.builtin_types.u32,
+                        "u64" => self.program.pool.builtin_types.u64,
+                        "u128" => self.program.pool.builtin_types.u128,
+                        "usize" => self.program.pool.builtin_types.usize,
                         "f32" | "float" => self.program.pool.builtin_types.f32,
                         "f64" => self.program.pool.builtin_types.f64,
                         "bool" => self.program.pool.builtin_types.bool,
                         "str" | "string" => self.program.pool.builtin_types.string,
-                        _ => self.program.pool.builtin_types.unit,
+                        name => self.program.pool.named_type(name).unwrap_or(self.program.pool.builtin_types.unit),
                     }
                 } else {
                     self.program.pool.builtin_types.unit
@@ -616,25 +1618,74 @@ impl<'a> IRGenerator<'a> {
 
     fn get_identifier_from_pattern(&self, pattern: &Pattern) -> Option<Ident> {
         match &pattern.kind {
-            PatternKind::Identifier(name) => Some(name.clone()),
+            PatternKind::Identifier(name, _) => Some(name.clone()),
             _ => None,
         }
     }
 
     fn create_value(&mut self) -> ValueId {
-        let id = ValueId(self.next_value_id);
-        self.next_value_id += 1;
-        id
+        self.ids.fresh_value()
     }
 
+    /// Appends a new, empty block to the current function.
     fn create_block(&mut self) -> BlockId {
-        let id = BlockId(self.next_block_id);
-        self.next_block_id += 1;
+        let id = self.ids.fresh_block();
+        let body = &mut self.module.functions[self.current_function].body;
+        debug_assert_eq!(id.0, body.len(), "blocks are indexed by id");
+        body.push(BasicBlock {
+            id,
+            instructions: Vec::new(),
+            terminator: Terminator::Unreachable,
+            predecessors: Vec::new(),
+            successors: Vec::new(),
+        });
         id
     }
+
+    fn switch_to_block(&mut self, block: BlockId) {
+        self.current_block = Some(block);
+    }
+
+    fn block_mut(&mut self, block: BlockId) -> &mut BasicBlock {
+        &mut self.module.functions[self.current_function].body[block.0]
+    }
+
+    /// Appends `inst` to the current block. Code following a terminator is
+    /// unreachable but still lowered, into a fresh block with no predecessors.
+    fn emit(&mut self, inst: IRInst) {
+        let block = match self.current_block {
+            Some(block) => block,
+            None => {
+                let block = self.create_block();
+                self.switch_to_block(block);
+                block
+            }
+        };
+        self.block_mut(block).instructions.push(inst);
+    }
+
+    /// Ends the current block with `terminator`, wiring up the CFG edges.
+    fn terminate(&mut self, terminator: Terminator) {
+        let Some(block) = self.current_block.take() else {
+            return;
+        };
+        let successors = terminator.successors();
+        for &succ in &successors {
+            self.block_mut(succ).predecessors.push(block);
+        }
+        let current = self.block_mut(block);
+        current.successors = successors.into_vec();
+        current.terminator = terminator;
+    }
 }
 
 pub fn generate_ir(ast_program: &Program, analyzed_program: &AnalyzedProgram) -> IRModule {
     let mut generator = IRGenerator::new(analyzed_program);
     generator.generate_module(ast_program)
+}
+
+/// Like `generate_ir`, with integer overflow handled per `mode`.
+pub fn generate_ir_with_overflow(ast_program: &Program, analyzed_program: &AnalyzedProgram, mode: OverflowMode) -> IRModule {
+    let mut generator = IRGenerator::new(analyzed_program).with_overflow_mode(mode);
+    generator.generate_module(ast_program)
 }
\ No newline at end of file
@@ -0,0 +1,166 @@
+use crate::*;
+use chim_semantic::VarId;
+use smallvec::SmallVec;
+
+/// Callees without an `@inline` hint are inlined only up to this many instructions.
+pub const INLINE_THRESHOLD: usize = 32;
+
+/// A call instruction: `caller.body[block].instructions[index]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallSite {
+    pub block: BlockId,
+    pub index: usize,
+}
+
+/// Whether `callee` may be inlined at all: never for `@inline(never)` or
+/// bodiless functions, always for `@inline`, otherwise only when small and
+/// not `@cold`.
+pub fn should_inline(callee: &IRFunction) -> bool {
+    if callee.is_extern || callee.body.is_empty() {
+        return false;
+    }
+    match callee.inline_hint {
+        InlineHint::Never => false,
+        InlineHint::Inline => true,
+        InlineHint::None => !callee.is_cold && instruction_count(callee) <= INLINE_THRESHOLD,
+    }
+}
+
+/// Replaces the call at `site` with a copy of `callee`'s body.
+///
+/// The call's block is split: everything before the call branches into the
+/// copied entry block, every copied `Return` branches to a continuation block
+/// holding the rest, and the call's result becomes a phi over the returned
+/// values. Callee parameters are replaced by the call's arguments; all other
+/// values and blocks get fresh ids. Returns `false`, leaving `caller`
+/// untouched, if `site` is not a matching call or the callee shouldn't be inlined.
+pub fn inline_call(caller: &mut IRFunction, site: CallSite, callee: &IRFunction) -> bool {
+    if !should_inline(callee) {
+        return false;
+    }
+    let (dest, args) = match caller.body.get(site.block.0).and_then(|block| block.instructions.get(site.index)) {
+        Some(IRInst::Call { dest, args, .. }) if args.len() == callee.params.len() => (*dest, args.clone()),
+        _ => return false,
+    };
+
+    let value_base = value_bound(caller);
+    let block_base = caller.body.len();
+    let entry = BlockId(block_base);
+    let continuation = BlockId(block_base + callee.body.len());
+    let map_value = |value: ValueId| match args.get(value.0) {
+        Some(arg) => *arg,
+        None => ValueId(value_base + value.0),
+    };
+    let map_block = |block: BlockId| BlockId(block_base + block.0);
+
+    let call_block = &mut caller.body[site.block.0];
+    let tail = call_block.instructions.split_off(site.index + 1);
+    call_block.instructions.pop();
+    let terminator = std::mem::replace(&mut call_block.terminator, Terminator::Branch(entry));
+    let successors = std::mem::replace(&mut call_block.successors, vec![entry]);
+
+    // The code after the call now runs in the continuation block.
+    for &succ in &successors {
+        let block = &mut caller.body[succ.0];
+        for pred in block.predecessors.iter_mut().filter(|pred| **pred == site.block) {
+            *pred = continuation;
+        }
+        for inst in &mut block.instructions {
+            for incoming in inst.blocks_mut().into_iter().filter(|block| **block == site.block) {
+                *incoming = continuation;
+            }
+        }
+    }
+
+    let mut returned: SmallVec<[(BlockId, ValueId); 4]> = SmallVec::new();
+    let mut returning = Vec::new();
+    for block in &callee.body {
+        let id = map_block(block.id);
+        let mut instructions = block.instructions.clone();
+        for inst in &mut instructions {
+            if let Some(dest) = inst.defined_value_mut() {
+                *dest = VarId(map_value(ValueId(dest.0)).0);
+            }
+            for value in inst.used_values_mut() {
+                *value = map_value(*value);
+            }
+            for target in inst.blocks_mut() {
+                *target = map_block(*target);
+            }
+        }
+
+        let terminator = match &block.terminator {
+            Terminator::Return(value) => {
+                returning.push(id);
+                returned.extend(value.map(|value| (id, map_value(value))));
+                Terminator::Branch(continuation)
+            }
+            other => {
+                let mut terminator = other.clone();
+                for value in terminator.used_values_mut() {
+                    *value = map_value(*value);
+                }
+                for target in terminator.successors_mut() {
+                    *target = map_block(*target);
+                }
+                terminator
+            }
+        };
+
+        let mut predecessors: Vec<BlockId> = block.predecessors.iter().map(|&pred| map_block(pred)).collect();
+        if id == entry {
+            predecessors.push(site.block);
+        }
+        caller.body.push(BasicBlock {
+            id,
+            instructions,
+            successors: terminator.successors().into_vec(),
+            terminator,
+            predecessors,
+        });
+    }
+
+    let mut instructions = Vec::with_capacity(tail.len() + 1);
+    if let Some(dest) = dest {
+        if !returned.is_empty() {
+            instructions.push(IRInst::Phi {
+                dest,
+                incoming: returned,
+                ty: callee.return_type,
+                span: callee.span,
+            });
+        }
+    }
+    instructions.extend(tail);
+    caller.body.push(BasicBlock {
+        id: continuation,
+        instructions,
+        terminator,
+        predecessors: returning,
+        successors,
+    });
+    true
+}
+
+fn instruction_count(func: &IRFunction) -> usize {
+    func.body.iter().map(|block| block.instructions.len()).sum()
+}
+
+/// One past the highest value id in `func`; parameters occupy the first ids.
+fn value_bound(func: &IRFunction) -> usize {
+    let mut bound = func.params.len();
+    for block in &func.body {
+        for inst in &block.instructions {
+            if let Some(dest) = inst.defined_value() {
+                bound = bound.max(dest.0 + 1);
+            }
+            for value in inst.used_values() {
+                bound = bound.max(value.0 + 1);
+            }
+        }
+        for value in block.terminator.used_values() {
+            bound = bound.max(value.0 + 1);
+        }
+    }
+    bound
+}
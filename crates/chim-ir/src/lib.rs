@@ -1,20 +1,65 @@
-use chim_semantic::{TypeId, TypeData, StructId, EnumId, FunctionId, VarId};
+use chim_semantic::{TypeId, TypeData, StructId, EnumId, VarId};
 use chim_span::Span;
 use smallvec::SmallVec;
 use std::sync::Arc;
 
+pub mod encode;
 pub mod generator;
+pub mod inline;
 
-pub use generator::{IRGenerator, generate_ir};
+pub use encode::{DecodeError, FORMAT_VERSION};
+pub use generator::{IRGenerator, generate_ir, generate_ir_with_overflow};
+pub use inline::{inline_call, should_inline, CallSite, INLINE_THRESHOLD};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct ValueId(usize);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ValueId(pub usize);
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct BlockId(usize);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BlockId(pub usize);
+
+/// Mints the block, value and variable ids of one function. Each counter
+/// only moves forward, so no id is handed out twice.
+#[derive(Debug, Clone, Default)]
+pub struct IdAllocator {
+    next_block: usize,
+    next_value: usize,
+    next_var: usize,
+}
+
+impl IdAllocator {
+    pub fn new() -> Self {
+        IdAllocator::default()
+    }
+
+    /// An allocator for a function with `count` parameters, which own the
+    /// first `count` value and variable ids.
+    pub fn with_params(count: usize) -> Self {
+        IdAllocator { next_block: 0, next_value: count, next_var: count }
+    }
+
+    pub fn fresh_block(&mut self) -> BlockId {
+        let id = BlockId(self.next_block);
+        self.next_block += 1;
+        id
+    }
+
+    pub fn fresh_value(&mut self) -> ValueId {
+        let id = ValueId(self.next_value);
+        self.next_value += 1;
+        id
+    }
+
+    pub fn fresh_var(&mut self) -> VarId {
+        let id = VarId(self.next_var);
+        self.next_var += 1;
+        id
+    }
+}
 
+/// Index of a function within an `IRModule`. Distinct from the semantic
+/// `chim_semantic::FunctionId`, which identifies a function signature in the type pool.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct FunctionId as IRFunctionId(usize);
+pub struct IRFunctionId(pub usize);
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct IRModule {
@@ -24,9 +69,36 @@ pub struct IRModule {
     pub enums: Vec<IREnum>,
 }
 
+impl IRModule {
+    /// Returns the index of the constant global holding `value` as a `ty`, adding one
+    /// if this string has not been seen yet at that type so identical literals share
+    /// storage. A `str` and a byte string with the same text get separate globals.
+    pub fn intern_string(&mut self, value: &str, ty: TypeId) -> usize {
+        let existing = self.globals.iter().position(|global| {
+            global.is_const
+                && global.ty == ty
+                && matches!(&global.initializer, Some(IRConstant::String(s)) if s == value)
+        });
+        if let Some(index) = existing {
+            return index;
+        }
+
+        let index = self.globals.len();
+        self.globals.push(Global {
+            name: format!(".str.{}", index),
+            ty,
+            initializer: Some(IRConstant::String(value.to_string())),
+            is_pub: false,
+            is_const: true,
+            align: 1,
+        });
+        index
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct IRFunction {
-    pub id: FunctionId,
+    pub id: IRFunctionId,
     pub name: String,
     pub params: Vec<IRParam>,
     pub return_type: TypeId,
@@ -35,6 +107,19 @@ pub struct IRFunction {
     pub is_pub: bool,
     pub is_extern: bool,
     pub is_unsafe: bool,
+    pub inline_hint: InlineHint,
+    /// From `@cold`: the function is rarely called, so callers should lay out
+    /// the path to it out of line.
+    pub is_cold: bool,
+}
+
+/// Inlining request carried over from `@inline` / `@inline(never)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum InlineHint {
+    #[default]
+    None,
+    Inline,
+    Never,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -79,6 +164,24 @@ pub enum IRInst {
         ty: TypeId,
         span: Span,
     },
+    Const {
+        dest: VarId,
+        value: IRConstant,
+        ty: TypeId,
+        span: Span,
+    },
+    GlobalAddr {
+        dest: VarId,
+        global: usize,
+        ty: TypeId,
+        span: Span,
+    },
+    /// The address of `function`, for calling it or passing it on.
+    FunctionAddr {
+        dest: VarId,
+        function: IRFunctionId,
+        span: Span,
+    },
     Load {
         dest: VarId,
         src: ValueId,
@@ -182,9 +285,11 @@ pub enum IRInst {
         op: CastOp,
         span: Span,
     },
+    /// Picks the value from whichever predecessor control arrived from.
+    /// Phis sit at the top of their block, before any other instruction.
     Phi {
         dest: VarId,
-        values: SmallVec<[(BlockId, ValueId); 4]>,
+        incoming: SmallVec<[(BlockId, ValueId); 4]>,
         ty: TypeId,
         span: Span,
     },
@@ -381,6 +486,382 @@ pub enum IRInst {
     },
 }
 
+impl IRInst {
+    /// The variable this instruction assigns, if any.
+    pub fn defined_value(&self) -> Option<VarId> {
+        match self {
+            IRInst::Alloca { dest, .. }
+            | IRInst::Const { dest, .. }
+            | IRInst::GlobalAddr { dest, .. }
+            | IRInst::FunctionAddr { dest, .. }
+            | IRInst::Load { dest, .. }
+            | IRInst::GetElementPtr { dest, .. }
+            | IRInst::Binary { dest, .. }
+            | IRInst::Unary { dest, .. }
+            | IRInst::Select { dest, .. }
+            | IRInst::ExtractValue { dest, .. }
+            | IRInst::InsertValue { dest, .. }
+            | IRInst::Cast { dest, .. }
+            | IRInst::Phi { dest, .. }
+            | IRInst::AtomicLoad { dest, .. }
+            | IRInst::AtomicFetchAdd { dest, .. }
+            | IRInst::AtomicFetchSub { dest, .. }
+            | IRInst::AtomicFetchAnd { dest, .. }
+            | IRInst::AtomicFetchOr { dest, .. }
+            | IRInst::AtomicFetchXor { dest, .. }
+            | IRInst::AtomicCompareExchange { dest, .. }
+            | IRInst::AtomicExchange { dest, .. }
+            | IRInst::MacroExpand { dest, .. }
+            | IRInst::ClosureCreate { dest, .. }
+            | IRInst::IteratorNext { dest, .. }
+            | IRInst::IteratorCollect { dest, .. }
+            | IRInst::IteratorChain { dest, .. }
+            | IRInst::IteratorFilter { dest, .. }
+            | IRInst::IteratorFold { dest, .. }
+            | IRInst::IteratorMap { dest, .. }
+            | IRInst::ResultOk { dest, .. }
+            | IRInst::ResultErr { dest, .. }
+            | IRInst::TryCatch { dest, .. }
+            | IRInst::FutureAwait { dest, .. } => Some(*dest),
+            IRInst::Call { dest, .. } | IRInst::Invoke { dest, .. } => *dest,
+            IRInst::Store { .. }
+            | IRInst::Br { .. }
+            | IRInst::CondBr { .. }
+            | IRInst::Ret { .. }
+            | IRInst::RetVoid { .. }
+            | IRInst::Switch { .. }
+            | IRInst::Skip { .. }
+            | IRInst::Debug { .. }
+            | IRInst::AtomicStore { .. }
+            | IRInst::AtomicFence { .. }
+            | IRInst::Wait { .. }
+            | IRInst::Notify { .. }
+            | IRInst::NotifyAll { .. }
+            | IRInst::MemoryBarrier { .. }
+            | IRInst::DataDependency { .. }
+            | IRInst::Throw { .. }
+            | IRInst::Yield { .. }
+            | IRInst::StreamYield { .. } => None,
+        }
+    }
+
+    /// Every value this instruction reads, in operand order. The address a
+    /// `Store` writes through counts as a use.
+    pub fn used_values(&self) -> SmallVec<[ValueId; 4]> {
+        let mut uses = SmallVec::new();
+        match self {
+            IRInst::Alloca { .. }
+            | IRInst::Const { .. }
+            | IRInst::GlobalAddr { .. }
+            | IRInst::FunctionAddr { .. }
+            | IRInst::Br { .. }
+            | IRInst::RetVoid { .. }
+            | IRInst::Skip { .. }
+            | IRInst::Debug { .. }
+            | IRInst::AtomicFence { .. }
+            | IRInst::MemoryBarrier { .. }
+            | IRInst::ClosureCreate { .. } => {}
+            IRInst::Load { src, .. } | IRInst::AtomicLoad { src, .. } => uses.push(*src),
+            IRInst::Store { dest, src, .. } | IRInst::AtomicStore { dest, src, .. } => {
+                uses.push(*dest);
+                uses.push(*src);
+            }
+            IRInst::GetElementPtr { ptr, indices, .. } => {
+                uses.push(*ptr);
+                uses.extend(indices.iter().copied());
+            }
+            IRInst::Binary { left, right, .. } => {
+                uses.push(*left);
+                uses.push(*right);
+            }
+            IRInst::Unary { operand, .. } => uses.push(*operand),
+            IRInst::Call { func, args, .. } | IRInst::Invoke { func, args, .. } => {
+                uses.push(*func);
+                uses.extend(args.iter().copied());
+            }
+            IRInst::CondBr { condition, .. } => uses.push(*condition),
+            IRInst::Ret { value, .. }
+            | IRInst::Yield { value, .. }
+            | IRInst::StreamYield { value, .. } => uses.extend(*value),
+            IRInst::Switch { value, cases, .. } => {
+                uses.push(*value);
+                uses.extend(cases.iter().map(|(case, _)| *case));
+            }
+            IRInst::Select { condition, true_val, false_val, .. } => {
+                uses.push(*condition);
+                uses.push(*true_val);
+                uses.push(*false_val);
+            }
+            IRInst::ExtractValue { aggregate, .. } => uses.push(*aggregate),
+            IRInst::InsertValue { aggregate, value, .. } => {
+                uses.push(*aggregate);
+                uses.push(*value);
+            }
+            IRInst::Cast { value, .. } => uses.push(*value),
+            IRInst::Phi { incoming, .. } => uses.extend(incoming.iter().map(|(_, value)| *value)),
+            IRInst::AtomicFetchAdd { src, value, .. }
+            | IRInst::AtomicFetchSub { src, value, .. }
+            | IRInst::AtomicFetchAnd { src, value, .. }
+            | IRInst::AtomicFetchOr { src, value, .. }
+            | IRInst::AtomicFetchXor { src, value, .. }
+            | IRInst::AtomicExchange { src, value, .. } => {
+                uses.push(*src);
+                uses.push(*value);
+            }
+            IRInst::AtomicCompareExchange { src, expected, desired, .. } => {
+                uses.push(*src);
+                uses.push(*expected);
+                uses.push(*desired);
+            }
+            IRInst::Wait { atomic, timeout, .. } => {
+                uses.push(*atomic);
+                uses.extend(*timeout);
+            }
+            IRInst::Notify { atomic, .. } | IRInst::NotifyAll { atomic, .. } => uses.push(*atomic),
+            IRInst::DataDependency { src, dest, .. } => {
+                uses.push(*src);
+                uses.push(*dest);
+            }
+            IRInst::MacroExpand { macro_name, args, .. } => {
+                uses.push(*macro_name);
+                uses.extend(args.iter().copied());
+            }
+            IRInst::IteratorNext { iterator, .. } | IRInst::IteratorCollect { iterator, .. } => {
+                uses.push(*iterator);
+            }
+            IRInst::IteratorChain { iterator1, iterator2, .. } => {
+                uses.push(*iterator1);
+                uses.push(*iterator2);
+            }
+            IRInst::IteratorFilter { iterator, predicate, .. } => {
+                uses.push(*iterator);
+                uses.push(*predicate);
+            }
+            IRInst::IteratorFold { iterator, init, .. } => {
+                uses.push(*iterator);
+                uses.push(*init);
+            }
+            IRInst::IteratorMap { iterator, mapper, .. } => {
+                uses.push(*iterator);
+                uses.push(*mapper);
+            }
+            IRInst::ResultOk { value, .. } => uses.push(*value),
+            IRInst::ResultErr { error, .. } | IRInst::Throw { error, .. } => uses.push(*error),
+            IRInst::TryCatch { try_expr, .. } => uses.push(*try_expr),
+            IRInst::FutureAwait { future, .. } => uses.push(*future),
+        }
+        uses
+    }
+
+    /// Mutable access to the variable this instruction assigns, for renumbering.
+    pub fn defined_value_mut(&mut self) -> Option<&mut VarId> {
+        match self {
+            IRInst::Alloca { dest, .. }
+            | IRInst::Const { dest, .. }
+            | IRInst::GlobalAddr { dest, .. }
+            | IRInst::FunctionAddr { dest, .. }
+            | IRInst::Load { dest, .. }
+            | IRInst::GetElementPtr { dest, .. }
+            | IRInst::Binary { dest, .. }
+            | IRInst::Unary { dest, .. }
+            | IRInst::Select { dest, .. }
+            | IRInst::ExtractValue { dest, .. }
+            | IRInst::InsertValue { dest, .. }
+            | IRInst::Cast { dest, .. }
+            | IRInst::Phi { dest, .. }
+            | IRInst::AtomicLoad { dest, .. }
+            | IRInst::AtomicFetchAdd { dest, .. }
+            | IRInst::AtomicFetchSub { dest, .. }
+            | IRInst::AtomicFetchAnd { dest, .. }
+            | IRInst::AtomicFetchOr { dest, .. }
+            | IRInst::AtomicFetchXor { dest, .. }
+            | IRInst::AtomicCompareExchange { dest, .. }
+            | IRInst::AtomicExchange { dest, .. }
+            | IRInst::MacroExpand { dest, .. }
+            | IRInst::ClosureCreate { dest, .. }
+            | IRInst::IteratorNext { dest, .. }
+            | IRInst::IteratorCollect { dest, .. }
+            | IRInst::IteratorChain { dest, .. }
+            | IRInst::IteratorFilter { dest, .. }
+            | IRInst::IteratorFold { dest, .. }
+            | IRInst::IteratorMap { dest, .. }
+            | IRInst::ResultOk { dest, .. }
+            | IRInst::ResultErr { dest, .. }
+            | IRInst::TryCatch { dest, .. }
+            | IRInst::FutureAwait { dest, .. } => Some(dest),
+            IRInst::Call { dest, .. } | IRInst::Invoke { dest, .. } => dest.as_mut(),
+            IRInst::Store { .. }
+            | IRInst::Br { .. }
+            | IRInst::CondBr { .. }
+            | IRInst::Ret { .. }
+            | IRInst::RetVoid { .. }
+            | IRInst::Switch { .. }
+            | IRInst::Skip { .. }
+            | IRInst::Debug { .. }
+            | IRInst::AtomicStore { .. }
+            | IRInst::AtomicFence { .. }
+            | IRInst::Wait { .. }
+            | IRInst::Notify { .. }
+            | IRInst::NotifyAll { .. }
+            | IRInst::MemoryBarrier { .. }
+            | IRInst::DataDependency { .. }
+            | IRInst::Throw { .. }
+            | IRInst::Yield { .. }
+            | IRInst::StreamYield { .. } => None,
+        }
+    }
+
+    /// Mutable access to the values `used_values` reports, in the same order.
+    pub fn used_values_mut(&mut self) -> SmallVec<[&mut ValueId; 4]> {
+        let mut uses = SmallVec::new();
+        match self {
+            IRInst::Alloca { .. }
+            | IRInst::Const { .. }
+            | IRInst::GlobalAddr { .. }
+            | IRInst::FunctionAddr { .. }
+            | IRInst::Br { .. }
+            | IRInst::RetVoid { .. }
+            | IRInst::Skip { .. }
+            | IRInst::Debug { .. }
+            | IRInst::AtomicFence { .. }
+            | IRInst::MemoryBarrier { .. }
+            | IRInst::ClosureCreate { .. } => {}
+            IRInst::Load { src, .. } | IRInst::AtomicLoad { src, .. } => uses.push(src),
+            IRInst::Store { dest, src, .. } | IRInst::AtomicStore { dest, src, .. } => {
+                uses.push(dest);
+                uses.push(src);
+            }
+            IRInst::GetElementPtr { ptr, indices, .. } => {
+                uses.push(ptr);
+                uses.extend(indices.iter_mut());
+            }
+            IRInst::Binary { left, right, .. } => {
+                uses.push(left);
+                uses.push(right);
+            }
+            IRInst::Unary { operand, .. } => uses.push(operand),
+            IRInst::Call { func, args, .. } | IRInst::Invoke { func, args, .. } => {
+                uses.push(func);
+                uses.extend(args.iter_mut());
+            }
+            IRInst::CondBr { condition, .. } => uses.push(condition),
+            IRInst::Ret { value, .. }
+            | IRInst::Yield { value, .. }
+            | IRInst::StreamYield { value, .. } => uses.extend(value.as_mut()),
+            IRInst::Switch { value, cases, .. } => {
+                uses.push(value);
+                uses.extend(cases.iter_mut().map(|(case, _)| case));
+            }
+            IRInst::Select { condition, true_val, false_val, .. } => {
+                uses.push(condition);
+                uses.push(true_val);
+                uses.push(false_val);
+            }
+            IRInst::ExtractValue { aggregate, .. } => uses.push(aggregate),
+            IRInst::InsertValue { aggregate, value, .. } => {
+                uses.push(aggregate);
+                uses.push(value);
+            }
+            IRInst::Cast { value, .. } => uses.push(value),
+            IRInst::Phi { incoming, .. } => uses.extend(incoming.iter_mut().map(|(_, value)| value)),
+            IRInst::AtomicFetchAdd { src, value, .. }
+            | IRInst::AtomicFetchSub { src, value, .. }
+            | IRInst::AtomicFetchAnd { src, value, .. }
+            | IRInst::AtomicFetchOr { src, value, .. }
+            | IRInst::AtomicFetchXor { src, value, .. }
+            | IRInst::AtomicExchange { src, value, .. } => {
+                uses.push(src);
+                uses.push(value);
+            }
+            IRInst::AtomicCompareExchange { src, expected, desired, .. } => {
+                uses.push(src);
+                uses.push(expected);
+                uses.push(desired);
+            }
+            IRInst::Wait { atomic, timeout, .. } => {
+                uses.push(atomic);
+                uses.extend(timeout.as_mut());
+            }
+            IRInst::Notify { atomic, .. } | IRInst::NotifyAll { atomic, .. } => uses.push(atomic),
+            IRInst::DataDependency { src, dest, .. } => {
+                uses.push(src);
+                uses.push(dest);
+            }
+            IRInst::MacroExpand { macro_name, args, .. } => {
+                uses.push(macro_name);
+                uses.extend(args.iter_mut());
+            }
+            IRInst::IteratorNext { iterator, .. } | IRInst::IteratorCollect { iterator, .. } => {
+                uses.push(iterator);
+            }
+            IRInst::IteratorChain { iterator1, iterator2, .. } => {
+                uses.push(iterator1);
+                uses.push(iterator2);
+            }
+            IRInst::IteratorFilter { iterator, predicate, .. } => {
+                uses.push(iterator);
+                uses.push(predicate);
+            }
+            IRInst::IteratorFold { iterator, init, .. } => {
+                uses.push(iterator);
+                uses.push(init);
+            }
+            IRInst::IteratorMap { iterator, mapper, .. } => {
+                uses.push(iterator);
+                uses.push(mapper);
+            }
+            IRInst::ResultOk { value, .. } => uses.push(value),
+            IRInst::ResultErr { error, .. } | IRInst::Throw { error, .. } => uses.push(error),
+            IRInst::TryCatch { try_expr, .. } => uses.push(try_expr),
+            IRInst::FutureAwait { future, .. } => uses.push(future),
+        }
+        uses
+    }
+
+    /// Mutable access to the blocks this instruction refers to: branch targets
+    /// and the incoming blocks of a `Phi`.
+    pub fn blocks_mut(&mut self) -> SmallVec<[&mut BlockId; 2]> {
+        let mut blocks = SmallVec::new();
+        match self {
+            IRInst::Br { target, .. } => blocks.push(target),
+            IRInst::CondBr { true_block, false_block, .. } => {
+                blocks.push(true_block);
+                blocks.push(false_block);
+            }
+            IRInst::Invoke { normal_block, unwind_block, .. } => {
+                blocks.push(normal_block);
+                blocks.push(unwind_block);
+            }
+            IRInst::Switch { default_block, cases, .. } => {
+                blocks.push(default_block);
+                blocks.extend(cases.iter_mut().map(|(_, target)| target));
+            }
+            IRInst::Phi { incoming, .. } => blocks.extend(incoming.iter_mut().map(|(block, _)| block)),
+            _ => {}
+        }
+        blocks
+    }
+}
+
+/// What integer `+`, `-` and `*` do when the result doesn't fit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum OverflowMode {
+    /// Wrap around in two's complement; a plain `Binary` instruction.
+    #[default]
+    Wrapping,
+    /// Test for overflow after the operation and panic if it happened.
+    Checked,
+    /// Clamp to the type's minimum or maximum.
+    Saturating,
+}
+
+impl OverflowMode {
+    /// Checked in debug builds, wrapping in release builds.
+    pub fn for_build(debug: bool) -> Self {
+        if debug { OverflowMode::Checked } else { OverflowMode::Wrapping }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum BinaryOp {
     Add,
@@ -398,6 +879,9 @@ pub enum BinaryOp {
     FMul,
     FDiv,
     FRem,
+    /// Unsigned `<`, yielding a `bool`; a negative left operand compares as
+    /// larger than any length, so one comparison bounds-checks an index.
+    ULt,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -431,6 +915,91 @@ pub enum Terminator {
     Switch { value: ValueId, default_block: BlockId, cases: Vec<(ValueId, BlockId)> },
 }
 
+impl Terminator {
+    /// Blocks control may transfer to, in order: for `Invoke` the normal
+    /// block precedes the unwind block, for `Switch` the default precedes the cases.
+    pub fn successors(&self) -> SmallVec<[BlockId; 2]> {
+        let mut succs = SmallVec::new();
+        match self {
+            Terminator::Return(_) | Terminator::Unreachable => {}
+            Terminator::Branch(target) => succs.push(*target),
+            Terminator::ConditionalBranch { true_block, false_block, .. } => {
+                succs.push(*true_block);
+                succs.push(*false_block);
+            }
+            Terminator::Invoke { normal_block, unwind_block, .. } => {
+                succs.push(*normal_block);
+                succs.push(*unwind_block);
+            }
+            Terminator::Switch { default_block, cases, .. } => {
+                succs.push(*default_block);
+                succs.extend(cases.iter().map(|(_, target)| *target));
+            }
+        }
+        succs
+    }
+
+    /// Mutable access to the blocks `successors` reports, in the same order.
+    pub fn successors_mut(&mut self) -> SmallVec<[&mut BlockId; 2]> {
+        let mut succs = SmallVec::new();
+        match self {
+            Terminator::Return(_) | Terminator::Unreachable => {}
+            Terminator::Branch(target) => succs.push(target),
+            Terminator::ConditionalBranch { true_block, false_block, .. } => {
+                succs.push(true_block);
+                succs.push(false_block);
+            }
+            Terminator::Invoke { normal_block, unwind_block, .. } => {
+                succs.push(normal_block);
+                succs.push(unwind_block);
+            }
+            Terminator::Switch { default_block, cases, .. } => {
+                succs.push(default_block);
+                succs.extend(cases.iter_mut().map(|(_, target)| target));
+            }
+        }
+        succs
+    }
+
+    /// Every value the terminator reads, in operand order.
+    pub fn used_values(&self) -> SmallVec<[ValueId; 4]> {
+        let mut uses = SmallVec::new();
+        match self {
+            Terminator::Branch(_) | Terminator::Unreachable => {}
+            Terminator::Return(value) => uses.extend(*value),
+            Terminator::ConditionalBranch { condition, .. } => uses.push(*condition),
+            Terminator::Invoke { func, args, .. } => {
+                uses.push(*func);
+                uses.extend(args.iter().copied());
+            }
+            Terminator::Switch { value, cases, .. } => {
+                uses.push(*value);
+                uses.extend(cases.iter().map(|(case, _)| *case));
+            }
+        }
+        uses
+    }
+
+    /// Mutable access to the values `used_values` reports, in the same order.
+    pub fn used_values_mut(&mut self) -> SmallVec<[&mut ValueId; 4]> {
+        let mut uses = SmallVec::new();
+        match self {
+            Terminator::Branch(_) | Terminator::Unreachable => {}
+            Terminator::Return(value) => uses.extend(value.as_mut()),
+            Terminator::ConditionalBranch { condition, .. } => uses.push(condition),
+            Terminator::Invoke { func, args, .. } => {
+                uses.push(func);
+                uses.extend(args.iter_mut());
+            }
+            Terminator::Switch { value, cases, .. } => {
+                uses.push(value);
+                uses.extend(cases.iter_mut().map(|(case, _)| case));
+            }
+        }
+        uses
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Global {
     pub name: String,
@@ -511,8 +1080,7 @@ pub struct IRBuilder<'a> {
     pub module: &'a mut IRModule,
     pub current_function: Option<&'a mut IRFunction>,
     pub current_block: Option<&'a mut BasicBlock>,
-    pub next_value_id: usize,
-    pub next_block_id: usize,
+    pub ids: IdAllocator,
 }
 
 impl<'a> IRBuilder<'a> {
@@ -521,21 +1089,16 @@ impl<'a> IRBuilder<'a> {
             module,
             current_function: None,
             current_block: None,
-            next_value_id: 0,
-            next_block_id: 0,
+            ids: IdAllocator::new(),
         }
     }
 
     pub fn create_value(&mut self) -> ValueId {
-        let id = ValueId(self.next_value_id);
-        self.next_value_id += 1;
-        id
+        self.ids.fresh_value()
     }
 
     pub fn create_block(&mut self) -> BlockId {
-        let id = BlockId(self.next_block_id);
-        self.next_block_id += 1;
-        id
+        self.ids.fresh_block()
     }
 
     pub fn set_current_block(&mut self, block: &'a mut BasicBlock) {
@@ -554,6 +1117,18 @@ impl<'a> IRBuilder<'a> {
         dest
     }
 
+    pub fn emit_const(&mut self, dest: VarId, value: IRConstant, ty: TypeId, span: Span) {
+        self.append_inst(IRInst::Const { dest, value, ty, span });
+    }
+
+    pub fn emit_global_addr(&mut self, dest: VarId, global: usize, ty: TypeId, span: Span) {
+        self.append_inst(IRInst::GlobalAddr { dest, global, ty, span });
+    }
+
+    pub fn emit_get_element_ptr(&mut self, dest: VarId, ptr: ValueId, indices: SmallVec<[ValueId; 4]>, ty: TypeId, span: Span) {
+        self.append_inst(IRInst::GetElementPtr { dest, ptr, indices, ty, span });
+    }
+
     pub fn emit_load(&mut self, dest: VarId, src: ValueId, ty: TypeId, span: Span) {
         self.append_inst(IRInst::Load { dest, src, ty, span });
     }
@@ -750,4 +1325,1254 @@ mod tests {
         let block = builder.create_block();
         assert_eq!(block.0, 0);
     }
+
+    #[test]
+    fn test_ir_function_id() {
+        let func = IRFunction {
+            id: IRFunctionId(3),
+            name: "f".to_string(),
+            params: Vec::new(),
+            return_type: TypeId(0),
+            body: Vec::new(),
+            span: Span::new(chim_span::FileId(0), 0, 0, 0, 0),
+            is_pub: false,
+            is_extern: false,
+            is_unsafe: false,
+            inline_hint: InlineHint::None,
+            is_cold: false,
+        };
+        assert_eq!(func.id, IRFunctionId(3));
+        assert_eq!(func.id.0, 3);
+    }
+
+    fn test_span() -> Span {
+        Span::new(chim_span::FileId(0), 0, 0, 0, 0)
+    }
+
+    fn test_path_type(name: &str) -> chim_ast::Type {
+        chim_ast::Type {
+            kind: Box::new(chim_ast::TypeKind::Path(chim_ast::Path {
+                segments: vec![chim_ast::PathSegment {
+                    ident: Arc::from(name),
+                    args: Vec::new(),
+                    span: test_span(),
+                }],
+                span: test_span(),
+            })),
+            span: test_span(),
+        }
+    }
+
+    fn test_expr(kind: chim_ast::ExprKind) -> chim_ast::Expr {
+        chim_ast::Expr {
+            kind: Box::new(kind),
+            span: test_span(),
+            ty: None,
+        }
+    }
+
+    fn test_param(name: &str, ty: chim_ast::Type) -> chim_ast::Param {
+        chim_ast::Param {
+            name: Arc::from(name),
+            ty,
+            span: test_span(),
+            is_mut: false,
+            is_ref: false,
+        }
+    }
+
+    fn test_function(name: &str, params: Vec<chim_ast::Param>, body: chim_ast::Expr) -> chim_ast::Item {
+        chim_ast::Item::Function(chim_ast::Function {
+            name: Arc::from(name),
+            params,
+            return_type: Some(test_path_type("int")),
+            body: vec![chim_ast::Stmt {
+                kind: chim_ast::StmtKind::Expr(body),
+                span: test_span(),
+            }],
+            span: test_span(),
+            is_pub: false,
+            is_async: false,
+            lifetimes: Vec::new(),
+            generics: Vec::new(),
+            where_clauses: Vec::new(),
+            attributes: Vec::new(),
+        })
+    }
+
+    fn test_point_struct() -> chim_ast::Item {
+        let field = |name: &str| chim_ast::Field {
+            name: Arc::from(name),
+            ty: test_path_type("int"),
+            span: test_span(),
+            is_pub: false,
+            attributes: Vec::new(),
+        };
+        chim_ast::Item::Struct(chim_ast::Struct {
+            name: Arc::from("Point"),
+            fields: vec![field("x"), field("y")],
+            span: test_span(),
+            is_pub: false,
+            generics: Vec::new(),
+            where_clauses: Vec::new(),
+            attributes: Vec::new(),
+        })
+    }
+
+    fn lower(items: Vec<chim_ast::Item>) -> IRModule {
+        let program = chim_ast::Program { items, span: test_span() };
+        let analyzed = chim_semantic::SemanticAnalyzer::new().analyze(&program).unwrap();
+        generate_ir(&program, &analyzed)
+    }
+
+    #[test]
+    fn test_field_access_emits_gep_with_field_index() {
+        let access = test_expr(chim_ast::ExprKind::FieldAccess(chim_ast::FieldAccessExpr {
+            expr: Box::new(test_expr(chim_ast::ExprKind::Identifier(Arc::from("p")))),
+            field: Arc::from("y"),
+        }));
+        let module = lower(vec![
+            test_point_struct(),
+            test_function("get_y", vec![test_param("p", test_path_type("Point"))], access),
+        ]);
+
+        let insts = &module.functions[0].body[0].instructions;
+        let gep = insts.iter().position(|inst| matches!(inst, IRInst::GetElementPtr { .. })).unwrap();
+        match (&insts[gep - 1], &insts[gep], &insts[gep + 1]) {
+            (IRInst::Const { dest, value, .. }, IRInst::GetElementPtr { dest: ptr, indices, .. }, IRInst::Load { src, .. }) => {
+                assert_eq!(*value, IRConstant::Int(1));
+                assert_eq!(indices.as_slice(), &[*dest]);
+                assert_eq!(src, ptr);
+            }
+            other => panic!("unexpected lowering {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_unknown_field_is_reported() {
+        let program = |field: &str| {
+            let access = test_expr(chim_ast::ExprKind::FieldAccess(chim_ast::FieldAccessExpr {
+                expr: Box::new(test_expr(chim_ast::ExprKind::Identifier(Arc::from("p")))),
+                field: Arc::from(field),
+            }));
+            chim_ast::Program {
+                items: vec![
+                    test_point_struct(),
+                    test_function("get", vec![test_param("p", test_path_type("Point"))], access),
+                ],
+                span: test_span(),
+            }
+        };
+        // The analyzer rejects `p.z` itself, so lower it against the analysis of `p.x`.
+        let analyzed = chim_semantic::SemanticAnalyzer::new().analyze(&program("x")).unwrap();
+        let mut generator = IRGenerator::new(&analyzed);
+        let module = generator.generate_module(&program("z"));
+
+        let errors = generator.take_errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message(), "no field `z` on struct `Point`");
+        let insts = &module.functions[0].body[0].instructions;
+        assert!(!insts.iter().any(|inst| matches!(inst, IRInst::GetElementPtr { .. })));
+    }
+
+    #[test]
+    fn test_index_emits_gep_with_index_value() {
+        let index = test_expr(chim_ast::ExprKind::Index(chim_ast::IndexExpr {
+            expr: Box::new(test_expr(chim_ast::ExprKind::Identifier(Arc::from("a")))),
+            index: Box::new(test_expr(chim_ast::ExprKind::Identifier(Arc::from("i")))),
+        }));
+        let module = lower(vec![test_function(
+            "get",
+            vec![test_param("a", test_path_type("Array")), test_param("i", test_path_type("int"))],
+            index,
+        )]);
+
+        // The access itself sits in the block reached once the bounds check passes.
+        let insts = module.functions[0].body.iter()
+            .map(|block| &block.instructions)
+            .find(|insts| insts.iter().any(|inst| matches!(inst, IRInst::GetElementPtr { .. })))
+            .unwrap();
+        let gep = insts.iter().position(|inst| matches!(inst, IRInst::GetElementPtr { .. })).unwrap();
+        match (&insts[gep], &insts[gep + 1]) {
+            (IRInst::GetElementPtr { dest: ptr, ptr: base, indices, .. }, IRInst::Load { src, .. }) => {
+                assert_eq!(*base, ValueId(0));
+                assert_eq!(indices.as_slice(), &[ValueId(1)]);
+                assert_eq!(src, ptr);
+            }
+            other => panic!("unexpected lowering {:?}", other),
+        }
+    }
+
+    fn lower_index(wrap_in_unsafe: bool) -> IRModule {
+        let mut index = test_expr(chim_ast::ExprKind::Index(chim_ast::IndexExpr {
+            expr: Box::new(test_expr(chim_ast::ExprKind::Identifier(Arc::from("a")))),
+            index: Box::new(test_expr(chim_ast::ExprKind::Identifier(Arc::from("i")))),
+        }));
+        if wrap_in_unsafe {
+            index = test_expr(chim_ast::ExprKind::Unsafe(chim_ast::UnsafeExpr { body: Box::new(index) }));
+        }
+        lower(vec![test_function(
+            "get",
+            vec![test_param("a", test_path_type("Array")), test_param("i", test_path_type("int"))],
+            index,
+        )])
+    }
+
+    #[test]
+    fn test_index_is_bounds_checked() {
+        let module = lower_index(false);
+        let body = &module.functions[0].body;
+
+        let entry = &body[0];
+        let (ok, fail) = match entry.terminator {
+            Terminator::ConditionalBranch { condition, true_block, false_block } => {
+                assert!(entry.instructions.iter().any(|inst| matches!(
+                    inst,
+                    IRInst::Binary { dest, op: BinaryOp::ULt, left: ValueId(1), .. } if *dest == condition
+                )));
+                (true_block, false_block)
+            }
+            ref other => panic!("expected bounds check, got {:?}", other),
+        };
+        assert!(matches!(body[fail.0].terminator, Terminator::Unreachable));
+        assert!(body[fail.0].instructions.iter().any(|inst| matches!(inst, IRInst::Call { .. })));
+        assert!(body[ok.0].instructions.iter().any(|inst| matches!(inst, IRInst::GetElementPtr { .. })));
+        assert_eq!(runtime_functions(&module), ["slice::len", "panic_bounds_check"]);
+    }
+
+    #[test]
+    fn test_index_in_unsafe_is_unchecked() {
+        let module = lower_index(true);
+        let body = &module.functions[0].body;
+
+        assert_eq!(body.len(), 1);
+        assert!(body[0].instructions.iter().any(|inst| matches!(inst, IRInst::GetElementPtr { .. })));
+        assert!(!body[0].instructions.iter().any(|inst| matches!(inst, IRInst::Binary { op: BinaryOp::ULt, .. })));
+        assert!(runtime_functions(&module).is_empty());
+    }
+
+    /// The names of the runtime functions `module` declares.
+    fn runtime_functions(module: &IRModule) -> Vec<&str> {
+        module.functions.iter().filter(|function| function.is_extern).map(|function| function.name.as_str()).collect()
+    }
+
+    /// Lowers `fn sum(x: int, y: int) -> int { body }` with `mode`.
+    fn lower_with_overflow(body: chim_ast::Expr, mode: OverflowMode) -> IRModule {
+        let params = vec![test_param("x", test_path_type("int")), test_param("y", test_path_type("int"))];
+        let program = chim_ast::Program { items: vec![test_function("sum", params, body)], span: test_span() };
+        let analyzed = chim_semantic::SemanticAnalyzer::new().analyze(&program).unwrap();
+        generate_ir_with_overflow(&program, &analyzed, mode)
+    }
+
+    #[test]
+    fn test_checked_add_branches_to_overflow_panic() {
+        let module = lower_with_overflow(test_binary(chim_ast::BinOp::Add), OverflowMode::for_build(true));
+        let body = &module.functions[0].body;
+
+        let entry = &body[0];
+        assert!(entry.instructions.iter().any(|inst| matches!(inst, IRInst::Binary { op: BinaryOp::Add, .. })));
+        let (fail, ok) = match entry.terminator {
+            Terminator::ConditionalBranch { condition, true_block, false_block } => {
+                assert!(entry.instructions.iter().any(|inst| matches!(
+                    inst,
+                    IRInst::Call { dest: Some(dest), .. } if *dest == condition
+                )));
+                (true_block, false_block)
+            }
+            ref other => panic!("expected overflow check, got {:?}", other),
+        };
+        assert!(matches!(body[fail.0].terminator, Terminator::Unreachable));
+        assert!(!matches!(body[ok.0].terminator, Terminator::Unreachable));
+        assert_eq!(runtime_functions(&module), ["i32::add_overflows", "panic_overflow"]);
+        let check = &module.functions[1];
+        assert_eq!(check.params.len(), 2);
+        assert_ne!(check.return_type, check.params[0].ty);
+    }
+
+    #[test]
+    fn test_release_add_is_plain_binary() {
+        let module = lower_with_overflow(test_binary(chim_ast::BinOp::Add), OverflowMode::for_build(false));
+        let body = &module.functions[0].body;
+
+        assert_eq!(body.len(), 1);
+        let adds = body[0].instructions.iter().filter(|inst| matches!(inst, IRInst::Binary { op: BinaryOp::Add, .. }));
+        assert_eq!(adds.count(), 1);
+        assert!(!body[0].instructions.iter().any(|inst| matches!(inst, IRInst::Call { .. })));
+        assert!(runtime_functions(&module).is_empty());
+    }
+
+    #[test]
+    fn test_overflow_intrinsics_ignore_build_mode() {
+        let call = |name: &str| test_expr(chim_ast::ExprKind::Call(chim_ast::CallExpr {
+            func: Box::new(test_ident(name)),
+            args: smallvec::smallvec![Box::new(test_ident("x")), Box::new(test_ident("y"))],
+        }));
+
+        let wrapping = lower_with_overflow(call("wrapping_add"), OverflowMode::Checked);
+        assert_eq!(wrapping.functions[0].body.len(), 1);
+        assert!(runtime_functions(&wrapping).is_empty());
+
+        let saturating = lower_with_overflow(call("saturating_mul"), OverflowMode::Wrapping);
+        assert_eq!(saturating.functions[0].body.len(), 1);
+        assert_eq!(runtime_functions(&saturating), ["i32::saturating_mul"]);
+        let saturating_mul = &saturating.functions[1];
+        assert_eq!(saturating_mul.return_type, saturating.functions[0].return_type);
+    }
+
+    #[test]
+    fn test_trait_path_call_calls_impl_method() {
+        let chim_ast::Item::Function(double) = test_function("double", vec![test_param("x", test_path_type("int"))], test_ident("x")) else {
+            unreachable!()
+        };
+        let imp = chim_ast::Item::Impl(chim_ast::Impl {
+            trait_name: Some(test_path_type("Double")),
+            type_name: test_path_type("int"),
+            items: vec![chim_ast::ImplItem::Function(double)],
+            span: test_span(),
+            generics: Vec::new(),
+            where_clauses: Vec::new(),
+            attributes: Vec::new(),
+        });
+        let path = chim_ast::Path {
+            segments: ["Double", "double"].iter().map(|name| chim_ast::PathSegment {
+                ident: Arc::from(*name),
+                args: Vec::new(),
+                span: test_span(),
+            }).collect(),
+            span: test_span(),
+        };
+        let mut receiver = test_ident("n");
+        receiver.ty = Some(test_path_type("int"));
+        let call = test_expr(chim_ast::ExprKind::Call(chim_ast::CallExpr {
+            func: Box::new(test_expr(chim_ast::ExprKind::Path(path))),
+            args: smallvec::smallvec![Box::new(receiver)],
+        }));
+        let module = lower(vec![test_function("main", vec![test_param("n", test_path_type("int"))], call), imp]);
+
+        let callee = module.functions[0].body[0].instructions.iter().find_map(|inst| match inst {
+            IRInst::FunctionAddr { function, .. } => Some(*function),
+            _ => None,
+        }).expect("the call takes the method's address");
+        // Declared by the call, then filled in when the impl is lowered.
+        assert_eq!(module.functions[callee.0].name, "<int as Double>::double");
+        assert!(!module.functions[callee.0].body.is_empty());
+        assert_eq!(module.functions.len(), 2);
+    }
+
+    #[test]
+    fn test_float_add_is_not_overflow_checked() {
+        let float = |name: &str| {
+            let mut operand = test_ident(name);
+            operand.ty = Some(test_path_type("f64"));
+            operand
+        };
+        let body = test_expr(chim_ast::ExprKind::Binary(chim_ast::BinaryExpr {
+            left: Box::new(float("x")),
+            op: chim_ast::BinOp::Add,
+            right: Box::new(float("y")),
+        }));
+        let module = lower_with_overflow(body, OverflowMode::Checked);
+
+        assert_eq!(module.functions[0].body.len(), 1);
+        assert!(module.functions[0].body[0].instructions.iter().any(|inst| matches!(inst, IRInst::Binary { op: BinaryOp::FAdd, .. })));
+        assert!(runtime_functions(&module).is_empty());
+    }
+
+    #[test]
+    fn test_user_function_shadows_overflow_intrinsic() {
+        let call = test_expr(chim_ast::ExprKind::Call(chim_ast::CallExpr {
+            func: Box::new(test_ident("wrapping_add")),
+            args: smallvec::smallvec![Box::new(test_ident("x")), Box::new(test_ident("y"))],
+        }));
+        let params = || vec![test_param("x", test_path_type("int")), test_param("y", test_path_type("int"))];
+        let module = lower(vec![
+            test_function("wrapping_add", params(), test_ident("x")),
+            test_function("sum", params(), call),
+        ]);
+
+        let sum = &module.functions[1];
+        assert!(sum.body[0].instructions.iter().any(|inst| matches!(inst, IRInst::Call { .. })));
+        assert!(!sum.body[0].instructions.iter().any(|inst| matches!(inst, IRInst::Binary { op: BinaryOp::Add, .. })));
+    }
+
+    #[test]
+    fn test_identical_string_literals_share_global() {
+        let string_lit = || test_expr(chim_ast::ExprKind::Literal(chim_ast::Literal {
+            kind: chim_ast::LiteralKind::String(Arc::from("hello")),
+            span: test_span(),
+        }));
+        let body = test_expr(chim_ast::ExprKind::Binary(chim_ast::BinaryExpr {
+            left: Box::new(string_lit()),
+            op: chim_ast::BinOp::Add,
+            right: Box::new(string_lit()),
+        }));
+        let module = lower(vec![test_function("greet", Vec::new(), body)]);
+
+        assert_eq!(module.globals.len(), 1);
+        assert_eq!(module.globals[0].initializer, Some(IRConstant::String("hello".to_string())));
+        let addrs: Vec<usize> = module.functions[0].body[0].instructions.iter()
+            .filter_map(|inst| match inst {
+                IRInst::GlobalAddr { global, .. } => Some(*global),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(addrs, vec![0, 0]);
+    }
+
+    #[test]
+    fn test_intern_string_dedup() {
+        let mut module = IRModule {
+            functions: Vec::new(),
+            globals: Vec::new(),
+            structs: Vec::new(),
+            enums: Vec::new(),
+        };
+        let a = module.intern_string("a", TypeId(0));
+        let b = module.intern_string("b", TypeId(0));
+        assert_eq!(module.intern_string("a", TypeId(0)), a);
+        assert_ne!(a, b);
+        assert_ne!(module.intern_string("a", TypeId(1)), a);
+        assert_eq!(module.globals.len(), 3);
+    }
+
+    #[test]
+    fn test_inst_defs_and_uses() {
+        let binary = IRInst::Binary {
+            dest: VarId(2),
+            op: BinaryOp::Add,
+            left: ValueId(0),
+            right: ValueId(1),
+            ty: TypeId(0),
+            span: test_span(),
+        };
+        assert_eq!(binary.defined_value(), Some(VarId(2)));
+        assert_eq!(binary.used_values().as_slice(), &[ValueId(0), ValueId(1)]);
+
+        let call = IRInst::Call {
+            dest: None,
+            func: ValueId(3),
+            args: SmallVec::from_slice(&[ValueId(4), ValueId(5)]),
+            ty: TypeId(0),
+            span: test_span(),
+        };
+        assert_eq!(call.defined_value(), None);
+        assert_eq!(call.used_values().as_slice(), &[ValueId(3), ValueId(4), ValueId(5)]);
+
+        let store = IRInst::Store {
+            dest: ValueId(6),
+            src: ValueId(7),
+            ty: TypeId(0),
+            span: test_span(),
+        };
+        assert_eq!(store.defined_value(), None);
+        assert_eq!(store.used_values().as_slice(), &[ValueId(6), ValueId(7)]);
+    }
+
+    #[test]
+    fn test_terminator_successors() {
+        assert_eq!(Terminator::Branch(BlockId(1)).successors().as_slice(), &[BlockId(1)]);
+        assert!(Terminator::Return(None).successors().is_empty());
+
+        let cond = Terminator::ConditionalBranch {
+            condition: ValueId(0),
+            true_block: BlockId(1),
+            false_block: BlockId(2),
+        };
+        assert_eq!(cond.successors().as_slice(), &[BlockId(1), BlockId(2)]);
+
+        let switch = Terminator::Switch {
+            value: ValueId(0),
+            default_block: BlockId(3),
+            cases: vec![(ValueId(1), BlockId(4)), (ValueId(2), BlockId(5))],
+        };
+        assert_eq!(switch.successors().as_slice(), &[BlockId(3), BlockId(4), BlockId(5)]);
+
+        let invoke = Terminator::Invoke {
+            func: ValueId(0),
+            args: SmallVec::new(),
+            normal_block: BlockId(6),
+            unwind_block: BlockId(7),
+        };
+        assert_eq!(invoke.successors().as_slice(), &[BlockId(6), BlockId(7)]);
+    }
+
+    fn test_ident(name: &str) -> chim_ast::Expr {
+        test_expr(chim_ast::ExprKind::Identifier(Arc::from(name)))
+    }
+
+    fn test_block(expr: chim_ast::Expr) -> chim_ast::BlockExpr {
+        chim_ast::BlockExpr {
+            label: None,
+            stmts: vec![chim_ast::Stmt {
+                kind: chim_ast::StmtKind::Expr(expr),
+                span: test_span(),
+            }],
+            ty: None,
+        }
+    }
+
+    fn lower_if(then_expr: chim_ast::Expr, else_expr: Option<chim_ast::Expr>) -> IRFunction {
+        let if_expr = test_expr(chim_ast::ExprKind::If(chim_ast::IfExpr {
+            condition: Box::new(test_ident("c")),
+            then_branch: test_block(then_expr),
+            else_branch: else_expr.map(|expr| Box::new(test_expr(chim_ast::ExprKind::Block(test_block(expr))))),
+        }));
+        let params = vec![
+            test_param("c", test_path_type("bool")),
+            test_param("x", test_path_type("int")),
+            test_param("y", test_path_type("int")),
+        ];
+        lower(vec![test_function("choose", params, if_expr)]).functions.remove(0)
+    }
+
+    fn test_sum(left: &str, right: &str) -> chim_ast::Expr {
+        test_expr(chim_ast::ExprKind::Binary(chim_ast::BinaryExpr {
+            left: Box::new(test_ident(left)),
+            op: chim_ast::BinOp::Add,
+            right: Box::new(test_ident(right)),
+        }))
+    }
+
+    #[test]
+    fn test_if_else_lowers_to_branches_and_phi() {
+        let func = lower_if(test_sum("x", "y"), Some(test_sum("y", "x")));
+
+        let (then_block, else_block) = match &func.body[0].terminator {
+            Terminator::ConditionalBranch { condition, true_block, false_block } => {
+                assert_eq!(*condition, ValueId(0));
+                (*true_block, *false_block)
+            }
+            other => panic!("expected conditional branch, got {:?}", other),
+        };
+        let merge = match &func.body[then_block.0].terminator {
+            Terminator::Branch(target) => *target,
+            other => panic!("expected branch to merge block, got {:?}", other),
+        };
+        assert!(matches!(func.body[else_block.0].terminator, Terminator::Branch(target) if target == merge));
+        assert_eq!(func.body[merge.0].predecessors, vec![then_block, else_block]);
+
+        let phi = match func.body[merge.0].instructions.first() {
+            Some(IRInst::Phi { dest, incoming, .. }) => {
+                let preds: Vec<BlockId> = incoming.iter().map(|(block, _)| *block).collect();
+                assert_eq!(preds, vec![then_block, else_block]);
+                *dest
+            }
+            other => panic!("expected phi, got {:?}", other),
+        };
+        // The `if` is the function's tail, so the phi is its return value.
+        assert_eq!(func.body[merge.0].terminator, Terminator::Return(Some(phi)));
+    }
+
+    #[test]
+    fn test_if_else_with_simple_arms_lowers_to_select() {
+        let func = lower_if(test_ident("x"), Some(test_ident("y")));
+
+        assert_eq!(func.body.len(), 1);
+        assert!(func.body[0].instructions.iter().any(|inst| matches!(
+            inst,
+            IRInst::Select { condition: ValueId(0), true_val: ValueId(1), false_val: ValueId(2), .. }
+        )));
+    }
+
+    #[test]
+    fn test_if_without_else_falls_through_to_merge() {
+        let func = lower_if(test_sum("x", "y"), None);
+
+        let (then_block, else_block) = match &func.body[0].terminator {
+            Terminator::ConditionalBranch { true_block, false_block, .. } => (*true_block, *false_block),
+            other => panic!("expected conditional branch, got {:?}", other),
+        };
+        assert!(matches!(func.body[then_block.0].terminator, Terminator::Branch(target) if target == else_block));
+        assert!(!func.body[else_block.0].instructions.iter().any(|inst| matches!(inst, IRInst::Phi { .. })));
+    }
+
+    fn test_binary(op: chim_ast::BinOp) -> chim_ast::Expr {
+        test_expr(chim_ast::ExprKind::Binary(chim_ast::BinaryExpr {
+            left: Box::new(test_ident("x")),
+            op,
+            right: Box::new(test_ident("y")),
+        }))
+    }
+
+    fn test_stmt(kind: chim_ast::StmtKind) -> chim_ast::Stmt {
+        chim_ast::Stmt { kind, span: test_span() }
+    }
+
+    /// Lowers a function over `x` and `y` whose body is `stmts` followed by
+    /// `x + y`, returning its entry block's binary ops and terminator.
+    fn lower_with_defer(stmts: Vec<chim_ast::Stmt>) -> (Vec<(BinaryOp, ValueId)>, Terminator) {
+        let params = vec![test_param("x", test_path_type("int")), test_param("y", test_path_type("int"))];
+        let mut item = test_function("work", params, test_binary(chim_ast::BinOp::Add));
+        if let chim_ast::Item::Function(func) = &mut item {
+            func.body.splice(0..0, stmts);
+        }
+        let func = lower(vec![item]).functions.remove(0);
+        let ops = func.body[0].instructions.iter()
+            .filter_map(|inst| match inst {
+                IRInst::Binary { op, dest, .. } => Some((*op, *dest)),
+                _ => None,
+            })
+            .collect();
+        (ops, func.body[0].terminator.clone())
+    }
+
+    #[test]
+    fn test_defer_runs_on_normal_exit() {
+        let defer = |op| test_stmt(chim_ast::StmtKind::Defer(vec![test_stmt(chim_ast::StmtKind::Expr(test_binary(op)))]));
+        let (ops, terminator) = lower_with_defer(vec![defer(chim_ast::BinOp::Mul), defer(chim_ast::BinOp::Sub)]);
+
+        // The body runs first, then the deferred blocks, last registered first;
+        // the tail's value is what the function returns.
+        let order: Vec<BinaryOp> = ops.iter().map(|(op, _)| *op).collect();
+        assert_eq!(order, vec![BinaryOp::Add, BinaryOp::Sub, BinaryOp::Mul]);
+        assert_eq!(terminator, Terminator::Return(Some(ops[0].1)));
+    }
+
+    #[test]
+    fn test_defer_runs_on_early_return() {
+        let defer = |op| test_stmt(chim_ast::StmtKind::Defer(vec![test_stmt(chim_ast::StmtKind::Expr(test_binary(op)))]));
+        let early_return = test_expr(chim_ast::ExprKind::Block(chim_ast::BlockExpr {
+            label: None,
+            stmts: vec![
+                defer(chim_ast::BinOp::Sub),
+                test_stmt(chim_ast::StmtKind::Return(Some(Box::new(test_binary(chim_ast::BinOp::Div))))),
+            ],
+            ty: None,
+        }));
+        let (ops, terminator) = lower_with_defer(vec![
+            defer(chim_ast::BinOp::Mul),
+            test_stmt(chim_ast::StmtKind::Expr(early_return)),
+        ]);
+
+        // Both enclosing scopes are left, the inner block's first, before the
+        // return; nothing after it is reachable from the entry block.
+        let order: Vec<BinaryOp> = ops.iter().map(|(op, _)| *op).collect();
+        assert_eq!(order, vec![BinaryOp::Div, BinaryOp::Sub, BinaryOp::Mul]);
+        assert!(matches!(terminator, Terminator::Return(Some(_))));
+    }
+
+    /// Lowers a unit function over `c: bool` and `n: int` whose body is `stmts`.
+    fn lower_loop(stmts: Vec<chim_ast::Stmt>) -> IRFunction {
+        let params = vec![test_param("c", test_path_type("bool")), test_param("n", test_path_type("int"))];
+        let mut item = test_function("spin", params, test_ident("n"));
+        if let chim_ast::Item::Function(func) = &mut item {
+            func.return_type = None;
+            func.body = stmts;
+        }
+        lower(vec![item]).functions.remove(0)
+    }
+
+    fn test_break(label: Option<&str>) -> chim_ast::Stmt {
+        let label = label.map(|name| chim_ast::Label { name: Arc::from(name), span: test_span() });
+        test_stmt(chim_ast::StmtKind::Expr(test_expr(chim_ast::ExprKind::Break(label, None))))
+    }
+
+    fn branch_target(block: &BasicBlock) -> BlockId {
+        match block.terminator {
+            Terminator::Branch(target) => target,
+            ref other => panic!("expected a branch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_while_with_break() {
+        let func = lower_loop(vec![test_stmt(chim_ast::StmtKind::While(chim_ast::WhileStmt {
+            label: None,
+            condition: test_ident("c"),
+            body: vec![test_break(None)],
+            span: test_span(),
+        }))]);
+
+        let header = branch_target(&func.body[0]);
+        let (body, exit) = match func.body[header.0].terminator {
+            Terminator::ConditionalBranch { true_block, false_block, .. } => (true_block, false_block),
+            ref other => panic!("expected the loop condition, got {:?}", other),
+        };
+        // `break` leaves for the exit block instead of looping back.
+        assert_eq!(branch_target(&func.body[body.0]), exit);
+        assert!(matches!(func.body[exit.0].terminator, Terminator::Return(None)));
+        assert_eq!(func.body[exit.0].predecessors, vec![header, body]);
+    }
+
+    #[test]
+    fn test_for_over_range() {
+        let range = test_expr(chim_ast::ExprKind::Range(chim_ast::RangeExpr {
+            start: Some(Box::new(test_expr(chim_ast::ExprKind::Literal(chim_ast::Literal {
+                kind: chim_ast::LiteralKind::Int(0),
+                span: test_span(),
+            })))),
+            end: Some(Box::new(test_ident("n"))),
+            inclusive: false,
+        }));
+        let func = lower_loop(vec![test_stmt(chim_ast::StmtKind::For(chim_ast::ForStmt {
+            label: None,
+            pattern: chim_ast::Pattern {
+                kind: chim_ast::PatternKind::Identifier(
+                    Arc::from("i"),
+                    chim_ast::BindingMode { is_mut: false, by_ref: false },
+                ),
+                span: test_span(),
+            },
+            iterable: range,
+            body: vec![test_stmt(chim_ast::StmtKind::Expr(test_sum("i", "n")))],
+            span: test_span(),
+        }))]);
+
+        let header = branch_target(&func.body[0]);
+        let next = func.body[header.0].instructions.iter().find_map(|inst| match inst {
+            IRInst::IteratorNext { dest, .. } => Some(*dest),
+            _ => None,
+        }).expect("the header asks the iterator for the next item");
+        let (body, exit) = match func.body[header.0].terminator {
+            Terminator::ConditionalBranch { true_block, false_block, .. } => (true_block, false_block),
+            ref other => panic!("expected a test for the next item, got {:?}", other),
+        };
+
+        // The body binds `i` to the item and loops back to the header.
+        let item = func.body[body.0].instructions.iter().find_map(|inst| match inst {
+            IRInst::ExtractValue { dest, aggregate, indices, .. } if *aggregate == next && indices[..] == [1] => Some(*dest),
+            _ => None,
+        }).expect("the body extracts the item");
+        assert!(func.body[body.0].instructions.iter().any(|inst| {
+            matches!(inst, IRInst::Binary { op: BinaryOp::Add, left, .. } if *left == item)
+        }));
+        assert_eq!(branch_target(&func.body[body.0]), header);
+        assert!(matches!(func.body[exit.0].terminator, Terminator::Return(None)));
+    }
+
+    #[test]
+    fn test_for_destructures_tuple_items() {
+        let binding = |name: &str| chim_ast::Pattern {
+            kind: chim_ast::PatternKind::Identifier(Arc::from(name), chim_ast::BindingMode::default()),
+            span: test_span(),
+        };
+        let pair = chim_ast::Type {
+            kind: Box::new(chim_ast::TypeKind::Tuple(vec![test_path_type("int"), test_path_type("int")])),
+            span: test_span(),
+        };
+        let mut pairs = test_ident("n");
+        pairs.ty = Some(chim_ast::Type { kind: Box::new(chim_ast::TypeKind::Slice(Box::new(pair))), span: test_span() });
+        let func = lower_loop(vec![test_stmt(chim_ast::StmtKind::For(chim_ast::ForStmt {
+            label: None,
+            pattern: chim_ast::Pattern {
+                kind: chim_ast::PatternKind::Tuple(vec![binding("a"), binding("b")]),
+                span: test_span(),
+            },
+            iterable: pairs,
+            body: vec![test_stmt(chim_ast::StmtKind::Expr(test_sum("a", "b")))],
+            span: test_span(),
+        }))]);
+
+        let header = branch_target(&func.body[0]);
+        let Terminator::ConditionalBranch { true_block: body, .. } = func.body[header.0].terminator else {
+            panic!("expected a test for the next item");
+        };
+        let extracts: Vec<(ValueId, ValueId, u32)> = func.body[body.0].instructions.iter().filter_map(|inst| match inst {
+            IRInst::ExtractValue { dest, aggregate, indices, .. } => Some((*dest, *aggregate, indices[0])),
+            _ => None,
+        }).collect();
+        // The item, then its two elements taken apart for `a` and `b`.
+        let [(item, _, 1), (a, first, 0), (b, second, 1)] = extracts[..] else {
+            panic!("expected the item and its two elements, got {:?}", extracts);
+        };
+        assert_eq!((first, second), (item, item));
+        assert!(func.body[body.0].instructions.iter().any(|inst| {
+            matches!(inst, IRInst::Binary { op: BinaryOp::Add, left, right, .. } if (*left, *right) == (a, b))
+        }));
+    }
+
+    #[test]
+    fn test_labeled_break_leaves_outer_loop() {
+        let inner = test_stmt(chim_ast::StmtKind::Loop(chim_ast::LoopStmt {
+            label: None,
+            body: vec![test_break(Some("outer"))],
+            span: test_span(),
+        }));
+        let func = lower_loop(vec![test_stmt(chim_ast::StmtKind::Loop(chim_ast::LoopStmt {
+            label: Some(Arc::from("outer")),
+            body: vec![inner],
+            span: test_span(),
+        }))]);
+
+        let outer_body = branch_target(&func.body[0]);
+        let inner_body = branch_target(&func.body[outer_body.0]);
+        let target = branch_target(&func.body[inner_body.0]);
+        // The break jumps to the outer loop's exit, where the function returns.
+        assert!(matches!(func.body[target.0].terminator, Terminator::Return(None)));
+        // The inner loop's exit, which would continue the outer loop, is unreachable.
+        let inner_exit = func.body.iter()
+            .skip(1)
+            .find(|block| matches!(block.terminator, Terminator::Branch(to) if to == outer_body))
+            .expect("the inner loop's exit continues the outer loop");
+        assert!(inner_exit.predecessors.is_empty());
+    }
+
+    fn lower_logical(op: chim_ast::BinOp) -> IRFunction {
+        let body = test_expr(chim_ast::ExprKind::Binary(chim_ast::BinaryExpr {
+            left: Box::new(test_ident("a")),
+            op,
+            right: Box::new(test_ident("b")),
+        }));
+        let params = vec![test_param("a", test_path_type("bool")), test_param("b", test_path_type("bool"))];
+        lower(vec![test_function("logic", params, body)]).functions.remove(0)
+    }
+
+    #[test]
+    fn test_collect_lowers_to_push_loop() {
+        let mapped = test_expr(chim_ast::ExprKind::Map(chim_ast::MapExpr {
+            iterator: Box::new(test_ident("xs")),
+            mapper: Box::new(test_ident("f")),
+        }));
+        let mut collect = test_expr(chim_ast::ExprKind::Collect(chim_ast::CollectExpr { iterator: Box::new(mapped) }));
+        let mut vec_of_int = test_path_type("Vec");
+        if let chim_ast::TypeKind::Path(path) = &mut *vec_of_int.kind {
+            path.segments[0].args.push(chim_ast::GenericArg {
+                kind: chim_ast::GenericArgKind::Type(test_path_type("int")),
+                span: test_span(),
+            });
+        }
+        collect.ty = Some(vec_of_int);
+        let params = vec![test_param("xs", test_path_type("int")), test_param("f", test_path_type("int"))];
+        let module = lower(vec![test_function("squares", params, collect)]);
+        let func = &module.functions[0];
+        let function_named = |value: ValueId, name: &str| {
+            func.body.iter().flat_map(|block| &block.instructions).any(|inst| matches!(
+                inst,
+                IRInst::FunctionAddr { dest, function, .. } if dest.0 == value.0 && module.functions[function.0].name == name
+            ))
+        };
+
+        let entry = &func.body[0];
+        assert!(entry.instructions.iter().any(|inst| matches!(
+            inst,
+            IRInst::IteratorMap { iterator: ValueId(0), mapper: ValueId(1), .. }
+        )));
+        let buffer = entry.instructions.iter().find_map(|inst| match inst {
+            IRInst::Call { dest: Some(dest), func: callee, .. } if function_named(*callee, "Vec::new") => Some(*dest),
+            _ => None,
+        }).expect("buffer is created before the loop");
+
+        let header = match entry.terminator {
+            Terminator::Branch(header) => header,
+            ref other => panic!("expected branch to loop header, got {:?}", other),
+        };
+        assert!(matches!(func.body[header.0].instructions[0], IRInst::IteratorNext { .. }));
+        let (body, exit) = match func.body[header.0].terminator {
+            Terminator::ConditionalBranch { true_block, false_block, .. } => (true_block, false_block),
+            ref other => panic!("expected loop test, got {:?}", other),
+        };
+
+        let body = &func.body[body.0];
+        assert_eq!(body.terminator, Terminator::Branch(header));
+        assert!(body.instructions.iter().any(|inst| matches!(
+            inst,
+            IRInst::Call { dest: None, func: callee, args, .. } if function_named(*callee, "Vec::push") && args[0] == ValueId(buffer.0)
+        )));
+        assert_eq!(func.body[header.0].predecessors, vec![BlockId(0), body.id]);
+        assert_eq!(func.body[exit.0].predecessors, vec![header]);
+        // `Vec::push` takes the buffer and an `int`, the element type of `Vec<int>`.
+        let push = module.functions.iter().find(|function| function.name == "Vec::push").unwrap();
+        assert_eq!(push.params[1].ty, func.params[0].ty);
+    }
+
+    #[test]
+    fn test_logical_and_guards_right_operand() {
+        let func = lower_logical(chim_ast::BinOp::And);
+
+        let (rhs, merge) = match &func.body[0].terminator {
+            Terminator::ConditionalBranch { condition, true_block, false_block } => {
+                assert_eq!(*condition, ValueId(0));
+                (*true_block, *false_block)
+            }
+            other => panic!("expected conditional branch, got {:?}", other),
+        };
+        assert_eq!(func.body[rhs.0].predecessors, vec![BlockId(0)]);
+        assert!(matches!(func.body[rhs.0].terminator, Terminator::Branch(target) if target == merge));
+        assert!(!func.body[0].instructions.iter().any(|inst| matches!(inst, IRInst::Binary { .. })));
+
+        match func.body[merge.0].instructions.first() {
+            Some(IRInst::Phi { incoming, .. }) => {
+                assert_eq!(incoming.as_slice(), &[(BlockId(0), incoming[0].1), (rhs, ValueId(1))]);
+            }
+            other => panic!("expected phi, got {:?}", other),
+        }
+        assert!(func.body[0].instructions.iter().any(|inst| matches!(
+            inst,
+            IRInst::Const { value: IRConstant::Bool(false), .. }
+        )));
+    }
+
+    #[test]
+    fn test_logical_or_skips_right_operand_when_true() {
+        let func = lower_logical(chim_ast::BinOp::Or);
+
+        let (merge, rhs) = match &func.body[0].terminator {
+            Terminator::ConditionalBranch { true_block, false_block, .. } => (*true_block, *false_block),
+            other => panic!("expected conditional branch, got {:?}", other),
+        };
+        assert!(matches!(func.body[rhs.0].terminator, Terminator::Branch(target) if target == merge));
+        assert!(func.body[0].instructions.iter().any(|inst| matches!(
+            inst,
+            IRInst::Const { value: IRConstant::Bool(true), .. }
+        )));
+    }
+
+    fn attributed_function(attributes: Vec<chim_ast::Attribute>) -> IRFunction {
+        let mut item = test_function("f", Vec::new(), test_ident("x"));
+        if let chim_ast::Item::Function(func) = &mut item {
+            func.params.push(test_param("x", test_path_type("int")));
+            func.attributes = attributes;
+        }
+        lower(vec![item]).functions.remove(0)
+    }
+
+    fn test_attribute(name: &str, args: Vec<chim_ast::Expr>) -> chim_ast::Attribute {
+        chim_ast::Attribute {
+            name: Arc::from(name),
+            args: args.into_iter().map(|expr| chim_ast::AttributeArg { expr, span: test_span() }).collect(),
+            span: test_span(),
+        }
+    }
+
+    #[test]
+    fn test_inline_and_cold_attributes_reach_ir() {
+        let func = attributed_function(vec![test_attribute("inline", Vec::new())]);
+        assert_eq!(func.inline_hint, InlineHint::Inline);
+        assert!(!func.is_cold);
+
+        let func = attributed_function(vec![test_attribute("inline", vec![test_ident("never")])]);
+        assert_eq!(func.inline_hint, InlineHint::Never);
+
+        let func = attributed_function(vec![test_attribute("cold", Vec::new())]);
+        assert_eq!(func.inline_hint, InlineHint::None);
+        assert!(func.is_cold);
+    }
+
+    fn ir_function(name: &str, param_count: usize, body: Vec<BasicBlock>) -> IRFunction {
+        IRFunction {
+            id: IRFunctionId(0),
+            name: name.to_string(),
+            params: (0..param_count)
+                .map(|i| IRParam { id: VarId(i), name: format!("p{}", i), ty: TypeId(0) })
+                .collect(),
+            return_type: TypeId(0),
+            body,
+            span: test_span(),
+            is_pub: false,
+            is_extern: false,
+            is_unsafe: false,
+            inline_hint: InlineHint::None,
+            is_cold: false,
+        }
+    }
+
+    fn ir_block(id: usize, instructions: Vec<IRInst>, terminator: Terminator) -> BasicBlock {
+        BasicBlock {
+            id: BlockId(id),
+            instructions,
+            successors: terminator.successors().into_vec(),
+            terminator,
+            predecessors: Vec::new(),
+        }
+    }
+
+    /// `fn add1(x) { return x + 1 }`
+    fn add_one_function() -> IRFunction {
+        ir_function("add1", 1, vec![ir_block(
+            0,
+            vec![
+                IRInst::Const { dest: VarId(1), value: IRConstant::Int(1), ty: TypeId(0), span: test_span() },
+                IRInst::Binary {
+                    dest: VarId(2),
+                    op: BinaryOp::Add,
+                    left: ValueId(0),
+                    right: ValueId(1),
+                    ty: TypeId(0),
+                    span: test_span(),
+                },
+            ],
+            Terminator::Return(Some(ValueId(2))),
+        )])
+    }
+
+    /// `fn caller(a) { return add1(a) }`
+    fn calling_function() -> IRFunction {
+        ir_function("caller", 1, vec![ir_block(
+            0,
+            vec![
+                IRInst::GlobalAddr { dest: VarId(1), global: 0, ty: TypeId(0), span: test_span() },
+                IRInst::Call {
+                    dest: Some(VarId(2)),
+                    func: ValueId(1),
+                    args: SmallVec::from_slice(&[ValueId(0)]),
+                    ty: TypeId(0),
+                    span: test_span(),
+                },
+            ],
+            Terminator::Return(Some(ValueId(2))),
+        )])
+    }
+
+    #[test]
+    fn test_inline_leaf_function() {
+        let mut caller = calling_function();
+        let site = CallSite { block: BlockId(0), index: 1 };
+        assert!(inline_call(&mut caller, site, &add_one_function()));
+
+        assert_eq!(caller.body.len(), 3);
+        assert_eq!(caller.body[0].terminator, Terminator::Branch(BlockId(1)));
+        assert!(!caller.body[0].instructions.iter().any(|inst| matches!(inst, IRInst::Call { .. })));
+
+        let inlined = &caller.body[1];
+        match &inlined.instructions[1] {
+            IRInst::Binary { dest, left, right, .. } => {
+                assert_eq!(*left, ValueId(0));
+                assert_eq!(*right, ValueId(4));
+                assert_eq!(*dest, VarId(5));
+            }
+            other => panic!("expected inlined add, got {:?}", other),
+        }
+        assert_eq!(inlined.terminator, Terminator::Branch(BlockId(2)));
+        assert_eq!(inlined.predecessors, vec![BlockId(0)]);
+
+        let continuation = &caller.body[2];
+        match &continuation.instructions[0] {
+            IRInst::Phi { dest, incoming, .. } => {
+                assert_eq!(*dest, VarId(2));
+                assert_eq!(incoming.as_slice(), &[(BlockId(1), ValueId(5))]);
+            }
+            other => panic!("expected phi for the call result, got {:?}", other),
+        }
+        assert_eq!(continuation.terminator, Terminator::Return(Some(ValueId(2))));
+        assert_eq!(continuation.predecessors, vec![BlockId(1)]);
+
+        for block in &caller.body {
+            for succ in &block.successors {
+                assert!(caller.body[succ.0].predecessors.contains(&block.id));
+            }
+        }
+    }
+
+    #[test]
+    fn test_inline_respects_never_hint() {
+        let mut caller = calling_function();
+        let mut callee = add_one_function();
+        callee.inline_hint = InlineHint::Never;
+        let before = caller.clone();
+
+        assert!(!inline_call(&mut caller, CallSite { block: BlockId(0), index: 1 }, &callee));
+        assert_eq!(caller, before);
+        assert!(!inline_call(&mut caller, CallSite { block: BlockId(0), index: 0 }, &add_one_function()));
+    }
+
+    /// One of every instruction, with operands that differ from each other so
+    /// a field written in the wrong order can't round-trip by accident.
+    fn every_instruction() -> Vec<IRInst> {
+        let span = Span::new(chim_span::FileId(2), 10, 20, 3, 4);
+        let (v, b, t) = (ValueId, BlockId, TypeId);
+        let order = MemoryOrder::AcqRel;
+        vec![
+            IRInst::Alloca { dest: VarId(1), ty: t(2), span },
+            IRInst::Const { dest: VarId(2), value: IRConstant::Int(-300), ty: t(1), span },
+            IRInst::GlobalAddr { dest: VarId(3), global: 7, ty: t(3), span },
+            IRInst::FunctionAddr { dest: VarId(37), function: IRFunctionId(2), span },
+            IRInst::Load { dest: VarId(4), src: v(1), ty: t(4), span },
+            IRInst::Store { dest: v(1), src: v(4), ty: t(4), span },
+            IRInst::GetElementPtr { dest: VarId(5), ptr: v(1), indices: SmallVec::from_slice(&[v(2), v(3)]), ty: t(5), span },
+            IRInst::Binary { dest: VarId(6), op: BinaryOp::FRem, left: v(2), right: v(4), ty: t(1), span },
+            IRInst::Unary { dest: VarId(7), op: UnaryOp::FNeg, operand: v(6), ty: t(1), span },
+            IRInst::Call { dest: Some(VarId(8)), func: v(3), args: SmallVec::from_slice(&[v(7)]), ty: t(1), span },
+            IRInst::Invoke { dest: None, func: v(3), args: SmallVec::new(), normal_block: b(1), unwind_block: b(2), ty: t(0), span },
+            IRInst::Br { target: b(1), span },
+            IRInst::CondBr { condition: v(8), true_block: b(1), false_block: b(2), span },
+            IRInst::Ret { value: Some(v(8)), span },
+            IRInst::RetVoid { span },
+            IRInst::Switch { value: v(2), default_block: b(2), cases: vec![(v(1), b(1)), (v(3), b(2))], span },
+            IRInst::Select { dest: VarId(9), condition: v(8), true_val: v(2), false_val: v(6), ty: t(1), span },
+            IRInst::ExtractValue { dest: VarId(10), aggregate: v(5), indices: SmallVec::from_slice(&[0, 300]), ty: t(2), span },
+            IRInst::InsertValue { dest: VarId(11), aggregate: v(5), value: v(9), indices: SmallVec::from_slice(&[1]), ty: t(5), span },
+            IRInst::Cast { dest: VarId(12), value: v(9), to_ty: t(6), op: CastOp::SiToF, span },
+            IRInst::Phi { dest: VarId(13), incoming: SmallVec::from_slice(&[(b(0), v(9)), (b(1), v(12))]), ty: t(1), span },
+            IRInst::Skip { span },
+            IRInst::Debug { location: "main.chim:3:4 ✓".to_string(), span },
+            IRInst::AtomicLoad { dest: VarId(14), src: v(1), order: MemoryOrder::Acquire, ty: t(1), span },
+            IRInst::AtomicStore { dest: v(1), src: v(14), order: MemoryOrder::Release, ty: t(1), span },
+            IRInst::AtomicFetchAdd { dest: VarId(15), src: v(1), value: v(2), order, ty: t(1), span },
+            IRInst::AtomicFetchSub { dest: VarId(16), src: v(1), value: v(2), order, ty: t(1), span },
+            IRInst::AtomicFetchAnd { dest: VarId(17), src: v(1), value: v(2), order, ty: t(1), span },
+            IRInst::AtomicFetchOr { dest: VarId(18), src: v(1), value: v(2), order, ty: t(1), span },
+            IRInst::AtomicFetchXor { dest: VarId(19), src: v(1), value: v(2), order, ty: t(1), span },
+            IRInst::AtomicCompareExchange {
+                dest: VarId(20),
+                src: v(1),
+                expected: v(2),
+                desired: v(3),
+                success_order: MemoryOrder::SeqCst,
+                failure_order: MemoryOrder::Relaxed,
+                ty: t(1),
+                span,
+            },
+            IRInst::AtomicExchange { dest: VarId(21), src: v(1), value: v(3), order, ty: t(1), span },
+            IRInst::AtomicFence { order: MemoryOrder::HappensBefore, span },
+            IRInst::Wait { atomic: v(1), timeout: Some(v(2)), span },
+            IRInst::Notify { atomic: v(1), span },
+            IRInst::NotifyAll { atomic: v(1), span },
+            IRInst::MemoryBarrier { span },
+            IRInst::DataDependency { src: v(2), dest: v(3), span },
+            IRInst::MacroExpand { dest: VarId(22), macro_name: v(3), args: SmallVec::from_slice(&[v(1), v(2)]), span },
+            IRInst::ClosureCreate {
+                dest: VarId(23),
+                params: SmallVec::from_slice(&[VarId(24)]),
+                body: b(2),
+                captures: SmallVec::from_slice(&[(VarId(1), CaptureKind::CaptureRef), (VarId(2), CaptureKind::CaptureValue)]),
+                span,
+            },
+            IRInst::IteratorNext { dest: VarId(25), iterator: v(23), span },
+            IRInst::IteratorCollect { dest: VarId(26), iterator: v(23), span },
+            IRInst::IteratorChain { dest: VarId(27), iterator1: v(23), iterator2: v(26), span },
+            IRInst::IteratorFilter { dest: VarId(28), iterator: v(27), predicate: v(23), span },
+            IRInst::IteratorFold { dest: VarId(29), iterator: v(28), init: v(2), accumulator: VarId(30), body: b(1), span },
+            IRInst::IteratorMap { dest: VarId(31), iterator: v(28), mapper: v(23), span },
+            IRInst::ResultOk { dest: VarId(32), value: v(2), ok_type: t(1), err_type: t(7), span },
+            IRInst::ResultErr { dest: VarId(33), error: v(3), ok_type: t(1), err_type: t(7), span },
+            IRInst::TryCatch { dest: VarId(34), try_expr: v(32), catch_block: b(2), error_var: VarId(35), span },
+            IRInst::Throw { error: v(33), span },
+            IRInst::FutureAwait { dest: VarId(36), future: v(3), span },
+            IRInst::Yield { value: Some(v(36)), span },
+            IRInst::StreamYield { value: None, span },
+        ]
+    }
+
+    fn encoding_test_module() -> IRModule {
+        let mut function = ir_function("everything", 2, vec![
+            ir_block(0, every_instruction(), Terminator::ConditionalBranch {
+                condition: ValueId(8),
+                true_block: BlockId(1),
+                false_block: BlockId(2),
+            }),
+            ir_block(1, Vec::new(), Terminator::Switch {
+                value: ValueId(2),
+                default_block: BlockId(2),
+                cases: vec![(ValueId(1), BlockId(3))],
+            }),
+            ir_block(2, Vec::new(), Terminator::Invoke {
+                func: ValueId(3),
+                args: SmallVec::from_slice(&[ValueId(1)]),
+                normal_block: BlockId(3),
+                unwind_block: BlockId(4),
+            }),
+            ir_block(3, Vec::new(), Terminator::Branch(BlockId(5))),
+            ir_block(4, Vec::new(), Terminator::Unreachable),
+            ir_block(5, Vec::new(), Terminator::Return(Some(ValueId(36)))),
+        ]);
+        function.body[3].predecessors = vec![BlockId(1), BlockId(2)];
+        function.inline_hint = InlineHint::Never;
+        function.is_cold = true;
+        function.is_pub = true;
+
+        let constants = [
+            IRConstant::Int(i128::MIN),
+            IRConstant::Int(i128::MAX),
+            IRConstant::Float(-2.5e300),
+            IRConstant::Bool(true),
+            IRConstant::Char('语'),
+            IRConstant::String(String::new()),
+            IRConstant::Null,
+            IRConstant::Aggregate(AggregateConstant::Struct(vec![
+                IRConstant::Int(1),
+                IRConstant::Aggregate(AggregateConstant::Array(vec![IRConstant::Bool(false)])),
+                IRConstant::Aggregate(AggregateConstant::Vector(Vec::new())),
+            ])),
+            IRConstant::GlobalReference(TypeId(9)),
+        ];
+        let field = |name: &str, offset| IRStructField { name: name.to_string(), ty: TypeId(1), offset, size: 8 };
+
+        IRModule {
+            functions: vec![function, add_one_function()],
+            globals: constants
+                .into_iter()
+                .enumerate()
+                .map(|(i, initializer)| Global {
+                    name: format!("g{}", i),
+                    ty: TypeId(i),
+                    initializer: Some(initializer),
+                    is_pub: i % 2 == 0,
+                    is_const: i % 3 == 0,
+                    align: 1 << (i % 4),
+                })
+                .chain(std::iter::once(Global {
+                    name: "uninit".to_string(),
+                    ty: TypeId(1),
+                    initializer: None,
+                    is_pub: false,
+                    is_const: false,
+                    align: 8,
+                }))
+                .collect(),
+            structs: vec![IRStruct {
+                id: StructId(4),
+                name: "Point".to_string(),
+                fields: vec![field("x", 0), field("y", 8)],
+                size: 16,
+                align: 8,
+                is_packed: true,
+            }],
+            enums: vec![IREnum {
+                id: EnumId(5),
+                name: "Shape".to_string(),
+                variants: vec![
+                    IREnumVariant { name: "Empty".to_string(), discriminant: -1, fields: Vec::new(), size: 0, align: 1 },
+                    IREnumVariant { name: "Dot".to_string(), discriminant: 1 << 40, fields: vec![field("at", 0)], size: 8, align: 8 },
+                ],
+                size: 16,
+                align: 8,
+                tag_repr: TagRepresentation::U16,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_module_encoding_round_trips() {
+        let module = encoding_test_module();
+        let bytes = module.encode();
+        assert_eq!(IRModule::decode(&bytes), Ok(module.clone()));
+
+        let empty = IRModule { functions: Vec::new(), globals: Vec::new(), structs: Vec::new(), enums: Vec::new() };
+        assert_eq!(IRModule::decode(&empty.encode()), Ok(empty));
+
+        // Every proper prefix is rejected rather than decoded into a smaller module.
+        for len in 0..bytes.len() {
+            assert!(IRModule::decode(&bytes[..len]).is_err(), "prefix of {} bytes decoded", len);
+        }
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(IRModule::decode(&trailing), Err(DecodeError::TrailingBytes(1)));
+
+        let mut future = bytes;
+        future[4] = FORMAT_VERSION + 1;
+        assert_eq!(IRModule::decode(&future), Err(DecodeError::UnsupportedVersion(FORMAT_VERSION + 1)));
+        assert_eq!(IRModule::decode(b"ELF\x7f"), Err(DecodeError::BadMagic));
+    }
+
+    #[test]
+    fn test_id_allocator_is_unique_and_monotonic() {
+        let mut ids = IdAllocator::with_params(2);
+        let values: Vec<ValueId> = (0..4).map(|_| ids.fresh_value()).collect();
+        assert_eq!(values, [ValueId(2), ValueId(3), ValueId(4), ValueId(5)]);
+        let blocks: Vec<BlockId> = (0..3).map(|_| ids.fresh_block()).collect();
+        assert_eq!(blocks, [BlockId(0), BlockId(1), BlockId(2)]);
+        assert_eq!(ids.fresh_var(), VarId(2));
+        assert_eq!(ids.fresh_var(), VarId(3));
+
+        let mut ids = IdAllocator::new();
+        let mut seen = std::collections::HashSet::new();
+        let mut last = None;
+        for _ in 0..100 {
+            let value = ids.fresh_value();
+            assert!(seen.insert(value));
+            assert!(last.map_or(true, |last: ValueId| last.0 < value.0));
+            last = Some(value);
+        }
+    }
 }
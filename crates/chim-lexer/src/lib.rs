@@ -1,22 +1,44 @@
+use chim_error::{ChimError, ErrorKind, Severity};
 use chim_span::{FileId, Span};
 use std::collections::HashMap;
 use std::fmt;
 
 #[derive(Debug, Clone)]
 pub struct LexerError {
+    pub kind: ErrorKind,
     pub message: String,
     pub span: Span,
+    pub severity: Severity,
 }
 
 impl LexerError {
     pub fn new(message: String, span: Span) -> Self {
-        LexerError { message, span }
+        LexerError { kind: ErrorKind::Lexer, message, span, severity: Severity::Error }
+    }
+
+    pub fn with_kind(kind: ErrorKind, message: String, span: Span) -> Self {
+        LexerError { kind, message, span, severity: Severity::Error }
+    }
+
+    /// A style lint that does not stop compilation.
+    pub fn warning(message: String, span: Span) -> Self {
+        LexerError { kind: ErrorKind::Lexer, message, span, severity: Severity::Warning }
+    }
+
+    pub fn is_warning(&self) -> bool {
+        self.severity == Severity::Warning
+    }
+}
+
+impl From<LexerError> for ChimError {
+    fn from(error: LexerError) -> Self {
+        ChimError::new(error.kind, error.message).with_span(error.span)
     }
 }
 
 impl fmt::Display for LexerError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{} at {}:{}:{}", self.message, self.span.file_id.0, self.span.start_line, self.span.start_col)
+        write!(f, "{} at {}:{}:{}", self.message, self.span.file_id.index(), self.span.line, self.span.column)
     }
 }
 
@@ -56,6 +78,7 @@ pub enum Token {
     Trait,
     Impl,
     For,
+    In,
     Match,
     While,
     Pub,
@@ -87,6 +110,7 @@ pub enum Token {
     Pattern,
     Range,
     Guard,
+    Defer,
     Generic,
     ForAll,
     Default,
@@ -206,14 +230,13 @@ pub enum Token {
     Fix,
     Class,
     Instance,
-    Where,
     EqProp,
     ReflProp,
     JMeq,
     Rewrite,
     With,
-    Underscore,
     Identifier,
+    Lifetime,
     Int,
     Float,
     String,
@@ -261,6 +284,7 @@ pub enum Token {
     Dot,
     DotDot,
     DotDotDot,
+    DotDotEq,
     Hash,
     At,
     Dollar,
@@ -282,18 +306,31 @@ pub enum Token {
     Eof,
 }
 
+/// Keywords that only have builtin meaning in specific grammar positions and may
+/// otherwise be used as ordinary identifiers (e.g. `let map = 1;`).
+pub const CONTEXTUAL_KEYWORDS: &[Token] = &[
+    Token::Map, Token::Filter, Token::Fold, Token::Chain, Token::Collect, Token::Next, Token::Item,
+    Token::Sort, Token::Reverse, Token::Unique, Token::Merge, Token::Splice, Token::Clear,
+    Token::Front, Token::Back, Token::Insert, Token::Remove, Token::Erase, Token::End,
+    Token::State, Token::Message, Token::Context, Token::Pattern, Token::Range,
+];
+
 impl Token {
+    pub fn is_contextual_keyword(&self) -> bool {
+        CONTEXTUAL_KEYWORDS.contains(self)
+    }
+
     pub fn is_keyword(&self) -> bool {
         matches!(self,
             Token::Let | Token::Var | Token::Const | Token::Func | Token::Return | Token::If | Token::Else |
             Token::Loop | Token::Break | Token::Continue | Token::Struct | Token::Enum |
-            Token::Trait | Token::Impl | Token::For | Token::Match | Token::While |
+            Token::Trait | Token::Impl | Token::For | Token::In | Token::Match | Token::While |
             Token::Pub | Token::Priv | Token::Use | Token::Mod | Token::Extern |
             Token::Async | Token::Await | Token::Move | Token::Clone | Token::Ref |
             Token::Mut | Token::SelfKeyword | Token::True | Token::False | Token::Null |
             Token::Unit | Token::Type | Token::As | Token::Where | Token::SelfRef |
             Token::Static | Token::LetAlt | Token::Is | Token::Not | Token::Or | Token::And |
-            Token::Pattern | Token::Range | Token::Guard | Token::Generic | Token::ForAll |
+            Token::Pattern | Token::Range | Token::Guard | Token::Defer | Token::Generic | Token::ForAll |
             Token::Default | Token::Sync | Token::Sized | Token::IntoIterator |
             Token::Macro | Token::MacroRules | Token::Procedural | Token::Functional |
             Token::Attribute | Token::Derive | Token::Closure | Token::Capture |
@@ -317,7 +354,7 @@ impl Token {
             Token::PtrOffsetOf | Token::PtrSizeOf | Token::AlignOf |
             Token::Proof | Token::Theorem | Token::Lemma | Token::Induction | Token::Case |
             Token::Refl | Token::Cong | Token::Sym | Token::Trans | Token::Rec | Token::Fix |
-            Token::Class | Token::Instance | Token::Where | Token::EqProp | Token::ReflProp | Token::JMeq |
+            Token::Class | Token::Instance | Token::EqProp | Token::ReflProp | Token::JMeq |
             Token::Rewrite | Token::With
         )
     }
@@ -337,11 +374,49 @@ impl Token {
             Token::Caret | Token::LShift | Token::RShift | Token::PlusEq | Token::MinusEq |
             Token::StarEq | Token::SlashEq | Token::PercentEq | Token::AndEq | Token::PipeEq |
             Token::CaretEq | Token::LShiftEq | Token::RShiftEq | Token::Arrow | Token::ThinArrow |
-            Token::PathSep | Token::DoubleColon | Token::Dot | Token::DotDot | Token::DotDotDot
+            Token::PathSep | Token::DoubleColon | Token::Dot | Token::DotDot | Token::DotDotDot |
+            Token::DotDotEq
         )
     }
 }
 
+/// Which spelling of the keywords a source file is written in: every keyword
+/// has an English spelling and most have a Chinese one (`fn` / `函数`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dialect {
+    #[default]
+    English,
+    Chinese,
+}
+
+impl Dialect {
+    /// The dialect most keywords in `tokens` are spelled in, English on a tie.
+    pub fn detect(source: &str, tokens: &[SpannedToken]) -> Dialect {
+        let (mut english, mut chinese) = (0usize, 0usize);
+        for token in tokens.iter().filter(|token| token.token.is_keyword()) {
+            if token.text(source).is_ascii() {
+                english += 1;
+            } else {
+                chinese += 1;
+            }
+        }
+        if chinese > english {
+            Dialect::Chinese
+        } else {
+            Dialect::English
+        }
+    }
+}
+
+/// How `token` is spelled in `dialect`, if it is a keyword with a spelling
+/// there. Where several spellings lex to the same token the shortest wins.
+pub fn keyword_spelling(token: &Token, dialect: Dialect) -> Option<&'static str> {
+    KEYWORDS.iter()
+        .filter(|(spelling, keyword)| keyword == token && spelling.is_ascii() == (dialect == Dialect::English))
+        .map(|&(spelling, _)| spelling)
+        .min_by_key(|spelling| (spelling.chars().count(), *spelling))
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct SpannedToken {
     pub token: Token,
@@ -352,8 +427,294 @@ impl SpannedToken {
     pub fn new(token: Token, span: Span) -> Self {
         SpannedToken { token, span }
     }
+
+    pub fn text<'s>(&self, source: &'s str) -> &'s str {
+        &source[self.span.start..self.span.end]
+    }
+
+    pub fn is_trivia(&self) -> bool {
+        matches!(self.token, Token::Whitespace | Token::Comment | Token::BlockComment | Token::DocComment)
+    }
 }
 
+/// Keyword spellings in both dialects, and the tokens they lex to.
+const KEYWORDS: &[(&str, Token)] = &[
+    ("let", Token::Let),
+    ("var", Token::Var),
+    ("const", Token::Const),
+    ("static", Token::Static),
+    ("fn", Token::Func),
+    ("return", Token::Return),
+    ("if", Token::If),
+    ("else", Token::Else),
+    ("loop", Token::Loop),
+    ("break", Token::Break),
+    ("continue", Token::Continue),
+    ("struct", Token::Struct),
+    ("enum", Token::Enum),
+    ("trait", Token::Trait),
+    ("impl", Token::Impl),
+    ("for", Token::For),
+    ("in", Token::In),
+    ("match", Token::Match),
+    ("while", Token::While),
+    ("pub", Token::Pub),
+    ("priv", Token::Priv),
+    ("use", Token::Use),
+    ("mod", Token::Mod),
+    ("extern", Token::Extern),
+    ("async", Token::Async),
+    ("await", Token::Await),
+    ("move", Token::Move),
+    ("clone", Token::Clone),
+    ("ref", Token::Ref),
+    ("mut", Token::Mut),
+    ("self", Token::SelfKeyword),
+    ("Self", Token::SelfRef),
+    ("true", Token::True),
+    ("false", Token::False),
+    ("null", Token::Null),
+    ("unit", Token::Unit),
+    ("type", Token::Type),
+    ("as", Token::As),
+    ("is", Token::Is),
+    ("not", Token::Not),
+    ("or", Token::Or),
+    ("and", Token::And),
+    ("pattern", Token::Pattern),
+    ("range", Token::Range),
+    ("guard", Token::Guard),
+    ("defer", Token::Defer),
+    ("generic", Token::Generic),
+    ("forall", Token::ForAll),
+    ("default", Token::Default),
+    ("sync", Token::Sync),
+    ("sized", Token::Sized),
+    ("intoiterator", Token::IntoIterator),
+    ("macro", Token::Macro),
+    ("macrorules", Token::MacroRules),
+    ("procedural", Token::Procedural),
+    ("functional", Token::Functional),
+    ("attribute", Token::Attribute),
+    ("derive", Token::Derive),
+    ("closure", Token::Closure),
+    ("capture", Token::Capture),
+    ("captureref", Token::CaptureRef),
+    ("capturevalue", Token::CaptureValue),
+    ("iterator", Token::Iterator),
+    ("next", Token::Next),
+    ("item", Token::Item),
+    ("collect", Token::Collect),
+    ("chain", Token::Chain),
+    ("filter", Token::Filter),
+    ("fold", Token::Fold),
+    ("map", Token::Map),
+    ("result", Token::Result),
+    ("ok", Token::Ok),
+    ("err", Token::Err),
+    ("try", Token::Try),
+    ("catch", Token::Catch),
+    ("error", Token::Error),
+    ("context", Token::Context),
+    ("throw", Token::Throw),
+    ("future", Token::Future),
+    ("yield", Token::Yield),
+    ("stream", Token::Stream),
+    ("_", Token::Underscore),
+    ("unsafe", Token::Unsafe),
+    ("alloc", Token::Alloc),
+    ("alloc_aligned", Token::AllocAligned),
+    ("free", Token::Free),
+    ("ptr", Token::Ptr),
+    ("ptr_add", Token::PtrAdd),
+    ("ptr_sub", Token::PtrSub),
+    ("ptr_load", Token::PtrLoad),
+    ("ptr_store", Token::PtrStore),
+    ("ptr_cast", Token::PtrCast),
+    ("ptr_offsetof", Token::PtrOffsetOf),
+    ("ptr_sizeof", Token::PtrSizeOf),
+    ("alignof", Token::AlignOf),
+    ("proof", Token::Proof),
+    ("theorem", Token::Theorem),
+    ("lemma", Token::Lemma),
+    ("induction", Token::Induction),
+    ("case", Token::Case),
+    ("refl", Token::Refl),
+    ("cong", Token::Cong),
+    ("sym", Token::Sym),
+    ("trans", Token::Trans),
+    ("rec", Token::Rec),
+    ("fix", Token::Fix),
+    ("class", Token::Class),
+    ("instance", Token::Instance),
+    ("where", Token::Where),
+    ("eqprop", Token::EqProp),
+    ("reflprop", Token::ReflProp),
+    ("jmeq", Token::JMeq),
+    ("rewrite", Token::Rewrite),
+    ("with", Token::With),
+    ("设", Token::Var),
+    ("常量", Token::Const),
+    ("函数", Token::Func),
+    ("返回", Token::Return),
+    ("如果", Token::If),
+    ("否则", Token::Else),
+    ("循环", Token::Loop),
+    ("中断", Token::Break),
+    ("继续", Token::Continue),
+    ("结构体", Token::Struct),
+    ("枚举", Token::Enum),
+    ("特征", Token::Trait),
+    ("实现", Token::Impl),
+    ("为", Token::For),
+    ("匹配", Token::Match),
+    ("当", Token::While),
+    ("公有的", Token::Pub),
+    ("私有的", Token::Priv),
+    ("引入", Token::Use),
+    ("模组", Token::Mod),
+    ("外部", Token::Extern),
+    ("异步", Token::Async),
+    ("移动", Token::Move),
+    ("克隆", Token::Clone),
+    ("借用", Token::Ref),
+    ("可变", Token::Mut),
+    ("自身", Token::SelfKeyword),
+    ("真", Token::True),
+    ("假", Token::False),
+    ("空", Token::Null),
+    ("单位", Token::Unit),
+    ("类型", Token::Type),
+    ("令", Token::LetAlt),
+    ("是", Token::Is),
+    ("不是", Token::Not),
+    ("或", Token::Or),
+    ("且", Token::And),
+    ("模式", Token::Pattern),
+    ("范围", Token::Range),
+    ("守卫", Token::Guard),
+    ("延迟", Token::Defer),
+    ("泛型", Token::Generic),
+    ("全称", Token::ForAll),
+    ("默认", Token::Default),
+    ("同步", Token::Sync),
+    ("大小", Token::Sized),
+    ("迭代器", Token::IntoIterator),
+    ("宏", Token::Macro),
+    ("宏规则", Token::MacroRules),
+    ("过程式", Token::Procedural),
+    ("函数式", Token::Functional),
+    ("属性", Token::Attribute),
+    ("派生", Token::Derive),
+    ("闭包", Token::Closure),
+    ("捕获", Token::Capture),
+    ("捕获引用", Token::CaptureRef),
+    ("捕获值", Token::CaptureValue),
+    ("迭代", Token::Iterator),
+    ("下一个", Token::Next),
+    ("项", Token::Item),
+    ("收集", Token::Collect),
+    ("链", Token::Chain),
+    ("过滤", Token::Filter),
+    ("折叠", Token::Fold),
+    ("映射", Token::Map),
+    ("结果", Token::Result),
+    ("成功", Token::Ok),
+    ("错误", Token::Err),
+    ("尝试", Token::Try),
+    ("捕获异常", Token::Catch),
+    ("异常类型", Token::Error),
+    ("上下文", Token::Context),
+    ("抛出", Token::Throw),
+    ("未来", Token::Future),
+    ("产出", Token::Yield),
+    ("流", Token::Stream),
+    ("ECS", Token::Ecs),
+    ("实体", Token::Entity),
+    ("组件", Token::Component),
+    ("系统", Token::System),
+    ("Actor", Token::Actor),
+    ("消息", Token::Message),
+    ("发送", Token::Send),
+    ("接收", Token::Receive),
+    ("并发", Token::Concurrency),
+    ("atomic", Token::Atomic),
+    ("relaxed", Token::Relaxed),
+    ("acquire", Token::Acquire),
+    ("release", Token::Release),
+    ("acqrel", Token::AcqRel),
+    ("seqcst", Token::SeqCst),
+    ("consume", Token::Consume),
+    ("happensbefore", Token::HappensBefore),
+    ("volatile", Token::Volatile),
+    ("memorybarrier", Token::MemoryBarrier),
+    ("wait", Token::Wait),
+    ("notify", Token::Notify),
+    ("notifyall", Token::NotifyAll),
+    ("datadependency", Token::DataDependency),
+    ("effect", Token::Effect),
+    ("ability", Token::Ability),
+    ("io", Token::IO),
+    ("exception", Token::Exception),
+    ("state", Token::State),
+    ("asynceffect", Token::AsyncEffect),
+    ("原子", Token::Atomic),
+    ("松弛", Token::Relaxed),
+    ("获取", Token::Acquire),
+    ("释放", Token::Release),
+    ("获取释放", Token::AcqRel),
+    ("顺序一致", Token::SeqCst),
+    ("消费", Token::Consume),
+    ("发生前", Token::HappensBefore),
+    ("易变", Token::Volatile),
+    ("内存屏障", Token::MemoryBarrier),
+    ("等待", Token::Wait),
+    ("通知", Token::Notify),
+    ("通知全部", Token::NotifyAll),
+    ("数据依赖", Token::DataDependency),
+    ("效果", Token::Effect),
+    ("能力", Token::Ability),
+    ("输入输出", Token::IO),
+    ("异常", Token::Exception),
+    ("状态", Token::State),
+    ("异步效果", Token::AsyncEffect),
+    ("linkedlist", Token::LinkedList),
+    ("listnode", Token::ListNode),
+    ("pushfront", Token::PushFront),
+    ("pushback", Token::PushBack),
+    ("popfront", Token::PopFront),
+    ("popback", Token::PopBack),
+    ("front", Token::Front),
+    ("back", Token::Back),
+    ("insert", Token::Insert),
+    ("erase", Token::Erase),
+    ("clear", Token::Clear),
+    ("splice", Token::Splice),
+    ("merge", Token::Merge),
+    ("reverse", Token::Reverse),
+    ("sort", Token::Sort),
+    ("unique", Token::Unique),
+    ("remove", Token::Remove),
+    ("双链表", Token::LinkedList),
+    ("链表节点", Token::ListNode),
+    ("前推", Token::PushFront),
+    ("后推", Token::PushBack),
+    ("前弹", Token::PopFront),
+    ("后弹", Token::PopBack),
+    ("前端", Token::Front),
+    ("后端", Token::Back),
+    ("插入", Token::Insert),
+    ("擦除", Token::Erase),
+    ("清空", Token::Clear),
+    ("拼接", Token::Splice),
+    ("合并", Token::Merge),
+    ("反转", Token::Reverse),
+    ("排序", Token::Sort),
+    ("唯一", Token::Unique),
+    ("移除", Token::Remove),
+    ("结束", Token::End),
+];
+
 #[derive(Debug)]
 pub struct Lexer<'a> {
     source: &'a str,
@@ -361,286 +722,24 @@ pub struct Lexer<'a> {
     pos: usize,
     line: usize,
     line_start: usize,
+    /// The last position `column` was asked about, and its column; the
+    /// next call scans on from there rather than from the line start.
+    column_pos: usize,
+    column: usize,
     keyword_map: HashMap<&'static str, Token>,
     errors: Vec<LexerError>,
+    keep_trivia: bool,
+    /// Columns a tab advances to the next multiple of; 1 counts it as one
+    /// column like any other byte.
+    tab_width: usize,
+    /// Whether `tokenize` also reports whitespace style lints.
+    lint_whitespace: bool,
+    warnings: Vec<LexerError>,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(source: &'a str, _file_id: FileId) -> Self {
-        let mut keyword_map = HashMap::new();
-        
-        keyword_map.insert("let", Token::Let);
-        keyword_map.insert("var", Token::Var);
-        keyword_map.insert("const", Token::Const);
-        keyword_map.insert("fn", Token::Func);
-        keyword_map.insert("return", Token::Return);
-        keyword_map.insert("if", Token::If);
-        keyword_map.insert("else", Token::Else);
-        keyword_map.insert("loop", Token::Loop);
-        keyword_map.insert("break", Token::Break);
-        keyword_map.insert("continue", Token::Continue);
-        keyword_map.insert("struct", Token::Struct);
-        keyword_map.insert("enum", Token::Enum);
-        keyword_map.insert("trait", Token::Trait);
-        keyword_map.insert("impl", Token::Impl);
-        keyword_map.insert("for", Token::For);
-        keyword_map.insert("match", Token::Match);
-        keyword_map.insert("while", Token::While);
-        keyword_map.insert("pub", Token::Pub);
-        keyword_map.insert("priv", Token::Priv);
-        keyword_map.insert("use", Token::Use);
-        keyword_map.insert("mod", Token::Mod);
-        keyword_map.insert("extern", Token::Extern);
-        keyword_map.insert("async", Token::Async);
-        keyword_map.insert("await", Token::Await);
-        keyword_map.insert("move", Token::Move);
-        keyword_map.insert("clone", Token::Clone);
-        keyword_map.insert("ref", Token::Ref);
-        keyword_map.insert("mut", Token::Mut);
-        keyword_map.insert("self", Token::SelfKeyword);
-        keyword_map.insert("true", Token::True);
-        keyword_map.insert("false", Token::False);
-        keyword_map.insert("null", Token::Null);
-        keyword_map.insert("unit", Token::Unit);
-        keyword_map.insert("type", Token::Type);
-        keyword_map.insert("as", Token::As);
-        keyword_map.insert("where", Token::Where);
-        keyword_map.insert("is", Token::Is);
-        keyword_map.insert("not", Token::Not);
-        keyword_map.insert("or", Token::Or);
-        keyword_map.insert("and", Token::And);
-        keyword_map.insert("pattern", Token::Pattern);
-        keyword_map.insert("range", Token::Range);
-        keyword_map.insert("guard", Token::Guard);
-        keyword_map.insert("generic", Token::Generic);
-        keyword_map.insert("forall", Token::ForAll);
-        keyword_map.insert("default", Token::Default);
-        keyword_map.insert("sync", Token::Sync);
-        keyword_map.insert("sized", Token::Sized);
-        keyword_map.insert("intoiterator", Token::IntoIterator);
-        keyword_map.insert("macro", Token::Macro);
-        keyword_map.insert("macrorules", Token::MacroRules);
-        keyword_map.insert("procedural", Token::Procedural);
-        keyword_map.insert("functional", Token::Functional);
-        keyword_map.insert("attribute", Token::Attribute);
-        keyword_map.insert("derive", Token::Derive);
-        keyword_map.insert("closure", Token::Closure);
-        keyword_map.insert("capture", Token::Capture);
-        keyword_map.insert("captureref", Token::CaptureRef);
-        keyword_map.insert("capturevalue", Token::CaptureValue);
-        keyword_map.insert("iterator", Token::Iterator);
-        keyword_map.insert("next", Token::Next);
-        keyword_map.insert("item", Token::Item);
-        keyword_map.insert("collect", Token::Collect);
-        keyword_map.insert("chain", Token::Chain);
-        keyword_map.insert("filter", Token::Filter);
-        keyword_map.insert("fold", Token::Fold);
-        keyword_map.insert("map", Token::Map);
-        keyword_map.insert("result", Token::Result);
-        keyword_map.insert("ok", Token::Ok);
-        keyword_map.insert("err", Token::Err);
-        keyword_map.insert("try", Token::Try);
-        keyword_map.insert("catch", Token::Catch);
-        keyword_map.insert("error", Token::Error);
-        keyword_map.insert("context", Token::Context);
-        keyword_map.insert("throw", Token::Throw);
-        keyword_map.insert("future", Token::Future);
-        keyword_map.insert("yield", Token::Yield);
-        keyword_map.insert("stream", Token::Stream);
-        keyword_map.insert("_", Token::Underscore);
-        keyword_map.insert("unsafe", Token::Unsafe);
-        keyword_map.insert("alloc", Token::Alloc);
-        keyword_map.insert("alloc_aligned", Token::AllocAligned);
-        keyword_map.insert("free", Token::Free);
-        keyword_map.insert("ptr", Token::Ptr);
-        keyword_map.insert("ptr_add", Token::PtrAdd);
-        keyword_map.insert("ptr_sub", Token::PtrSub);
-        keyword_map.insert("ptr_load", Token::PtrLoad);
-        keyword_map.insert("ptr_store", Token::PtrStore);
-        keyword_map.insert("ptr_cast", Token::PtrCast);
-        keyword_map.insert("ptr_offsetof", Token::PtrOffsetOf);
-        keyword_map.insert("ptr_sizeof", Token::PtrSizeOf);
-        keyword_map.insert("alignof", Token::AlignOf);
-        keyword_map.insert("proof", Token::Proof);
-        keyword_map.insert("theorem", Token::Theorem);
-        keyword_map.insert("lemma", Token::Lemma);
-        keyword_map.insert("induction", Token::Induction);
-        keyword_map.insert("case", Token::Case);
-        keyword_map.insert("refl", Token::Refl);
-        keyword_map.insert("cong", Token::Cong);
-        keyword_map.insert("sym", Token::Sym);
-        keyword_map.insert("trans", Token::Trans);
-        keyword_map.insert("rec", Token::Rec);
-        keyword_map.insert("fix", Token::Fix);
-        keyword_map.insert("class", Token::Class);
-        keyword_map.insert("instance", Token::Instance);
-        keyword_map.insert("where", Token::Where);
-        keyword_map.insert("eqprop", Token::EqProp);
-        keyword_map.insert("reflprop", Token::ReflProp);
-        keyword_map.insert("jmeq", Token::JMeq);
-        keyword_map.insert("rewrite", Token::Rewrite);
-        keyword_map.insert("with", Token::With);
-        keyword_map.insert("令", Token::Let);
-        keyword_map.insert("设", Token::Var);
-        keyword_map.insert("常量", Token::Const);
-        keyword_map.insert("函数", Token::Func);
-        keyword_map.insert("返回", Token::Return);
-        keyword_map.insert("如果", Token::If);
-        keyword_map.insert("否则", Token::Else);
-        keyword_map.insert("循环", Token::Loop);
-        keyword_map.insert("中断", Token::Break);
-        keyword_map.insert("继续", Token::Continue);
-        keyword_map.insert("结构体", Token::Struct);
-        keyword_map.insert("枚举", Token::Enum);
-        keyword_map.insert("特征", Token::Trait);
-        keyword_map.insert("实现", Token::Impl);
-        keyword_map.insert("为", Token::For);
-        keyword_map.insert("匹配", Token::Match);
-        keyword_map.insert("当", Token::While);
-        keyword_map.insert("公有的", Token::Pub);
-        keyword_map.insert("私有的", Token::Priv);
-        keyword_map.insert("引入", Token::Use);
-        keyword_map.insert("模组", Token::Mod);
-        keyword_map.insert("外部", Token::Extern);
-        keyword_map.insert("异步", Token::Async);
-        keyword_map.insert("等待", Token::Await);
-        keyword_map.insert("移动", Token::Move);
-        keyword_map.insert("克隆", Token::Clone);
-        keyword_map.insert("借用", Token::Ref);
-        keyword_map.insert("可变", Token::Mut);
-        keyword_map.insert("自身", Token::SelfKeyword);
-        keyword_map.insert("真", Token::True);
-        keyword_map.insert("假", Token::False);
-        keyword_map.insert("空", Token::Null);
-        keyword_map.insert("单位", Token::Unit);
-        keyword_map.insert("类型", Token::Type);
-        keyword_map.insert("令", Token::LetAlt);
-        keyword_map.insert("是", Token::Is);
-        keyword_map.insert("不是", Token::Not);
-        keyword_map.insert("或", Token::Or);
-        keyword_map.insert("且", Token::And);
-        keyword_map.insert("模式", Token::Pattern);
-        keyword_map.insert("范围", Token::Range);
-        keyword_map.insert("守卫", Token::Guard);
-        keyword_map.insert("泛型", Token::Generic);
-        keyword_map.insert("全称", Token::ForAll);
-        keyword_map.insert("默认", Token::Default);
-        keyword_map.insert("同步", Token::Sync);
-        keyword_map.insert("大小", Token::Sized);
-        keyword_map.insert("迭代器", Token::IntoIterator);
-        keyword_map.insert("宏", Token::Macro);
-        keyword_map.insert("宏规则", Token::MacroRules);
-        keyword_map.insert("过程式", Token::Procedural);
-        keyword_map.insert("函数式", Token::Functional);
-        keyword_map.insert("属性", Token::Attribute);
-        keyword_map.insert("派生", Token::Derive);
-        keyword_map.insert("闭包", Token::Closure);
-        keyword_map.insert("捕获", Token::Capture);
-        keyword_map.insert("捕获引用", Token::CaptureRef);
-        keyword_map.insert("捕获值", Token::CaptureValue);
-        keyword_map.insert("迭代", Token::Iterator);
-        keyword_map.insert("下一个", Token::Next);
-        keyword_map.insert("项", Token::Item);
-        keyword_map.insert("收集", Token::Collect);
-        keyword_map.insert("链", Token::Chain);
-        keyword_map.insert("过滤", Token::Filter);
-        keyword_map.insert("折叠", Token::Fold);
-        keyword_map.insert("映射", Token::Map);
-        keyword_map.insert("结果", Token::Result);
-        keyword_map.insert("成功", Token::Ok);
-        keyword_map.insert("错误", Token::Err);
-        keyword_map.insert("尝试", Token::Try);
-        keyword_map.insert("捕获异常", Token::Catch);
-        keyword_map.insert("异常类型", Token::Error);
-        keyword_map.insert("上下文", Token::Context);
-        keyword_map.insert("抛出", Token::Throw);
-        keyword_map.insert("未来", Token::Future);
-        keyword_map.insert("产出", Token::Yield);
-        keyword_map.insert("流", Token::Stream);
-        keyword_map.insert("ECS", Token::Ecs);
-        keyword_map.insert("实体", Token::Entity);
-        keyword_map.insert("组件", Token::Component);
-        keyword_map.insert("系统", Token::System);
-        keyword_map.insert("Actor", Token::Actor);
-        keyword_map.insert("消息", Token::Message);
-        keyword_map.insert("发送", Token::Send);
-        keyword_map.insert("接收", Token::Receive);
-        keyword_map.insert("并发", Token::Concurrency);
-        keyword_map.insert("atomic", Token::Atomic);
-        keyword_map.insert("relaxed", Token::Relaxed);
-        keyword_map.insert("acquire", Token::Acquire);
-        keyword_map.insert("release", Token::Release);
-        keyword_map.insert("acqrel", Token::AcqRel);
-        keyword_map.insert("seqcst", Token::SeqCst);
-        keyword_map.insert("consume", Token::Consume);
-        keyword_map.insert("happensbefore", Token::HappensBefore);
-        keyword_map.insert("volatile", Token::Volatile);
-        keyword_map.insert("memorybarrier", Token::MemoryBarrier);
-        keyword_map.insert("wait", Token::Wait);
-        keyword_map.insert("notify", Token::Notify);
-        keyword_map.insert("notifyall", Token::NotifyAll);
-        keyword_map.insert("datadependency", Token::DataDependency);
-        keyword_map.insert("effect", Token::Effect);
-        keyword_map.insert("ability", Token::Ability);
-        keyword_map.insert("io", Token::IO);
-        keyword_map.insert("exception", Token::Exception);
-        keyword_map.insert("state", Token::State);
-        keyword_map.insert("asynceffect", Token::AsyncEffect);
-        keyword_map.insert("原子", Token::Atomic);
-        keyword_map.insert("松弛", Token::Relaxed);
-        keyword_map.insert("获取", Token::Acquire);
-        keyword_map.insert("释放", Token::Release);
-        keyword_map.insert("获取释放", Token::AcqRel);
-        keyword_map.insert("顺序一致", Token::SeqCst);
-        keyword_map.insert("消费", Token::Consume);
-        keyword_map.insert("发生前", Token::HappensBefore);
-        keyword_map.insert("易变", Token::Volatile);
-        keyword_map.insert("内存屏障", Token::MemoryBarrier);
-        keyword_map.insert("等待", Token::Wait);
-        keyword_map.insert("通知", Token::Notify);
-        keyword_map.insert("通知全部", Token::NotifyAll);
-        keyword_map.insert("数据依赖", Token::DataDependency);
-        keyword_map.insert("效果", Token::Effect);
-        keyword_map.insert("能力", Token::Ability);
-        keyword_map.insert("输入输出", Token::IO);
-        keyword_map.insert("异常", Token::Exception);
-        keyword_map.insert("状态", Token::State);
-        keyword_map.insert("异步效果", Token::AsyncEffect);
-        keyword_map.insert("linkedlist", Token::LinkedList);
-        keyword_map.insert("listnode", Token::ListNode);
-        keyword_map.insert("pushfront", Token::PushFront);
-        keyword_map.insert("pushback", Token::PushBack);
-        keyword_map.insert("popfront", Token::PopFront);
-        keyword_map.insert("popback", Token::PopBack);
-        keyword_map.insert("front", Token::Front);
-        keyword_map.insert("back", Token::Back);
-        keyword_map.insert("insert", Token::Insert);
-        keyword_map.insert("erase", Token::Erase);
-        keyword_map.insert("clear", Token::Clear);
-        keyword_map.insert("splice", Token::Splice);
-        keyword_map.insert("merge", Token::Merge);
-        keyword_map.insert("reverse", Token::Reverse);
-        keyword_map.insert("sort", Token::Sort);
-        keyword_map.insert("unique", Token::Unique);
-        keyword_map.insert("remove", Token::Remove);
-        keyword_map.insert("双链表", Token::LinkedList);
-        keyword_map.insert("链表节点", Token::ListNode);
-        keyword_map.insert("前推", Token::PushFront);
-        keyword_map.insert("后推", Token::PushBack);
-        keyword_map.insert("前弹", Token::PopFront);
-        keyword_map.insert("后弹", Token::PopBack);
-        keyword_map.insert("前端", Token::Front);
-        keyword_map.insert("后端", Token::Back);
-        keyword_map.insert("插入", Token::Insert);
-        keyword_map.insert("擦除", Token::Erase);
-        keyword_map.insert("清空", Token::Clear);
-        keyword_map.insert("拼接", Token::Splice);
-        keyword_map.insert("合并", Token::Merge);
-        keyword_map.insert("反转", Token::Reverse);
-        keyword_map.insert("排序", Token::Sort);
-        keyword_map.insert("唯一", Token::Unique);
-        keyword_map.insert("移除", Token::Remove);
-        keyword_map.insert("结束", Token::End);
+        let keyword_map = KEYWORDS.iter().cloned().collect();
 
         Lexer {
             source,
@@ -648,9 +747,92 @@ impl<'a> Lexer<'a> {
             pos: 0,
             line: 1,
             line_start: 0,
+            column_pos: 0,
+            column: 0,
             keyword_map,
             errors: Vec::new(),
+            keep_trivia: false,
+            tab_width: 1,
+            lint_whitespace: false,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Reports columns with tabs expanded to stops every `tab_width`
+    /// columns, so carets line up with what an editor shows.
+    pub fn with_tab_width(mut self, tab_width: usize) -> Self {
+        self.tab_width = tab_width.max(1);
+        self
+    }
+
+    /// Warns about trailing whitespace and about indentation mixing tabs and
+    /// spaces. Off by default; the warnings are kept apart from the errors.
+    pub fn with_whitespace_lints(mut self, enabled: bool) -> Self {
+        self.lint_whitespace = enabled;
+        self
+    }
+
+    pub fn warnings(&self) -> &[LexerError] {
+        &self.warnings
+    }
+
+    pub fn take_warnings(&mut self) -> Vec<LexerError> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Whitespace inside the string literals among `tokens` is their
+    /// content, not layout, and is never reported.
+    fn lint_whitespace(&mut self, tokens: &[SpannedToken]) {
+        let strings: Vec<(usize, usize)> = tokens.iter()
+            .filter(|token| matches!(token.token, Token::String | Token::RawString | Token::ByteString))
+            .map(|token| (token.span.start, token.span.end))
+            .collect();
+        let in_string = |start: usize, end: usize| strings.iter().any(|&(from, to)| from < end && start < to);
+        let mut line_start = 0;
+        for (index, raw_line) in self.source.split_inclusive('\n').enumerate() {
+            let line = raw_line.trim_end_matches(['\n', '\r']);
+            let span = |start: usize, end: usize| {
+                let column = self.advance_column(0, line_start, line_start + start);
+                Span::new(self.file_id, line_start + start, line_start + end, index + 1, column)
+            };
+
+            let indent = line.len() - line.trim_start_matches([' ', '\t']).len();
+            let indentation = &line[..indent];
+            let mixed = indentation.contains(' ') && indentation.contains('\t');
+            let content = line.trim_end_matches([' ', '\t']).len();
+            let mut warnings = Vec::new();
+            if mixed && !in_string(line_start, line_start + indent) {
+                warnings.push(LexerError::warning("indentation mixes tabs and spaces".to_string(), span(0, indent)));
+            }
+            if content < line.len() && !in_string(line_start + content, line_start + line.len()) {
+                warnings.push(LexerError::warning("trailing whitespace".to_string(), span(content, line.len())));
+            }
+            self.warnings.extend(warnings);
+
+            line_start += raw_line.len();
+        }
+    }
+
+    /// The column of the current position on its line, with tabs expanded.
+    fn column(&mut self) -> usize {
+        if self.column_pos < self.line_start || self.column_pos > self.pos {
+            self.column_pos = self.line_start;
+            self.column = 0;
         }
+        self.column = self.advance_column(self.column, self.column_pos, self.pos);
+        self.column_pos = self.pos;
+        self.column
+    }
+
+    /// The column reached from `column` at `from` by the text up to `to`.
+    fn advance_column(&self, column: usize, from: usize, to: usize) -> usize {
+        self.source[from..to].chars().fold(column, |column, c| {
+            if c == '\t' {
+                (column / self.tab_width + 1) * self.tab_width
+            } else {
+                column + c.len_utf8()
+            }
+        })
     }
 
     pub fn errors(&self) -> &[LexerError] {
@@ -665,6 +847,10 @@ impl<'a> Lexer<'a> {
         self.errors.push(LexerError::new(message, span));
     }
 
+    fn report_error_kind(&mut self, kind: ErrorKind, message: String, span: Span) {
+        self.errors.push(LexerError::with_kind(kind, message, span));
+    }
+
     fn report_unknown_char(&mut self, c: char, start: usize, start_line: usize, start_col: usize) {
         let span = Span::new(self.file_id, start, start + c.len_utf8(), start_line, start_col);
         let message = format!("unknown character '{}'", c);
@@ -674,22 +860,43 @@ impl<'a> Lexer<'a> {
     pub fn tokenize(&mut self) -> Vec<SpannedToken> {
         let mut tokens = Vec::new();
         while let Some(token) = self.next_token() {
-            if token.token != Token::Whitespace && token.token != Token::Comment && token.token != Token::BlockComment {
+            if token.token == Token::Eof {
+                break;
+            }
+            if !token.is_trivia() {
                 tokens.push(token);
             }
         }
+        if self.lint_whitespace {
+            self.lint_whitespace(&tokens);
+        }
+        tokens
+    }
+
+    /// Like `tokenize`, but keeps whitespace and comment tokens interleaved with
+    /// the real tokens so formatters can reproduce the source layout.
+    pub fn tokenize_with_trivia(&mut self) -> Vec<SpannedToken> {
+        self.keep_trivia = true;
+        let mut tokens = Vec::new();
+        while let Some(token) = self.next_token() {
+            if token.token == Token::Eof {
+                break;
+            }
+            tokens.push(token);
+        }
+        self.keep_trivia = false;
         tokens
     }
 
     fn next_token(&mut self) -> Option<SpannedToken> {
         if self.pos >= self.source.len() {
-            let span = Span::new(self.file_id, self.pos, self.pos, self.line, self.pos - self.line_start);
+            let span = Span::new(self.file_id, self.pos, self.pos, self.line, self.column());
             return Some(SpannedToken::new(Token::Eof, span));
         }
 
         let start = self.pos;
         let start_line = self.line;
-        let start_col = self.pos - self.line_start;
+        let start_col = self.column();
 
         let c = self.source[self.pos..].chars().next()?;
 
@@ -712,7 +919,7 @@ impl<'a> Lexer<'a> {
                     Token::MinusEq
                 } else if self.source[self.pos..].starts_with('>') {
                     self.pos += 1;
-                    Token::Arrow
+                    Token::ThinArrow
                 } else {
                     Token::Minus
                 };
@@ -733,11 +940,21 @@ impl<'a> Lexer<'a> {
             '/' => {
                 self.pos += 1;
                 if self.source[self.pos..].starts_with('/') {
-                    self.skip_line_comment();
-                    return self.next_token();
+                    let is_doc = self.source[self.pos..].starts_with("//") && !self.source[self.pos..].starts_with("///");
+                    if self.keep_trivia {
+                        self.consume_line_comment();
+                        let token = if is_doc { Token::DocComment } else { Token::Comment };
+                        let span = Span::new(self.file_id, start, self.pos, start_line, start_col);
+                        return Some(SpannedToken::new(token, span));
+                    }
+                    return self.skip_line_comment();
                 } else if self.source[self.pos..].starts_with('*') {
                     self.pos += 1;
                     self.skip_block_comment();
+                    if self.keep_trivia {
+                        let span = Span::new(self.file_id, start, self.pos, start_line, start_col);
+                        return Some(SpannedToken::new(Token::BlockComment, span));
+                    }
                     return self.next_token();
                 }
                 let token = if self.source[self.pos..].starts_with('=') {
@@ -885,6 +1102,9 @@ impl<'a> Lexer<'a> {
                     if self.source[self.pos..].starts_with('.') {
                         self.pos += 1;
                         Token::DotDotDot
+                    } else if self.source[self.pos..].starts_with('=') {
+                        self.pos += 1;
+                        Token::DotDotEq
                     } else {
                         Token::DotDot
                     }
@@ -900,8 +1120,11 @@ impl<'a> Lexer<'a> {
             '}' => self.single_token(Token::RBrace, start, start_line, start_col),
             '[' => self.single_token(Token::LBracket, start, start_line, start_col),
             ']' => self.single_token(Token::RBracket, start, start_line, start_col),
-            '<' => self.single_token(Token::LAngle, start, start_line, start_col),
-            '>' => self.single_token(Token::RAngle, start, start_line, start_col),
+            '#' if self.keep_trivia => {
+                self.consume_line_comment();
+                let span = Span::new(self.file_id, start, self.pos, start_line, start_col);
+                Some(SpannedToken::new(Token::Comment, span))
+            }
             '#' => self.skip_line_comment(),
             '@' => self.single_token(Token::At, start, start_line, start_col),
             '$' => self.single_token(Token::Dollar, start, start_line, start_col),
@@ -918,11 +1141,16 @@ impl<'a> Lexer<'a> {
             }
             '"' => self.read_string(start, start_line, start_col),
             '\'' => self.read_char(start, start_line, start_col),
+            'r' if self.is_raw_identifier_start() => self.read_raw_identifier(start_line),
             'r' if self.source[self.pos..].starts_with("r#") => self.read_raw_string(start, start_line, start_col),
             'b' if self.source[self.pos..].starts_with("b\"") => self.read_byte_string(start, start_line, start_col),
             'b' if self.source[self.pos..].starts_with("b'") => self.read_byte(start, start_line, start_col),
             c if c.is_whitespace() => {
                 self.skip_whitespace();
+                if self.keep_trivia {
+                    let span = Span::new(self.file_id, start, self.pos, start_line, start_col);
+                    return Some(SpannedToken::new(Token::Whitespace, span));
+                }
                 self.next_token()
             }
             c if c.is_alphabetic() || c == '_' => self.read_identifier(start, start_line, start_col),
@@ -958,10 +1186,14 @@ impl<'a> Lexer<'a> {
     }
 
     fn skip_line_comment(&mut self) -> Option<SpannedToken> {
+        self.consume_line_comment();
+        self.next_token()
+    }
+
+    fn consume_line_comment(&mut self) {
         while self.pos < self.source.len() && !self.source[self.pos..].starts_with('\n') {
             self.pos += 1;
         }
-        self.next_token()
     }
 
     fn skip_block_comment(&mut self) {
@@ -990,7 +1222,7 @@ impl<'a> Lexer<'a> {
         }
         if self.pos >= self.source.len() {
             let span = Span::new(self.file_id, start, self.pos, start_line, start_col);
-            self.report_error("unterminated string literal".to_string(), span);
+            self.report_error_kind(ErrorKind::UnterminatedString, "unterminated string literal".to_string(), span);
         } else {
             self.pos += 1;
         }
@@ -1015,7 +1247,7 @@ impl<'a> Lexer<'a> {
         }
         
         let span = Span::new(self.file_id, start, self.pos, start_line, start_col);
-        self.report_error("unterminated raw string literal".to_string(), span);
+        self.report_error_kind(ErrorKind::UnterminatedString, "unterminated raw string literal".to_string(), span);
         Some(SpannedToken::new(Token::RawString, span))
     }
 
@@ -1049,7 +1281,7 @@ impl<'a> Lexer<'a> {
         }
         if self.pos >= self.source.len() {
             let span = Span::new(self.file_id, start, self.pos, start_line, start_col);
-            self.report_error("unterminated byte string literal".to_string(), span);
+            self.report_error_kind(ErrorKind::UnterminatedString, "unterminated byte string literal".to_string(), span);
         } else {
             self.pos += 1;
         }
@@ -1059,6 +1291,16 @@ impl<'a> Lexer<'a> {
 
     fn read_char(&mut self, start: usize, start_line: usize, start_col: usize) -> Option<SpannedToken> {
         self.pos += 1;
+        if self.at_lifetime_name() {
+            while let Some(c) = self.source[self.pos..].chars().next() {
+                if !(c.is_alphanumeric() || c == '_') {
+                    break;
+                }
+                self.pos += c.len_utf8();
+            }
+            let span = Span::new(self.file_id, start, self.pos, start_line, start_col);
+            return Some(SpannedToken::new(Token::Lifetime, span));
+        }
         if self.pos < self.source.len() && self.source[self.pos..].starts_with('\\') {
             self.pos += 2;
         } else if self.pos < self.source.len() {
@@ -1074,6 +1316,16 @@ impl<'a> Lexer<'a> {
         Some(SpannedToken::new(Token::Char, span))
     }
 
+    /// After a `'`: an identifier not closed by another `'` is a lifetime
+    /// (`'a`), whereas `'a'` is a char literal.
+    fn at_lifetime_name(&self) -> bool {
+        let mut chars = self.source[self.pos..].chars();
+        match chars.next() {
+            Some(c) if c.is_alphabetic() || c == '_' => chars.next() != Some('\''),
+            _ => false,
+        }
+    }
+
     fn read_byte(&mut self, start: usize, start_line: usize, start_col: usize) -> Option<SpannedToken> {
         self.pos += 2;
         if self.pos < self.source.len() && self.source[self.pos..].starts_with('\\') {
@@ -1115,8 +1367,87 @@ impl<'a> Lexer<'a> {
         Some(SpannedToken::new(token, span))
     }
 
+    fn is_raw_identifier_start(&self) -> bool {
+        if !self.source[self.pos..].starts_with("r#") {
+            return false;
+        }
+        match self.source[self.pos + 2..].chars().next() {
+            Some(c) => c.is_alphabetic() || c == '_',
+            None => false,
+        }
+    }
+
+    /// Lexes `r#name` as a plain identifier, bypassing the keyword table.
+    /// The span covers only `name`, so the `r#` prefix is not part of the identifier text.
+    fn read_raw_identifier(&mut self, start_line: usize) -> Option<SpannedToken> {
+        self.pos += 2;
+        let start = self.pos;
+        let start_col = self.column();
+        while self.pos < self.source.len() {
+            let c = self.source[self.pos..].chars().next().unwrap();
+            if c.is_alphanumeric() || c == '_' || c == '$' || c as u32 > 0x4E00 {
+                self.pos += c.len_utf8();
+            } else {
+                break;
+            }
+        }
+
+        let span = Span::new(self.file_id, start, self.pos, start_line, start_col);
+        Some(SpannedToken::new(Token::Identifier, span))
+    }
+
+    fn finish_prefixed_int(&mut self, start: usize, start_line: usize, start_col: usize) -> Option<SpannedToken> {
+        let span = Span::new(self.file_id, start, self.pos, start_line, start_col);
+        if !self.source[start + 2..self.pos].chars().any(|c| c != '_') {
+            self.report_error_kind(
+                ErrorKind::InvalidLiteral,
+                "missing digits after integer base prefix".to_string(),
+                span,
+            );
+        } else {
+            self.check_separators(start + 2, |c| c.is_ascii_alphanumeric(), span);
+        }
+        Some(SpannedToken::new(Token::Int, span))
+    }
+
+    /// `_` may only separate digits: it can't lead or trail them, or touch the
+    /// base prefix, the decimal point or the exponent marker.
+    fn check_separators(&mut self, digits_start: usize, is_digit: fn(&u8) -> bool, span: Span) {
+        let bytes = &self.source.as_bytes()[digits_start..self.pos];
+        let misplaced = bytes.iter().enumerate().any(|(i, &c)| {
+            if c != b'_' {
+                return false;
+            }
+            let before = bytes[..i].iter().rev().find(|&&c| c != b'_');
+            let after = bytes[i + 1..].iter().find(|&&c| c != b'_');
+            !(before.is_some_and(is_digit) && after.is_some_and(is_digit))
+        });
+        if misplaced {
+            self.report_error_kind(
+                ErrorKind::InvalidLiteral,
+                "invalid numeric separator: `_` must be between digits".to_string(),
+                span,
+            );
+        }
+    }
+
+    fn digit_at(&self, pos: usize) -> bool {
+        self.source.as_bytes().get(pos).is_some_and(u8::is_ascii_digit)
+    }
+
     fn read_number(&mut self, start: usize, start_line: usize, start_col: usize) -> Option<SpannedToken> {
         let original_pos = self.pos;
+
+        // A tuple index (`t.0.1`) is a plain integer; lexing it as a number would
+        // turn `0.1` into a float.
+        let before = &self.source[..self.pos];
+        if before.ends_with('.') && !before.ends_with("..") {
+            while self.digit_at(self.pos) {
+                self.pos += 1;
+            }
+            let span = Span::new(self.file_id, start, self.pos, start_line, start_col);
+            return Some(SpannedToken::new(Token::Int, span));
+        }
         
         if self.source[self.pos..].starts_with("0x") || self.source[self.pos..].starts_with("0X") {
             self.pos += 2;
@@ -1128,8 +1459,7 @@ impl<'a> Lexer<'a> {
                     break;
                 }
             }
-            let span = Span::new(self.file_id, start, self.pos, start_line, start_col);
-            return Some(SpannedToken::new(Token::Int, span));
+            return self.finish_prefixed_int(start, start_line, start_col);
         }
         
         if self.source[self.pos..].starts_with("0b") || self.source[self.pos..].starts_with("0B") {
@@ -1142,8 +1472,7 @@ impl<'a> Lexer<'a> {
                     break;
                 }
             }
-            let span = Span::new(self.file_id, start, self.pos, start_line, start_col);
-            return Some(SpannedToken::new(Token::Int, span));
+            return self.finish_prefixed_int(start, start_line, start_col);
         }
         
         if self.source[self.pos..].starts_with("0o") || self.source[self.pos..].starts_with("0O") {
@@ -1156,8 +1485,7 @@ impl<'a> Lexer<'a> {
                     break;
                 }
             }
-            let span = Span::new(self.file_id, start, self.pos, start_line, start_col);
-            return Some(SpannedToken::new(Token::Int, span));
+            return self.finish_prefixed_int(start, start_line, start_col);
         }
         
         if self.source[self.pos..].starts_with("0t") || self.source[self.pos..].starts_with("0T") {
@@ -1170,8 +1498,7 @@ impl<'a> Lexer<'a> {
                     break;
                 }
             }
-            let span = Span::new(self.file_id, start, self.pos, start_line, start_col);
-            return Some(SpannedToken::new(Token::Int, span));
+            return self.finish_prefixed_int(start, start_line, start_col);
         }
         
         if self.source[self.pos..].starts_with("0e") || self.source[self.pos..].starts_with("0E") {
@@ -1184,50 +1511,46 @@ impl<'a> Lexer<'a> {
                     break;
                 }
             }
-            let span = Span::new(self.file_id, start, self.pos, start_line, start_col);
-            return Some(SpannedToken::new(Token::Int, span));
+            return self.finish_prefixed_int(start, start_line, start_col);
         }
         
         if self.source[self.pos..].starts_with("0d") || self.source[self.pos..].starts_with("0D") {
             self.pos += 2;
             while self.pos < self.source.len() {
                 let c = self.source[self.pos..].chars().next().unwrap();
-                if ('0'..='9').contains(&c) || ('a'..='b').contains(&c) || ('A'..='B').contains(&c) || c == '_' {
+                if c.is_ascii_digit() || ('a'..='b').contains(&c) || ('A'..='B').contains(&c) || c == '_' {
                     self.pos += 1;
                 } else {
                     break;
                 }
             }
-            let span = Span::new(self.file_id, start, self.pos, start_line, start_col);
-            return Some(SpannedToken::new(Token::Int, span));
+            return self.finish_prefixed_int(start, start_line, start_col);
         }
         
         if self.source[self.pos..].starts_with("0h") || self.source[self.pos..].starts_with("0H") {
             self.pos += 2;
             while self.pos < self.source.len() {
                 let c = self.source[self.pos..].chars().next().unwrap();
-                if ('0'..='9').contains(&c) || ('a'..='n').contains(&c) || ('A'..='N').contains(&c) || c == '_' {
+                if c.is_ascii_digit() || ('a'..='n').contains(&c) || ('A'..='N').contains(&c) || c == '_' {
                     self.pos += 1;
                 } else {
                     break;
                 }
             }
-            let span = Span::new(self.file_id, start, self.pos, start_line, start_col);
-            return Some(SpannedToken::new(Token::Int, span));
+            return self.finish_prefixed_int(start, start_line, start_col);
         }
         
         if self.source[self.pos..].starts_with("0s") || self.source[self.pos..].starts_with("0S") {
             self.pos += 2;
             while self.pos < self.source.len() {
                 let c = self.source[self.pos..].chars().next().unwrap();
-                if ('0'..='9').contains(&c) || ('a'..='z').contains(&c) || ('A'..='Z').contains(&c) || c == '_' {
+                if c.is_ascii_alphanumeric() || c == '_' {
                     self.pos += 1;
                 } else {
                     break;
                 }
             }
-            let span = Span::new(self.file_id, start, self.pos, start_line, start_col);
-            return Some(SpannedToken::new(Token::Int, span));
+            return self.finish_prefixed_int(start, start_line, start_col);
         }
 
         let mut has_dot = false;
@@ -1237,9 +1560,27 @@ impl<'a> Lexer<'a> {
             let c = self.source[self.pos..].chars().next().unwrap();
             if c.is_ascii_digit() {
                 self.pos += 1;
-            } else if c == '.' && !has_dot {
+            } else if c == '.' && !has_dot && self.digit_at(self.pos + 1) {
+                // Only a digit makes this a decimal point; otherwise it starts a
+                // range (`1..2`) or a method call (`1.foo()`).
                 has_dot = true;
                 self.pos += 1;
+            } else if c == '.' && self.digit_at(self.pos + 1) {
+                // `1.2.3`: take the whole run so it is reported once rather than
+                // read as a field access on `1.2`.
+                while self.pos < self.source.len()
+                    && (self.digit_at(self.pos) || self.source[self.pos..].starts_with('_')
+                        || (self.source[self.pos..].starts_with('.') && self.digit_at(self.pos + 1)))
+                {
+                    self.pos += 1;
+                }
+                let span = Span::new(self.file_id, start, self.pos, start_line, start_col);
+                self.report_error_kind(
+                    ErrorKind::InvalidLiteral,
+                    "invalid float literal: multiple decimal points".to_string(),
+                    span,
+                );
+                break;
             } else if (c == 'e' || c == 'E') && !has_exponent {
                 has_exponent = true;
                 self.pos += 1;
@@ -1263,10 +1604,12 @@ impl<'a> Lexer<'a> {
         }
 
         let span = Span::new(self.file_id, start, self.pos, start_line, start_col);
+        self.check_separators(start, u8::is_ascii_digit, span);
         Some(SpannedToken::new(if has_dot || has_exponent { Token::Float } else { Token::Int }, span))
     }
 }
 
+#[derive(Debug)]
 pub struct TokenStream {
     tokens: Vec<SpannedToken>,
     current: usize,
@@ -1288,12 +1631,14 @@ impl TokenStream {
         self.tokens.get(self.current + n)
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Option<&SpannedToken> {
         let token = self.tokens.get(self.current);
         self.current += 1;
         token
     }
 
+    #[allow(clippy::result_unit_err)]
     pub fn expect(&mut self, expected: Token) -> Result<&SpannedToken, ()> {
         if let Some(token) = self.peek() {
             if token.token == expected {
@@ -1307,24 +1652,61 @@ impl TokenStream {
         self.current >= self.tokens.len()
     }
 
+    /// The span of the most recently consumed token, for errors that belong
+    /// just after it, like a missing `;`. `None` before the first token.
+    pub fn prev_span(&self) -> Option<Span> {
+        let prev = self.current.checked_sub(1)?;
+        self.tokens.get(prev).map(|token| token.span)
+    }
+
     pub fn remaining(&self) -> &[SpannedToken] {
         &self.tokens[self.current..]
     }
 
+    /// The tokens from position `start` up to, not including, `end`, as
+    /// returned by `position()`; out-of-range bounds are clamped.
+    pub fn slice_between(&self, start: usize, end: usize) -> &[SpannedToken] {
+        let end = end.min(self.tokens.len());
+        &self.tokens[start.min(end)..end]
+    }
+
     pub fn consume(&mut self, token: Token) -> bool {
-        if let Some(t) = self.peek() {
-            if t.token == token {
-                self.next();
-                return true;
-            }
+        self.eat(token)
+    }
+
+    /// Whether the next token is `token`, without consuming it.
+    pub fn peek_is(&self, token: Token) -> bool {
+        self.peek().is_some_and(|t| t.token == token)
+    }
+
+    pub fn peek_kind(&self) -> Option<Token> {
+        self.peek().map(|t| t.token)
+    }
+
+    /// Consumes the next token if it is `token`; reports whether it did.
+    pub fn eat(&mut self, token: Token) -> bool {
+        if self.peek_is(token) {
+            self.current += 1;
+            true
+        } else {
+            false
         }
-        false
     }
 
     pub fn position(&self) -> usize {
         self.current
     }
 
+    /// Splits a compound token in place, consuming its first character; used to
+    /// close nested generics like `Vec<Vec<T>>` where `>>` lexes as one token.
+    pub fn split_current(&mut self, rest: Token) {
+        if let Some(token) = self.tokens.get_mut(self.current) {
+            token.token = rest;
+            token.span.start += 1;
+            token.span.column += 1;
+        }
+    }
+
     pub fn set_position(&mut self, pos: usize) {
         self.current = pos;
     }
@@ -1332,8 +1714,13 @@ impl TokenStream {
     pub fn len(&self) -> usize {
         self.tokens.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
 }
 
+#[derive(Debug, Default)]
 pub struct Rodeo {
     strings: Vec<String>,
 }
@@ -1361,17 +1748,84 @@ impl Rodeo {
     pub fn len(&self) -> usize {
         self.strings.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Spur(usize);
 
-pub fn tokenize(source: &str, file_id: FileId) -> (Vec<SpannedToken>, Rodeo, Vec<LexerError>) {
-    let mut interner = Rodeo::new();
+/// Everything lexing one source file produces.
+#[derive(Debug)]
+pub struct TokenizeResult {
+    pub tokens: Vec<SpannedToken>,
+    /// The text of every identifier in `tokens`, each distinct name once.
+    pub interner: Rodeo,
+    pub errors: Vec<LexerError>,
+}
+
+impl TokenizeResult {
+    fn new(source: &str, tokens: Vec<SpannedToken>, errors: Vec<LexerError>) -> Self {
+        let mut interner = Rodeo::new();
+        for token in tokens.iter().filter(|token| token.token == Token::Identifier) {
+            interner.get_or_intern(token.text(source));
+        }
+        TokenizeResult { tokens, interner, errors }
+    }
+}
+
+pub fn tokenize(source: &str, file_id: FileId) -> TokenizeResult {
     let mut lexer = Lexer::new(source, file_id);
     let tokens = lexer.tokenize();
     let errors = lexer.take_errors();
-    (tokens, interner, errors)
+    TokenizeResult::new(source, tokens, errors)
+}
+
+/// Like `tokenize`, but with columns computed for tabs `tab_width` wide.
+pub fn tokenize_with_tab_width(source: &str, file_id: FileId, tab_width: usize) -> TokenizeResult {
+    let mut lexer = Lexer::new(source, file_id).with_tab_width(tab_width);
+    let tokens = lexer.tokenize();
+    let errors = lexer.take_errors();
+    TokenizeResult::new(source, tokens, errors)
+}
+
+pub fn tokenize_with_trivia(source: &str, file_id: FileId) -> TokenizeResult {
+    let mut lexer = Lexer::new(source, file_id);
+    let tokens = lexer.tokenize_with_trivia();
+    let errors = lexer.take_errors();
+    TokenizeResult::new(source, tokens, errors)
+}
+
+/// The `///` doc comments among `tokens`, keyed by the start offset of the
+/// token each documents: the first token after it. Consecutive lines are
+/// joined with newlines, without the `///` and one space after it. An
+/// ordinary comment ends the run, so only the lines after it are kept.
+///
+/// `tokens` are the tokens of `source` with trivia, from
+/// `tokenize_with_trivia`, so `///` inside a block comment or a string is
+/// never taken for a doc comment.
+pub fn doc_comments(source: &str, tokens: &[SpannedToken]) -> HashMap<usize, String> {
+    let mut docs = HashMap::new();
+    let mut lines = Vec::new();
+    for token in tokens {
+        match token.token {
+            Token::DocComment => {
+                let text = &token.text(source)[3..];
+                lines.push(text.strip_prefix(' ').unwrap_or(text).trim_end());
+            }
+            Token::Comment | Token::BlockComment => lines.clear(),
+            Token::Whitespace => {}
+            _ => {
+                if !lines.is_empty() {
+                    docs.insert(token.span.start, lines.join("\n"));
+                    lines.clear();
+                }
+            }
+        }
+    }
+    docs
 }
 
 #[cfg(test)]
@@ -1381,113 +1835,170 @@ mod tests {
     #[test]
     fn test_tokenize_keywords() {
         let source = "let x = 42;";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().any(|t| t.token == Token::Let));
         assert!(tokens.iter().any(|t| t.token == Token::Semicolon));
     }
 
+    #[test]
+    fn test_tab_width_columns() {
+        let source = "fn f() {\n\tlet x = 1;\n\t\tx\n}";
+        let column_of = |tokens: &[SpannedToken], token: Token| {
+            tokens.iter().find(|t| t.token == token).unwrap().span.column
+        };
+
+        let raw = tokenize(source, FileId::new(0)).tokens;
+        assert_eq!(column_of(&raw, Token::Let), 1);
+
+        let expanded = tokenize_with_tab_width(source, FileId::new(0), 4).tokens;
+        assert_eq!(column_of(&expanded, Token::Let), 4);
+        assert_eq!(column_of(&expanded, Token::Eq), 10);
+        let x = expanded.iter().rfind(|t| t.token == Token::Identifier).unwrap();
+        assert_eq!((x.span.line, x.span.column), (3, 8));
+
+        let aligned = tokenize_with_tab_width("ab\tc", FileId::new(0), 4).tokens;
+        assert_eq!(aligned[1].span.column, 4);
+    }
+
+    #[test]
+    fn test_whitespace_lints() {
+        let source = "fn f() {  \n\t  let x = 1;\n    x\t\n}\n";
+
+        let mut lexer = Lexer::new(source, FileId::new(0));
+        lexer.tokenize();
+        assert!(lexer.warnings().is_empty());
+
+        let mut lexer = Lexer::new(source, FileId::new(0)).with_whitespace_lints(true);
+        lexer.tokenize();
+        assert!(lexer.errors().is_empty());
+        let warnings: Vec<(&str, usize, usize, usize)> = lexer.warnings().iter()
+            .map(|w| (w.message.as_str(), w.span.line, w.span.start, w.span.end))
+            .collect();
+        assert_eq!(warnings, vec![
+            ("trailing whitespace", 1, 8, 10),
+            ("indentation mixes tabs and spaces", 2, 11, 14),
+            ("trailing whitespace", 3, 30, 31),
+        ]);
+        assert!(lexer.warnings().iter().all(LexerError::is_warning));
+
+        // Whitespace inside a string literal is its content.
+        let mut lexer = Lexer::new("let s = \"a  \n\t  b\";\n", FileId::new(0)).with_whitespace_lints(true);
+        lexer.tokenize();
+        assert!(lexer.warnings().is_empty());
+    }
+
     #[test]
     fn test_tokenize_chinese_keywords() {
         let source = "令 x = 42;";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
-        assert!(tokens.iter().any(|t| t.token == Token::Let));
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
+        assert!(tokens.iter().any(|t| t.token == Token::LetAlt));
     }
 
     #[test]
     fn test_tokenize_number() {
         let source = "42";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().any(|t| t.token == Token::Int));
     }
 
     #[test]
     fn test_tokenize_float() {
         let source = "3.14";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().any(|t| t.token == Token::Float));
     }
 
     #[test]
     fn test_tokenize_hex() {
         let source = "0xFF";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().any(|t| t.token == Token::Int));
     }
 
     #[test]
     fn test_tokenize_binary() {
         let source = "0b1010";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().any(|t| t.token == Token::Int));
     }
 
     #[test]
     fn test_tokenize_octal() {
         let source = "0o755";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().any(|t| t.token == Token::Int));
     }
 
     #[test]
     fn test_tokenize_scientific() {
         let source = "1.5e10";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().any(|t| t.token == Token::Float));
     }
 
     #[test]
     fn test_tokenize_string() {
         let source = "\"hello\"";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().any(|t| t.token == Token::String));
     }
 
     #[test]
     fn test_tokenize_raw_string() {
         let source = "r#\"hello\"#";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().any(|t| t.token == Token::RawString));
     }
 
+    #[test]
+    fn test_tokenize_raw_identifier() {
+        let source = "r#map map";
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
+        assert_eq!(tokens[0].token, Token::Identifier);
+        assert_eq!(&source[tokens[0].span.start..tokens[0].span.end], "map");
+        assert_eq!(tokens[1].token, Token::Map);
+    }
+
     #[test]
     fn test_tokenize_byte_string() {
         let source = "b\"hello\"";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().any(|t| t.token == Token::ByteString));
     }
 
     #[test]
     fn test_tokenize_char() {
         let source = "'a'";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().any(|t| t.token == Token::Char));
     }
 
     #[test]
     fn test_tokenize_byte() {
         let source = "b'a'";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().any(|t| t.token == Token::Byte));
     }
 
     #[test]
     fn test_tokenize_operators() {
         let source = "+ - * / % == != < > <= >= && || ! & | ^ << >>";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().any(|t| t.token == Token::Plus));
         assert!(tokens.iter().any(|t| t.token == Token::EqEq));
         assert!(tokens.iter().any(|t| t.token == Token::LShift));
@@ -1496,8 +2007,8 @@ mod tests {
     #[test]
     fn test_tokenize_compound_assignment() {
         let source = "+= -= *= /= %= &= |= ^= <<= >>=";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().any(|t| t.token == Token::PlusEq));
         assert!(tokens.iter().any(|t| t.token == Token::LShiftEq));
     }
@@ -1505,73 +2016,90 @@ mod tests {
     #[test]
     fn test_tokenize_comments() {
         let source = "// line comment\nlet x = 42; /* block comment */";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().any(|t| t.token == Token::Let));
         assert!(!tokens.iter().any(|t| t.token == Token::Comment));
     }
 
+    #[test]
+    fn test_tokenize_with_trivia_keeps_comments() {
+        let source = "// note\nlet x = 1; /* block */";
+        let file_id = FileId::new(0);
+        let tokens = tokenize_with_trivia(source, file_id).tokens;
+        let comment = tokens.iter().find(|t| t.token == Token::Comment).unwrap();
+        assert_eq!(comment.text(source), "// note");
+        let block = tokens.iter().find(|t| t.token == Token::BlockComment).unwrap();
+        assert_eq!(block.text(source), "/* block */");
+        assert!(tokens.iter().any(|t| t.token == Token::Whitespace));
+        let rebuilt: String = tokens.iter().map(|t| t.text(source)).collect();
+        assert_eq!(rebuilt, source);
+
+        let plain = tokenize(source, file_id).tokens;
+        assert!(!plain.iter().any(|t| t.is_trivia()));
+    }
+
     #[test]
     fn test_tokenize_underscore() {
         let source = "_";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().any(|t| t.token == Token::Underscore));
     }
 
     #[test]
     fn test_tokenize_double_colon() {
         let source = "std::collections::HashMap";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().filter(|t| t.token == Token::DoubleColon).count() == 2);
     }
 
     #[test]
     fn test_tokenize_question() {
         let source = "x ? y : z";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().any(|t| t.token == Token::Question));
     }
 
     #[test]
     fn test_tokenize_double_question() {
         let source = "x ?? y";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().any(|t| t.token == Token::DoubleQuestion));
     }
 
     #[test]
     fn test_tokenize_arrow() {
         let source = "->";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().any(|t| t.token == Token::ThinArrow));
     }
 
     #[test]
     fn test_tokenize_thick_arrow() {
         let source = "=>";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().any(|t| t.token == Token::Arrow));
     }
 
     #[test]
     fn test_tokenize_at_sign() {
         let source = "@attribute";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().any(|t| t.token == Token::At));
     }
 
     #[test]
     fn test_tokenize_dollar() {
         let source = "$lifetime";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().any(|t| t.token == Token::Dollar));
     }
 
@@ -1591,8 +2119,8 @@ mod tests {
     #[test]
     fn test_tokenize_extended_memory_order() {
         let source = "consume happensbefore volatile memorybarrier wait notify notifyall datadependency";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().any(|t| t.token == Token::Consume));
         assert!(tokens.iter().any(|t| t.token == Token::HappensBefore));
         assert!(tokens.iter().any(|t| t.token == Token::Volatile));
@@ -1606,8 +2134,8 @@ mod tests {
     #[test]
     fn test_tokenize_chinese_extended_memory_order() {
         let source = "消费 发生前 易变 内存屏障 等待 通知 通知全部 数据依赖";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().any(|t| t.token == Token::Consume));
         assert!(tokens.iter().any(|t| t.token == Token::HappensBefore));
         assert!(tokens.iter().any(|t| t.token == Token::Volatile));
@@ -1620,12 +2148,13 @@ mod tests {
 
     #[test]
     fn test_tokenize_language_features() {
-        let source = "pattern range guard generic forall default sync sized intoiterator";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let source = "pattern range guard defer generic forall default sync sized intoiterator";
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().any(|t| t.token == Token::Pattern));
         assert!(tokens.iter().any(|t| t.token == Token::Range));
         assert!(tokens.iter().any(|t| t.token == Token::Guard));
+        assert!(tokens.iter().any(|t| t.token == Token::Defer));
         assert!(tokens.iter().any(|t| t.token == Token::Generic));
         assert!(tokens.iter().any(|t| t.token == Token::ForAll));
         assert!(tokens.iter().any(|t| t.token == Token::Default));
@@ -1636,12 +2165,13 @@ mod tests {
 
     #[test]
     fn test_tokenize_chinese_language_features() {
-        let source = "模式 范围 守卫 泛型 全称 默认 同步 大小 迭代器";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let source = "模式 范围 守卫 延迟 泛型 全称 默认 同步 大小 迭代器";
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().any(|t| t.token == Token::Pattern));
         assert!(tokens.iter().any(|t| t.token == Token::Range));
         assert!(tokens.iter().any(|t| t.token == Token::Guard));
+        assert!(tokens.iter().any(|t| t.token == Token::Defer));
         assert!(tokens.iter().any(|t| t.token == Token::Generic));
         assert!(tokens.iter().any(|t| t.token == Token::ForAll));
         assert!(tokens.iter().any(|t| t.token == Token::Default));
@@ -1653,8 +2183,8 @@ mod tests {
     #[test]
     fn test_tokenize_macro_closure() {
         let source = "macro macrorules procedural functional attribute derive closure capture captureref capturevalue";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().any(|t| t.token == Token::Macro));
         assert!(tokens.iter().any(|t| t.token == Token::MacroRules));
         assert!(tokens.iter().any(|t| t.token == Token::Procedural));
@@ -1670,8 +2200,8 @@ mod tests {
     #[test]
     fn test_tokenize_chinese_macro_closure() {
         let source = "宏 宏规则 过程式 函数式 属性 派生 闭包 捕获 捕获引用 捕获值";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().any(|t| t.token == Token::Macro));
         assert!(tokens.iter().any(|t| t.token == Token::MacroRules));
         assert!(tokens.iter().any(|t| t.token == Token::Procedural));
@@ -1687,8 +2217,8 @@ mod tests {
     #[test]
     fn test_tokenize_iterator_error_handling() {
         let source = "iterator next item collect chain filter fold map result ok err try catch error context throw";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().any(|t| t.token == Token::Iterator));
         assert!(tokens.iter().any(|t| t.token == Token::Next));
         assert!(tokens.iter().any(|t| t.token == Token::Item));
@@ -1710,8 +2240,8 @@ mod tests {
     #[test]
     fn test_tokenize_chinese_iterator_error_handling() {
         let source = "迭代 下一个 项 收集 链 过滤 折叠 映射 结果 成功 错误 尝试 捕获异常 异常类型 上下文 抛出";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().any(|t| t.token == Token::Iterator));
         assert!(tokens.iter().any(|t| t.token == Token::Next));
         assert!(tokens.iter().any(|t| t.token == Token::Item));
@@ -1733,8 +2263,8 @@ mod tests {
     #[test]
     fn test_tokenize_async_programming() {
         let source = "future yield stream";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().any(|t| t.token == Token::Future));
         assert!(tokens.iter().any(|t| t.token == Token::Yield));
         assert!(tokens.iter().any(|t| t.token == Token::Stream));
@@ -1743,10 +2273,118 @@ mod tests {
     #[test]
     fn test_tokenize_chinese_async_programming() {
         let source = "未来 产出 流";
-        let file_id = FileId(0);
-        let (tokens, _) = tokenize(source, file_id);
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
         assert!(tokens.iter().any(|t| t.token == Token::Future));
         assert!(tokens.iter().any(|t| t.token == Token::Yield));
         assert!(tokens.iter().any(|t| t.token == Token::Stream));
     }
+
+    #[test]
+    fn test_token_stream_eat() {
+        let source = "( x";
+        let file_id = FileId::new(0);
+        let tokens = tokenize(source, file_id).tokens;
+        let mut stream = TokenStream::new(tokens);
+
+        assert!(stream.peek_is(Token::LParen));
+        assert!(!stream.eat(Token::RParen));
+        assert_eq!(stream.position(), 0);
+        assert!(stream.eat(Token::LParen));
+        assert_eq!(stream.position(), 1);
+        assert_eq!(stream.peek_kind(), Some(Token::Identifier));
+    }
+
+    #[test]
+    fn test_tokenize_multiple_decimal_points() {
+        let file_id = FileId::new(0);
+        let TokenizeResult { tokens, errors, .. } = tokenize("1.2.3", file_id);
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].token, Token::Float);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, ErrorKind::InvalidLiteral);
+    }
+
+    #[test]
+    fn test_tokenize_int_range() {
+        let file_id = FileId::new(0);
+        let TokenizeResult { tokens, errors, .. } = tokenize("1..2", file_id);
+        let kinds: Vec<Token> = tokens.into_iter().map(|t| t.token).collect();
+        assert_eq!(kinds, vec![Token::Int, Token::DotDot, Token::Int]);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn test_tokenize_tuple_index() {
+        let file_id = FileId::new(0);
+        let TokenizeResult { tokens, errors, .. } = tokenize("t.0.1", file_id);
+        assert!(errors.is_empty());
+        let kinds: Vec<Token> = tokens.into_iter().map(|t| t.token).collect();
+        assert_eq!(kinds, vec![Token::Identifier, Token::Dot, Token::Int, Token::Dot, Token::Int]);
+    }
+
+    #[test]
+    fn test_tokenize_method_call_on_int() {
+        let file_id = FileId::new(0);
+        let tokens = tokenize("1.foo()", file_id).tokens;
+        let kinds: Vec<Token> = tokens.into_iter().map(|t| t.token).collect();
+        assert_eq!(kinds, vec![Token::Int, Token::Dot, Token::Identifier, Token::LParen, Token::RParen]);
+
+        let tokens = tokenize("1.5", file_id).tokens;
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].token, Token::Float);
+    }
+
+    #[test]
+    fn test_tokenize_lifetime_vs_char() {
+        let file_id = FileId::new(0);
+        let TokenizeResult { tokens, errors, .. } = tokenize("&'a T", file_id);
+        assert!(errors.is_empty());
+        let kinds: Vec<Token> = tokens.iter().map(|t| t.token).collect();
+        assert_eq!(kinds, vec![Token::Ampersand, Token::Lifetime, Token::Identifier]);
+        assert_eq!(tokens[1].span.end - tokens[1].span.start, 2);
+
+        let TokenizeResult { tokens, errors, .. } = tokenize("'a'", file_id);
+        assert!(errors.is_empty());
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].token, Token::Char);
+    }
+
+    #[test]
+    fn test_tokenize_inclusive_range() {
+        let file_id = FileId::new(0);
+        let TokenizeResult { tokens, errors, .. } = tokenize("1..=5", file_id);
+        assert!(errors.is_empty());
+        let kinds: Vec<Token> = tokens.into_iter().map(|t| t.token).collect();
+        assert_eq!(kinds, vec![Token::Int, Token::DotDotEq, Token::Int]);
+    }
+
+    #[test]
+    fn test_tokenize_numeric_separators() {
+        let file_id = FileId::new(0);
+        for source in ["1_000", "0xFF_FF", "1_000.000_1", "0b1010_1010"] {
+            let TokenizeResult { tokens, errors, .. } = tokenize(source, file_id);
+            assert!(errors.is_empty(), "unexpected error for `{}`", source);
+            assert_eq!(tokens.len(), 1);
+        }
+
+        for source in ["1_.0", "0x_1", "1e_5", "10_"] {
+            let errors = tokenize(source, file_id).errors;
+            assert_eq!(errors.len(), 1, "expected one error for `{}`", source);
+            assert_eq!(errors[0].kind, ErrorKind::InvalidLiteral);
+        }
+
+        // A leading underscore makes an identifier, not a number.
+        let TokenizeResult { tokens, errors, .. } = tokenize("_1", file_id);
+        assert!(errors.is_empty());
+        assert_eq!(tokens[0].token, Token::Identifier);
+    }
+
+    #[test]
+    fn test_tokenize_result_interns_identifiers() {
+        let TokenizeResult { tokens, interner, errors } = tokenize("let x = y + x; let 值 = x;", FileId::new(0));
+        assert!(errors.is_empty());
+        assert_eq!(tokens.iter().filter(|t| t.token == Token::Identifier).count(), 5);
+        assert_eq!(interner.len(), 3);
+    }
 }
@@ -0,0 +1,81 @@
+use crate::parse;
+use chim_ast::Program;
+use chim_error::ChimError;
+use chim_span::FileId;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+/// Parse results kept across runs of a build, so files whose content hasn't
+/// changed aren't parsed again.
+///
+/// Each file keeps the result for its latest content only: parsing different
+/// content for the same `FileId` replaces the entry.
+#[derive(Debug, Default)]
+pub struct ParseCache {
+    entries: HashMap<FileId, CacheEntry>,
+    hits: usize,
+    misses: usize,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    content_hash: u64,
+    result: Result<Program, Vec<ChimError>>,
+}
+
+impl ParseCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How many lookups were served from the cache.
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// How many lookups had to parse.
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Drops the entry for `file_id`, e.g. when the file is deleted.
+    pub fn invalidate(&mut self, file_id: FileId) {
+        self.entries.remove(&file_id);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+fn content_hash(source: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Like `parse`, but returns the cached result when `source` is what was
+/// last parsed for `file_id`.
+pub fn parse_cached(source: &str, file_id: FileId, cache: &mut ParseCache) -> Result<Program, Vec<ChimError>> {
+    let content_hash = content_hash(source);
+    if let Some(entry) = cache.entries.get(&file_id) {
+        if entry.content_hash == content_hash {
+            cache.hits += 1;
+            return entry.result.clone();
+        }
+    }
+
+    cache.misses += 1;
+    let result = parse(source, file_id);
+    cache.entries.insert(file_id, CacheEntry { content_hash, result: result.clone() });
+    result
+}
@@ -1,32 +1,120 @@
-use chim_lexer::{Token, SpannedToken, TokenStream, Identifier};
+// `ChimError` carries its labels, notes and suggestions inline.
+#![allow(clippy::result_large_err)]
+
+use chim_lexer::{
+    doc_comments, keyword_spelling, Dialect, LexerError, Token, SpannedToken, TokenStream, TokenizeResult,
+    CONTEXTUAL_KEYWORDS,
+};
 use chim_ast::*;
 use chim_span::{FileId, Span};
 use chim_error::{ChimError, ErrorKind};
+use std::collections::HashMap;
 use std::sync::Arc;
 use smallvec::SmallVec;
 
+mod cache;
+mod loader;
+mod macros;
+
+pub use cache::{parse_cached, ParseCache};
+pub use loader::ModuleLoader;
+pub use macros::MacroExpander;
+
 #[derive(Debug)]
 pub struct Parser<'a> {
+    /// The text `tokens` were lexed from; names and literals are read from it
+    /// by token span.
+    source: &'a str,
     tokens: TokenStream,
     interner: &'a mut lasso::Rodeo,
     file_id: FileId,
     errors: Vec<ChimError>,
+    contextual_keywords: Vec<Token>,
+    generator_depth: usize,
+    expr_depth: usize,
+    /// Labels of the enclosing loops, innermost last; `break 'l` must name one.
+    loop_labels: Vec<Ident>,
+    /// Set while parsing an `if`/`while`/`match` condition or a `for`
+    /// iterable, where `name {` starts the body rather than a struct literal.
+    no_struct_literal: bool,
+    /// Keyword spelling used when naming tokens in diagnostics.
+    dialect: Dialect,
+    /// `///` comments by the offset of the token they precede; see
+    /// `chim_lexer::doc_comments`.
+    doc_comments: HashMap<usize, String>,
 }
 
+/// Maximum nesting of expressions before the parser gives up rather than risk
+/// exhausting the stack.
+const MAX_EXPR_DEPTH: usize = 64;
+
 impl<'a> Parser<'a> {
-    pub fn new(tokens: Vec<SpannedToken>, interner: &'a mut lasso::Rodeo, file_id: FileId) -> Self {
+    pub fn new(source: &'a str, tokens: Vec<SpannedToken>, interner: &'a mut lasso::Rodeo, file_id: FileId) -> Self {
         Parser {
-            tokens: TokenStream::new(tokens, std::mem::take(interner)),
+            source,
+            tokens: TokenStream::new(tokens),
             interner,
             file_id,
             errors: Vec::new(),
+            contextual_keywords: CONTEXTUAL_KEYWORDS.to_vec(),
+            generator_depth: 0,
+            expr_depth: 0,
+            loop_labels: Vec::new(),
+            no_struct_literal: false,
+            dialect: Dialect::default(),
+            doc_comments: HashMap::new(),
         }
     }
 
+    /// Replaces the set of keywords that may be used as identifiers outside
+    /// the positions where the grammar expects the builtin.
+    pub fn with_contextual_keywords(mut self, keywords: Vec<Token>) -> Self {
+        self.contextual_keywords = keywords;
+        self
+    }
+
+    /// Names keywords in diagnostics the way `dialect` spells them, so an
+    /// error in Chinese source says `函数` rather than `Func`.
+    pub fn with_dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = dialect;
+        self
+    }
+
+    /// Attaches each `///` comment in `docs` to the item it precedes, as a
+    /// `@doc("...")` attribute.
+    pub fn with_doc_comments(mut self, docs: HashMap<usize, String>) -> Self {
+        self.doc_comments = docs;
+        self
+    }
+
     pub fn parse(&mut self) -> Result<Program, Vec<ChimError>> {
+        let program = self.parse_items();
+        if self.errors.is_empty() {
+            Ok(program)
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    /// Parses the whole input and returns the items that parsed alongside
+    /// every error, for tools that want a tree even from broken source. The
+    /// program is `None` only when errors left no item at all.
+    pub fn into_result_with_partial(mut self) -> (Option<Program>, Vec<ChimError>) {
+        let program = self.parse_items();
+        let program = (self.errors.is_empty() || !program.items.is_empty()).then_some(program);
+        (program, self.errors)
+    }
+
+    /// The errors recorded so far.
+    pub fn errors(&self) -> &[ChimError] {
+        &self.errors
+    }
+
+    fn parse_items(&mut self) -> Program {
         let mut items = Vec::new();
 
         while !self.tokens.at_end() {
+            let start = self.tokens.position();
             match self.parse_item() {
                 Ok(Some(item)) => items.push(item),
                 Ok(None) => {}
@@ -35,22 +123,101 @@ impl<'a> Parser<'a> {
                     self.recover();
                 }
             }
+            self.ensure_progress(start);
+            // Past a fatal error every further diagnostic is a cascade.
+            if self.errors.iter().any(ChimError::is_fatal) {
+                break;
+            }
         }
 
-        if self.errors.is_empty() {
-            Ok(Program {
-                items,
-                span: Span::new(self.file_id, 0, 0, 0, 0),
-            })
-        } else {
-            Err(std::mem::take(&mut self.errors))
+        let tokens = self.tokens.slice_between(0, usize::MAX);
+        let span = match (tokens.first(), tokens.last()) {
+            (Some(first), Some(last)) => first.span.merge(&last.span),
+            _ => Span::dummy(),
+        };
+        Program { items, span }
+    }
+
+    /// Parses a single expression that must span the whole input.
+    pub fn parse_expression(&mut self) -> Result<Expr, Vec<ChimError>> {
+        let result = self.parse_expr();
+        self.finish_single(result)
+    }
+
+    /// Parses a single statement that must span the whole input.
+    pub fn parse_statement(&mut self) -> Result<Stmt, Vec<ChimError>> {
+        let result = self.parse_stmt();
+        self.finish_single(result)
+    }
+
+    fn finish_single<T>(&mut self, result: Result<T, ChimError>) -> Result<T, Vec<ChimError>> {
+        match result {
+            Ok(node) => {
+                if let Some(token) = self.tokens.peek() {
+                    self.errors.push(ChimError::new(
+                        ErrorKind::UnexpectedToken,
+                        format!("expected end of input, found {:?}", token.token),
+                    ).with_span(token.span));
+                }
+                if self.errors.is_empty() {
+                    Ok(node)
+                } else {
+                    Err(std::mem::take(&mut self.errors))
+                }
+            }
+            Err(e) => {
+                // `expect` and friends already record the error they return.
+                if !self.errors.iter().any(|seen| seen.message == e.message && seen.span == e.span) {
+                    self.errors.push(e);
+                }
+                Err(std::mem::take(&mut self.errors))
+            }
         }
     }
 
     fn parse_item(&mut self) -> Result<Option<Item>, ChimError> {
         let start_span = self.current_span()?;
+        if let Some(doc) = self.doc_comments.remove(&start_span.start) {
+            let item = self.parse_item()?;
+            return Ok(item.map(|item| Self::with_doc(item, doc, start_span)));
+        }
 
         match self.tokens.peek().map(|t| &t.token) {
+            Some(&Token::At) => {
+                let attributes = self.parse_attributes()?;
+                match self.parse_item()? {
+                    Some(Item::Function(mut func)) => {
+                        func.attributes.extend(attributes);
+                        Ok(Some(Item::Function(func)))
+                    }
+                    Some(Item::Struct(mut struct_def)) => {
+                        struct_def.attributes.extend(attributes);
+                        Ok(Some(Item::Struct(struct_def)))
+                    }
+                    Some(Item::Enum(mut enum_def)) => {
+                        enum_def.attributes.extend(attributes);
+                        Ok(Some(Item::Enum(enum_def)))
+                    }
+                    Some(Item::Trait(mut trait_def)) => {
+                        trait_def.attributes.extend(attributes);
+                        Ok(Some(Item::Trait(trait_def)))
+                    }
+                    Some(Item::Impl(mut imp)) => {
+                        imp.attributes.extend(attributes);
+                        Ok(Some(Item::Impl(imp)))
+                    }
+                    Some(Item::Mod(mut module)) => {
+                        module.attributes.extend(attributes);
+                        Ok(Some(Item::Mod(module)))
+                    }
+                    Some(_) => Err(ChimError::new(
+                        ErrorKind::Parser,
+                        "attributes are only supported on functions, structs, enums, traits, impls and modules"
+                            .to_string(),
+                    ).with_span(start_span)),
+                    None => Ok(None),
+                }
+            }
             Some(&Token::Func) => self.parse_function().map(Some),
             Some(&Token::Struct) => self.parse_struct().map(Some),
             Some(&Token::Enum) => self.parse_enum().map(Some),
@@ -67,26 +234,112 @@ impl<'a> Parser<'a> {
             Some(&Token::Sync) => self.parse_sync().map(Some),
             Some(&Token::Sized) => self.parse_sized().map(Some),
             Some(&Token::IntoIterator) => self.parse_intoiterator().map(Some),
+            Some(&Token::Identifier) if self.at_macro_call(1) => {
+                let name = self.parse_identifier()?;
+                let call = self.parse_macro_call(name, start_span)?;
+                // `name! { ... }` stands alone like any braced item; the
+                // other forms end with `;`.
+                if call.delimiter != MacroDelimiter::Brace {
+                    self.expect(Token::Semicolon)?;
+                }
+                Ok(Some(Item::MacroCall(call)))
+            }
             Some(&Token::Let) | Some(&Token::LetAlt) | Some(&Token::Var) => {
                 self.errors.push(ChimError::new(
                     ErrorKind::Parser,
                     "items cannot start with variable declarations".to_string(),
-                ).with_span(start_span));
-                let err = self.errors.last().cloned().unwrap_or_else(|| {
-                    ChimError::new(ErrorKind::Parser, "unknown error".to_string())
-                });
-                Err(err)
-            }
+                ).with_span(start_span).with_note(
+                    "move this into a function body, or use `const`/`static` for a global".to_string(),
+                ));
+                self.skip_top_level_declaration();
+                Ok(None)
+            }
+            // Item loops stop at their own `}`, so one seen here closes nothing.
+            Some(&Token::RBrace) => Err(ChimError::new(
+                ErrorKind::UnexpectedToken,
+                "unmatched `}`".to_string(),
+            ).with_span(start_span)),
             _ => Ok(None),
         }
     }
 
+    /// Prepends `@doc("doc")` to the attributes of `item`. Items without
+    /// attributes, such as `use` and `const`, keep no docs.
+    fn with_doc(mut item: Item, doc: String, span: Span) -> Item {
+        let attributes = match &mut item {
+            Item::Function(func) => &mut func.attributes,
+            Item::Struct(struct_def) => &mut struct_def.attributes,
+            Item::Enum(enum_def) => &mut enum_def.attributes,
+            Item::Trait(trait_def) => &mut trait_def.attributes,
+            Item::Impl(imp) => &mut imp.attributes,
+            Item::Mod(module) => &mut module.attributes,
+            _ => return item,
+        };
+        let text = Expr {
+            kind: Box::new(ExprKind::Literal(Literal { kind: LiteralKind::String(Arc::from(doc)), span })),
+            span,
+            ty: None,
+        };
+        attributes.insert(0, Attribute {
+            name: Arc::from("doc"),
+            args: vec![AttributeArg { expr: text, span }],
+            span,
+        });
+        item
+    }
+
+    /// Parses a run of `@name` / `@name(args, ...)` attributes.
+    fn parse_attributes(&mut self) -> Result<Vec<Attribute>, ChimError> {
+        let mut attributes = Vec::new();
+
+        while self.tokens.peek_is(Token::At) {
+            let start_span = self.current_span()?;
+            self.tokens.next();
+            let name = self.parse_attribute_name()?;
+            let mut args = Vec::new();
+            if self.tokens.peek_is(Token::LParen) {
+                let open_span = self.current_span()?;
+                self.tokens.next();
+                while !self.tokens.peek_is(Token::RParen) {
+                    let expr = self.parse_expr()?;
+                    args.push(AttributeArg { span: expr.span, expr });
+                    if self.tokens.peek_is(Token::Comma) {
+                        self.tokens.next();
+                    } else {
+                        break;
+                    }
+                }
+                self.expect_with_context(Token::RParen, open_span, "attribute arguments opened here")?;
+            }
+
+            let span = start_span.merge(&self.current_span().unwrap_or(start_span));
+            attributes.push(Attribute {
+                name: Arc::from(name),
+                args,
+                span,
+            });
+        }
+
+        Ok(attributes)
+    }
+
+    /// Attribute names are plain words, even ones that spell a keyword such
+    /// as `derive`.
+    fn parse_attribute_name(&mut self) -> Result<String, ChimError> {
+        let text = self.get_source_text(self.tokens.position());
+        if text.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+            self.tokens.next();
+            return Ok(self.intern_identifier());
+        }
+        self.parse_identifier()
+    }
+
     fn parse_function(&mut self) -> Result<Item, ChimError> {
         let _ = self.tokens.next();
 
         let start_span = self.current_span()?;
         let is_pub = self.parse_visibility()?;
-        let is_async = self.tokens.peek().map(|t| &t.token) == Some(&Token::Async);
+        let is_async = self.tokens.peek_is(Token::Async);
         if is_async {
             self.tokens.next();
         }
@@ -104,7 +357,7 @@ impl<'a> Parser<'a> {
             }
         };
         
-        let generics = self.parse_generics()?;
+        let (lifetimes, generics) = self.parse_generics()?;
         
         let params = match self.parse_function_params() {
             Ok(p) => p,
@@ -115,6 +368,10 @@ impl<'a> Parser<'a> {
             }
         };
         
+        // Without a return type this is where the clause belongs; with one,
+        // `fn f<T>(x: T) where T: Clone -> T` has it on the wrong side.
+        let mut where_clauses = self.parse_where_clauses()?;
+
         let return_type = match self.parse_return_type() {
             Ok(t) => t,
             Err(e) => {
@@ -122,8 +379,12 @@ impl<'a> Parser<'a> {
                 None
             }
         };
+        if let (Some(clause), Some(_)) = (where_clauses.first(), &return_type) {
+            let error = self.misplaced_where(clause.span, "after the return type");
+            self.errors.push(error);
+        }
         
-        let where_clauses = self.parse_where_clauses()?;
+        where_clauses.extend(self.parse_where_clauses()?);
         
         let body = match self.parse_block() {
             Ok(b) => b,
@@ -144,8 +405,10 @@ impl<'a> Parser<'a> {
             span,
             is_pub,
             is_async,
-            lifetimes: generics,
+            lifetimes,
+            generics,
             where_clauses,
+            attributes: Vec::new(),
         }))
     }
 
@@ -155,9 +418,10 @@ impl<'a> Parser<'a> {
         let start_span = self.current_span()?;
         let is_pub = self.parse_visibility()?;
         let name = self.parse_identifier()?;
-        let generics = self.parse_generics()?;
+        let generics = self.parse_generic_params()?;
         let where_clauses = self.parse_where_clauses()?;
         let fields = self.parse_struct_fields()?;
+        self.parse_misplaced_where("before the struct body")?;
 
         let span = start_span.merge(&self.current_span().unwrap_or(start_span));
 
@@ -168,6 +432,7 @@ impl<'a> Parser<'a> {
             is_pub,
             generics,
             where_clauses,
+            attributes: Vec::new(),
         }))
     }
 
@@ -177,9 +442,10 @@ impl<'a> Parser<'a> {
         let start_span = self.current_span()?;
         let is_pub = self.parse_visibility()?;
         let name = self.parse_identifier()?;
-        let generics = self.parse_generics()?;
+        let generics = self.parse_generic_params()?;
         let where_clauses = self.parse_where_clauses()?;
         let variants = self.parse_enum_variants()?;
+        self.parse_misplaced_where("before the enum body")?;
 
         let span = start_span.merge(&self.current_span().unwrap_or(start_span));
 
@@ -190,6 +456,7 @@ impl<'a> Parser<'a> {
             is_pub,
             generics,
             where_clauses,
+            attributes: Vec::new(),
         }))
     }
 
@@ -199,10 +466,26 @@ impl<'a> Parser<'a> {
         let start_span = self.current_span()?;
         let is_pub = self.parse_visibility()?;
         let name = self.parse_identifier()?;
-        let generics = self.parse_generics()?;
-        let super_traits = self.parse_trait_bounds()?;
+        let generics = self.parse_generic_params()?;
+        let super_traits = if self.tokens.peek_is(Token::Colon) {
+            self.tokens.next();
+            let mut super_traits = Vec::new();
+            for bound in self.parse_bounds()? {
+                if bound.relaxed {
+                    self.errors.push(ChimError::new(
+                        ErrorKind::Parser,
+                        "`?Sized` is only allowed on type parameters".to_string(),
+                    ).with_span(bound.span));
+                }
+                super_traits.extend(bound.trait_ref);
+            }
+            super_traits
+        } else {
+            Vec::new()
+        };
         let where_clauses = self.parse_where_clauses()?;
         let items = self.parse_trait_items()?;
+        self.parse_misplaced_where("before the trait body")?;
 
         let span = start_span.merge(&self.current_span().unwrap_or(start_span));
 
@@ -214,6 +497,7 @@ impl<'a> Parser<'a> {
             generics,
             super_traits,
             where_clauses,
+            attributes: Vec::new(),
         }))
     }
 
@@ -221,11 +505,30 @@ impl<'a> Parser<'a> {
         let _ = self.tokens.next();
 
         let start_span = self.current_span()?;
-        let generics = self.parse_generics()?;
-        let trait_name = self.parse_optional_type_path()?;
-        let type_name = self.parse_type()?;
-        let where_clauses = self.parse_where_clauses()?;
+        let generics = self.parse_generic_params()?;
+        let first = self.parse_type()?;
+        // `impl<T> Trait where T: Bound for Type` puts the clause between the
+        // trait and the implementing type; only an inherent impl may have it here.
+        let mut where_clauses = self.parse_where_clauses()?;
+        let (trait_name, type_name) = if self.tokens.peek_is(Token::For) {
+            if let Some(clause) = where_clauses.first() {
+                let error = self.misplaced_where(clause.span, "after the implementing type");
+                self.errors.push(error);
+            }
+            if !matches!(*first.kind, TypeKind::Path(_)) {
+                self.errors.push(ChimError::new(
+                    ErrorKind::Parser,
+                    format!("expected a trait before `for`, found `{}`", first),
+                ).with_span(first.span));
+            }
+            self.tokens.next();
+            (Some(first), self.parse_type()?)
+        } else {
+            (None, first)
+        };
+        where_clauses.extend(self.parse_where_clauses()?);
         let items = self.parse_impl_items()?;
+        self.parse_misplaced_where("before the impl body")?;
 
         let span = start_span.merge(&self.current_span().unwrap_or(start_span));
 
@@ -236,6 +539,7 @@ impl<'a> Parser<'a> {
             span,
             generics,
             where_clauses,
+            attributes: Vec::new(),
         }))
     }
 
@@ -245,7 +549,7 @@ impl<'a> Parser<'a> {
         let start_span = self.current_span()?;
         let is_pub = self.parse_visibility()?;
         let path = self.parse_path()?;
-        let alias = if self.tokens.peek().map(|t| &t.token) == Some(&Token::As) {
+        let alias = if self.tokens.peek_is(Token::As) {
             self.tokens.next();
             Some(Arc::from(self.parse_identifier()?))
         } else {
@@ -269,15 +573,21 @@ impl<'a> Parser<'a> {
         let start_span = self.current_span()?;
         let is_pub = self.parse_visibility()?;
         let name = self.parse_identifier()?;
-        let body = if self.tokens.peek().map(|t| &t.token) == Some(&Token::LBrace) {
+        let is_inline = self.tokens.peek_is(Token::LBrace);
+        let body = if is_inline {
             self.tokens.next();
             let mut items = Vec::new();
-            while self.tokens.peek().map(|t| &t.token) != Some(&Token::RBrace) {
+            while !self.tokens.peek_is(Token::RBrace) && !self.tokens.at_end() {
+                let start = self.tokens.position();
                 match self.parse_item() {
                     Ok(Some(item)) => items.push(item),
                     Ok(None) => {}
-                    Err(e) => self.errors.push(e),
+                    Err(e) => {
+                        self.errors.push(e);
+                        self.recover();
+                    }
                 }
+                self.ensure_progress(start);
             }
             self.expect(Token::RBrace)?;
             items
@@ -294,6 +604,8 @@ impl<'a> Parser<'a> {
             span,
             is_pub,
             file_path: None,
+            is_inline,
+            attributes: Vec::new(),
         }))
     }
 
@@ -301,18 +613,49 @@ impl<'a> Parser<'a> {
         let _ = self.tokens.next();
 
         let start_span = self.current_span()?;
-        let abi = if let Some(&Token::String(_)) = self.tokens.peek().map(|t| &t.token) {
-            let abi = self.intern_string();
+        let abi = if self.tokens.peek_is(Token::String) {
             self.tokens.next();
-            abi
+            self.intern_string()
         } else {
             "C".to_string()
         };
 
         self.expect(Token::LBrace)?;
         let mut items = Vec::new();
-        while self.tokens.peek().map(|t| &t.token) != Some(&Token::RBrace) {
+        while !self.tokens.peek_is(Token::RBrace) {
+            let item_span = self.current_span()?;
             let is_pub = self.parse_visibility()?;
+
+            if self.tokens.peek_is(Token::Func) {
+                let sig = self.parse_extern_function_sig()?;
+                self.expect(Token::Semicolon)?;
+
+                let ty = Type {
+                    kind: Box::new(TypeKind::Function(FunctionType {
+                        params: sig.params.iter().map(|p| p.ty.clone()).collect(),
+                        return_type: Box::new(sig.return_type.clone().unwrap_or(Type {
+                            kind: Box::new(TypeKind::Tuple(Vec::new())),
+                            span: sig.span,
+                        })),
+                        is_async: false,
+                        is_unsafe: false,
+                        abi: Some(abi.clone()),
+                    })),
+                    span: sig.span,
+                };
+
+                items.push(ExternItem {
+                    name: sig.name.clone(),
+                    ty,
+                    span: item_span.merge(&sig.span),
+                    is_pub,
+                    is_mut: false,
+                    sig: Some(sig),
+                });
+                continue;
+            }
+
+            let is_mut = self.tokens.eat(Token::Static) && self.tokens.eat(Token::Mut);
             let name = self.parse_identifier()?;
             self.expect(Token::Colon)?;
             let ty = self.parse_type()?;
@@ -321,8 +664,10 @@ impl<'a> Parser<'a> {
             items.push(ExternItem {
                 name: Arc::from(name),
                 ty,
-                span: Span::new(self.file_id, 0, 0, 0, 0),
+                span: item_span.merge(&self.current_span().unwrap_or(item_span)),
                 is_pub,
+                is_mut,
+                sig: None,
             });
         }
         self.expect(Token::RBrace)?;
@@ -342,8 +687,12 @@ impl<'a> Parser<'a> {
         let start_span = self.current_span()?;
         let is_pub = self.parse_visibility()?;
         let name = self.parse_identifier()?;
-        self.expect(Token::Colon)?;
-        let ty = self.parse_type()?;
+        let ty = if self.tokens.peek_is(Token::Colon) {
+            self.tokens.next();
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
         self.expect(Token::Eq)?;
         let value = self.parse_expr()?;
         self.expect(Token::Semicolon)?;
@@ -352,7 +701,7 @@ impl<'a> Parser<'a> {
 
         Ok(Item::Constant(Constant {
             name: Arc::from(name),
-            ty: Some(ty),
+            ty,
             value,
             span,
             is_pub,
@@ -364,14 +713,22 @@ impl<'a> Parser<'a> {
 
         let start_span = self.current_span()?;
         let is_pub = self.parse_visibility()?;
-        let is_mut = self.tokens.peek().map(|t| &t.token) == Some(&Token::Mut);
+        let is_mut = self.tokens.peek_is(Token::Mut);
         if is_mut {
             self.tokens.next();
         }
         let name = self.parse_identifier()?;
-        self.expect(Token::Colon)?;
+        // Unlike constants, statics denote a fixed memory location, so their type is
+        // always spelled out rather than inferred from the initializer.
+        if !self.tokens.peek_is(Token::Colon) {
+            return Err(ChimError::new(
+                ErrorKind::Parser,
+                format!("static `{}` requires a type annotation", name),
+            ).with_span(self.current_span()?));
+        }
+        self.tokens.next();
         let ty = self.parse_type()?;
-        let value = if self.tokens.peek().map(|t| &t.token) == Some(&Token::Eq) {
+        let value = if self.tokens.peek_is(Token::Eq) {
             self.tokens.next();
             Some(self.parse_expr()?)
         } else {
@@ -380,6 +737,13 @@ impl<'a> Parser<'a> {
         self.expect(Token::Semicolon)?;
 
         let span = start_span.merge(&self.current_span().unwrap_or(start_span));
+        // Only statics declared in an `extern` block may leave their storage to the linker.
+        if value.is_none() {
+            self.errors.push(ChimError::new(
+                ErrorKind::Parser,
+                format!("static `{}` must have an initializer", name),
+            ).with_span(span));
+        }
 
         Ok(Item::Static(Static {
             name: Arc::from(name),
@@ -396,7 +760,7 @@ impl<'a> Parser<'a> {
 
         let start_span = self.current_span()?;
         let is_pub = self.parse_visibility()?;
-        let is_procedural = self.tokens.peek().map(|t| &t.token) == Some(&Token::Procedural);
+        let is_procedural = self.tokens.peek_is(Token::Procedural);
         if is_procedural {
             self.tokens.next();
         }
@@ -419,12 +783,13 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_macro_params(&mut self) -> Result<Vec<MacroParam>, ChimError> {
+        let open_span = self.current_span()?;
         self.expect(Token::LParen)?;
         let mut params = Vec::new();
 
-        while self.tokens.peek().map(|t| &t.token) != Some(&Token::RParen) {
+        while !self.tokens.peek_is(Token::RParen) {
             let name = self.parse_identifier()?;
-            let ty = if self.tokens.peek().map(|t| &t.token) == Some(&Token::Colon) {
+            let ty = if self.tokens.peek_is(Token::Colon) {
                 self.tokens.next();
                 Some(self.parse_type()?)
             } else {
@@ -435,26 +800,22 @@ impl<'a> Parser<'a> {
                 ty,
                 span: self.current_span()?,
             });
-            if self.tokens.peek().map(|t| &t.token) == Some(&Token::Comma) {
-                self.tokens.next();
-            }
+            self.tokens.eat(Token::Comma);
         }
-        self.expect(Token::RParen)?;
+        self.expect_with_context(Token::RParen, open_span, "parameter list opened here")?;
         Ok(params)
     }
 
     fn parse_macro_body(&mut self) -> Result<MacroBody, ChimError> {
-        if self.tokens.peek().map(|t| &t.token) == Some(&Token::LBrace) {
+        if self.tokens.peek_is(Token::LBrace) {
             self.tokens.next();
             let mut rules = Vec::new();
-            while self.tokens.peek().map(|t| &t.token) != Some(&Token::RBrace) {
+            while !self.tokens.peek_is(Token::RBrace) {
                 let pattern = self.parse_macro_pattern()?;
                 self.expect(Token::Arrow)?;
                 let expansion = self.parse_macro_expansion()?;
                 rules.push(MacroRule { pattern, expansion });
-                if self.tokens.peek().map(|t| &t.token) == Some(&Token::Comma) {
-                    self.tokens.next();
-                }
+                self.tokens.eat(Token::Comma);
             }
             self.expect(Token::RBrace)?;
             Ok(MacroBody::Rules(rules))
@@ -463,22 +824,133 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parses a rule's matcher, a delimited group such as `($x:expr, $y:expr)`;
+    /// the outer delimiters only enclose it and are not matched.
     fn parse_macro_pattern(&mut self) -> Result<MacroPattern, ChimError> {
-        Ok(MacroPattern::Token(MacroToken::Ident(Arc::from("test"))))
+        let close = self.expect_macro_delimiter()?;
+        Ok(MacroPattern::Sequence(self.parse_macro_patterns(close, true)?))
     }
 
+    /// Parses a rule's expansion: a delimited template such as `{ $x * $x }`,
+    /// or a plain expression.
     fn parse_macro_expansion(&mut self) -> Result<MacroExpansion, ChimError> {
+        if matches!(self.tokens.peek().map(|t| &t.token), Some(Token::LParen | Token::LBracket | Token::LBrace)) {
+            let close = self.expect_macro_delimiter()?;
+            return Ok(MacroExpansion::Template(self.parse_macro_patterns(close, false)?));
+        }
         let expr = self.parse_expr()?;
         Ok(MacroExpansion::Expr(expr))
     }
 
+    /// Consumes an opening delimiter and returns the token that closes it.
+    fn expect_macro_delimiter(&mut self) -> Result<Token, ChimError> {
+        let close = match self.tokens.peek().map(|t| &t.token) {
+            Some(Token::LParen) => Token::RParen,
+            Some(Token::LBracket) => Token::RBracket,
+            Some(Token::LBrace) => Token::RBrace,
+            _ => {
+                let span = self.current_span()?;
+                return Err(self.report_error(
+                    ErrorKind::UnexpectedToken,
+                    "expected `(`, `[` or `{` around a macro rule".to_string(),
+                    span,
+                ));
+            }
+        };
+        self.tokens.next();
+        Ok(close)
+    }
+
+    /// Parses macro patterns up to and including `close`. Variables carry a
+    /// fragment specifier only in a matcher.
+    fn parse_macro_patterns(&mut self, close: Token, in_matcher: bool) -> Result<Vec<MacroPattern>, ChimError> {
+        let mut patterns = Vec::new();
+        while !self.tokens.eat(close) {
+            if self.tokens.at_end() {
+                let span = self.current_span()?;
+                return Err(self.report_error(ErrorKind::UnexpectedEof, "unclosed macro rule".to_string(), span));
+            }
+            if self.tokens.eat(Token::Dollar) {
+                patterns.push(self.parse_macro_var_or_repeat(in_matcher)?);
+                continue;
+            }
+            let delimiter = match self.tokens.peek().map(|t| &t.token) {
+                Some(Token::LParen) => Some(MacroDelimiter::Paren),
+                Some(Token::LBracket) => Some(MacroDelimiter::Bracket),
+                Some(Token::LBrace) => Some(MacroDelimiter::Brace),
+                _ => None,
+            };
+            if let Some(delimiter) = delimiter {
+                let inner_close = self.expect_macro_delimiter()?;
+                patterns.push(MacroPattern::Group(delimiter, self.parse_macro_patterns(inner_close, in_matcher)?));
+                continue;
+            }
+            let position = self.tokens.position();
+            self.tokens.next();
+            patterns.extend(self.macro_tokens(position, position + 1).into_iter().map(MacroPattern::Token));
+        }
+        Ok(patterns)
+    }
+
+    /// Parses what follows a `$`: `name`, `name:fragment`, or a repetition
+    /// `( ... ) sep? op`.
+    fn parse_macro_var_or_repeat(&mut self, in_matcher: bool) -> Result<MacroPattern, ChimError> {
+        if self.tokens.eat(Token::LParen) {
+            let patterns = self.parse_macro_patterns(Token::RParen, in_matcher)?;
+            let kind_at = |token: Option<&SpannedToken>| match token.map(|t| &t.token) {
+                Some(Token::Star) => Some(MacroRepeatKind::ZeroOrMore),
+                Some(Token::Plus) => Some(MacroRepeatKind::OneOrMore),
+                Some(Token::Question) => Some(MacroRepeatKind::ZeroOrOne),
+                _ => None,
+            };
+            let separator = if kind_at(self.tokens.peek()).is_none() {
+                let position = self.tokens.position();
+                self.tokens.next();
+                self.macro_tokens(position, position + 1).pop()
+            } else {
+                None
+            };
+            let Some(kind) = kind_at(self.tokens.peek()) else {
+                let span = self.current_span()?;
+                return Err(self.report_error(
+                    ErrorKind::UnexpectedToken,
+                    "expected `*`, `+` or `?` after a macro repetition".to_string(),
+                    span,
+                ));
+            };
+            self.tokens.next();
+            return Ok(MacroPattern::Repeat(patterns, separator, kind));
+        }
+
+        let name = self.parse_identifier()?;
+        if !in_matcher {
+            return Ok(MacroPattern::Var(Arc::from(name), None));
+        }
+        self.expect(Token::Colon)?;
+        let fragment_span = self.current_span()?;
+        let fragment = match self.parse_identifier()?.as_str() {
+            "expr" => MacroFragment::Expr,
+            "ident" => MacroFragment::Ident,
+            "literal" => MacroFragment::Literal,
+            "tt" => MacroFragment::Tt,
+            other => {
+                return Err(self.report_error(
+                    ErrorKind::UnexpectedToken,
+                    format!("unknown macro fragment `{}`; expected `expr`, `ident`, `literal` or `tt`", other),
+                    fragment_span,
+                ));
+            }
+        };
+        Ok(MacroPattern::Var(Arc::from(name), Some(fragment)))
+    }
+
     fn parse_forall(&mut self) -> Result<Item, ChimError> {
         let _ = self.tokens.next();
 
         let start_span = self.current_span()?;
         let is_pub = self.parse_visibility()?;
         let name = self.parse_identifier()?;
-        let generics = self.parse_generics()?;
+        let (_, generics) = self.parse_generics()?;
         let params = self.parse_function_params()?;
         let return_type = self.parse_return_type()?;
         let where_clauses = self.parse_where_clauses()?;
@@ -582,23 +1054,50 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_function_params(&mut self) -> Result<Vec<Param>, ChimError> {
+        let start_span = self.current_span()?;
+        let (params, is_variadic) = self.parse_variadic_function_params()?;
+        if is_variadic {
+            return Err(ChimError::new(
+                ErrorKind::Parser,
+                "variadic parameters are only allowed in extern function signatures".to_string(),
+            ).with_span(start_span));
+        }
+        Ok(params)
+    }
+
+    fn parse_variadic_function_params(&mut self) -> Result<(Vec<Param>, bool), ChimError> {
+        let open_span = self.current_span()?;
         self.expect(Token::LParen)?;
         let mut params = Vec::new();
+        let mut is_variadic = false;
 
-        while self.tokens.peek().map(|t| &t.token) != Some(&Token::RParen) {
+        while !self.tokens.peek_is(Token::RParen) {
             let start_span = self.current_span()?;
-            let is_mut = self.tokens.peek().map(|t| &t.token) == Some(&Token::Mut);
+            if self.tokens.peek_is(Token::DotDotDot) {
+                self.tokens.next();
+                is_variadic = true;
+                self.tokens.eat(Token::Comma);
+                if !self.tokens.peek_is(Token::RParen) {
+                    return Err(ChimError::new(
+                        ErrorKind::Parser,
+                        "`...` must be the last parameter".to_string(),
+                    ).with_span(start_span));
+                }
+                break;
+            }
+            let is_mut = self.tokens.peek_is(Token::Mut);
             if is_mut {
                 self.tokens.next();
             }
-            let is_ref = self.tokens.peek().map(|t| &t.token) == Some(&Token::Ref);
+            let is_ref = self.tokens.peek_is(Token::Ref);
             if is_ref {
                 self.tokens.next();
             }
 
-            let name = self.parse_identifier()?;
+            let is_self = self.tokens.eat(Token::SelfKeyword);
+            let name = if is_self { "self".to_string() } else { self.parse_identifier()? };
 
-            if self.tokens.peek().map(|t| &t.token) == Some(&Token::Colon) {
+            if self.tokens.peek_is(Token::Colon) {
                 self.tokens.next();
                 let ty = self.parse_type()?;
                 let span = start_span.merge(&self.current_span().unwrap_or(start_span));
@@ -611,10 +1110,12 @@ impl<'a> Parser<'a> {
                 });
             } else {
                 let span = start_span.merge(&self.current_span().unwrap_or(start_span));
+                // An unannotated `self` is the receiver, of type `Self`.
+                let kind = if is_self { TypeKind::SelfType } else { TypeKind::Infer };
                 params.push(Param {
                     name: Arc::from(name),
                     ty: Type {
-                        kind: Box::new(TypeKind::Infer),
+                        kind: Box::new(kind),
                         span,
                     },
                     span,
@@ -623,21 +1124,38 @@ impl<'a> Parser<'a> {
                 });
             }
 
-            if self.tokens.peek().map(|t| &t.token) == Some(&Token::Comma) {
+            if self.tokens.peek_is(Token::Comma) {
                 self.tokens.next();
+            } else {
+                break;
             }
         }
 
-        self.expect(Token::RParen)?;
-        Ok(params)
+        self.expect_with_context(Token::RParen, open_span, "parameter list opened here")?;
+        self.check_duplicate_names("parameter", params.iter().map(|p| (&p.name, p.span)));
+        Ok((params, is_variadic))
     }
 
-    fn parse_function_params_full(&mut self) -> Result<Vec<Param>, ChimError> {
-        self.parse_function_params()
+    /// Reports every name that repeats an earlier one in the same declaration list,
+    /// pointing at the repeat and labelling the first declaration.
+    fn check_duplicate_names<'n>(&mut self, what: &str, names: impl Iterator<Item = (&'n Ident, Span)>) {
+        let mut seen: HashMap<&'n Ident, Span> = HashMap::new();
+        for (name, span) in names {
+            if let Some(first) = seen.get(name) {
+                self.errors.push(ChimError::new(
+                    ErrorKind::Redefinition,
+                    format!("{} `{}` declared twice", what, name),
+                ).with_span(span)
+                    .with_label(span, format!("`{}` redeclared here", name))
+                    .with_secondary_label(*first, format!("first declaration of `{}`", name)));
+            } else {
+                seen.insert(name, span);
+            }
+        }
     }
 
     fn parse_return_type(&mut self) -> Result<Option<Type>, ChimError> {
-        if self.tokens.peek().map(|t| &t.token) == Some(&Token::ThinArrow) {
+        if self.tokens.peek_is(Token::ThinArrow) {
             self.tokens.next();
             let ty = self.parse_type()?;
             Ok(Some(ty))
@@ -650,7 +1168,7 @@ impl<'a> Parser<'a> {
         self.expect(Token::LBrace)?;
         let mut fields = Vec::new();
 
-        while self.tokens.peek().map(|t| &t.token) != Some(&Token::RBrace) {
+        while !self.tokens.peek_is(Token::RBrace) {
             let start_span = self.current_span()?;
             let is_pub = self.parse_visibility()?;
             let name = self.parse_identifier()?;
@@ -669,43 +1187,54 @@ impl<'a> Parser<'a> {
         }
 
         self.expect(Token::RBrace)?;
+        self.check_duplicate_names("field", fields.iter().map(|f| (&f.name, f.span)));
         Ok(fields)
     }
 
     fn parse_enum_variants(&mut self) -> Result<Vec<Variant>, ChimError> {
         self.expect(Token::LBrace)?;
         let mut variants = Vec::new();
-        let mut index = 0i128;
 
-        while self.tokens.peek().map(|t| &t.token) != Some(&Token::RBrace) {
+        while !self.tokens.peek_is(Token::RBrace) {
             let start_span = self.current_span()?;
             let name = self.parse_identifier()?;
 
-            let fields = if self.tokens.peek().map(|t| &t.token) == Some(&Token::LParen) {
+            let fields = if self.tokens.peek_is(Token::LParen) {
                 self.tokens.next();
                 let mut fields = Vec::new();
-                while self.tokens.peek().map(|t| &t.token) != Some(&Token::RParen) {
-                    let field_name = self.parse_identifier()?;
-                    self.expect(Token::Colon)?;
+                while !self.tokens.peek_is(Token::RParen) {
+                    let field_span = self.current_span()?;
+                    // `Some(int)` declares positional fields, named by index.
+                    let field_name = if matches!(self.tokens.peek_n(1).map(|t| &t.token), Some(Token::Colon)) {
+                        let name = self.parse_identifier()?;
+                        self.expect(Token::Colon)?;
+                        name
+                    } else {
+                        fields.len().to_string()
+                    };
                     let field_ty = self.parse_type()?;
 
                     fields.push(Field {
                         name: Arc::from(field_name),
+                        span: field_span.merge(&field_ty.span),
                         ty: field_ty,
-                        span: Span::new(self.file_id, 0, 0, 0, 0),
                         is_pub: true,
                         attributes: Vec::new(),
                     });
 
-                    if self.tokens.peek().map(|t| &t.token) == Some(&Token::Comma) {
-                        self.tokens.next();
-                    }
+                    self.tokens.eat(Token::Comma);
                 }
                 self.expect(Token::RParen)?;
+                self.check_duplicate_names("field", fields.iter().map(|f| (&f.name, f.span)));
                 fields
             } else {
                 Vec::new()
             };
+            let discriminant = if self.tokens.eat(Token::Eq) {
+                Some(self.parse_expr()?)
+            } else {
+                None
+            };
 
             let span = start_span.merge(&self.current_span().unwrap_or(start_span));
             variants.push(Variant {
@@ -713,15 +1242,16 @@ impl<'a> Parser<'a> {
                 fields,
                 span,
                 attributes: Vec::new(),
+                discriminant,
             });
 
-            if self.tokens.peek().map(|t| &t.token) == Some(&Token::Comma) {
-                self.tokens.next();
-                index += 1;
+            if !self.tokens.eat(Token::Comma) {
+                self.tokens.eat(Token::Semicolon);
             }
         }
 
         self.expect(Token::RBrace)?;
+        self.check_duplicate_names("variant", variants.iter().map(|v| (&v.name, v.span)));
         Ok(variants)
     }
 
@@ -729,10 +1259,12 @@ impl<'a> Parser<'a> {
         self.expect(Token::LBrace)?;
         let mut items = Vec::new();
 
-        while self.tokens.peek().map(|t| &t.token) != Some(&Token::RBrace) {
+        while !self.tokens.peek_is(Token::RBrace) && !self.tokens.at_end() {
+            let start_span = self.current_span()?;
             match self.tokens.peek().map(|t| &t.token) {
                 Some(&Token::Func) => {
                     let func = self.parse_function_sig()?;
+                    self.expect(Token::Semicolon)?;
                     items.push(TraitItem::Function(func));
                 }
                 Some(&Token::Const) => {
@@ -740,7 +1272,7 @@ impl<'a> Parser<'a> {
                     let name = self.parse_identifier()?;
                     self.expect(Token::Colon)?;
                     let ty = self.parse_type()?;
-                    let default = if self.tokens.peek().map(|t| &t.token) == Some(&Token::Eq) {
+                    let default = if self.tokens.peek_is(Token::Eq) {
                         self.tokens.next();
                         Some(self.parse_expr()?)
                     } else {
@@ -751,14 +1283,17 @@ impl<'a> Parser<'a> {
                         name: Arc::from(name),
                         ty,
                         default,
-                        span: Span::new(self.file_id, 0, 0, 0, 0),
+                        span: start_span.merge(&self.current_span().unwrap_or(start_span)),
                     }));
                 }
                 Some(&Token::Type) => {
                     self.tokens.next();
                     let name = self.parse_identifier()?;
-                    let _bounds = self.parse_trait_bounds()?;
-                    let default = if self.tokens.peek().map(|t| &t.token) == Some(&Token::Eq) {
+                    if self.tokens.peek_is(Token::Colon) {
+                        self.tokens.next();
+                        self.parse_bounds()?;
+                    }
+                    let default = if self.tokens.peek_is(Token::Eq) {
                         self.tokens.next();
                         Some(self.parse_type()?)
                     } else {
@@ -769,9 +1304,11 @@ impl<'a> Parser<'a> {
                         name: Arc::from(name),
                         ty: default.unwrap_or(Type {
                             kind: Box::new(TypeKind::Infer),
-                            span: Span::new(self.file_id, 0, 0, 0, 0),
+                            span: Span::dummy(),
                         }),
-                        span: Span::new(self.file_id, 0, 0, 0, 0),
+                        span: start_span.merge(&self.current_span().unwrap_or(start_span)),
+                        // Trait items are as visible as the trait itself.
+                        is_pub: true,
                     }));
                 }
                 _ => {
@@ -792,15 +1329,20 @@ impl<'a> Parser<'a> {
         self.expect(Token::LBrace)?;
         let mut items = Vec::new();
 
-        while self.tokens.peek().map(|t| &t.token) != Some(&Token::RBrace) {
+        while !self.tokens.peek_is(Token::RBrace) && !self.tokens.at_end() {
+            let start_span = self.current_span()?;
+            // Visibility may come before the keyword (`pub const X`) or, as
+            // for top-level items, right after it (`const pub X`).
+            let leading_pub = self.parse_visibility()?;
             match self.tokens.peek().map(|t| &t.token) {
                 Some(&Token::Func) => {
-                    let func = self.parse_impl_function()?;
+                    let mut func = self.parse_impl_function()?;
+                    func.is_pub |= leading_pub;
                     items.push(ImplItem::Function(func));
                 }
                 Some(&Token::Const) => {
                     self.tokens.next();
-                    let _is_pub = self.parse_visibility()?;
+                    let is_pub = self.parse_visibility()? || leading_pub;
                     let name = self.parse_identifier()?;
                     self.expect(Token::Colon)?;
                     let ty = self.parse_type()?;
@@ -811,12 +1353,13 @@ impl<'a> Parser<'a> {
                         name: Arc::from(name),
                         ty: Some(ty),
                         value,
-                        span: Span::new(self.file_id, 0, 0, 0, 0),
-                        is_pub: false,
+                        span: start_span.merge(&self.current_span().unwrap_or(start_span)),
+                        is_pub,
                     }));
                 }
                 Some(&Token::Type) => {
                     self.tokens.next();
+                    let is_pub = self.parse_visibility()? || leading_pub;
                     let name = self.parse_identifier()?;
                     self.expect(Token::Eq)?;
                     let ty = self.parse_type()?;
@@ -824,7 +1367,8 @@ impl<'a> Parser<'a> {
                     items.push(ImplItem::Type(TypeBinding {
                         name: Arc::from(name),
                         ty,
-                        span: Span::new(self.file_id, 0, 0, 0, 0),
+                        span: start_span.merge(&self.current_span().unwrap_or(start_span)),
+                        is_pub,
                     }));
                 }
                 _ => {
@@ -842,6 +1386,7 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_function_sig(&mut self) -> Result<FunctionSig, ChimError> {
+        let start_span = self.current_span()?;
         let _ = self.tokens.next();
 
         let name = self.parse_identifier()?;
@@ -852,7 +1397,27 @@ impl<'a> Parser<'a> {
             name: Arc::from(name),
             params,
             return_type,
-            span: Span::new(self.file_id, 0, 0, 0, 0),
+            span: start_span.merge(&self.current_span().unwrap_or(start_span)),
+            is_variadic: false,
+        })
+    }
+
+    fn parse_extern_function_sig(&mut self) -> Result<FunctionSig, ChimError> {
+        let _ = self.tokens.next();
+
+        let start_span = self.current_span()?;
+        let name = self.parse_identifier()?;
+        let (params, is_variadic) = self.parse_variadic_function_params()?;
+        let return_type = self.parse_return_type()?;
+
+        let span = start_span.merge(&self.current_span().unwrap_or(start_span));
+
+        Ok(FunctionSig {
+            name: Arc::from(name),
+            params,
+            return_type,
+            span,
+            is_variadic,
         })
     }
 
@@ -877,16 +1442,21 @@ impl<'a> Parser<'a> {
             is_pub,
             is_async: false,
             lifetimes: Vec::new(),
+            generics: Vec::new(),
             where_clauses: Vec::new(),
+            attributes: Vec::new(),
         })
     }
 
     fn parse_block(&mut self) -> Result<Vec<Stmt>, ChimError> {
-        let start_span = self.current_span()?;
+        let open_span = self.current_span()?;
         self.expect(Token::LBrace)?;
         let mut stmts = Vec::new();
+        // A block in a condition, e.g. a closure body, is unrestricted.
+        let no_struct_literal = std::mem::replace(&mut self.no_struct_literal, false);
 
-        while self.tokens.peek().map(|t| &t.token) != Some(&Token::RBrace) {
+        while !self.tokens.peek_is(Token::RBrace) && !self.tokens.at_end() {
+            let start = self.tokens.position();
             match self.parse_stmt() {
                 Ok(stmt) => stmts.push(stmt),
                 Err(e) => {
@@ -894,51 +1464,73 @@ impl<'a> Parser<'a> {
                     self.recover_in_block();
                 }
             }
+            self.ensure_progress(start);
         }
 
-        self.expect(Token::RBrace)?;
+        self.no_struct_literal = no_struct_literal;
+        self.expect_with_context(Token::RBrace, open_span, "block opened here")?;
         Ok(stmts)
     }
 
     fn parse_stmt(&mut self) -> Result<Stmt, ChimError> {
         let start_span = self.current_span()?;
 
-        match self.tokens.peek().map(|t| &t.token) {
-            Some(&Token::Let) | Some(&Token::LetAlt) => self.parse_let_stmt(),
-            Some(&Token::Var) => self.parse_var_stmt(),
-            Some(&Token::Return) => self.parse_return_stmt(),
-            Some(&Token::Break) => self.parse_break_stmt(),
-            Some(&Token::Continue) => self.parse_continue_stmt(),
-            Some(&Token::Loop) => self.parse_loop_stmt(),
-            Some(&Token::While) => self.parse_while_stmt(),
-            Some(&Token::For) => self.parse_for_stmt(),
+        let stmt = match self.tokens.peek().map(|t| &t.token) {
+            Some(&Token::Let) | Some(&Token::LetAlt) => return self.parse_let_stmt(),
+            Some(&Token::Var) => return self.parse_var_stmt(),
+            Some(&Token::Return) => return self.parse_return_stmt(),
+            Some(&Token::Continue) => return self.parse_continue_stmt(),
+            Some(&Token::Guard) => self.parse_guard_stmt(),
+            Some(&Token::Defer) => self.parse_defer_stmt(),
+            Some(&Token::Loop) => self.parse_loop_stmt(None),
+            Some(&Token::While) => self.parse_while_stmt(None),
+            Some(&Token::For) => self.parse_for_stmt(None),
+            Some(&Token::Lifetime) if matches!(self.tokens.peek_n(1).map(|t| &t.token), Some(Token::Colon)) => {
+                let label = self.parse_loop_label()?;
+                match self.tokens.peek().map(|t| &t.token) {
+                    Some(Token::Loop) => self.parse_loop_stmt(Some(label)),
+                    Some(Token::While) => self.parse_while_stmt(Some(label)),
+                    Some(Token::For) => self.parse_for_stmt(Some(label)),
+                    _ => Err(self.label_without_loop(label)),
+                }
+            }
             Some(&Token::Match) => self.parse_match_stmt(),
             Some(&Token::LBrace) => {
                 let body = self.parse_block()?;
                 Ok(Stmt {
                     kind: StmtKind::Expr(Expr {
-                        kind: ExprKind::Block(BlockExpr {
+                        kind: Box::new(ExprKind::Block(BlockExpr {
                             label: None,
                             stmts: body,
                             ty: None,
-                        }),
+                        })),
                         span: start_span,
                         ty: None,
                     }),
                     span: start_span,
                 })
             }
-            _ => {
-                let expr = self.parse_expr()?;
-                if self.tokens.peek().map(|t| &t.token) == Some(&Token::Semicolon) {
-                    self.tokens.next();
-                }
-                Ok(Stmt {
-                    kind: StmtKind::Expr(expr),
-                    span: start_span,
-                })
-            }
+            _ => return self.parse_expr_stmt(start_span),
+        }?;
+        // Block-like statements end at their closing brace; a `;` may follow.
+        self.tokens.eat(Token::Semicolon);
+        Ok(stmt)
+    }
+
+    /// An expression statement. A block-like expression such as `if c {}`
+    /// needs no `;`; any other expression does, unless it ends the block and
+    /// so is the block's value.
+    fn parse_expr_stmt(&mut self, start_span: Span) -> Result<Stmt, ChimError> {
+        let expr = self.parse_expr()?;
+        if expr.is_block_like() || self.tokens.peek_is(Token::RBrace) || self.tokens.at_end() {
+            self.tokens.eat(Token::Semicolon);
+        } else {
+            self.expect(Token::Semicolon)?;
         }
+        Ok(Stmt {
+            kind: StmtKind::Expr(expr),
+            span: start_span.merge(&self.current_span().unwrap_or(start_span)),
+        })
     }
 
     fn parse_let_stmt(&mut self) -> Result<Stmt, ChimError> {
@@ -946,13 +1538,13 @@ impl<'a> Parser<'a> {
 
         let start_span = self.current_span()?;
         let pattern = self.parse_pattern()?;
-        let ty = if self.tokens.peek().map(|t| &t.token) == Some(&Token::Colon) {
+        let ty = if self.tokens.peek_is(Token::Colon) {
             self.tokens.next();
             Some(self.parse_type()?)
         } else {
             None
         };
-        let initializer = if self.tokens.peek().map(|t| &t.token) == Some(&Token::Eq) {
+        let initializer = if self.tokens.peek_is(Token::Eq) {
             self.tokens.next();
             Some(self.parse_expr()?)
         } else {
@@ -976,13 +1568,13 @@ impl<'a> Parser<'a> {
 
         let start_span = self.current_span()?;
         let pattern = self.parse_pattern()?;
-        let ty = if self.tokens.peek().map(|t| &t.token) == Some(&Token::Colon) {
+        let ty = if self.tokens.peek_is(Token::Colon) {
             self.tokens.next();
             Some(self.parse_type()?)
         } else {
             None
         };
-        let initializer = if self.tokens.peek().map(|t| &t.token) == Some(&Token::Eq) {
+        let initializer = if self.tokens.peek_is(Token::Eq) {
             self.tokens.next();
             Some(self.parse_expr()?)
         } else {
@@ -1005,12 +1597,15 @@ impl<'a> Parser<'a> {
         let _ = self.tokens.next();
 
         let start_span = self.current_span()?;
-        let value = if self.tokens.peek().map(|t| &t.token) == Some(&Token::Semicolon) {
+        let value = if self.at_operand_end() {
             None
         } else {
-            Some(Box::new(self.parse_expr()?))
+            Some(self.parse_expr()?)
         };
-        self.expect(Token::Semicolon)?;
+        // Like any statement that ends a block, `return` needs no `;` before `}`.
+        if !self.tokens.peek_is(Token::RBrace) {
+            self.expect(Token::Semicolon)?;
+        }
 
         Ok(Stmt {
             kind: StmtKind::Return(value),
@@ -1018,42 +1613,125 @@ impl<'a> Parser<'a> {
         })
     }
 
-    fn parse_break_stmt(&mut self) -> Result<Stmt, ChimError> {
+    fn parse_guard_stmt(&mut self) -> Result<Stmt, ChimError> {
+        let start_span = self.current_span()?;
         let _ = self.tokens.next();
 
-        let start_span = self.current_span()?;
-        let value = if self.tokens.peek().map(|t| &t.token) == Some(&Token::Semicolon) {
-            None
-        } else {
-            Some(Box::new(self.parse_expr()?))
-        };
-        self.expect(Token::Semicolon)?;
+        let condition = self.parse_expr()?;
+        self.expect(Token::Else)?;
+        let else_branch = self.parse_block()?;
 
         Ok(Stmt {
-            kind: StmtKind::Break(None, value),
+            kind: StmtKind::Guard(GuardStmt {
+                condition,
+                else_branch,
+                span: start_span,
+            }),
             span: start_span,
         })
     }
 
+    fn parse_defer_stmt(&mut self) -> Result<Stmt, ChimError> {
+        let start_span = self.current_span()?;
+        let _ = self.tokens.next();
+
+        let body = self.parse_block()?;
+
+        Ok(Stmt {
+            kind: StmtKind::Defer(body),
+            span: start_span.merge(&self.current_span().unwrap_or(start_span)),
+        })
+    }
+
     fn parse_continue_stmt(&mut self) -> Result<Stmt, ChimError> {
         let _ = self.tokens.next();
-        self.expect(Token::Semicolon)?;
+        let label = self.parse_jump_label()?;
+        if !self.tokens.peek_is(Token::RBrace) {
+            self.expect(Token::Semicolon)?;
+        }
 
         Ok(Stmt {
-            kind: StmtKind::Continue,
+            kind: StmtKind::Continue(label),
             span: self.current_span()?,
         })
     }
 
-    fn parse_loop_stmt(&mut self) -> Result<Stmt, ChimError> {
+    /// Parses the optional `'name` after `break` or `continue`, reporting
+    /// labels that no enclosing loop declares.
+    fn parse_jump_label(&mut self) -> Result<Option<Label>, ChimError> {
+        if !self.tokens.peek_is(Token::Lifetime) {
+            return Ok(None);
+        }
+        let lifetime = self.parse_lifetime()?;
+        if !self.loop_labels.contains(&lifetime.name) {
+            self.errors.push(ChimError::new(
+                ErrorKind::UndefinedIdentifier,
+                format!("use of undeclared label `'{}`", lifetime.name),
+            ).with_span(lifetime.span));
+        }
+        Ok(Some(Label {
+            name: lifetime.name,
+            span: lifetime.span,
+        }))
+    }
+
+    /// Parses the body of a `loop` in expression position, whose value is
+    /// whatever its `break`s carry.
+    fn finish_loop_expr(&mut self, label: Option<Label>, start_span: Span) -> Result<Expr, ChimError> {
+        let stmts = self.parse_loop_body(label.as_ref())?;
+        Ok(Expr {
+            kind: Box::new(ExprKind::Loop(LoopExpr {
+                label: label.map(|label| label.name),
+                body: BlockExpr {
+                    label: None,
+                    stmts,
+                    ty: None,
+                },
+            })),
+            span: start_span,
+            ty: None,
+        })
+    }
+
+    /// Parses `'name:` in front of a loop.
+    fn parse_loop_label(&mut self) -> Result<Label, ChimError> {
+        let lifetime = self.parse_lifetime()?;
+        self.expect(Token::Colon)?;
+        Ok(Label {
+            name: lifetime.name,
+            span: lifetime.span,
+        })
+    }
+
+    fn label_without_loop(&self, label: Label) -> ChimError {
+        ChimError::new(
+            ErrorKind::UnexpectedToken,
+            format!("label `'{}` must be followed by `loop`, `while` or `for`", label.name),
+        ).with_span(label.span)
+    }
+
+    /// Parses a loop body with `label` in scope for the `break`s inside it.
+    fn parse_loop_body(&mut self, label: Option<&Label>) -> Result<Vec<Stmt>, ChimError> {
+        let scoped = label.is_some();
+        if let Some(label) = label {
+            self.loop_labels.push(label.name.clone());
+        }
+        let body = self.parse_block();
+        if scoped {
+            self.loop_labels.pop();
+        }
+        body
+    }
+
+    fn parse_loop_stmt(&mut self, label: Option<Label>) -> Result<Stmt, ChimError> {
         let _ = self.tokens.next();
 
         let start_span = self.current_span()?;
-        let body = self.parse_block()?;
+        let body = self.parse_loop_body(label.as_ref())?;
 
         Ok(Stmt {
             kind: StmtKind::Loop(LoopStmt {
-                label: None,
+                label: label.map(|label| label.name),
                 body,
                 span: start_span,
             }),
@@ -1061,16 +1739,16 @@ impl<'a> Parser<'a> {
         })
     }
 
-    fn parse_while_stmt(&mut self) -> Result<Stmt, ChimError> {
+    fn parse_while_stmt(&mut self, label: Option<Label>) -> Result<Stmt, ChimError> {
         let _ = self.tokens.next();
 
         let start_span = self.current_span()?;
-        let condition = self.parse_expr()?;
-        let body = self.parse_block()?;
+        let condition = self.parse_condition()?;
+        let body = self.parse_loop_body(label.as_ref())?;
 
         Ok(Stmt {
             kind: StmtKind::While(WhileStmt {
-                label: None,
+                label: label.map(|label| label.name),
                 condition,
                 body,
                 span: start_span,
@@ -1079,18 +1757,18 @@ impl<'a> Parser<'a> {
         })
     }
 
-    fn parse_for_stmt(&mut self) -> Result<Stmt, ChimError> {
+    fn parse_for_stmt(&mut self, label: Option<Label>) -> Result<Stmt, ChimError> {
         let _ = self.tokens.next();
 
         let start_span = self.current_span()?;
         let pattern = self.parse_pattern()?;
         self.expect(Token::In)?;
-        let iterable = self.parse_expr()?;
-        let body = self.parse_block()?;
+        let iterable = self.parse_condition()?;
+        let body = self.parse_loop_body(label.as_ref())?;
 
         Ok(Stmt {
             kind: StmtKind::For(ForStmt {
-                label: None,
+                label: label.map(|label| label.name),
                 pattern,
                 iterable,
                 body,
@@ -1104,7 +1782,7 @@ impl<'a> Parser<'a> {
         let _ = self.tokens.next();
 
         let start_span = self.current_span()?;
-        let expr = match self.parse_expr() {
+        let expr = match self.parse_condition() {
             Ok(e) => e,
             Err(e) => {
                 self.report_error_with_context(
@@ -1124,7 +1802,7 @@ impl<'a> Parser<'a> {
         }
 
         let mut arms = Vec::new();
-        while self.tokens.peek().map(|t| &t.token) != Some(&Token::RBrace) {
+        while !self.tokens.peek_is(Token::RBrace) {
             let pattern = match self.parse_pattern() {
                 Ok(p) => p,
                 Err(e) => {
@@ -1134,7 +1812,7 @@ impl<'a> Parser<'a> {
                 }
             };
             
-            let guard = if self.tokens.peek().map(|t| &t.token) == Some(&Token::If) {
+            let guard = if self.tokens.peek_is(Token::If) {
                 self.tokens.next();
                 match self.parse_expr() {
                     Ok(g) => Some(g),
@@ -1154,7 +1832,7 @@ impl<'a> Parser<'a> {
                 continue;
             }
             
-            let body = match self.parse_expr() {
+            let body = match self.parse_arm_body() {
                 Ok(b) => b,
                 Err(e) => {
                     self.errors.push(e);
@@ -1163,14 +1841,8 @@ impl<'a> Parser<'a> {
                 }
             };
             
-            if self.tokens.peek().map(|t| &t.token) != Some(&Token::Semicolon) {
-                self.report_error(
-                    ErrorKind::Parser,
-                    "expected semicolon after match arm".to_string(),
-                    self.current_span().unwrap_or(start_span)
-                );
-            } else {
-                self.tokens.next();
+            if let Err(e) = self.parse_arm_separator(&body) {
+                self.errors.push(e);
             }
 
             arms.push(MatchArm {
@@ -1184,17 +1856,73 @@ impl<'a> Parser<'a> {
         self.expect(Token::RBrace)?;
 
         Ok(Stmt {
-            kind: StmtKind::Match(MatchStmt {
-                expr: Box::new(expr),
-                arms,
+            kind: StmtKind::Expr(Expr {
+                kind: Box::new(ExprKind::Match(MatchExpr {
+                    expr: Box::new(expr),
+                    arms,
+                })),
                 span: start_span,
+                ty: None,
             }),
             span: start_span,
         })
     }
 
+    /// Parses a match arm's body. A body starting with `{` is just that
+    /// block, so `0 => { .. } (a, b) => ..` reads `(a, b)` as the next
+    /// pattern rather than a call on the block.
+    fn parse_arm_body(&mut self) -> Result<Expr, ChimError> {
+        if !self.tokens.peek_is(Token::LBrace) {
+            return self.parse_expr();
+        }
+        let start_span = self.current_span()?;
+        let stmts = self.parse_block()?;
+        Ok(Expr {
+            kind: Box::new(ExprKind::Block(BlockExpr {
+                label: None,
+                stmts,
+                ty: None,
+            })),
+            span: start_span,
+            ty: None,
+        })
+    }
+
+    /// Consumes the `,` or `;` after a match arm. As with statements, it may
+    /// be left out after a block-like body such as `{ .. }`, and after the
+    /// last arm.
+    fn parse_arm_separator(&mut self, body: &Expr) -> Result<(), ChimError> {
+        if self.tokens.eat(Token::Comma) || self.tokens.eat(Token::Semicolon) {
+            return Ok(());
+        }
+        if body.is_block_like() || self.tokens.peek_is(Token::RBrace) || self.tokens.at_end() {
+            return Ok(());
+        }
+        let span = Self::span_after(self.tokens.prev_span().unwrap_or(body.span));
+        let mut error = ChimError::new(ErrorKind::Parser, "expected `,` after match arm".to_string())
+            .with_span(span)
+            .with_label(span, "expected `,`".to_string());
+        if let Ok(found) = self.current_span() {
+            error = error.with_secondary_label(found, "the next arm starts here".to_string());
+        }
+        Err(error)
+    }
+
     fn parse_expr(&mut self) -> Result<Expr, ChimError> {
-        match self.parse_assign_expr() {
+        if self.expr_depth >= MAX_EXPR_DEPTH {
+            let span = self.current_span()?;
+            self.recover_in_expr();
+            return Err(ChimError::new(
+                ErrorKind::RecursionLimit,
+                format!("expression nesting exceeds the limit of {}", MAX_EXPR_DEPTH),
+            ).with_span(span));
+        }
+
+        self.expr_depth += 1;
+        let result = self.parse_assign_expr();
+        self.expr_depth -= 1;
+
+        match result {
             Ok(expr) => Ok(expr),
             Err(e) => {
                 self.recover_in_expr();
@@ -1204,18 +1932,19 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_assign_expr(&mut self) -> Result<Expr, ChimError> {
-        let left = self.parse_range_expr()?;
+        let left = self.parse_ternary_expr()?;
 
         match self.tokens.peek().map(|t| &t.token) {
             Some(&Token::Eq) => {
                 self.tokens.next();
+                self.check_place(&left);
                 let right = self.parse_assign_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Assign(AssignExpr {
+                    span: left.span,
+                    kind: Box::new(ExprKind::Assign(AssignExpr {
                         left: Box::new(left),
                         right: Box::new(right),
-                    }),
-                    span: left.span,
+                    })),
                     ty: None,
                 })
             }
@@ -1223,14 +1952,15 @@ impl<'a> Parser<'a> {
             | Some(&Token::PercentEq) | Some(&Token::AndEq) | Some(&Token::PipeEq) | Some(&Token::CaretEq)
             | Some(&Token::LShiftEq) | Some(&Token::RShiftEq) => {
                 let op = self.parse_assign_op()?;
+                self.check_place(&left);
                 let right = self.parse_assign_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::AssignOp(AssignOpExpr {
+                    span: left.span,
+                    kind: Box::new(ExprKind::AssignOp(AssignOpExpr {
                         left: Box::new(left),
                         op,
                         right: Box::new(right),
-                    }),
-                    span: left.span,
+                    })),
                     ty: None,
                 })
             }
@@ -1238,6 +1968,41 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parses `cond ? then : else`, which binds looser than every binary
+    /// operator and tighter than assignment. It is right-associative, so
+    /// `a ? b : c ? d : e` is `a ? b : (c ? d : e)`.
+    fn parse_ternary_expr(&mut self) -> Result<Expr, ChimError> {
+        let condition = self.parse_range_expr()?;
+        if !self.tokens.peek_is(Token::Question) {
+            return Ok(condition);
+        }
+        self.tokens.next();
+        let then_branch = self.parse_expr()?;
+        self.expect(Token::Colon)?;
+        let else_branch = self.parse_ternary_expr()?;
+
+        Ok(Expr {
+            span: condition.span.merge(&else_branch.span),
+            kind: Box::new(ExprKind::Ternary(TernaryExpr {
+                condition: Box::new(condition),
+                then_branch: Box::new(then_branch),
+                else_branch: Box::new(else_branch),
+            })),
+            ty: None,
+        })
+    }
+
+    /// Reports the left side of an assignment unless it is a place. The
+    /// assignment is still built so parsing carries on past it.
+    fn check_place(&mut self, target: &Expr) {
+        if !target.is_place() {
+            self.errors.push(ChimError::new(
+                ErrorKind::Parser,
+                "invalid assignment target".to_string(),
+            ).with_span(target.span).with_label(target.span, "cannot assign to this expression".to_string()));
+        }
+    }
+
     fn parse_assign_op(&mut self) -> Result<BinOp, ChimError> {
         let op = match self.tokens.next().map(|t| &t.token) {
             Some(&Token::PlusEq) => BinOp::Add,
@@ -1255,50 +2020,75 @@ impl<'a> Parser<'a> {
         Ok(op)
     }
 
+    /// Parses `a..b`, `a..=b` (or `a...b`) and their open forms `a..`, `..b`,
+    /// `..=b` and `..`.
     fn parse_range_expr(&mut self) -> Result<Expr, ChimError> {
-        let start = self.parse_logical_or_expr()?;
+        let start_span = self.current_span()?;
+        let start = if self.at_range_op() {
+            None
+        } else {
+            Some(self.parse_logical_or_expr()?)
+        };
+        if !self.at_range_op() {
+            return Ok(start.expect("a range without a start begins with a range operator"));
+        }
 
-        match self.tokens.peek().map(|t| &t.token) {
-            Some(Token::DotDot) | Some(Token::DotDotDot) => {
-                let tok = self.tokens.next();
-                let is_dotdotdot = match tok.map(|t| &t.token) {
-                    Some(Token::DotDotDot) => true,
-                    _ => false,
-                };
-                let end = if self.tokens.peek().map(|t| &t.token).map(|t| {
-                    matches!(t, Token::LParen | Token::LBrace | Token::Identifier | Token::Int | Token::String)
-                }).unwrap_or(false) {
-                    Some(Box::new(self.parse_logical_or_expr()?))
-                } else {
-                    None
-                };
-                Ok(Expr {
-                    kind: ExprKind::Range(RangeExpr {
-                        start: Some(Box::new(start)),
-                        end,
-                        inclusive: is_dotdotdot,
-                    }),
-                    span: start.span,
-                    ty: None,
-                })
-            }
-            _ => Ok(start),
+        let op_span = self.current_span()?;
+        let inclusive = !self.tokens.peek_is(Token::DotDot);
+        self.tokens.next();
+        let end = if self.at_range_end() {
+            Some(Box::new(self.parse_logical_or_expr()?))
+        } else {
+            None
+        };
+        if inclusive && end.is_none() {
+            self.errors.push(ChimError::new(
+                ErrorKind::Parser,
+                "inclusive range must have an end".to_string(),
+            ).with_span(op_span));
         }
+
+        Ok(Expr {
+            span: start.as_ref().map_or(start_span, |start| start.span),
+            kind: Box::new(ExprKind::Range(RangeExpr {
+                start: start.map(Box::new),
+                end,
+                inclusive,
+            })),
+            ty: None,
+        })
+    }
+
+    fn at_range_op(&self) -> bool {
+        matches!(
+            self.tokens.peek().map(|t| &t.token),
+            Some(Token::DotDot) | Some(Token::DotDotDot) | Some(Token::DotDotEq)
+        )
+    }
+
+    /// Whether an end operand follows a range operator; `a..` is open when the
+    /// range is followed by a closing delimiter, a separator, or a block.
+    fn at_range_end(&self) -> bool {
+        !matches!(
+            self.tokens.peek().map(|t| &t.token),
+            None | Some(Token::RParen) | Some(Token::RBracket) | Some(Token::RBrace) | Some(Token::LBrace)
+                | Some(Token::Comma) | Some(Token::Semicolon) | Some(Token::Arrow) | Some(Token::Eq)
+        )
     }
 
     fn parse_logical_or_expr(&mut self) -> Result<Expr, ChimError> {
         let mut left = self.parse_logical_and_expr()?;
 
-        while let Some(Token::OrOr) = self.tokens.peek().map(|t| &t.token) {
+        while self.tokens.peek_is(Token::OrOr) {
             self.tokens.next();
             let right = self.parse_logical_and_expr()?;
             left = Expr {
-                kind: ExprKind::Binary(BinaryExpr {
+                span: left.span,
+                kind: Box::new(ExprKind::Binary(BinaryExpr {
                     left: Box::new(left),
                     op: BinOp::Or,
                     right: Box::new(right),
-                }),
-                span: left.span,
+                })),
                 ty: None,
             };
         }
@@ -1309,16 +2099,16 @@ impl<'a> Parser<'a> {
     fn parse_logical_and_expr(&mut self) -> Result<Expr, ChimError> {
         let mut left = self.parse_bitwise_or_expr()?;
 
-        while let Some(Token::AndAnd) = self.tokens.peek().map(|t| &t.token) {
+        while self.tokens.peek_is(Token::AndAnd) {
             self.tokens.next();
             let right = self.parse_bitwise_or_expr()?;
             left = Expr {
-                kind: ExprKind::Binary(BinaryExpr {
+                span: left.span,
+                kind: Box::new(ExprKind::Binary(BinaryExpr {
                     left: Box::new(left),
                     op: BinOp::And,
                     right: Box::new(right),
-                }),
-                span: left.span,
+                })),
                 ty: None,
             };
         }
@@ -1329,16 +2119,16 @@ impl<'a> Parser<'a> {
     fn parse_bitwise_or_expr(&mut self) -> Result<Expr, ChimError> {
         let mut left = self.parse_bitwise_xor_expr()?;
 
-        while let Some(Token::Pipe) = self.tokens.peek().map(|t| &t.token) {
+        while self.tokens.peek_is(Token::Pipe) {
             self.tokens.next();
             let right = self.parse_bitwise_xor_expr()?;
             left = Expr {
-                kind: ExprKind::Binary(BinaryExpr {
+                span: left.span,
+                kind: Box::new(ExprKind::Binary(BinaryExpr {
                     left: Box::new(left),
                     op: BinOp::BitOr,
                     right: Box::new(right),
-                }),
-                span: left.span,
+                })),
                 ty: None,
             };
         }
@@ -1349,16 +2139,16 @@ impl<'a> Parser<'a> {
     fn parse_bitwise_xor_expr(&mut self) -> Result<Expr, ChimError> {
         let mut left = self.parse_bitwise_and_expr()?;
 
-        while let Some(Token::Caret) = self.tokens.peek().map(|t| &t.token) {
+        while self.tokens.peek_is(Token::Caret) {
             self.tokens.next();
             let right = self.parse_bitwise_and_expr()?;
             left = Expr {
-                kind: ExprKind::Binary(BinaryExpr {
+                span: left.span,
+                kind: Box::new(ExprKind::Binary(BinaryExpr {
                     left: Box::new(left),
                     op: BinOp::BitXor,
                     right: Box::new(right),
-                }),
-                span: left.span,
+                })),
                 ty: None,
             };
         }
@@ -1369,16 +2159,16 @@ impl<'a> Parser<'a> {
     fn parse_bitwise_and_expr(&mut self) -> Result<Expr, ChimError> {
         let mut left = self.parse_comparison_expr()?;
 
-        while let Some(Token::Ampersand) = self.tokens.peek().map(|t| &t.token) {
+        while self.tokens.peek_is(Token::Ampersand) {
             self.tokens.next();
             let right = self.parse_comparison_expr()?;
             left = Expr {
-                kind: ExprKind::Binary(BinaryExpr {
+                span: left.span,
+                kind: Box::new(ExprKind::Binary(BinaryExpr {
                     left: Box::new(left),
                     op: BinOp::BitAnd,
                     right: Box::new(right),
-                }),
-                span: left.span,
+                })),
                 ty: None,
             };
         }
@@ -1394,12 +2184,12 @@ impl<'a> Parser<'a> {
                 self.tokens.next();
                 let right = self.parse_shift_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Binary(BinaryExpr {
+                    span: left.span,
+                    kind: Box::new(ExprKind::Binary(BinaryExpr {
                         left: Box::new(left),
                         op: BinOp::Eq,
                         right: Box::new(right),
-                    }),
-                    span: left.span,
+                    })),
                     ty: None,
                 })
             }
@@ -1407,12 +2197,12 @@ impl<'a> Parser<'a> {
                 self.tokens.next();
                 let right = self.parse_shift_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Binary(BinaryExpr {
+                    span: left.span,
+                    kind: Box::new(ExprKind::Binary(BinaryExpr {
                         left: Box::new(left),
                         op: BinOp::Ne,
                         right: Box::new(right),
-                    }),
-                    span: left.span,
+                    })),
                     ty: None,
                 })
             }
@@ -1420,12 +2210,12 @@ impl<'a> Parser<'a> {
                 self.tokens.next();
                 let right = self.parse_shift_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Binary(BinaryExpr {
+                    span: left.span,
+                    kind: Box::new(ExprKind::Binary(BinaryExpr {
                         left: Box::new(left),
                         op: BinOp::Lt,
                         right: Box::new(right),
-                    }),
-                    span: left.span,
+                    })),
                     ty: None,
                 })
             }
@@ -1433,12 +2223,12 @@ impl<'a> Parser<'a> {
                 self.tokens.next();
                 let right = self.parse_shift_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Binary(BinaryExpr {
+                    span: left.span,
+                    kind: Box::new(ExprKind::Binary(BinaryExpr {
                         left: Box::new(left),
                         op: BinOp::Le,
                         right: Box::new(right),
-                    }),
-                    span: left.span,
+                    })),
                     ty: None,
                 })
             }
@@ -1446,12 +2236,12 @@ impl<'a> Parser<'a> {
                 self.tokens.next();
                 let right = self.parse_shift_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Binary(BinaryExpr {
+                    span: left.span,
+                    kind: Box::new(ExprKind::Binary(BinaryExpr {
                         left: Box::new(left),
                         op: BinOp::Gt,
                         right: Box::new(right),
-                    }),
-                    span: left.span,
+                    })),
                     ty: None,
                 })
             }
@@ -1459,12 +2249,12 @@ impl<'a> Parser<'a> {
                 self.tokens.next();
                 let right = self.parse_shift_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Binary(BinaryExpr {
+                    span: left.span,
+                    kind: Box::new(ExprKind::Binary(BinaryExpr {
                         left: Box::new(left),
                         op: BinOp::Ge,
                         right: Box::new(right),
-                    }),
-                    span: left.span,
+                    })),
                     ty: None,
                 })
             }
@@ -1475,30 +2265,30 @@ impl<'a> Parser<'a> {
     fn parse_shift_expr(&mut self) -> Result<Expr, ChimError> {
         let mut left = self.parse_additive_expr()?;
 
-        while let Some(Token::LShift) = self.tokens.peek().map(|t| &t.token) {
+        while self.tokens.peek_is(Token::LShift) {
             self.tokens.next();
             let right = self.parse_additive_expr()?;
             left = Expr {
-                kind: ExprKind::Binary(BinaryExpr {
+                span: left.span,
+                kind: Box::new(ExprKind::Binary(BinaryExpr {
                     left: Box::new(left),
                     op: BinOp::Shl,
                     right: Box::new(right),
-                }),
-                span: left.span,
+                })),
                 ty: None,
             };
         }
 
-        while let Some(Token::RShift) = self.tokens.peek().map(|t| &t.token) {
+        while self.tokens.peek_is(Token::RShift) {
             self.tokens.next();
             let right = self.parse_additive_expr()?;
             left = Expr {
-                kind: ExprKind::Binary(BinaryExpr {
+                span: left.span,
+                kind: Box::new(ExprKind::Binary(BinaryExpr {
                     left: Box::new(left),
                     op: BinOp::Shr,
                     right: Box::new(right),
-                }),
-                span: left.span,
+                })),
                 ty: None,
             };
         }
@@ -1515,12 +2305,12 @@ impl<'a> Parser<'a> {
                     self.tokens.next();
                     let right = self.parse_multiplicative_expr()?;
                     left = Expr {
-                        kind: ExprKind::Binary(BinaryExpr {
+                        span: left.span,
+                        kind: Box::new(ExprKind::Binary(BinaryExpr {
                             left: Box::new(left),
                             op: BinOp::Add,
                             right: Box::new(right),
-                        }),
-                        span: left.span,
+                        })),
                         ty: None,
                     };
                 }
@@ -1528,12 +2318,12 @@ impl<'a> Parser<'a> {
                     self.tokens.next();
                     let right = self.parse_multiplicative_expr()?;
                     left = Expr {
-                        kind: ExprKind::Binary(BinaryExpr {
+                        span: left.span,
+                        kind: Box::new(ExprKind::Binary(BinaryExpr {
                             left: Box::new(left),
                             op: BinOp::Sub,
                             right: Box::new(right),
-                        }),
-                        span: left.span,
+                        })),
                         ty: None,
                     };
                 }
@@ -1553,12 +2343,12 @@ impl<'a> Parser<'a> {
                     self.tokens.next();
                     let right = self.parse_unary_expr()?;
                     left = Expr {
-                        kind: ExprKind::Binary(BinaryExpr {
+                        span: left.span,
+                        kind: Box::new(ExprKind::Binary(BinaryExpr {
                             left: Box::new(left),
                             op: BinOp::Mul,
                             right: Box::new(right),
-                        }),
-                        span: left.span,
+                        })),
                         ty: None,
                     };
                 }
@@ -1566,12 +2356,12 @@ impl<'a> Parser<'a> {
                     self.tokens.next();
                     let right = self.parse_unary_expr()?;
                     left = Expr {
-                        kind: ExprKind::Binary(BinaryExpr {
+                        span: left.span,
+                        kind: Box::new(ExprKind::Binary(BinaryExpr {
                             left: Box::new(left),
                             op: BinOp::Div,
                             right: Box::new(right),
-                        }),
-                        span: left.span,
+                        })),
                         ty: None,
                     };
                 }
@@ -1579,12 +2369,12 @@ impl<'a> Parser<'a> {
                     self.tokens.next();
                     let right = self.parse_unary_expr()?;
                     left = Expr {
-                        kind: ExprKind::Binary(BinaryExpr {
+                        span: left.span,
+                        kind: Box::new(ExprKind::Binary(BinaryExpr {
                             left: Box::new(left),
                             op: BinOp::Mod,
                             right: Box::new(right),
-                        }),
-                        span: left.span,
+                        })),
                         ty: None,
                     };
                 }
@@ -1601,11 +2391,11 @@ impl<'a> Parser<'a> {
                 self.tokens.next();
                 let expr = self.parse_unary_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Unary(UnaryExpr {
+                    span: expr.span,
+                    kind: Box::new(ExprKind::Unary(UnaryExpr {
                         op: UnOp::Neg,
                         expr: Box::new(expr),
-                    }),
-                    span: expr.span,
+                    })),
                     ty: None,
                 })
             }
@@ -1613,17 +2403,17 @@ impl<'a> Parser<'a> {
                 self.tokens.next();
                 let expr = self.parse_unary_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Unary(UnaryExpr {
+                    span: expr.span,
+                    kind: Box::new(ExprKind::Unary(UnaryExpr {
                         op: UnOp::Not,
                         expr: Box::new(expr),
-                    }),
-                    span: expr.span,
+                    })),
                     ty: None,
                 })
             }
             Some(&Token::Ampersand) => {
                 self.tokens.next();
-                let mutability = if self.tokens.peek().map(|t| &t.token) == Some(&Token::Mut) {
+                let mutability = if self.tokens.peek_is(Token::Mut) {
                     self.tokens.next();
                     Mutability::Mutable
                 } else {
@@ -1631,11 +2421,11 @@ impl<'a> Parser<'a> {
                 };
                 let expr = self.parse_unary_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Unary(UnaryExpr {
+                    span: expr.span,
+                    kind: Box::new(ExprKind::Unary(UnaryExpr {
                         op: if mutability == Mutability::Mutable { UnOp::RefMut } else { UnOp::Ref },
                         expr: Box::new(expr),
-                    }),
-                    span: expr.span,
+                    })),
                     ty: None,
                 })
             }
@@ -1643,11 +2433,11 @@ impl<'a> Parser<'a> {
                 self.tokens.next();
                 let expr = self.parse_unary_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Unary(UnaryExpr {
+                    span: expr.span,
+                    kind: Box::new(ExprKind::Unary(UnaryExpr {
                         op: UnOp::Deref,
                         expr: Box::new(expr),
-                    }),
-                    span: expr.span,
+                    })),
                     ty: None,
                 })
             }
@@ -1662,46 +2452,57 @@ impl<'a> Parser<'a> {
             match self.tokens.peek().map(|t| &t.token) {
                 Some(&Token::Dot) => {
                     self.tokens.next();
-                    let field = self.parse_identifier()?;
+                    // `t.0` is a tuple index; the field name is the index itself.
+                    let (field, is_index) = if self.tokens.eat(Token::Int) {
+                        (self.intern_string(), true)
+                    } else {
+                        (self.parse_identifier()?, false)
+                    };
                     let span = expr.span;
+                    if !is_index && self.tokens.peek_is(Token::LParen) {
+                        let args = self.parse_call_args()?;
+                        expr = Expr {
+                            kind: Box::new(ExprKind::MethodCall(MethodCallExpr {
+                                expr: Box::new(expr),
+                                method: Arc::from(field),
+                                args,
+                                generics: Vec::new(),
+                            })),
+                            span,
+                            ty: None,
+                        };
+                        continue;
+                    }
                     expr = Expr {
-                        kind: ExprKind::FieldAccess(FieldAccessExpr {
+                        kind: Box::new(ExprKind::FieldAccess(FieldAccessExpr {
                             expr: Box::new(expr),
                             field: Arc::from(field),
-                        }),
+                        })),
                         span,
                         ty: None,
                     };
                 }
                 Some(&Token::LParen) => {
-                    self.tokens.next();
-                    let mut args = SmallVec::new();
-                    while self.tokens.peek().map(|t| &t.token) != Some(&Token::RParen) {
-                        args.push(self.parse_expr()?);
-                        if self.tokens.peek().map(|t| &t.token) == Some(&Token::Comma) {
-                            self.tokens.next();
-                        }
-                    }
-                    self.expect(Token::RParen)?;
+                    let args = self.parse_call_args()?;
                     expr = Expr {
-                        kind: ExprKind::Call(CallExpr {
+                        span: expr.span,
+                        kind: Box::new(ExprKind::Call(CallExpr {
                             func: Box::new(expr),
                             args,
-                        }),
-                        span: expr.span,
+                        })),
                         ty: None,
                     };
                 }
                 Some(&Token::LBracket) => {
                     self.tokens.next();
-                    let index = self.parse_expr()?;
+                    let index = self.parse_delimited_expr()?;
                     self.expect(Token::RBracket)?;
                     expr = Expr {
-                        kind: ExprKind::Index(IndexExpr {
+                        span: expr.span,
+                        kind: Box::new(ExprKind::Index(IndexExpr {
                             expr: Box::new(expr),
                             index: Box::new(index),
-                        }),
-                        span: expr.span,
+                        })),
                         ty: None,
                     };
                 }
@@ -1709,29 +2510,26 @@ impl<'a> Parser<'a> {
                     self.tokens.next();
                     let ty = self.parse_type()?;
                     expr = Expr {
-                        kind: ExprKind::Cast(CastExpr {
+                        span: expr.span,
+                        kind: Box::new(ExprKind::Cast(CastExpr {
                             expr: Box::new(expr),
                             ty,
-                        }),
-                        span: expr.span,
+                        })),
                         ty: None,
                     };
                 }
-                Some(&Token::Question) => {
+                Some(&Token::Question) if !self.ternary_follows() => {
                     self.tokens.next();
-                    let then_branch = self.parse_expr()?;
-                    self.expect(Token::Colon)?;
-                    let else_branch = self.parse_expr()?;
+                    let span = expr.span;
                     expr = Expr {
-                        kind: ExprKind::Ternary(TernaryExpr {
-                            condition: Box::new(expr),
-                            then_branch: Box::new(then_branch),
-                            else_branch: Box::new(else_branch),
-                        }),
-                        span: expr.span,
+                        kind: Box::new(ExprKind::Try(TryExpr {
+                            expr: Box::new(expr),
+                        })),
+                        span,
                         ty: None,
                     };
                 }
+                // A `?` starting `? then : else` is left to `parse_ternary_expr`.
                 _ => break,
             }
         }
@@ -1739,39 +2537,370 @@ impl<'a> Parser<'a> {
         Ok(expr)
     }
 
-    fn parse_primary_expr(&mut self) -> Result<Expr, ChimError> {
-        let start_span = self.current_span()?;
+    /// With the current token a `?`: whether it starts `? then : else` rather
+    /// than a try. It does only if a `:` follows before the enclosing argument,
+    /// index, or statement ends, so `f(a?)`, `xs[i?]` and `a?.b` are tries.
+    /// A `{` outside brackets ends the search too: in `if check()? { .. }` it
+    /// opens the body, and a `:` after it belongs to a later statement.
+    fn ternary_follows(&self) -> bool {
+        let mut depth = 0usize;
+        let mut offset = 1;
+        while let Some(token) = self.tokens.peek_n(offset).map(|t| &t.token) {
+            match token {
+                Token::LBrace if depth == 0 => return false,
+                Token::LParen | Token::LBracket | Token::LBrace => depth += 1,
+                Token::RParen | Token::RBracket | Token::RBrace if depth == 0 => return false,
+                Token::RParen | Token::RBracket | Token::RBrace => depth -= 1,
+                Token::Colon if depth == 0 => return true,
+                Token::Comma | Token::Semicolon | Token::Arrow | Token::Eof if depth == 0 => return false,
+                Token::Let | Token::Var | Token::Const | Token::Static | Token::Return | Token::Func
+                | Token::While | Token::For | Token::Loop | Token::Defer | Token::Guard
+                    if depth == 0 => return false,
+                _ => {}
+            }
+            offset += 1;
+        }
+        false
+    }
 
-        match self.tokens.next().map(|t| &t.token) {
-            Some(Token::Identifier) => {
-                let name = self.intern_identifier();
-                let span = start_span;
-                Ok(Expr {
-                    kind: ExprKind::Identifier(Arc::from(name)),
-                    span,
-                    ty: None,
-                })
+    /// The rest of a closure once its opening `|` (or the empty `||`) has
+    /// been consumed: parameters, an optional `capture(a, ref b)` list and
+    /// the body.
+    fn parse_closure(&mut self, start_span: Span, is_move: bool, has_params: bool) -> Result<Expr, ChimError> {
+        let mut params = Vec::new();
+        if has_params && !self.tokens.peek_is(Token::Pipe) {
+            loop {
+                let param_span = self.current_span()?;
+                let name = self.parse_identifier()?;
+                let ty = if self.tokens.eat(Token::Colon) {
+                    self.parse_type()?
+                } else {
+                    Type { kind: Box::new(TypeKind::Infer), span: param_span }
+                };
+                params.push(Param {
+                    name: Arc::from(name),
+                    ty,
+                    span: param_span,
+                    is_mut: false,
+                    is_ref: false,
+                });
+                if !self.tokens.eat(Token::Comma) {
+                    break;
+                }
             }
-            Some(Token::Int) => {
-                let text = self.intern_string();
-                let value = text.parse::<i128>().unwrap_or(0);
-                Ok(Expr {
-                    kind: ExprKind::Literal(Literal {
-                        kind: LiteralKind::Int(value),
-                        span: start_span,
-                    }),
+        }
+        if has_params {
+            self.expect(Token::Pipe)?;
+        }
+        let captures = if self.tokens.eat(Token::Capture) {
+            self.parse_capture_list()?
+        } else {
+            Vec::new()
+        };
+        let body = Box::new(self.parse_closure_body()?);
+        Ok(Expr {
+            kind: Box::new(ExprKind::Closure(ClosureExpr {
+                params,
+                body,
+                is_async: false,
+                is_move,
+                captures,
+            })),
+            span: start_span.merge(&self.current_span().unwrap_or(start_span)),
+            ty: None,
+        })
+    }
+
+    /// `(a, ref b)` after `capture`: a bare name is captured by value, one
+    /// prefixed with `ref` (or `captureref`) by reference. `capturevalue`
+    /// spells out a by-value capture.
+    fn parse_capture_list(&mut self) -> Result<Vec<Capture>, ChimError> {
+        self.expect(Token::LParen)?;
+        let mut captures: Vec<Capture> = Vec::new();
+        while !self.tokens.peek_is(Token::RParen) {
+            let by_ref = if self.tokens.eat(Token::Ref) || self.tokens.eat(Token::CaptureRef) {
+                true
+            } else {
+                self.tokens.eat(Token::CaptureValue);
+                false
+            };
+            let span = self.current_span()?;
+            let name: Ident = Arc::from(self.parse_identifier()?);
+            let already_captured = captures.iter().any(|capture| match capture {
+                Capture::CaptureRef(captured) | Capture::CaptureValue(captured) => *captured == name,
+            });
+            if already_captured {
+                self.report_error(ErrorKind::Redefinition, format!("`{}` is captured more than once", name), span);
+            } else if by_ref {
+                captures.push(Capture::CaptureRef(name));
+            } else {
+                captures.push(Capture::CaptureValue(name));
+            }
+            if !self.tokens.eat(Token::Comma) {
+                break;
+            }
+        }
+        self.expect(Token::RParen)?;
+        Ok(captures)
+    }
+
+    /// A closure body is either a block or a single expression. The expression
+    /// form is a full expression, assignment included, so `|x| total += x`
+    /// assigns in the body; a comma is not an operator, so in `f(|x| x + 1, g)`
+    /// it still separates call arguments.
+    fn parse_closure_body(&mut self) -> Result<Expr, ChimError> {
+        if self.tokens.peek_is(Token::LBrace) {
+            let start_span = self.current_span()?;
+            let stmts = self.parse_block()?;
+            return Ok(Expr {
+                kind: Box::new(ExprKind::Block(BlockExpr {
+                    label: None,
+                    stmts,
+                    ty: None,
+                })),
+                span: start_span,
+                ty: None,
+            });
+        }
+        self.parse_expr()
+    }
+
+    /// Whether the token `offset` ahead is the `!` of a macro invocation,
+    /// i.e. followed by an opening delimiter.
+    fn at_macro_call(&self, offset: usize) -> bool {
+        self.tokens.peek_n(offset).map(|t| &t.token) == Some(&Token::Bang)
+            && matches!(
+                self.tokens.peek_n(offset + 1).map(|t| &t.token),
+                Some(Token::LParen | Token::LBracket | Token::LBrace)
+            )
+    }
+
+    /// Parses the `!` and delimited token tree of a macro invocation whose
+    /// `name` has just been consumed.
+    fn parse_macro_call(&mut self, name: String, start_span: Span) -> Result<MacroCall, ChimError> {
+        self.expect(Token::Bang)?;
+        let delimiter = match self.tokens.peek().map(|t| &t.token) {
+            Some(Token::LBracket) => MacroDelimiter::Bracket,
+            Some(Token::LBrace) => MacroDelimiter::Brace,
+            _ => MacroDelimiter::Paren,
+        };
+        let (start, end) = self.skip_token_tree()?;
+        let close_span = self.tokens.slice_between(end - 1, end)[0].span;
+        Ok(MacroCall {
+            name: Arc::from(name),
+            delimiter,
+            tokens: self.macro_tokens(start + 1, end - 1),
+            span: start_span.merge(&close_span),
+        })
+    }
+
+    /// Converts the balanced tokens between positions `start` and `end` into
+    /// macro tokens, nesting bracketed groups.
+    fn macro_tokens(&self, start: usize, end: usize) -> Vec<MacroToken> {
+        let mut tokens = Vec::new();
+        let mut open_groups: Vec<(MacroDelimiter, Vec<MacroToken>)> = Vec::new();
+        for (offset, token) in self.tokens.slice_between(start, end).iter().enumerate() {
+            let open = match token.token {
+                Token::LParen => Some(MacroDelimiter::Paren),
+                Token::LBracket => Some(MacroDelimiter::Bracket),
+                Token::LBrace => Some(MacroDelimiter::Brace),
+                _ => None,
+            };
+            if let Some(delimiter) = open {
+                open_groups.push((delimiter, std::mem::take(&mut tokens)));
+                continue;
+            }
+            if matches!(token.token, Token::RParen | Token::RBracket | Token::RBrace) {
+                let (delimiter, outer) = open_groups.pop().expect("token trees are balanced");
+                let group = std::mem::replace(&mut tokens, outer);
+                tokens.push(MacroToken::Group(delimiter, group));
+                continue;
+            }
+
+            let text = self.get_source_text(start + offset);
+            let mut chars = text.chars();
+            tokens.push(match (&token.token, chars.next(), chars.next()) {
+                (Token::Int | Token::Float | Token::String | Token::RawString | Token::Char | Token::Byte | Token::ByteString, _, _) => {
+                    MacroToken::Literal(text.to_string())
+                }
+                (Token::Identifier, _, _) => MacroToken::Ident(Arc::from(text)),
+                // Keywords travel as identifiers and are re-lexed on expansion.
+                (_, Some(first), _) if first.is_alphabetic() || first == '_' => MacroToken::Ident(Arc::from(text)),
+                (_, Some(c), None) => MacroToken::Punctuation(c),
+                _ => MacroToken::Operator(Arc::from(text)),
+            });
+        }
+        tokens
+    }
+
+    /// Parses a parenthesized, comma-separated argument list.
+    fn parse_call_args(&mut self) -> Result<SmallVec<[Box<Expr>; 4]>, ChimError> {
+        let open_span = self.current_span()?;
+        self.expect(Token::LParen)?;
+        let mut args = SmallVec::new();
+        while !self.tokens.peek_is(Token::RParen) {
+            args.push(Box::new(self.parse_delimited_expr()?));
+            if !self.tokens.eat(Token::Comma) {
+                break;
+            }
+        }
+        self.expect_with_context(Token::RParen, open_span, "argument list opened here")?;
+        Ok(args)
+    }
+
+    /// Parses an expression inside `(..)` or `[..]`, where a struct literal
+    /// is unambiguous even within a condition: `if contains(P { x: 1 }) {`.
+    fn parse_delimited_expr(&mut self) -> Result<Expr, ChimError> {
+        let no_struct_literal = std::mem::replace(&mut self.no_struct_literal, false);
+        let expr = self.parse_expr();
+        self.no_struct_literal = no_struct_literal;
+        expr
+    }
+
+    /// Parses the condition of an `if`, `while` or `match`, or the iterable
+    /// of a `for`, none of which may be a bare struct literal.
+    fn parse_condition(&mut self) -> Result<Expr, ChimError> {
+        let no_struct_literal = std::mem::replace(&mut self.no_struct_literal, true);
+        let condition = self.parse_expr();
+        self.no_struct_literal = no_struct_literal;
+        condition
+    }
+
+    fn at_struct_literal(&self) -> bool {
+        !self.no_struct_literal && self.tokens.peek_is(Token::LBrace)
+    }
+
+    /// Parses `{ x: 1, y, ..base }` after the path naming the struct. The
+    /// base, if any, comes last.
+    fn parse_struct_literal(&mut self, path: Path, start_span: Span) -> Result<Expr, ChimError> {
+        self.expect(Token::LBrace)?;
+        let mut fields = Vec::new();
+        let mut base = None;
+        while !self.tokens.peek_is(Token::RBrace) && !self.tokens.at_end() {
+            if self.tokens.peek_is(Token::DotDot) {
+                let dots = self.current_span()?;
+                self.tokens.next();
+                if matches!(self.tokens.peek().map(|t| &t.token), Some(Token::RBrace) | Some(Token::Comma) | None) {
+                    self.errors.push(ChimError::new(
+                        ErrorKind::Parser,
+                        "expected base expression after `..`".to_string(),
+                    ).with_span(dots).with_label(dots, "struct update needs a base, e.g. `..base`".to_string()));
+                    self.tokens.eat(Token::Comma);
+                } else {
+                    base = Some(Box::new(self.parse_expr()?));
+                }
+                break;
+            }
+
+            let field_span = self.current_span()?;
+            let name: Ident = Arc::from(self.parse_identifier()?);
+            // `P { x }` is shorthand for `P { x: x }`.
+            let expr = if self.tokens.eat(Token::Colon) {
+                self.parse_expr()?
+            } else {
+                Expr {
+                    kind: Box::new(ExprKind::Identifier(name.clone())),
+                    span: field_span,
+                    ty: None,
+                }
+            };
+            fields.push(StructField {
+                name,
+                expr,
+                span: field_span.merge(&self.tokens.prev_span().unwrap_or(field_span)),
+            });
+            if !self.tokens.eat(Token::Comma) {
+                break;
+            }
+        }
+        self.expect(Token::RBrace)?;
+
+        Ok(Expr {
+            kind: Box::new(ExprKind::Struct(StructExpr { path, fields, base })),
+            span: start_span.merge(&self.tokens.prev_span().unwrap_or(start_span)),
+            ty: None,
+        })
+    }
+
+    fn parse_primary_expr(&mut self) -> Result<Expr, ChimError> {
+        let start_span = self.current_span()?;
+
+        if self.contextual_keyword_is_identifier() {
+            let name = self.parse_identifier()?;
+            return Ok(Expr {
+                kind: Box::new(ExprKind::Identifier(Arc::from(name))),
+                span: start_span,
+                ty: None,
+            });
+        }
+
+        match self.tokens.next().map(|t| &t.token) {
+            // `Self::new()` and `Self { .. }` are paths like any other.
+            Some(Token::Identifier) | Some(Token::SelfRef) | Some(Token::SelfKeyword) => {
+                let name = self.intern_identifier();
+                let span = start_span;
+                if self.at_macro_call(0) {
+                    let call = self.parse_macro_call(name, start_span)?;
+                    let span = call.span;
+                    return Ok(Expr {
+                        kind: Box::new(ExprKind::MacroCall(call)),
+                        span,
+                        ty: None,
+                    });
+                }
+                if self.tokens.peek_is(Token::DoubleColon) {
+                    // `Color::Red`, `Option::Some`: left for resolution to
+                    // decide whether it names a variant, function or constant.
+                    let mut segments = vec![PathSegment { ident: Arc::from(name), args: Vec::new(), span }];
+                    while self.tokens.eat(Token::DoubleColon) {
+                        let segment_span = self.current_span()?;
+                        let ident = self.parse_identifier()?;
+                        segments.push(PathSegment { ident: Arc::from(ident), args: Vec::new(), span: segment_span });
+                    }
+                    let span = start_span.merge(&segments[segments.len() - 1].span);
+                    let path = Path { segments, span };
+                    if self.at_struct_literal() {
+                        return self.parse_struct_literal(path, start_span);
+                    }
+                    return Ok(Expr {
+                        kind: Box::new(ExprKind::Path(path)),
+                        span,
+                        ty: None,
+                    });
+                }
+                if self.at_struct_literal() {
+                    let path = Path {
+                        segments: vec![PathSegment { ident: Arc::from(name), args: Vec::new(), span }],
+                        span,
+                    };
+                    return self.parse_struct_literal(path, start_span);
+                }
+                Ok(Expr {
+                    kind: Box::new(ExprKind::Identifier(Arc::from(name))),
+                    span,
+                    ty: None,
+                })
+            }
+            Some(Token::Int) => {
+                let text = self.intern_string();
+                let value = self.parse_base_literal(&text);
+                Ok(Expr {
+                    kind: Box::new(ExprKind::Literal(Literal {
+                        kind: LiteralKind::Int(value),
+                        span: start_span,
+                    })),
                     span: start_span,
                     ty: None,
                 })
             }
             Some(Token::Float) => {
                 let text = self.intern_string();
-                let value = text.parse::<f64>().unwrap_or(0.0);
+                let value = self.parse_float_literal(&text, start_span)?;
                 Ok(Expr {
-                    kind: ExprKind::Literal(Literal {
+                    kind: Box::new(ExprKind::Literal(Literal {
                         kind: LiteralKind::Float(value),
                         span: start_span,
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -1779,10 +2908,10 @@ impl<'a> Parser<'a> {
             Some(Token::String) | Some(Token::RawString) => {
                 let text = Arc::from(self.intern_string());
                 Ok(Expr {
-                    kind: ExprKind::Literal(Literal {
+                    kind: Box::new(ExprKind::Literal(Literal {
                         kind: LiteralKind::String(text),
                         span: start_span,
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -1790,10 +2919,10 @@ impl<'a> Parser<'a> {
             Some(Token::ByteString) => {
                 let text = Arc::from(self.intern_string());
                 Ok(Expr {
-                    kind: ExprKind::Literal(Literal {
+                    kind: Box::new(ExprKind::Literal(Literal {
                         kind: LiteralKind::ByteString(text),
                         span: start_span,
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -1802,210 +2931,179 @@ impl<'a> Parser<'a> {
                 let text = self.intern_string();
                 let value = text.chars().next().unwrap_or('\0');
                 Ok(Expr {
-                    kind: ExprKind::Literal(Literal {
+                    kind: Box::new(ExprKind::Literal(Literal {
                         kind: LiteralKind::Char(value),
                         span: start_span,
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
             }
             Some(Token::Byte) => {
                 let text = self.intern_string();
-                let value = text.parse::<u8>().unwrap_or(0);
+                let value = text.bytes().next().unwrap_or(0);
                 Ok(Expr {
-                    kind: ExprKind::Literal(Literal {
+                    kind: Box::new(ExprKind::Literal(Literal {
                         kind: LiteralKind::Byte(value),
                         span: start_span,
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
             }
             Some(Token::True) => {
                 Ok(Expr {
-                    kind: ExprKind::Literal(Literal {
+                    kind: Box::new(ExprKind::Literal(Literal {
                         kind: LiteralKind::Bool(true),
                         span: start_span,
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
             }
             Some(Token::False) => {
                 Ok(Expr {
-                    kind: ExprKind::Literal(Literal {
+                    kind: Box::new(ExprKind::Literal(Literal {
                         kind: LiteralKind::Bool(false),
                         span: start_span,
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
             }
             Some(Token::Null) => {
                 Ok(Expr {
-                    kind: ExprKind::Literal(Literal {
+                    kind: Box::new(ExprKind::Literal(Literal {
                         kind: LiteralKind::Null,
                         span: start_span,
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
             }
             Some(Token::Unit) => {
                 Ok(Expr {
-                    kind: ExprKind::Literal(Literal {
+                    kind: Box::new(ExprKind::Literal(Literal {
                         kind: LiteralKind::Unit,
                         span: start_span,
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
             }
             Some(Token::LParen) => {
-                let expr = self.parse_expr()?;
+                // Parentheses lift the condition restriction: `if (P { x }).ok { .. }`.
+                let expr = self.parse_delimited_expr()?;
                 self.expect(Token::RParen)?;
                 Ok(expr)
             }
             Some(Token::LBracket) => {
                 let mut elements = SmallVec::new();
-                while self.tokens.peek().map(|t| &t.token) != Some(&Token::RBracket) {
-                    elements.push(self.parse_expr()?);
-                    if self.tokens.peek().map(|t| &t.token) == Some(&Token::Comma) {
-                        self.tokens.next();
-                    }
+                while !self.tokens.peek_is(Token::RBracket) {
+                    elements.push(Box::new(self.parse_delimited_expr()?));
+                    self.tokens.eat(Token::Comma);
                 }
                 self.expect(Token::RBracket)?;
                 Ok(Expr {
-                    kind: ExprKind::Array(ArrayExpr { elements }),
+                    kind: Box::new(ExprKind::Array(ArrayExpr { elements })),
                     span: start_span,
                     ty: None,
                 })
             }
             Some(Token::LBrace) => {
+                // `parse_block` reads the brace itself.
+                self.tokens.set_position(self.tokens.position() - 1);
                 let stmts = self.parse_block()?;
                 Ok(Expr {
-                    kind: ExprKind::Block(BlockExpr {
+                    kind: Box::new(ExprKind::Block(BlockExpr {
                         label: None,
                         stmts,
                         ty: None,
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
             }
             Some(Token::If) => {
-                let condition = self.parse_expr()?;
+                let condition = self.parse_condition()?;
                 let then_branch = BlockExpr {
                     label: None,
                     stmts: self.parse_block()?,
                     ty: None,
                 };
-                let else_branch = if self.tokens.peek().map(|t| &t.token) == Some(&Token::Else) {
+                let else_branch = if self.tokens.peek_is(Token::Else) {
                     self.tokens.next();
                     Some(Box::new(self.parse_expr()?))
                 } else {
                     None
                 };
                 Ok(Expr {
-                    kind: ExprKind::If(IfExpr {
+                    kind: Box::new(ExprKind::If(IfExpr {
                         condition: Box::new(condition),
                         then_branch,
                         else_branch,
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
             }
             Some(Token::Match) => {
-                let expr = self.parse_expr()?;
+                let expr = self.parse_condition()?;
                 self.expect(Token::LBrace)?;
                 let mut arms = Vec::new();
-                while self.tokens.peek().map(|t| &t.token) != Some(&Token::RBrace) {
+                while !self.tokens.peek_is(Token::RBrace) {
                     let pattern = self.parse_pattern()?;
-                    let guard = if self.tokens.peek().map(|t| &t.token) == Some(&Token::If) {
+                    let guard = if self.tokens.peek_is(Token::If) {
                         self.tokens.next();
                         Some(self.parse_expr()?)
                     } else {
                         None
                     };
                     self.expect(Token::Arrow)?;
-                    let body = self.parse_expr()?;
+                    let body = self.parse_arm_body()?;
+                    self.parse_arm_separator(&body)?;
                     arms.push(MatchArm {
                         pattern,
                         guard,
                         body,
                         span: start_span,
                     });
-                    if self.tokens.peek().map(|t| &t.token) == Some(&Token::Comma) {
-                        self.tokens.next();
-                    }
                 }
                 self.expect(Token::RBrace)?;
                 Ok(Expr {
-                    kind: ExprKind::Match(MatchExpr {
+                    kind: Box::new(ExprKind::Match(MatchExpr {
                         expr: Box::new(expr),
                         arms,
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
             }
-            Some(Token::Pipe) => {
-                let _is_move = false;
-                let mut params = Vec::new();
-                if self.tokens.peek().map(|t| &t.token) != Some(&Token::Pipe) {
-                    loop {
-                        let name = self.parse_identifier()?;
-                        let ty = if self.tokens.peek().map(|t| &t.token) == Some(&Token::Colon) {
-                            self.tokens.next();
-                            Some(self.parse_type()?)
-                        } else {
-                            None
-                        };
-                        params.push(Param {
-                            name: Arc::from(name),
-                            ty: ty.unwrap_or(Type {
-                                kind: Box::new(TypeKind::Infer),
-                                span: start_span,
-                            }),
-                            span: start_span,
-                            is_mut: false,
-                            is_ref: false,
-                        });
-                        if self.tokens.peek().map(|t| &t.token) == Some(&Token::Comma) {
-                            self.tokens.next();
-                        } else {
-                            break;
-                        }
-                    }
+            Some(Token::Pipe) => self.parse_closure(start_span, false, true),
+            Some(Token::OrOr) => self.parse_closure(start_span, false, false),
+            Some(Token::Move) => {
+                if self.tokens.eat(Token::Pipe) {
+                    self.parse_closure(start_span, true, true)
+                } else if self.tokens.eat(Token::OrOr) {
+                    self.parse_closure(start_span, true, false)
+                } else {
+                    let span = self.current_span().unwrap_or(start_span);
+                    Err(self.report_error(ErrorKind::UnexpectedToken, "expected a closure after `move`".to_string(), span))
                 }
-                self.expect(Token::Pipe)?;
-                let body = Box::new(self.parse_expr()?);
-                Ok(Expr {
-                    kind: ExprKind::Closure(ClosureExpr {
-                        params,
-                        body,
-                        is_async: false,
-                        is_move: _is_move,
-                    }),
-                    span: start_span,
-                    ty: None,
-                })
             }
             Some(Token::Wait) => {
                 let atomic = self.parse_expr()?;
-                let timeout = if self.tokens.peek().map(|t| &t.token) == Some(&Token::Comma) {
+                let timeout = if self.tokens.peek_is(Token::Comma) {
                     self.tokens.next();
                     Some(Box::new(self.parse_expr()?))
                 } else {
                     None
                 };
                 Ok(Expr {
-                    kind: ExprKind::Wait(WaitExpr {
+                    kind: Box::new(ExprKind::Wait(WaitExpr {
                         atomic: Box::new(atomic),
                         timeout,
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2013,9 +3111,9 @@ impl<'a> Parser<'a> {
             Some(Token::Notify) => {
                 let atomic = self.parse_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Notify(NotifyExpr {
+                    kind: Box::new(ExprKind::Notify(NotifyExpr {
                         atomic: Box::new(atomic),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2023,9 +3121,9 @@ impl<'a> Parser<'a> {
             Some(Token::NotifyAll) => {
                 let atomic = self.parse_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::NotifyAll(NotifyAllExpr {
+                    kind: Box::new(ExprKind::NotifyAll(NotifyAllExpr {
                         atomic: Box::new(atomic),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2033,9 +3131,9 @@ impl<'a> Parser<'a> {
             Some(Token::Iterator) => {
                 let iterable = self.parse_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Iterator(IteratorExpr {
+                    kind: Box::new(ExprKind::Iterator(IteratorExpr {
                         iterable: Box::new(iterable),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2043,9 +3141,9 @@ impl<'a> Parser<'a> {
             Some(Token::Next) => {
                 let iterator = self.parse_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Next(NextExpr {
+                    kind: Box::new(ExprKind::Next(NextExpr {
                         iterator: Box::new(iterator),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2053,9 +3151,9 @@ impl<'a> Parser<'a> {
             Some(Token::Item) => {
                 let iterator = self.parse_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Item(ItemExpr {
+                    kind: Box::new(ExprKind::Item(ItemExpr {
                         iterator: Box::new(iterator),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2063,9 +3161,9 @@ impl<'a> Parser<'a> {
             Some(Token::Collect) => {
                 let iterator = self.parse_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Collect(CollectExpr {
+                    kind: Box::new(ExprKind::Collect(CollectExpr {
                         iterator: Box::new(iterator),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2075,10 +3173,10 @@ impl<'a> Parser<'a> {
                 self.expect(Token::Comma)?;
                 let iterator2 = self.parse_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Chain(ChainExpr {
+                    kind: Box::new(ExprKind::Chain(ChainExpr {
                         iterator1: Box::new(iterator1),
                         iterator2: Box::new(iterator2),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2088,10 +3186,10 @@ impl<'a> Parser<'a> {
                 self.expect(Token::Comma)?;
                 let predicate = self.parse_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Filter(FilterExpr {
+                    kind: Box::new(ExprKind::Filter(FilterExpr {
                         iterator: Box::new(iterator),
                         predicate: Box::new(predicate),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2105,12 +3203,12 @@ impl<'a> Parser<'a> {
                 self.expect(Token::Comma)?;
                 let body = self.parse_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Fold(FoldExpr {
+                    kind: Box::new(ExprKind::Fold(FoldExpr {
                         iterator: Box::new(iterator),
                         init: Box::new(init),
                         accumulator: Arc::from(accumulator),
                         body: Box::new(body),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2120,10 +3218,10 @@ impl<'a> Parser<'a> {
                 self.expect(Token::Comma)?;
                 let mapper = self.parse_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Map(MapExpr {
+                    kind: Box::new(ExprKind::Map(MapExpr {
                         iterator: Box::new(iterator),
                         mapper: Box::new(mapper),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2135,10 +3233,10 @@ impl<'a> Parser<'a> {
                 let err_type = self.parse_type()?;
                 self.expect(Token::RAngle)?;
                 Ok(Expr {
-                    kind: ExprKind::Result(ResultExpr {
+                    kind: Box::new(ExprKind::Result(ResultExpr {
                         ok_type,
                         err_type,
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2146,9 +3244,9 @@ impl<'a> Parser<'a> {
             Some(Token::Ok) => {
                 let value = self.parse_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Ok(OkExpr {
+                    kind: Box::new(ExprKind::Ok(OkExpr {
                         value: Box::new(value),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2156,9 +3254,9 @@ impl<'a> Parser<'a> {
             Some(Token::Err) => {
                 let error = self.parse_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Err(ErrExpr {
+                    kind: Box::new(ExprKind::Err(ErrExpr {
                         error: Box::new(error),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2166,9 +3264,9 @@ impl<'a> Parser<'a> {
             Some(Token::Try) => {
                 let expr = self.parse_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Try(TryExpr {
+                    kind: Box::new(ExprKind::Try(TryExpr {
                         expr: Box::new(expr),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2180,11 +3278,11 @@ impl<'a> Parser<'a> {
                 self.expect(Token::Comma)?;
                 let catch_expr = self.parse_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Catch(CatchExpr {
+                    kind: Box::new(ExprKind::Catch(CatchExpr {
                         try_expr: Box::new(try_expr),
                         error_var: Arc::from(error_var),
                         catch_expr: Box::new(catch_expr),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2192,9 +3290,9 @@ impl<'a> Parser<'a> {
             Some(Token::Error) => {
                 let message = self.parse_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::ErrorExpr(ErrorExpr {
+                    kind: Box::new(ExprKind::ErrorExpr(ErrorExpr {
                         message: Box::new(message),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2202,9 +3300,9 @@ impl<'a> Parser<'a> {
             Some(Token::Context) => {
                 let context = self.parse_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Context(ContextExpr {
+                    kind: Box::new(ExprKind::Context(ContextExpr {
                         context: Box::new(context),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2212,24 +3310,30 @@ impl<'a> Parser<'a> {
             Some(Token::Throw) => {
                 let error = self.parse_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Throw(ThrowExpr {
+                    kind: Box::new(ExprKind::Throw(ThrowExpr {
                         error: Box::new(error),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
             }
             Some(Token::Future) => {
-                let body = self.parse_expr()?;
+                let body = self.parse_generator_body()?;
                 Ok(Expr {
-                    kind: ExprKind::Future(FutureExpr {
+                    kind: Box::new(ExprKind::Future(FutureExpr {
                         body: Box::new(body),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
             }
             Some(Token::Yield) => {
+                if self.generator_depth == 0 {
+                    self.errors.push(ChimError::new(
+                        ErrorKind::Parser,
+                        "`yield` is only allowed inside a `stream` or `future` block".to_string(),
+                    ).with_span(start_span));
+                }
                 let value = if self.tokens.peek().map(|t| &t.token).map(|t| {
                     matches!(t, Token::LParen | Token::LBrace | Token::Identifier | Token::Int | Token::String)
                 }).unwrap_or(false) {
@@ -2238,19 +3342,78 @@ impl<'a> Parser<'a> {
                     None
                 };
                 Ok(Expr {
-                    kind: ExprKind::Yield(YieldExpr {
+                    kind: Box::new(ExprKind::Yield(YieldExpr {
                         value,
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
             }
             Some(Token::Stream) => {
-                let body = self.parse_expr()?;
+                let body = self.parse_generator_body()?;
                 Ok(Expr {
-                    kind: ExprKind::Stream(StreamExpr {
+                    kind: Box::new(ExprKind::Stream(StreamExpr {
                         body: Box::new(body),
-                    }),
+                    })),
+                    span: start_span,
+                    ty: None,
+                })
+            }
+            Some(Token::Loop) => self.finish_loop_expr(None, start_span),
+            Some(Token::Lifetime) => {
+                let name = self.intern_identifier();
+                let label = Label {
+                    name: Arc::from(name.trim_start_matches('\'')),
+                    span: start_span,
+                };
+                self.expect(Token::Colon)?;
+                if !self.tokens.eat(Token::Loop) {
+                    return Err(self.label_without_loop(label));
+                }
+                self.finish_loop_expr(Some(label), start_span)
+            }
+            Some(Token::Break) => {
+                let label = self.parse_jump_label()?;
+                let value = if self.at_operand_end() {
+                    None
+                } else {
+                    Some(Box::new(self.parse_expr()?))
+                };
+                Ok(Expr {
+                    kind: Box::new(ExprKind::Break(label, value)),
+                    span: start_span,
+                    ty: None,
+                })
+            }
+            Some(Token::Return) => {
+                let value = if self.at_operand_end() {
+                    None
+                } else {
+                    Some(Box::new(self.parse_expr()?))
+                };
+                Ok(Expr {
+                    kind: Box::new(ExprKind::Return(value)),
+                    span: start_span.merge(&self.current_span().unwrap_or(start_span)),
+                    ty: None,
+                })
+            }
+            Some(Token::Continue) => Ok(Expr {
+                kind: Box::new(ExprKind::Continue(self.parse_jump_label()?)),
+                span: start_span,
+                ty: None,
+            }),
+            Some(Token::Async) => {
+                let is_move = self.tokens.eat(Token::Move);
+                let stmts = self.parse_block()?;
+                Ok(Expr {
+                    kind: Box::new(ExprKind::AsyncBlock(AsyncBlockExpr {
+                        body: BlockExpr {
+                            label: None,
+                            stmts,
+                            ty: None,
+                        },
+                        is_move,
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2258,26 +3421,26 @@ impl<'a> Parser<'a> {
             Some(Token::Unsafe) => {
                 let body = self.parse_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Unsafe(UnsafeExpr {
+                    kind: Box::new(ExprKind::Unsafe(UnsafeExpr {
                         body: Box::new(body),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
             }
             Some(Token::Alloc) => {
                 let ty = self.parse_type()?;
-                let size = if self.tokens.peek().map(|t| &t.token) == Some(&Token::LParen) {
+                let size = if self.tokens.peek_is(Token::LParen) {
                     self.tokens.next();
                     Some(Box::new(self.parse_expr()?))
                 } else {
                     None
                 };
                 Ok(Expr {
-                    kind: ExprKind::Alloc(AllocExpr {
+                    kind: Box::new(ExprKind::Alloc(AllocExpr {
                         ty,
                         size,
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2290,11 +3453,11 @@ impl<'a> Parser<'a> {
                 let alignment = Box::new(self.parse_expr()?);
                 self.expect(Token::RParen)?;
                 Ok(Expr {
-                    kind: ExprKind::AllocAligned(AllocAlignedExpr {
+                    kind: Box::new(ExprKind::AllocAligned(AllocAlignedExpr {
                         ty,
                         size,
                         alignment,
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2302,9 +3465,9 @@ impl<'a> Parser<'a> {
             Some(Token::Free) => {
                 let ptr = self.parse_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Free(FreeExpr {
+                    kind: Box::new(ExprKind::Free(FreeExpr {
                         ptr: Box::new(ptr),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2312,9 +3475,9 @@ impl<'a> Parser<'a> {
             Some(Token::Ptr) => {
                 let ty = self.parse_type()?;
                 Ok(Expr {
-                    kind: ExprKind::Ptr(PtrExpr {
+                    kind: Box::new(ExprKind::Ptr(PtrExpr {
                         ty,
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2324,10 +3487,10 @@ impl<'a> Parser<'a> {
                 self.expect(Token::Comma)?;
                 let offset = self.parse_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::PtrAdd(PtrAddExpr {
+                    kind: Box::new(ExprKind::PtrAdd(PtrAddExpr {
                         ptr: Box::new(ptr),
                         offset: Box::new(offset),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2337,10 +3500,10 @@ impl<'a> Parser<'a> {
                 self.expect(Token::Comma)?;
                 let ptr2 = self.parse_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::PtrSub(PtrSubExpr {
+                    kind: Box::new(ExprKind::PtrSub(PtrSubExpr {
                         ptr1: Box::new(ptr1),
                         ptr2: Box::new(ptr2),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2349,10 +3512,10 @@ impl<'a> Parser<'a> {
                 let ptr = self.parse_expr()?;
                 let ty = self.parse_type()?;
                 Ok(Expr {
-                    kind: ExprKind::PtrLoad(PtrLoadExpr {
+                    kind: Box::new(ExprKind::PtrLoad(PtrLoadExpr {
                         ptr: Box::new(ptr),
                         ty,
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2362,10 +3525,10 @@ impl<'a> Parser<'a> {
                 self.expect(Token::Comma)?;
                 let value = self.parse_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::PtrStore(PtrStoreExpr {
+                    kind: Box::new(ExprKind::PtrStore(PtrStoreExpr {
                         ptr: Box::new(ptr),
                         value: Box::new(value),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2375,10 +3538,10 @@ impl<'a> Parser<'a> {
                 self.expect(Token::As)?;
                 let target_ty = self.parse_type()?;
                 Ok(Expr {
-                    kind: ExprKind::PtrCast(PtrCastExpr {
+                    kind: Box::new(ExprKind::PtrCast(PtrCastExpr {
                         ptr: Box::new(ptr),
                         target_ty,
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2388,10 +3551,10 @@ impl<'a> Parser<'a> {
                 self.expect(Token::Comma)?;
                 let field = self.parse_identifier()?;
                 Ok(Expr {
-                    kind: ExprKind::PtrOffsetOf(PtrOffsetOfExpr {
+                    kind: Box::new(ExprKind::PtrOffsetOf(PtrOffsetOfExpr {
                         ptr: Box::new(ptr),
                         field: Arc::from(field),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2401,9 +3564,9 @@ impl<'a> Parser<'a> {
                 let ty = self.parse_type()?;
                 self.expect(Token::RParen)?;
                 Ok(Expr {
-                    kind: ExprKind::PtrSizeOf(PtrSizeOfExpr {
+                    kind: Box::new(ExprKind::PtrSizeOf(PtrSizeOfExpr {
                         ty,
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2413,9 +3576,9 @@ impl<'a> Parser<'a> {
                 let ty = self.parse_type()?;
                 self.expect(Token::RParen)?;
                 Ok(Expr {
-                    kind: ExprKind::AlignOf(AlignOfExpr {
+                    kind: Box::new(ExprKind::AlignOf(AlignOfExpr {
                         ty,
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2425,10 +3588,10 @@ impl<'a> Parser<'a> {
                 self.expect(Token::Colon)?;
                 let proof = self.parse_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Proof(ProofExpr {
+                    kind: Box::new(ExprKind::Proof(ProofExpr {
                         proposition: Box::new(proposition),
                         proof: Box::new(proof),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2441,12 +3604,12 @@ impl<'a> Parser<'a> {
                 self.expect(Token::Eq)?;
                 let proof = self.parse_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Theorem(TheoremExpr {
+                    kind: Box::new(ExprKind::Theorem(TheoremExpr {
                         name: Arc::from(name),
                         params,
                         proposition: Box::new(proposition),
                         proof: Box::new(proof),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2459,12 +3622,12 @@ impl<'a> Parser<'a> {
                 self.expect(Token::Eq)?;
                 let proof = self.parse_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Lemma(LemmaExpr {
+                    kind: Box::new(ExprKind::Lemma(LemmaExpr {
                         name: Arc::from(name),
                         params,
                         proposition: Box::new(proposition),
                         proof: Box::new(proof),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2476,11 +3639,11 @@ impl<'a> Parser<'a> {
                 self.expect(Token::Comma)?;
                 let inductive_step = self.parse_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Induction(InductionExpr {
+                    kind: Box::new(ExprKind::Induction(InductionExpr {
                         variable: Arc::from(variable),
                         base_case: Box::new(base_case),
                         inductive_step: Box::new(inductive_step),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2490,10 +3653,10 @@ impl<'a> Parser<'a> {
                 self.expect(Token::Colon)?;
                 let cases = self.parse_match_cases()?;
                 Ok(Expr {
-                    kind: ExprKind::Case(CaseExpr {
+                    kind: Box::new(ExprKind::Case(CaseExpr {
                         value: Box::new(value),
                         cases,
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2501,9 +3664,9 @@ impl<'a> Parser<'a> {
             Some(Token::Refl) => {
                 let ty = self.parse_type()?;
                 Ok(Expr {
-                    kind: ExprKind::Refl(ReflExpr {
+                    kind: Box::new(ExprKind::Refl(ReflExpr {
                         ty,
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2516,11 +3679,11 @@ impl<'a> Parser<'a> {
                 let expr2 = self.parse_expr()?;
                 self.expect(Token::RParen)?;
                 Ok(Expr {
-                    kind: ExprKind::Cong(CongExpr {
+                    kind: Box::new(ExprKind::Cong(CongExpr {
                         ty,
                         expr1: Box::new(expr1),
                         expr2: Box::new(expr2),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2531,10 +3694,10 @@ impl<'a> Parser<'a> {
                 let expr = self.parse_expr()?;
                 self.expect(Token::RParen)?;
                 Ok(Expr {
-                    kind: ExprKind::Sym(SymExpr {
+                    kind: Box::new(ExprKind::Sym(SymExpr {
                         ty,
                         expr: Box::new(expr),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2549,12 +3712,12 @@ impl<'a> Parser<'a> {
                 let expr3 = self.parse_expr()?;
                 self.expect(Token::RParen)?;
                 Ok(Expr {
-                    kind: ExprKind::Trans(TransExpr {
+                    kind: Box::new(ExprKind::Trans(TransExpr {
                         ty,
                         expr1: Box::new(expr1),
                         expr2: Box::new(expr2),
                         expr3: Box::new(expr3),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2564,10 +3727,10 @@ impl<'a> Parser<'a> {
                 self.expect(Token::Colon)?;
                 let body = self.parse_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Rec(RecExpr {
+                    kind: Box::new(ExprKind::Rec(RecExpr {
                         ty,
                         body: Box::new(body),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2577,10 +3740,10 @@ impl<'a> Parser<'a> {
                 self.expect(Token::Colon)?;
                 let body = self.parse_expr()?;
                 Ok(Expr {
-                    kind: ExprKind::Fix(FixExpr {
+                    kind: Box::new(ExprKind::Fix(FixExpr {
                         ty,
                         body: Box::new(body),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2590,45 +3753,45 @@ impl<'a> Parser<'a> {
                 let params = self.parse_function_params()?;
                 let methods = self.parse_class_methods()?;
                 Ok(Expr {
-                    kind: ExprKind::Class(ClassExpr {
+                    kind: Box::new(ExprKind::Class(ClassExpr {
                         name: Arc::from(name),
                         params,
                         methods,
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
             }
             Some(Token::Instance) => {
+                let generics = self.parse_generic_params()?;
                 let class_name = self.parse_identifier()?;
                 let ty = self.parse_type()?;
                 let methods = self.parse_class_methods()?;
                 Ok(Expr {
-                    kind: ExprKind::Instance(InstanceExpr {
+                    kind: Box::new(ExprKind::Instance(InstanceExpr {
+                        generics,
                         class_name: Arc::from(class_name),
                         ty,
                         methods,
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
             }
             Some(Token::Where) => {
-                let expr = self.parse_expr()?;
+                let expr = self.parse_condition()?;
                 self.expect(Token::LBrace)?;
                 let mut constraints = Vec::new();
-                while self.tokens.peek().map(|t| &t.token) != Some(&Token::RBrace) {
+                while !self.tokens.peek_is(Token::RBrace) {
                     constraints.push(self.parse_expr()?);
-                    if self.tokens.peek().map(|t| &t.token) == Some(&Token::Comma) {
-                        self.tokens.next();
-                    }
+                    self.tokens.eat(Token::Comma);
                 }
                 self.expect(Token::RBrace)?;
                 Ok(Expr {
-                    kind: ExprKind::Where(WhereExpr {
+                    kind: Box::new(ExprKind::Where(WhereExpr {
                         expr: Box::new(expr),
                         constraints,
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2641,11 +3804,11 @@ impl<'a> Parser<'a> {
                 let right = self.parse_expr()?;
                 self.expect(Token::RParen)?;
                 Ok(Expr {
-                    kind: ExprKind::EqProp(EqPropExpr {
+                    kind: Box::new(ExprKind::EqProp(EqPropExpr {
                         ty,
                         left: Box::new(left),
                         right: Box::new(right),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2656,10 +3819,10 @@ impl<'a> Parser<'a> {
                 let expr = self.parse_expr()?;
                 self.expect(Token::RParen)?;
                 Ok(Expr {
-                    kind: ExprKind::ReflProp(ReflPropExpr {
+                    kind: Box::new(ExprKind::ReflProp(ReflPropExpr {
                         ty,
                         expr: Box::new(expr),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2672,11 +3835,11 @@ impl<'a> Parser<'a> {
                 let expr2 = self.parse_expr()?;
                 self.expect(Token::RParen)?;
                 Ok(Expr {
-                    kind: ExprKind::JMeq(JMeqExpr {
+                    kind: Box::new(ExprKind::JMeq(JMeqExpr {
                         ty,
                         expr1: Box::new(expr1),
                         expr2: Box::new(expr2),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2689,34 +3852,32 @@ impl<'a> Parser<'a> {
                 let rule = self.parse_expr()?;
                 self.expect(Token::RParen)?;
                 Ok(Expr {
-                    kind: ExprKind::Rewrite(RewriteExpr {
+                    kind: Box::new(ExprKind::Rewrite(RewriteExpr {
                         ty,
                         expr: Box::new(expr),
                         rule: Box::new(rule),
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
             }
             Some(Token::With) => {
-                let expr = self.parse_expr()?;
+                let expr = self.parse_condition()?;
                 self.expect(Token::LBrace)?;
                 let mut bindings = Vec::new();
-                while self.tokens.peek().map(|t| &t.token) != Some(&Token::RBrace) {
+                while !self.tokens.peek_is(Token::RBrace) {
                     let name = self.parse_identifier()?;
                     self.expect(Token::Eq)?;
                     let value = self.parse_expr()?;
                     bindings.push((Arc::from(name), value));
-                    if self.tokens.peek().map(|t| &t.token) == Some(&Token::Comma) {
-                        self.tokens.next();
-                    }
+                    self.tokens.eat(Token::Comma);
                 }
                 self.expect(Token::RBrace)?;
                 Ok(Expr {
-                    kind: ExprKind::With(WithExpr {
+                    kind: Box::new(ExprKind::With(WithExpr {
                         expr: Box::new(expr),
                         bindings,
-                    }),
+                    })),
                     span: start_span,
                     ty: None,
                 })
@@ -2731,17 +3892,57 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parses the body of a `future`/`stream`, either a `{ ... }` block or a single
+    /// expression, in which `yield` is permitted.
+    fn parse_generator_body(&mut self) -> Result<Expr, ChimError> {
+        let start_span = self.current_span()?;
+        self.generator_depth += 1;
+        let body = if self.tokens.peek_is(Token::LBrace) {
+            self.parse_block().map(|stmts| Expr {
+                kind: Box::new(ExprKind::Block(BlockExpr {
+                    label: None,
+                    stmts,
+                    ty: None,
+                })),
+                span: start_span.merge(&self.current_span().unwrap_or(start_span)),
+                ty: None,
+            })
+        } else {
+            self.parse_expr()
+        };
+        self.generator_depth -= 1;
+        body
+    }
+
     fn parse_pattern(&mut self) -> Result<Pattern, ChimError> {
         let start_span = self.current_span()?;
 
+        let by_ref = self.tokens.eat(Token::Ref);
+        let is_mut = self.tokens.eat(Token::Mut);
+        let mode = BindingMode { is_mut, by_ref };
+
+        if self.at_identifier() {
+            let name = self.parse_identifier()?;
+            return Ok(Pattern {
+                kind: PatternKind::Identifier(Arc::from(name), mode),
+                span: start_span,
+            });
+        }
+
+        if by_ref {
+            return Err(ChimError::new(
+                ErrorKind::Parser,
+                "`ref` must be followed by a binding name".to_string(),
+            ).with_span(start_span));
+        }
+        if is_mut {
+            // `let mut (a, b) = ...` makes every binding in the pattern mutable.
+            let mut pattern = self.parse_pattern()?;
+            Self::mark_bindings_mut(&mut pattern);
+            return Ok(pattern);
+        }
+
         match self.tokens.peek().map(|t| &t.token) {
-            Some(Token::Identifier) => {
-                let name = self.parse_identifier()?;
-                Ok(Pattern {
-                    kind: PatternKind::Identifier(Arc::from(name)),
-                    span: start_span,
-                })
-            }
             Some(Token::Underscore) => {
                 self.tokens.next();
                 Ok(Pattern {
@@ -2752,11 +3953,9 @@ impl<'a> Parser<'a> {
             Some(Token::LParen) => {
                 self.tokens.next();
                 let mut patterns = Vec::new();
-                while self.tokens.peek().map(|t| &t.token) != Some(&Token::RParen) {
+                while !self.tokens.peek_is(Token::RParen) {
                     patterns.push(self.parse_pattern()?);
-                    if self.tokens.peek().map(|t| &t.token) == Some(&Token::Comma) {
-                        self.tokens.next();
-                    }
+                    self.tokens.eat(Token::Comma);
                 }
                 self.expect(Token::RParen)?;
                 Ok(Pattern {
@@ -2766,16 +3965,52 @@ impl<'a> Parser<'a> {
             }
             Some(Token::LBracket) => {
                 self.tokens.next();
-                let mut patterns = Vec::new();
-                while self.tokens.peek().map(|t| &t.token) != Some(&Token::RBracket) {
-                    patterns.push(self.parse_pattern()?);
-                    if self.tokens.peek().map(|t| &t.token) == Some(&Token::Comma) {
+                let mut before = Vec::new();
+                let mut rest = None;
+                let mut after = Vec::new();
+                while !self.tokens.peek_is(Token::RBracket) {
+                    if self.tokens.peek_is(Token::DotDot) {
+                        let rest_span = self.current_span()?;
+                        if rest.is_some() {
+                            return Err(ChimError::new(
+                                ErrorKind::Parser,
+                                "`..` can only be used once per slice pattern".to_string(),
+                            ).with_span(rest_span));
+                        }
+                        self.tokens.next();
+                        let binding = if self.at_identifier() {
+                            Some(Arc::from(self.parse_identifier()?))
+                        } else {
+                            None
+                        };
+                        rest = Some(SliceRest { binding, span: rest_span });
+                    } else if rest.is_some() {
+                        after.push(self.parse_pattern()?);
+                    } else {
+                        before.push(self.parse_pattern()?);
+                    }
+                    if self.tokens.peek_is(Token::Comma) {
                         self.tokens.next();
+                    } else {
+                        break;
                     }
                 }
                 self.expect(Token::RBracket)?;
                 Ok(Pattern {
-                    kind: PatternKind::Slice(patterns),
+                    kind: PatternKind::Slice(before, rest, after),
+                    span: start_span,
+                })
+            }
+            Some(
+                Token::Int | Token::Float | Token::String | Token::RawString | Token::Char | Token::Byte | Token::True
+                | Token::False,
+            ) => {
+                let expr = self.parse_primary_expr()?;
+                let ExprKind::Literal(literal) = *expr.kind else {
+                    unreachable!("a literal token always parses to a literal expression");
+                };
+                Ok(Pattern {
+                    kind: PatternKind::Literal(literal),
                     span: start_span,
                 })
             }
@@ -2786,6 +4021,22 @@ impl<'a> Parser<'a> {
         }
     }
 
+    fn mark_bindings_mut(pattern: &mut Pattern) {
+        match &mut pattern.kind {
+            PatternKind::Identifier(_, mode) => mode.is_mut = true,
+            PatternKind::Tuple(patterns) | PatternKind::Or(patterns) => {
+                patterns.iter_mut().for_each(Self::mark_bindings_mut);
+            }
+            PatternKind::Struct(_, fields) | PatternKind::Enum(_, _, fields) => {
+                fields.iter_mut().filter_map(|field| field.pattern.as_mut()).for_each(Self::mark_bindings_mut);
+            }
+            PatternKind::Slice(before, _, after) => {
+                before.iter_mut().chain(after.iter_mut()).for_each(Self::mark_bindings_mut);
+            }
+            PatternKind::Wildcard | PatternKind::Literal(_) | PatternKind::Range(..) | PatternKind::Error => {}
+        }
+    }
+
     fn parse_type(&mut self) -> Result<Type, ChimError> {
         let start_span = self.current_span()?;
 
@@ -2793,11 +4044,9 @@ impl<'a> Parser<'a> {
             Some(Token::LParen) => {
                 self.tokens.next();
                 let mut types = Vec::new();
-                while self.tokens.peek().map(|t| &t.token) != Some(&Token::RParen) {
+                while !self.tokens.peek_is(Token::RParen) {
                     types.push(self.parse_type()?);
-                    if self.tokens.peek().map(|t| &t.token) == Some(&Token::Comma) {
-                        self.tokens.next();
-                    }
+                    self.tokens.eat(Token::Comma);
                 }
                 self.expect(Token::RParen)?;
                 Ok(Type {
@@ -2808,17 +4057,19 @@ impl<'a> Parser<'a> {
             Some(Token::LBracket) => {
                 self.tokens.next();
                 let inner = self.parse_type()?;
-                if self.tokens.peek().map(|t| &t.token) == Some(&Token::Semicolon) {
+                if self.tokens.peek_is(Token::Semicolon) {
                     self.tokens.next();
-                    if let Some(Token::Int) = self.tokens.peek().map(|t| &t.token) {
-                        let size = self.intern_string().parse::<usize>().unwrap_or(0);
+                    let len = if self.tokens.peek_is(Token::Underscore) {
                         self.tokens.next();
-                        self.expect(Token::RBracket)?;
-                        return Ok(Type {
-                            kind: Box::new(TypeKind::Array(Box::new(inner), size)),
-                            span: start_span,
-                        });
-                    }
+                        ArrayLen::Inferred
+                    } else {
+                        ArrayLen::Const(self.parse_expr()?)
+                    };
+                    self.expect(Token::RBracket)?;
+                    return Ok(Type {
+                        kind: Box::new(TypeKind::Array(Box::new(inner), len)),
+                        span: start_span,
+                    });
                 }
                 self.expect(Token::RBracket)?;
                 Ok(Type {
@@ -2836,16 +4087,12 @@ impl<'a> Parser<'a> {
             }
             Some(Token::Ampersand) => {
                 self.tokens.next();
-                let lifetime = if let Some(Token::Identifier) = self.tokens.peek().map(|t| &t.token) {
-                    let name = self.parse_identifier()?;
-                    Some(Lifetime {
-                        name: Arc::from(name),
-                        span: start_span,
-                    })
+                let lifetime = if self.tokens.peek_is(Token::Lifetime) {
+                    Some(self.parse_lifetime()?)
                 } else {
                     None
                 };
-                let mutability = if self.tokens.peek().map(|t| &t.token) == Some(&Token::Mut) {
+                let mutability = if self.tokens.peek_is(Token::Mut) {
                     self.tokens.next();
                     Mutability::Mutable
                 } else {
@@ -2857,36 +4104,32 @@ impl<'a> Parser<'a> {
                     span: start_span,
                 })
             }
-            Some(Token::Func) => {
+            Some(Token::Func) | Some(Token::Async) | Some(Token::Unsafe) | Some(Token::Extern) => {
+                self.parse_function_type(start_span)
+            }
+            Some(Token::SelfRef) => {
                 self.tokens.next();
-                self.expect(Token::LParen)?;
-                let mut params = Vec::new();
-                while self.tokens.peek().map(|t| &t.token) != Some(&Token::RParen) {
-                    params.push(self.parse_type()?);
-                    if self.tokens.peek().map(|t| &t.token) == Some(&Token::Comma) {
-                        self.tokens.next();
-                    }
+                if !self.tokens.eat(Token::DoubleColon) {
+                    return Ok(Type {
+                        kind: Box::new(TypeKind::SelfType),
+                        span: start_span,
+                    });
                 }
-                self.expect(Token::RParen)?;
-                let return_type = if self.tokens.peek().map(|t| &t.token) == Some(&Token::ThinArrow) {
-                    self.tokens.next();
-                    Some(self.parse_type()?)
-                } else {
-                    None
-                };
+                // `Self::Item` names an associated type, so it is a path
+                // starting at `Self` rather than `Self` itself.
+                let mut path = self.parse_type_path()?;
+                path.segments.insert(0, PathSegment {
+                    ident: Arc::from("Self"),
+                    args: Vec::new(),
+                    span: start_span,
+                });
+                path.span = start_span;
                 Ok(Type {
-                    kind: Box::new(TypeKind::Function(FunctionType {
-                        params,
-                        return_type: Box::new(return_type.unwrap_or(Type {
-                            kind: Box::new(TypeKind::Tuple(Vec::new())),
-                            span: start_span,
-                        })),
-                        is_async: false,
-                    })),
+                    kind: Box::new(TypeKind::Path(path)),
                     span: start_span,
                 })
             }
-            Some(Token::PathSep) => {
+            Some(Token::DoubleColon) => {
                 self.tokens.next();
                 let name = self.parse_identifier()?;
                 Ok(Type {
@@ -2902,7 +4145,7 @@ impl<'a> Parser<'a> {
                 })
             }
             _ => {
-                let path = self.parse_path()?;
+                let path = self.parse_type_path()?;
                 Ok(Type {
                     kind: Box::new(TypeKind::Path(path)),
                     span: start_span,
@@ -2911,11 +4154,193 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parses `async unsafe extern "ABI" fn(A, B) -> R`, where each modifier
+    /// is optional and `extern` alone means `extern "C"`.
+    fn parse_function_type(&mut self, start_span: Span) -> Result<Type, ChimError> {
+        let is_async = self.tokens.eat(Token::Async);
+        let is_unsafe = self.tokens.eat(Token::Unsafe);
+        let abi = if self.tokens.eat(Token::Extern) {
+            if self.tokens.peek_is(Token::String) {
+                self.tokens.next();
+                Some(self.intern_string())
+            } else {
+                Some("C".to_string())
+            }
+        } else {
+            None
+        };
+        self.expect(Token::Func)?;
+        self.expect(Token::LParen)?;
+        let mut params = Vec::new();
+        while !self.tokens.peek_is(Token::RParen) {
+            params.push(self.parse_type()?);
+            self.tokens.eat(Token::Comma);
+        }
+        self.expect(Token::RParen)?;
+        let return_type = if self.tokens.peek_is(Token::ThinArrow) {
+            self.tokens.next();
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+        Ok(Type {
+            kind: Box::new(TypeKind::Function(FunctionType {
+                params,
+                return_type: Box::new(return_type.unwrap_or(Type {
+                    kind: Box::new(TypeKind::Tuple(Vec::new())),
+                    span: start_span,
+                })),
+                is_async,
+                is_unsafe,
+                abi,
+            })),
+            span: start_span,
+        })
+    }
+
+    /// Parses a path in type position, where each segment may carry `<...>` arguments.
+    fn parse_type_path(&mut self) -> Result<Path, ChimError> {
+        let mut path = self.parse_path()?;
+        if self.at_langle() {
+            if let Some(last) = path.segments.last_mut() {
+                last.args = self.parse_generic_args()?;
+            }
+        }
+        Ok(path)
+    }
+
+    fn parse_generic_args(&mut self) -> Result<Vec<GenericArg>, ChimError> {
+        self.tokens.next();
+        let mut args = Vec::new();
+
+        while !self.at_rangle() {
+            let span = self.current_span()?;
+            let kind = if self.tokens.peek_is(Token::Lifetime) {
+                GenericArgKind::Lifetime(self.parse_lifetime()?)
+            } else {
+                GenericArgKind::Type(self.parse_type()?)
+            };
+            args.push(GenericArg { kind, span });
+            if self.tokens.peek_is(Token::Comma) {
+                self.tokens.next();
+            } else {
+                break;
+            }
+        }
+
+        self.expect_rangle()?;
+        Ok(args)
+    }
+
+    /// Parses `<T, U: Bound + Other>` on a type, trait, or impl header.
+    fn parse_generic_params(&mut self) -> Result<Vec<GenericParam>, ChimError> {
+        if !self.at_langle() {
+            return Ok(Vec::new());
+        }
+        self.tokens.next();
+        let mut params = Vec::new();
+
+        while !self.at_rangle() {
+            params.push(self.parse_generic_param()?);
+            if self.tokens.peek_is(Token::Comma) {
+                self.tokens.next();
+            } else {
+                break;
+            }
+        }
+
+        self.expect_rangle()?;
+        self.check_duplicate_names("generic parameter", params.iter().map(|p| (&p.name, p.span)));
+        Ok(params)
+    }
+
+    /// Parses `Bound + Other + 'a + ...`. A lifetime joins the bound before
+    /// it, so `Trait + 'a` is one bound; a list of only lifetimes is one
+    /// bound without a trait.
+    fn parse_bounds(&mut self) -> Result<Vec<WhereBound>, ChimError> {
+        let mut bounds: Vec<WhereBound> = Vec::new();
+        loop {
+            let bound = self.parse_bound()?;
+            match bounds.last_mut() {
+                Some(last) if bound.trait_ref.is_none() => {
+                    last.span = last.span.merge(&bound.span);
+                    last.lifetime_bounds.extend(bound.lifetime_bounds);
+                }
+                _ => bounds.push(bound),
+            }
+            if !self.tokens.eat(Token::Plus) {
+                break;
+            }
+        }
+        Ok(bounds)
+    }
+
+    /// Parses one bound: a trait or a lifetime. A `?` prefix relaxes an
+    /// implicit bound rather than adding one, and `Sized` is the only bound
+    /// that is implicit.
+    fn parse_bound(&mut self) -> Result<WhereBound, ChimError> {
+        let start_span = self.current_span()?;
+        let relaxed = self.tokens.eat(Token::Question);
+        if self.tokens.peek_is(Token::Lifetime) {
+            let lifetime = self.parse_lifetime()?;
+            let span = start_span.merge(&lifetime.span);
+            if relaxed {
+                self.errors.push(ChimError::new(
+                    ErrorKind::Parser,
+                    format!("`?'{}` relaxes nothing; only `?Sized` is allowed", lifetime.name),
+                ).with_span(span));
+            }
+            return Ok(WhereBound { trait_ref: None, lifetime_bounds: vec![lifetime], relaxed: false, span });
+        }
+        let trait_ref = self.parse_type()?;
+        let span = start_span.merge(&trait_ref.span);
+        let names_sized = match &*trait_ref.kind {
+            TypeKind::Path(path) => path.segments.len() == 1 && &*path.segments[0].ident == "Sized",
+            _ => false,
+        };
+        if relaxed && !names_sized {
+            self.errors.push(ChimError::new(
+                ErrorKind::Parser,
+                format!("`?{}` relaxes nothing; only `?Sized` is allowed", trait_ref),
+            ).with_span(span));
+        }
+        Ok(WhereBound {
+            trait_ref: Some(trait_ref),
+            lifetime_bounds: Vec::new(),
+            relaxed,
+            span,
+        })
+    }
+
+    fn at_langle(&self) -> bool {
+        matches!(self.tokens.peek().map(|t| &t.token), Some(Token::Lt) | Some(Token::LAngle))
+    }
+
+    fn at_rangle(&self) -> bool {
+        matches!(
+            self.tokens.peek().map(|t| &t.token),
+            Some(Token::Gt) | Some(Token::RAngle) | Some(Token::RShift)
+        )
+    }
+
+    fn expect_rangle(&mut self) -> Result<(), ChimError> {
+        match self.tokens.peek().map(|t| &t.token) {
+            Some(Token::Gt) | Some(Token::RAngle) => {
+                self.tokens.next();
+                Ok(())
+            }
+            Some(Token::RShift) => {
+                self.tokens.split_current(Token::Gt);
+                Ok(())
+            }
+            _ => self.expect(Token::Gt),
+        }
+    }
+
     fn parse_class_methods(&mut self) -> Result<Vec<Function>, ChimError> {
         let mut methods = Vec::new();
-        while self.tokens.peek().map(|t| &t.token) != Some(&Token::RBrace) {
-            let method = self.parse_function()?;
-            methods.push(method);
+        while !self.tokens.peek_is(Token::RBrace) {
+            methods.push(self.parse_impl_function()?);
         }
         self.expect(Token::RBrace)?;
         Ok(methods)
@@ -2923,7 +4348,7 @@ impl<'a> Parser<'a> {
 
     fn parse_match_cases(&mut self) -> Result<Vec<MatchCase>, ChimError> {
         let mut cases = Vec::new();
-        while self.tokens.peek().map(|t| &t.token) != Some(&Token::RBrace) {
+        while !self.tokens.peek_is(Token::RBrace) {
             let pattern = self.parse_pattern()?;
             self.expect(Token::Arrow)?;
             let body = self.parse_expr()?;
@@ -2931,9 +4356,7 @@ impl<'a> Parser<'a> {
                 pattern,
                 body,
             });
-            if self.tokens.peek().map(|t| &t.token) == Some(&Token::Comma) {
-                self.tokens.next();
-            }
+            self.tokens.eat(Token::Comma);
         }
         self.expect(Token::RBrace)?;
         Ok(cases)
@@ -2952,7 +4375,7 @@ impl<'a> Parser<'a> {
                 span: segment_start,
             });
 
-            if self.tokens.peek().map(|t| &t.token) == Some(&Token::PathSep) {
+            if self.tokens.peek_is(Token::DoubleColon) {
                 self.tokens.next();
             } else {
                 break;
@@ -2965,74 +4388,141 @@ impl<'a> Parser<'a> {
         })
     }
 
-    fn parse_optional_type_path(&mut self) -> Result<Option<Type>, ChimError> {
-        if self.tokens.peek().map(|t| &t.token) == Some(&Token::LParen) {
-            return Ok(None);
-        }
-        self.parse_type().map(Some)
-    }
-
-    fn parse_generics(&mut self) -> Result<Vec<LifetimeParam>, ChimError> {
-        if self.tokens.peek().map(|t| &t.token) != Some(&Token::LAngle) {
-            return Ok(Vec::new());
+    /// Parses a function's `<'a, 'b: 'a, T: ?Sized>` header into its lifetime
+    /// and type parameters.
+    fn parse_generics(&mut self) -> Result<(Vec<LifetimeParam>, Vec<GenericParam>), ChimError> {
+        if !self.at_langle() {
+            return Ok((Vec::new(), Vec::new()));
         }
         self.tokens.next();
         let mut lifetimes = Vec::new();
+        let mut params = Vec::new();
 
-        while self.tokens.peek().map(|t| &t.token) != Some(&Token::RAngle) {
-            if let Some(Token::Identifier) = self.tokens.peek().map(|t| &t.token) {
-                let name = self.parse_identifier()?;
-                lifetimes.push(LifetimeParam {
-                    name: Arc::from(name),
-                    bounds: Vec::new(),
-                    span: Span::new(self.file_id, 0, 0, 0, 0),
-                });
+        while !self.at_rangle() {
+            let span = self.current_span()?;
+            if self.tokens.peek_is(Token::Lifetime) {
+                let name = self.parse_lifetime()?.name;
+                let mut bounds = Vec::new();
+                if self.tokens.eat(Token::Colon) {
+                    bounds.push(self.parse_lifetime()?);
+                    while self.tokens.eat(Token::Plus) {
+                        bounds.push(self.parse_lifetime()?);
+                    }
+                }
+                lifetimes.push(LifetimeParam { name, bounds, span });
+            } else {
+                params.push(self.parse_generic_param()?);
             }
-            if self.tokens.peek().map(|t| &t.token) == Some(&Token::Comma) {
-                self.tokens.next();
+            if !self.tokens.eat(Token::Comma) {
+                break;
             }
         }
 
-        self.expect(Token::RAngle)?;
-        Ok(lifetimes)
+        self.expect_rangle()?;
+        self.check_duplicate_names("generic parameter", params.iter().map(|p| (&p.name, p.span)));
+        Ok((lifetimes, params))
+    }
+
+    /// Parses a type parameter `T: Bound + Other` or a const parameter
+    /// `const N: usize`.
+    fn parse_generic_param(&mut self) -> Result<GenericParam, ChimError> {
+        let span = self.current_span()?;
+        if self.tokens.eat(Token::Const) {
+            let name = Arc::from(self.parse_identifier()?);
+            self.expect(Token::Colon)?;
+            let ty = self.parse_type()?;
+            return Ok(GenericParam { name, bounds: Vec::new(), const_ty: Some(ty), span });
+        }
+        let name = Arc::from(self.parse_identifier()?);
+        let bounds = if self.tokens.eat(Token::Colon) {
+            self.parse_bounds()?
+        } else {
+            Vec::new()
+        };
+        Ok(GenericParam { name, bounds, const_ty: None, span })
+    }
+
+    /// Parses a `'a` token; the name is stored without the apostrophe.
+    fn parse_lifetime(&mut self) -> Result<Lifetime, ChimError> {
+        let span = self.current_span()?;
+        self.expect(Token::Lifetime)?;
+        let name = self.intern_identifier();
+        Ok(Lifetime {
+            name: Arc::from(name.trim_start_matches('\'')),
+            span,
+        })
     }
 
     fn parse_where_clauses(&mut self) -> Result<Vec<WhereClause>, ChimError> {
-        if self.tokens.peek().map(|t| &t.token) != Some(&Token::Where) {
+        if !self.tokens.peek_is(Token::Where) {
             return Ok(Vec::new());
         }
+        let start_span = self.current_span()?;
         self.tokens.next();
         let mut predicates = Vec::new();
 
         while !matches!(self.tokens.peek().map(|t| &t.token), Some(&Token::LBrace) | None) {
-            let bounded_type = self.parse_type()?;
-            predicates.push(WherePredicate {
-                bounded_type,
-                bounds: Vec::new(),
-                span: Span::new(self.file_id, 0, 0, 0, 0),
-            });
-            if self.tokens.peek().map(|t| &t.token) == Some(&Token::Comma) {
+            let predicate_span = self.current_span()?;
+            if self.tokens.peek_is(Token::Lifetime) {
+                predicates.push(WherePredicate::Lifetime(self.parse_lifetime_predicate(predicate_span)?));
+            } else {
+                let bounded_type = self.parse_type()?;
+                let bounds = if self.tokens.peek_is(Token::Colon) {
+                    self.tokens.next();
+                    self.parse_bounds()?
+                } else {
+                    Vec::new()
+                };
+                predicates.push(WherePredicate::Type(TypePredicate {
+                    bounded_type,
+                    bounds,
+                    span: predicate_span,
+                }));
+            }
+            if self.tokens.peek_is(Token::Comma) {
                 self.tokens.next();
+            } else {
+                break;
             }
         }
 
         Ok(vec![WhereClause {
             predicates,
-            span: Span::new(self.file_id, 0, 0, 0, 0),
+            span: start_span.merge(&self.current_span().unwrap_or(start_span)),
         }])
     }
 
-    fn parse_trait_bounds(&mut self) -> Result<Vec<Type>, ChimError> {
-        let mut bounds = Vec::new();
+    /// Parses `'a: 'b + 'c` in a `where` clause.
+    fn parse_lifetime_predicate(&mut self, start_span: Span) -> Result<LifetimePredicate, ChimError> {
+        let lifetime = self.parse_lifetime()?;
+        self.expect(Token::Colon)?;
+        let mut bounds = vec![self.parse_lifetime()?];
+        while self.tokens.eat(Token::Plus) {
+            bounds.push(self.parse_lifetime()?);
+        }
+        Ok(LifetimePredicate {
+            lifetime,
+            bounds,
+            span: start_span.merge(&self.current_span().unwrap_or(start_span)),
+        })
+    }
 
-        while self.tokens.peek().map(|t| &t.token) != Some(&Token::LBrace)
-            && self.tokens.peek().map(|t| &t.token) != Some(&Token::Where)
-            && !matches!(self.tokens.peek().map(|t| &t.token), Some(&Token::LParen) | None)
-        {
-            bounds.push(self.parse_type()?);
+    /// Parses a `where` clause in a position the grammar doesn't allow,
+    /// reporting it but keeping its predicates so parsing can go on.
+    fn parse_misplaced_where(&mut self, expected: &str) -> Result<Vec<WhereClause>, ChimError> {
+        let clauses = self.parse_where_clauses()?;
+        if let Some(clause) = clauses.first() {
+            let error = self.misplaced_where(clause.span, expected);
+            self.errors.push(error);
         }
+        Ok(clauses)
+    }
 
-        Ok(bounds)
+    fn misplaced_where(&self, span: Span, expected: &str) -> ChimError {
+        ChimError::new(
+            ErrorKind::UnexpectedToken,
+            format!("`where` clause must come {}", expected),
+        ).with_span(span)
     }
 
     fn parse_visibility(&mut self) -> Result<bool, ChimError> {
@@ -3049,12 +4539,54 @@ impl<'a> Parser<'a> {
         }
     }
 
+    fn is_contextual_keyword(&self, token: &Token) -> bool {
+        self.contextual_keywords.contains(token)
+    }
+
+    fn at_identifier(&self) -> bool {
+        match self.tokens.peek().map(|t| &t.token) {
+            Some(Token::Identifier) => true,
+            Some(token) => self.is_contextual_keyword(token),
+            None => false,
+        }
+    }
+
+    /// A contextual keyword in expression position is a plain name only when
+    /// the next token cannot start the builtin's operand. Anything that can
+    /// start an expression, such as `(`, `[`, `-` or `*`, keeps the builtin,
+    /// so `map (xs), f` is a map and `map(xs)` is not a call.
+    fn contextual_keyword_is_identifier(&self) -> bool {
+        match self.tokens.peek().map(|t| &t.token) {
+            Some(token) if self.is_contextual_keyword(token) => {}
+            _ => return false,
+        }
+        matches!(
+            self.tokens.peek_n(1).map(|t| &t.token),
+            None | Some(Token::Eof) | Some(Token::Semicolon) | Some(Token::Comma) |
+            Some(Token::RParen) | Some(Token::RBracket) | Some(Token::RBrace) |
+            Some(Token::Dot) | Some(Token::Colon) |
+            Some(Token::Eq) | Some(Token::PlusEq) | Some(Token::MinusEq) | Some(Token::StarEq) |
+            Some(Token::SlashEq) | Some(Token::PercentEq) | Some(Token::AndEq) | Some(Token::PipeEq) |
+            Some(Token::CaretEq) | Some(Token::LShiftEq) | Some(Token::RShiftEq) |
+            Some(Token::Plus) | Some(Token::Slash) |
+            Some(Token::Percent) | Some(Token::EqEq) | Some(Token::Neq) | Some(Token::Lt) |
+            Some(Token::Lte) | Some(Token::Gt) | Some(Token::Gte) | Some(Token::AndAnd) |
+            Some(Token::OrOr) | Some(Token::Caret) | Some(Token::LShift) | Some(Token::RShift) |
+            Some(Token::Question) | Some(Token::As)
+        )
+    }
+
     fn parse_identifier(&mut self) -> Result<String, ChimError> {
+        let is_contextual = self.tokens.peek()
+            .map(|t| self.is_contextual_keyword(&t.token))
+            .unwrap_or(false);
         match self.tokens.next().map(|t| &t.token) {
             Some(Token::Identifier) => Ok(self.intern_identifier()),
-            _ => {
+            Some(_) if is_contextual => Ok(self.intern_identifier()),
+            found => {
+                let kind = found.map(Self::unexpected_kind).unwrap_or(ErrorKind::UnexpectedEof);
                 self.errors.push(ChimError::new(
-                    ErrorKind::Parser,
+                    kind,
                     "expected identifier".to_string(),
                 ).with_span(self.current_span()?));
                 let err = self.errors.last().cloned().unwrap_or_else(|| {
@@ -3065,15 +4597,18 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn parse_int_literal(&mut self) -> Result<i128, ChimError> {
-        if let Some(Token::Int) = self.tokens.next().map(|t| &t.token) {
-            let text = self.intern_string();
-            Ok(self.parse_base_literal(&text))
-        } else {
-            Err(ChimError::new(
-                ErrorKind::Parser,
-                "expected integer literal".to_string(),
-            ).with_span(self.current_span()?))
+    /// Converts float literal text, rejecting values `f64` cannot represent
+    /// instead of letting them silently become infinity. Malformed digits such
+    /// as `1.2.3` have already been reported by the lexer.
+    fn parse_float_literal(&self, text: &str, span: Span) -> Result<f64, ChimError> {
+        let digits: String = text.chars().filter(|&c| c != '_').collect();
+        match digits.parse::<f64>() {
+            Ok(value) if value.is_infinite() => Err(ChimError::new(
+                ErrorKind::InvalidLiteral,
+                format!("float literal `{}` is out of range for f64", text),
+            ).with_span(span)),
+            Ok(value) => Ok(value),
+            Err(_) => Ok(0.0),
         }
     }
 
@@ -3095,7 +4630,7 @@ impl<'a> Parser<'a> {
         } else if text.starts_with("0s") || text.starts_with("0S") {
             self.parse_sexagesimal(&text[2..].replace('_', ""))
         } else {
-            i128::from_str_radix(&text.replace('_', ""), 10).unwrap_or(0)
+            text.replace('_', "").parse().unwrap_or(0)
         }
     }
 
@@ -3149,117 +4684,145 @@ impl<'a> Parser<'a> {
     }
 
     fn intern_identifier(&mut self) -> String {
-        if let Some(token) = self.tokens.peek() {
-            if let Some(span) = self.tokens.position().checked_sub(1) {
-                if span < self.tokens.len() {
-                    let source = self.get_source_text(span);
-                    return source.to_string();
-                }
-            }
-        }
-        String::new()
+        self.previous_text().to_string()
     }
 
+    /// The value of the literal just consumed: its text without the quotes
+    /// and prefix, with escapes resolved outside raw strings.
     fn intern_string(&mut self) -> String {
-        if let Some(token) = self.tokens.peek() {
-            if let Some(span) = self.tokens.position().checked_sub(1) {
-                if span < self.tokens.len() {
-                    let source = self.get_source_text(span);
-                    return source.to_string();
-                }
-            }
+        let text = self.previous_text();
+        match self.tokens.position().checked_sub(1).and_then(|index| self.tokens.slice_between(index, index + 1).first()) {
+            Some(SpannedToken { token: Token::String | Token::Char, .. }) => unescape(strip_quotes(text)),
+            Some(SpannedToken { token: Token::ByteString | Token::Byte, .. }) => unescape(strip_quotes(&text[1..])),
+            Some(SpannedToken { token: Token::RawString, .. }) => {
+                let fence = text[1..].len() - text[1..].trim_start_matches('#').len();
+                text.get(fence + 2..text.len() - fence - 1).unwrap_or_default().to_string()
+            }
+            _ => text.to_string(),
         }
-        String::new()
     }
 
-    fn get_source_text(&self, index: usize) -> String {
-        String::new()
+    /// The source text of the token just consumed.
+    fn previous_text(&self) -> &'a str {
+        match self.tokens.position().checked_sub(1) {
+            Some(index) => self.get_source_text(index),
+            None => "",
+        }
+    }
+
+    /// The source text of the token at `index`, sliced from `source` by its
+    /// span.
+    fn get_source_text(&self, index: usize) -> &'a str {
+        let source = self.source;
+        self.tokens.slice_between(index, index + 1).first().map_or("", |token| token.text(source))
     }
 
     fn current_span(&self) -> Result<Span, ChimError> {
         if let Some(token) = self.tokens.peek() {
             Ok(token.span)
         } else {
-            Ok(Span::new(self.file_id, 0, 0, 0, 0))
+            Ok(self.eof_span())
+        }
+    }
+
+    /// Zero-width span just past the last token, where running out of input
+    /// is reported; a placeholder if there are no tokens at all.
+    fn eof_span(&self) -> Span {
+        match self.tokens.slice_between(0, usize::MAX).last() {
+            Some(token) => Self::span_after(token.span),
+            None => Span::dummy(),
         }
     }
 
+    /// The empty span just past the end of `span`.
+    fn span_after(span: Span) -> Span {
+        let width = span.end - span.start;
+        Span::new(span.file_id, span.end, span.end, span.line, span.column + width)
+    }
+
     fn expect(&mut self, expected: Token) -> Result<(), ChimError> {
-        if let Some(token) = self.tokens.peek() {
+        self.expect_inner(expected, None)
+    }
+
+    /// Like `expect`, but on failure also points at `context` (e.g. the opening
+    /// delimiter of the list being closed) with a secondary label.
+    fn expect_with_context(&mut self, expected: Token, context: Span, context_label: &str) -> Result<(), ChimError> {
+        self.expect_inner(expected, Some((context, context_label)))
+    }
+
+    fn expect_inner(&mut self, expected: Token, context: Option<(Span, &str)>) -> Result<(), ChimError> {
+        let mut error = if let Some(token) = self.tokens.peek() {
             if token.token == expected {
                 self.tokens.next();
                 return Ok(());
             }
             
             let error_msg = format!(
-                "expected {:?}, found {:?} at {}:{}",
-                expected,
-                token.token,
-                token.span.start_line,
-                token.span.start_col
+                "expected {}, found {} at {}:{}",
+                self.describe_token(&expected),
+                self.describe_token(&token.token),
+                token.span.line,
+                token.span.column
             );
-            self.errors.push(ChimError::new(
-                ErrorKind::Parser,
-                error_msg,
-            ).with_span(token.span));
-            let err = self.errors.last().cloned().unwrap_or_else(|| {
-                ChimError::new(ErrorKind::Parser, "unknown error".to_string())
-            });
-            Err(err)
+            let kind = Self::unexpected_kind(&token.token);
+            let error = ChimError::new(kind, error_msg);
+            match self.tokens.prev_span() {
+                // A missing `;` belongs right after what it should end, not at
+                // the next token, which may be lines away.
+                Some(prev) if expected == Token::Semicolon => {
+                    let after = Self::span_after(prev);
+                    error.with_span(after)
+                        .with_label(after, format!("expected {}", self.describe_token(&expected)))
+                        .with_secondary_label(token.span, format!("found {}", self.describe_token(&token.token)))
+                }
+                _ => error.with_span(token.span)
+                    .with_label(token.span, format!("expected {}", self.describe_token(&expected))),
+            }
         } else {
             let error_msg = format!(
-                "expected {:?}, found end of input",
-                expected
+                "expected {}, found end of input",
+                self.describe_token(&expected)
             );
-            self.errors.push(ChimError::new(
-                ErrorKind::Parser,
+            ChimError::new(
+                ErrorKind::UnexpectedEof,
                 error_msg,
-            ).with_span(Span::new(self.file_id, 0, 0, 0, 0)));
-            let err = self.errors.last().cloned().unwrap_or_else(|| {
-                ChimError::new(ErrorKind::Parser, "unknown error".to_string())
-            });
-            Err(err)
+            ).with_span(self.eof_span())
+        };
+
+        if let Some((span, label)) = context {
+            error = error.with_secondary_label(span, label.to_string());
         }
+        self.errors.push(error.clone());
+        Err(error)
     }
 
-    fn expect_one_of(&mut self, expected: &[Token]) -> Result<Token, ChimError> {
-        if let Some(token) = self.tokens.peek() {
-            if expected.contains(&token.token) {
-                let tok = token.token.clone();
-                self.tokens.next();
-                return Ok(tok);
-            }
-            
-            let expected_str: Vec<String> = expected.iter().map(|t| format!("{:?}", t)).collect();
-            let error_msg = format!(
-                "expected one of [{}], found {:?} at {}:{}",
-                expected_str.join(", "),
-                token.token,
-                token.span.start_line,
-                token.span.start_col
-            );
-            self.errors.push(ChimError::new(
-                ErrorKind::Parser,
-                error_msg,
-            ).with_span(token.span));
-            let err = self.errors.last().cloned().unwrap_or_else(|| {
-                ChimError::new(ErrorKind::Parser, "unknown error".to_string())
-            });
-            Err(err)
+    /// `token` as named in a diagnostic: its debug name, or for a keyword in
+    /// Chinese source its Chinese spelling in backticks.
+    fn describe_token(&self, token: &Token) -> String {
+        match self.dialect {
+            Dialect::Chinese => match keyword_spelling(token, Dialect::Chinese) {
+                Some(spelling) => format!("`{}`", spelling),
+                None => format!("{:?}", token),
+            },
+            Dialect::English => format!("{:?}", token),
+        }
+    }
+
+    /// Whether the next token closes the enclosing expression, so a `break` or
+    /// `return` here carries no value.
+    fn at_operand_end(&self) -> bool {
+        matches!(
+            self.tokens.peek().map(|t| &t.token),
+            None | Some(Token::Semicolon) | Some(Token::RBrace) | Some(Token::RParen)
+                | Some(Token::RBracket) | Some(Token::Comma)
+        )
+    }
+
+    fn unexpected_kind(found: &Token) -> ErrorKind {
+        if *found == Token::Eof {
+            ErrorKind::UnexpectedEof
         } else {
-            let expected_str: Vec<String> = expected.iter().map(|t| format!("{:?}", t)).collect();
-            let error_msg = format!(
-                "expected one of [{}], found end of input",
-                expected_str.join(", ")
-            );
-            self.errors.push(ChimError::new(
-                ErrorKind::Parser,
-                error_msg,
-            ).with_span(Span::new(self.file_id, 0, 0, 0, 0)));
-            let err = self.errors.last().cloned().unwrap_or_else(|| {
-                ChimError::new(ErrorKind::Parser, "unknown error".to_string())
-            });
-            Err(err)
+            ErrorKind::UnexpectedToken
         }
     }
 
@@ -3279,6 +4842,37 @@ impl<'a> Parser<'a> {
         ]);
     }
 
+    /// Skips a misplaced `let`/`var` through its terminating `;`, stopping
+    /// early at the start of the next item if the `;` is missing.
+    fn skip_top_level_declaration(&mut self) {
+        self.tokens.next();
+        let mut depth = 0usize;
+        while let Some(token) = self.tokens.peek().map(|t| t.token) {
+            match token {
+                Token::LParen | Token::LBracket | Token::LBrace => depth += 1,
+                Token::RParen | Token::RBracket | Token::RBrace => depth = depth.saturating_sub(1),
+                Token::Semicolon if depth == 0 => {
+                    self.tokens.next();
+                    break;
+                }
+                Token::Func | Token::Struct | Token::Enum | Token::Trait | Token::Impl | Token::Const
+                | Token::Static | Token::Mod | Token::Use | Token::Let | Token::LetAlt | Token::Var
+                    if depth == 0 => break,
+                _ => {}
+            }
+            self.tokens.next();
+        }
+    }
+
+    /// Consumes one token if nothing was consumed since `start`. Recovery
+    /// stops in front of sync tokens, so an item or statement that fails on
+    /// one without advancing would otherwise be retried forever.
+    fn ensure_progress(&mut self, start: usize) {
+        if self.tokens.position() == start && !self.tokens.at_end() {
+            self.tokens.next();
+        }
+    }
+
     fn recover_to_sync_points(&mut self, sync_tokens: &[Token]) {
         while !self.tokens.at_end() {
             if let Some(token) = self.tokens.peek() {
@@ -3312,6 +4906,47 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Consumes one token tree — a single token, or a bracketed group through
+    /// its matching close — and returns the positions it spans, for `$x:tt`
+    /// capture with `TokenStream::slice_between`.
+    fn skip_token_tree(&mut self) -> Result<(usize, usize), ChimError> {
+        let start = self.tokens.position();
+        let open_span = self.current_span()?;
+        let mut closers = Vec::new();
+        loop {
+            let token = match self.tokens.next() {
+                Some(token) => token.token,
+                None => {
+                    return Err(ChimError::new(
+                        ErrorKind::UnexpectedEof,
+                        "unclosed delimiter in token tree".to_string(),
+                    ).with_span(open_span));
+                }
+            };
+            match token {
+                Token::LParen => closers.push(Token::RParen),
+                Token::LBracket => closers.push(Token::RBracket),
+                Token::LBrace => closers.push(Token::RBrace),
+                Token::RParen | Token::RBracket | Token::RBrace if closers.pop() != Some(token) => {
+                    return Err(ChimError::new(
+                        ErrorKind::UnexpectedToken,
+                        "mismatched closing delimiter in token tree".to_string(),
+                    ).with_span(open_span));
+                }
+                _ => {}
+            }
+            if closers.is_empty() {
+                return Ok((start, self.tokens.position()));
+            }
+        }
+    }
+
+    /// A parser over `tokens` sharing this parser's interner, for re-parsing
+    /// tokens captured by a macro matcher.
+    pub fn sub_parser(&mut self, tokens: &[SpannedToken]) -> Parser<'_> {
+        Parser::new(self.source, tokens.to_vec(), &mut *self.interner, self.file_id).with_dialect(self.dialect)
+    }
+
     fn skip_to(&mut self, target: Token) -> bool {
         while !self.tokens.at_end() {
             if self.tokens.peek().map(|t| &t.token) == Some(&target) {
@@ -3337,9 +4972,93 @@ impl<'a> Parser<'a> {
 }
 
 pub fn parse(source: &str, file_id: FileId) -> Result<Program, Vec<ChimError>> {
-    let (tokens, _, _) = chim_lexer::tokenize(source, file_id);
-    let mut parser = Parser::new(tokens, &mut lasso::Rodeo::new(), file_id);
-    parser.parse()
+    parse_with(source, file_id, |parser| parser.parse())
+}
+
+/// Parses `source` as one expression, e.g. a line typed into a REPL.
+pub fn parse_expression(source: &str, file_id: FileId) -> Result<Expr, Vec<ChimError>> {
+    parse_with(source, file_id, |parser| parser.parse_expression())
+}
+
+/// Like `parse`, but keeps whatever items parsed when there are errors; see
+/// `Parser::into_result_with_partial`.
+pub fn parse_partial(source: &str, file_id: FileId) -> (Option<Program>, Vec<ChimError>) {
+    let (tokens, docs, lexer_errors) = tokenize_with_docs(source, file_id);
+    let mut interner = lasso::Rodeo::new();
+    let dialect = Dialect::detect(source, &tokens);
+    let (program, parse_errors) = Parser::new(source, tokens, &mut interner, file_id)
+        .with_dialect(dialect)
+        .with_doc_comments(docs)
+        .into_result_with_partial();
+    let mut errors: Vec<ChimError> = lexer_errors.into_iter().map(ChimError::from).collect();
+    errors.extend(parse_errors);
+    (program, errors)
+}
+
+/// The tokens of `source` without trivia, its doc comments as
+/// `chim_lexer::doc_comments` finds them, and the lexer's errors.
+fn tokenize_with_docs(source: &str, file_id: FileId) -> (Vec<SpannedToken>, HashMap<usize, String>, Vec<LexerError>) {
+    let TokenizeResult { tokens, errors, .. } = chim_lexer::tokenize_with_trivia(source, file_id);
+    let docs = doc_comments(source, &tokens);
+    let tokens = tokens.into_iter().filter(|token| !token.is_trivia()).collect();
+    (tokens, docs, errors)
+}
+
+/// `text` without its surrounding quotes; an unterminated literal keeps
+/// whatever follows the opening quote.
+fn strip_quotes(text: &str) -> &str {
+    let body = &text[1.min(text.len())..];
+    match text.chars().next() {
+        Some(quote) => body.strip_suffix(quote).unwrap_or(body),
+        None => body,
+    }
+}
+
+/// Resolves the escapes the lexer accepts in string and character literals.
+fn unescape(text: &str) -> String {
+    let mut value = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => value.push('\n'),
+            Some('t') => value.push('\t'),
+            Some('r') => value.push('\r'),
+            Some('0') => value.push('\0'),
+            Some(other) => value.push(other),
+            None => value.push('\\'),
+        }
+    }
+    value
+}
+
+/// Parses `source` as one statement, e.g. a line typed into a REPL.
+pub fn parse_statement(source: &str, file_id: FileId) -> Result<Stmt, Vec<ChimError>> {
+    parse_with(source, file_id, |parser| parser.parse_statement())
+}
+
+fn parse_with<T>(
+    source: &str,
+    file_id: FileId,
+    production: impl FnOnce(&mut Parser) -> Result<T, Vec<ChimError>>,
+) -> Result<T, Vec<ChimError>> {
+    let (tokens, docs, lexer_errors) = tokenize_with_docs(source, file_id);
+    let mut interner = lasso::Rodeo::new();
+    let dialect = Dialect::detect(source, &tokens);
+    let mut parser = Parser::new(source, tokens, &mut interner, file_id).with_dialect(dialect).with_doc_comments(docs);
+    let result = production(&mut parser);
+    if lexer_errors.is_empty() {
+        return result;
+    }
+
+    let mut errors: Vec<ChimError> = lexer_errors.into_iter().map(ChimError::from).collect();
+    if let Err(parse_errors) = result {
+        errors.extend(parse_errors);
+    }
+    Err(errors)
 }
 
 #[cfg(test)]
@@ -3348,7 +5067,7 @@ mod tests {
 
     #[test]
     fn test_parse_empty_program() {
-        let file_id = FileId(0);
+        let file_id = FileId::new(0);
         let result = parse("", file_id);
         assert!(result.is_ok());
         let program = result.unwrap();
@@ -3362,7 +5081,7 @@ mod tests {
                 return 42;
             }
         "#;
-        let file_id = FileId(0);
+        let file_id = FileId::new(0);
         let result = parse(source, file_id);
         assert!(result.is_ok());
     }
@@ -3375,7 +5094,7 @@ mod tests {
                 y: int;
             }
         "#;
-        let file_id = FileId(0);
+        let file_id = FileId::new(0);
         let result = parse(source, file_id);
         assert!(result.is_ok());
     }
@@ -3388,7 +5107,7 @@ mod tests {
                 None;
             }
         "#;
-        let file_id = FileId(0);
+        let file_id = FileId::new(0);
         let result = parse(source, file_id);
         assert!(result.is_ok());
     }
@@ -3400,7 +5119,7 @@ mod tests {
                 let x: int = 42;
             }
         "#;
-        let file_id = FileId(0);
+        let file_id = FileId::new(0);
         let result = parse(source, file_id);
         assert!(result.is_ok());
     }
@@ -3416,7 +5135,7 @@ mod tests {
                 }
             }
         "#;
-        let file_id = FileId(0);
+        let file_id = FileId::new(0);
         let result = parse(source, file_id);
         assert!(result.is_ok());
     }
@@ -3428,7 +5147,7 @@ mod tests {
                 let f = |x: int| x * 2;
             }
         "#;
-        let file_id = FileId(0);
+        let file_id = FileId::new(0);
         let result = parse(source, file_id);
         assert!(result.is_ok());
     }
@@ -3442,7 +5161,7 @@ mod tests {
                 }
             }
         "#;
-        let file_id = FileId(0);
+        let file_id = FileId::new(0);
         let result = parse(source, file_id);
         assert!(result.is_ok());
     }
@@ -3456,7 +5175,7 @@ mod tests {
                 }
             }
         "#;
-        let file_id = FileId(0);
+        let file_id = FileId::new(0);
         let result = parse(source, file_id);
         assert!(result.is_ok());
     }
@@ -3468,7 +5187,7 @@ mod tests {
                 let arr = [1, 2, 3, 4, 5];
             }
         "#;
-        let file_id = FileId(0);
+        let file_id = FileId::new(0);
         let result = parse(source, file_id);
         assert!(result.is_ok());
     }
@@ -3480,8 +5199,1504 @@ mod tests {
                 let x = a > b ? a : b;
             }
         "#;
-        let file_id = FileId(0);
+        let file_id = FileId::new(0);
         let result = parse(source, file_id);
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_ternary_precedence_and_associativity() {
+        let file_id = FileId::new(0);
+        let ternary = |expr: &Expr| match &*expr.kind {
+            ExprKind::Ternary(ternary) => ternary.clone(),
+            other => panic!("expected ternary, got {:?}", other),
+        };
+
+        let expr = parse_expression("a + b ? c : d", file_id).unwrap();
+        let outer = ternary(&expr);
+        assert!(matches!(&*outer.condition.kind, ExprKind::Binary(BinaryExpr { op: BinOp::Add, .. })));
+
+        let expr = parse_expression("x > 0 ? 1 : 0", file_id).unwrap();
+        assert!(matches!(&*ternary(&expr).condition.kind, ExprKind::Binary(BinaryExpr { op: BinOp::Gt, .. })));
+
+        let expr = parse_expression("a ? b : c ? d : e", file_id).unwrap();
+        let outer = ternary(&expr);
+        assert!(matches!(&*outer.condition.kind, ExprKind::Identifier(name) if &**name == "a"));
+        let inner = ternary(&outer.else_branch);
+        assert!(matches!(&*inner.condition.kind, ExprKind::Identifier(name) if &**name == "c"));
+
+        let expr = parse_expression("a ? b ? c : d : e", file_id).unwrap();
+        assert!(matches!(&*ternary(&expr).then_branch.kind, ExprKind::Ternary(_)));
+
+        let expr = parse_expression("x = a ? b : c", file_id).unwrap();
+        match &*expr.kind {
+            ExprKind::Assign(assign) => assert!(matches!(&*assign.right.kind, ExprKind::Ternary(_))),
+            other => panic!("expected assignment, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_variadic_extern_function() {
+        let source = r#"
+            extern "C" {
+                fn printf(fmt: *char, ...) -> int;
+            }
+        "#;
+        let file_id = FileId::new(0);
+        let program = parse(source, file_id).unwrap();
+        match &program.items[0] {
+            Item::Extern(block) => {
+                let sig = block.items[0].sig.as_ref().unwrap();
+                assert_eq!(sig.params.len(), 1);
+                assert!(sig.is_variadic);
+            }
+            other => panic!("expected extern block, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_contextual_keywords_as_identifiers() {
+        let source = r#"
+            fn main() {
+                let map = 1;
+                let sort = map + 2;
+                sort = sort / map;
+            }
+        "#;
+        let file_id = FileId::new(0);
+        let program = parse(source, file_id).unwrap();
+        match &program.items[0] {
+            Item::Function(func) => {
+                assert_eq!(func.body.len(), 3);
+                match &func.body[0].kind {
+                    StmtKind::Let(let_stmt) => {
+                        assert!(matches!(let_stmt.pattern.kind, PatternKind::Identifier(..)));
+                    }
+                    other => panic!("expected let, got {:?}", other),
+                }
+            }
+            other => panic!("expected function, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_contextual_keyword_prefix_forms() {
+        let file_id = FileId::new(0);
+        let expr = parse_expression("map (xs), f", file_id).unwrap();
+        assert!(matches!(&*expr.kind, ExprKind::Map(_)));
+        let expr = parse_expression("next (it)", file_id).unwrap();
+        assert!(matches!(&*expr.kind, ExprKind::Next(_)));
+        let expr = parse_expression("item -it", file_id).unwrap();
+        assert!(matches!(&*expr.kind, ExprKind::Item(_)));
+    }
+
+    #[test]
+    fn test_parse_stream_block_with_yields() {
+        let source = r#"
+            fn numbers() {
+                let s = stream { yield 1; yield 2; };
+            }
+        "#;
+        let file_id = FileId::new(0);
+        let result = parse(source, file_id);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_parse_yield_outside_generator() {
+        let source = r#"
+            fn numbers() {
+                yield 1;
+            }
+        "#;
+        let file_id = FileId::new(0);
+        let errors = parse(source, file_id).unwrap_err();
+        assert!(errors.iter().any(|e| e.message().contains("`yield` is only allowed")));
+    }
+
+    #[test]
+    fn test_error_kind_unexpected_token() {
+        let source = r#"
+            struct Point {
+                x int;
+            }
+        "#;
+        let file_id = FileId::new(0);
+        let errors = parse(source, file_id).unwrap_err();
+        assert_eq!(errors[0].kind(), &ErrorKind::UnexpectedToken);
+    }
+
+    #[test]
+    fn test_missing_paren_points_at_opening_paren() {
+        let source = "fn add(a: int, b: int {\n}";
+        let file_id = FileId::new(0);
+        let errors = parse(source, file_id).unwrap_err();
+        let open = source.find('(').unwrap();
+        let error = &errors[0];
+        assert_eq!(error.kind(), &ErrorKind::UnexpectedToken);
+        assert!(error.labels().iter().any(|l| l.style == chim_error::LabelStyle::Primary));
+        let context = error.labels().iter()
+            .find(|l| l.style == chim_error::LabelStyle::Secondary)
+            .expect("missing secondary label");
+        assert_eq!(context.span.start, open);
+        assert_eq!(error.merge_spans().unwrap().start, open);
+    }
+
+    #[test]
+    fn test_error_kind_unexpected_eof() {
+        let source = "struct Point { x: int;";
+        let file_id = FileId::new(0);
+        let errors = parse(source, file_id).unwrap_err();
+        assert!(errors.iter().any(|e| e.kind() == &ErrorKind::UnexpectedEof));
+    }
+
+    #[test]
+    fn test_error_kind_unterminated_string() {
+        let source = r#"fn main() { let s = "abc"#;
+        let file_id = FileId::new(0);
+        let errors = parse(source, file_id).unwrap_err();
+        assert_eq!(errors[0].kind(), &ErrorKind::UnterminatedString);
+    }
+
+    #[test]
+    fn test_error_kind_recursion_limit() {
+        let source = format!("fn main() {{ let x = {}1{}; }}", "(".repeat(100), ")".repeat(100));
+        let file_id = FileId::new(0);
+        let errors = parse(&source, file_id).unwrap_err();
+        assert!(errors.iter().any(|e| e.kind() == &ErrorKind::RecursionLimit));
+    }
+
+    #[test]
+    fn test_fatal_error_stops_parsing() {
+        let source = format!(
+            "fn a() {{ let x = {}1{}; }}\nfn b() {{ let = ; }}",
+            "(".repeat(100),
+            ")".repeat(100)
+        );
+        let second = source.find("fn b").unwrap();
+        let file_id = FileId::new(0);
+        let errors = parse(&source, file_id).unwrap_err();
+        assert!(errors.iter().any(|e| e.is_fatal()));
+        assert!(errors.iter().all(|e| e.span.is_none_or(|span| span.start < second)));
+    }
+
+    #[test]
+    fn test_recoverable_error_continues_parsing() {
+        let source = "fn a() { let = ; }\nfn b() { let = ; }";
+        let second = source.find("fn b").unwrap();
+        let file_id = FileId::new(0);
+        let errors = parse(source, file_id).unwrap_err();
+        assert!(errors.iter().all(|e| !e.is_fatal()));
+        assert!(errors.iter().any(|e| e.span.is_some_and(|span| span.start > second)));
+    }
+
+    /// A scratch directory for one loader test, removed again when the test
+    /// ends, pass or fail.
+    struct ModuleTestDir(std::path::PathBuf);
+
+    impl ModuleTestDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("chim_loader_{}_{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            ModuleTestDir(dir)
+        }
+    }
+
+    impl std::ops::Deref for ModuleTestDir {
+        type Target = std::path::Path;
+
+        fn deref(&self) -> &std::path::Path {
+            &self.0
+        }
+    }
+
+    impl Drop for ModuleTestDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_module_loader_two_files() {
+        let dir = ModuleTestDir::new("two_files");
+        std::fs::write(dir.join("main.chim"), "mod util;\nfn main() {}\n").unwrap();
+        std::fs::write(dir.join("util.chim"), "fn helper() {}\n").unwrap();
+
+        let mut source_map = chim_span::SourceMap::new();
+        let program = ModuleLoader::new(&mut source_map).load(&dir.join("main.chim")).unwrap();
+        match &program.items[0] {
+            Item::Mod(module) => {
+                assert_eq!(module.items.len(), 1);
+                assert!(module.file_path.as_ref().unwrap().ends_with("util.chim"));
+            }
+            other => panic!("expected module, got {:?}", other),
+        }
+        assert_eq!(source_map.file_count(), 2);
+    }
+
+    #[test]
+    fn test_module_loader_missing_module() {
+        let dir = ModuleTestDir::new("missing");
+        std::fs::write(dir.join("main.chim"), "mod absent;\n").unwrap();
+
+        let mut source_map = chim_span::SourceMap::new();
+        let errors = ModuleLoader::new(&mut source_map).load(&dir.join("main.chim")).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message().contains("file not found for module"));
+        assert!(errors[0].span.is_some());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_module_loader_cycle() {
+        let dir = ModuleTestDir::new("cycle");
+        std::fs::write(dir.join("main.chim"), "mod a;\n").unwrap();
+        std::fs::write(dir.join("a.chim"), "mod b;\n").unwrap();
+        std::fs::create_dir_all(dir.join("a")).unwrap();
+        std::os::unix::fs::symlink(dir.join("a.chim"), dir.join("a").join("b.chim")).unwrap();
+
+        let mut source_map = chim_span::SourceMap::new();
+        let errors = ModuleLoader::new(&mut source_map).load(&dir.join("main.chim")).unwrap_err();
+        assert!(errors.iter().any(|e| e.message().contains("cyclic module reference")));
+    }
+
+    #[test]
+    fn test_parse_const_with_inferred_type() {
+        let file_id = FileId::new(0);
+        let program = parse("const X = 5;", file_id).unwrap();
+        match &program.items[0] {
+            Item::Constant(constant) => assert!(constant.ty.is_none()),
+            other => panic!("expected constant, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_const_with_annotated_type() {
+        let file_id = FileId::new(0);
+        let program = parse("const Y: int = 5;", file_id).unwrap();
+        match &program.items[0] {
+            Item::Constant(constant) => assert!(constant.ty.is_some()),
+            other => panic!("expected constant, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_static_requires_type() {
+        let file_id = FileId::new(0);
+        assert!(parse("static Z = 5;", file_id).is_err());
+    }
+
+    #[test]
+    fn test_duplicate_struct_field() {
+        let source = "struct S { x: int; x: int; }";
+        let file_id = FileId::new(0);
+        let errors = parse(source, file_id).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind(), &ErrorKind::Redefinition);
+        assert!(errors[0].message().contains("field `x` declared twice"));
+        assert_eq!(errors[0].labels().len(), 2);
+    }
+
+    #[test]
+    fn test_duplicate_enum_variant() {
+        let source = "enum E { A, B, A }";
+        let file_id = FileId::new(0);
+        let errors = parse(source, file_id).unwrap_err();
+        assert!(errors.iter().any(|e| e.message().contains("variant `A` declared twice")));
+    }
+
+    #[test]
+    fn test_duplicate_function_param() {
+        let source = "fn f(a: int, a: int) {}";
+        let file_id = FileId::new(0);
+        let errors = parse(source, file_id).unwrap_err();
+        assert!(errors.iter().any(|e| e.message().contains("parameter `a` declared twice")));
+    }
+
+    #[test]
+    fn test_parse_generic_impl_with_bounds() {
+        let source = r#"
+            impl<T: Clone> Container<T> {
+                fn get(self) -> T {
+                    return self.value;
+                }
+            }
+        "#;
+        let file_id = FileId::new(0);
+        let program = parse(source, file_id).unwrap();
+        match &program.items[0] {
+            Item::Impl(impl_def) => {
+                assert!(impl_def.trait_name.is_none());
+                assert_eq!(impl_def.generics.len(), 1);
+                assert_eq!(impl_def.generics[0].bounds.len(), 1);
+                match &*impl_def.type_name.kind {
+                    TypeKind::Path(path) => assert_eq!(path.segments[0].args.len(), 1),
+                    other => panic!("expected path type, got {:?}", other),
+                }
+            }
+            other => panic!("expected impl, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_trait_impl_with_where_clause() {
+        let source = r#"
+            impl<T> Show for Wrapper<T> where T: Display + Clone {
+            }
+        "#;
+        let file_id = FileId::new(0);
+        let program = parse(source, file_id).unwrap();
+        match &program.items[0] {
+            Item::Impl(impl_def) => {
+                assert!(impl_def.trait_name.is_some());
+                let predicate = &impl_def.where_clauses[0].predicates[0];
+                assert_eq!(predicate.bounds().len(), 2);
+            }
+            other => panic!("expected impl, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_where_clause_on_every_item_kind() {
+        let source = r#"
+            fn f<T>(x: T) -> T where T: Clone { x }
+            fn g<T>(x: T) where T: Clone { }
+            struct S<T> where T: Clone { value: T; }
+            enum E<T> where T: Clone { A }
+            trait Tr<T> where T: Clone { }
+            impl<T> S<T> where T: Clone { }
+        "#;
+        let program = parse(source, FileId::new(0)).unwrap();
+        assert_eq!(program.items.len(), 6);
+        for item in &program.items {
+            let where_clauses = match item {
+                Item::Function(func) => &func.where_clauses,
+                Item::Struct(def) => &def.where_clauses,
+                Item::Enum(def) => &def.where_clauses,
+                Item::Trait(def) => &def.where_clauses,
+                Item::Impl(def) => &def.where_clauses,
+                other => panic!("unexpected item {:?}", other),
+            };
+            assert_eq!(where_clauses.len(), 1, "{:?}", item);
+            assert_eq!(where_clauses[0].predicates[0].bounds().len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_misplaced_where_clause() {
+        for (source, expected) in [
+            ("fn f<T>(x: T) where T: Clone -> T { x }", "after the return type"),
+            ("impl<T> Show where T: Clone for Wrapper<T> { }", "after the implementing type"),
+            ("struct S<T> { value: T; } where T: Clone", "before the struct body"),
+            ("enum E<T> { A } where T: Clone", "before the enum body"),
+            ("trait Tr<T> { } where T: Clone", "before the trait body"),
+            ("impl<T> S<T> { } where T: Clone", "before the impl body"),
+        ] {
+            let errors = parse(source, FileId::new(0)).unwrap_err();
+            assert!(
+                errors.iter().any(|e| e.message() == format!("`where` clause must come {}", expected)),
+                "{}: {:?}",
+                source,
+                errors
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_enum_discriminants() {
+        let program = parse("@repr(i16) enum E { A = -1, B = 128, C }", FileId::new(0)).unwrap();
+        let Item::Enum(enum_def) = &program.items[0] else {
+            panic!("expected enum, got {:?}", program.items[0]);
+        };
+        assert_eq!(&*enum_def.attributes[0].name, "repr");
+        assert!(matches!(
+            enum_def.variants[0].discriminant.as_ref().map(|expr| &*expr.kind),
+            Some(ExprKind::Unary(UnaryExpr { op: UnOp::Neg, .. }))
+        ));
+        assert!(matches!(
+            enum_def.variants[1].discriminant.as_ref().map(|expr| &*expr.kind),
+            Some(ExprKind::Literal(Literal { kind: LiteralKind::Int(128), .. }))
+        ));
+        assert!(enum_def.variants[2].discriminant.is_none());
+    }
+
+    #[test]
+    fn test_parse_struct_attributes() {
+        let program = parse("@derive(Clone, Default) struct P { x: int; }", FileId::new(0)).unwrap();
+        let Item::Struct(struct_def) = &program.items[0] else {
+            panic!("expected struct, got {:?}", program.items[0]);
+        };
+        assert_eq!(&*struct_def.attributes[0].name, "derive");
+        assert_eq!(struct_def.attributes[0].args.len(), 2);
+    }
+
+    #[test]
+    fn test_docs_for_documented_function() {
+        let source = "/// Adds two numbers.\n///   Wraps on overflow.\n@doc(\"See also `sub`.\")\nfn add(a: int, b: int) -> int { a + b }\n\n// not a doc comment\nfn sub(a: int, b: int) -> int { a - b }";
+        let program = parse(source, FileId::new(0)).unwrap();
+        assert_eq!(
+            program.docs_for(&program.items[0]).as_deref(),
+            Some("Adds two numbers.\n  Wraps on overflow.\nSee also `sub`."),
+        );
+        assert_eq!(program.docs_for(&program.items[1]), None);
+
+        // `///` inside a block comment is not a doc comment, and an ordinary
+        // comment ends a run of doc lines.
+        let source = "/* /// hidden */\nfn f() {}\n/// dropped\n// note\n/// Kept.\nfn g() {}";
+        let program = parse(source, FileId::new(0)).unwrap();
+        assert_eq!(program.docs_for(&program.items[0]), None);
+        assert_eq!(program.docs_for(&program.items[1]).as_deref(), Some("Kept."));
+
+        let source = "/// A shape.\ntrait Shape {}\n/// Squares.\nimpl Shape for int {}\n/// Geometry.\nmod geo {}";
+        let program = parse(source, FileId::new(0)).unwrap();
+        let docs: Vec<_> = program.items.iter().map(|item| program.docs_for(item)).collect();
+        assert_eq!(docs, [Some("A shape.".to_string()), Some("Squares.".to_string()), Some("Geometry.".to_string())]);
+    }
+
+    #[test]
+    fn test_missing_semicolon_points_after_expression() {
+        let source = "fn f() {\n    let x = 1 + 2\n    let y = 3;\n}";
+        let errors = parse(source, FileId::new(0)).unwrap_err();
+        let error = errors.iter()
+            .find(|e| e.message().starts_with("expected Semicolon"))
+            .expect("expected a missing-`;` error");
+        let end_of_expr = source.find("2").unwrap() + 1;
+        let span = error.span.unwrap();
+        assert_eq!((span.start, span.end), (end_of_expr, end_of_expr));
+        assert_eq!(span.line, 2);
+    }
+
+    #[test]
+    fn test_parse_cache_hits_on_unchanged_source() {
+        let mut cache = ParseCache::new();
+        let first = parse_cached("fn f() -> int { 1 }", FileId::new(0), &mut cache).unwrap();
+        let second = parse_cached("fn f() -> int { 1 }", FileId::new(0), &mut cache).unwrap();
+        assert_eq!(first, second);
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+
+        // Changed content is parsed again and replaces the cached entry.
+        parse_cached("fn f() -> int { 2 }", FileId::new(0), &mut cache).unwrap();
+        assert_eq!((cache.hits(), cache.misses()), (1, 2));
+        assert_eq!(cache.len(), 1);
+
+        // Errors are cached too.
+        assert!(parse_cached("fn (", FileId::new(1), &mut cache).is_err());
+        assert!(parse_cached("fn (", FileId::new(1), &mut cache).is_err());
+        assert_eq!(cache.hits(), 2);
+    }
+
+    #[test]
+    fn test_capture_token_tree_and_reparse() {
+        let tokens = chim_lexer::tokenize("(1 + 2) * 3", FileId::new(0)).tokens;
+        let mut interner = lasso::Rodeo::new();
+        let mut parser = Parser::new("(1 + 2) * 3", tokens, &mut interner, FileId::new(0));
+        let (start, end) = parser.skip_token_tree().unwrap();
+        let captured = parser.tokens.slice_between(start, end).to_vec();
+        assert_eq!(captured.len(), 5);
+        assert_eq!(captured[0].token, Token::LParen);
+        assert_eq!(captured[4].token, Token::RParen);
+        assert!(parser.tokens.peek_is(Token::Star));
+
+        let expr = parser.sub_parser(&captured).parse_expression().unwrap();
+        assert!(matches!(&*expr.kind, ExprKind::Binary(BinaryExpr { op: BinOp::Add, .. })));
+    }
+
+    #[test]
+    fn test_parse_macro_calls() {
+        let expr = parse_expression("vec![1, 2, 3]", FileId::new(0)).unwrap();
+        let ExprKind::MacroCall(call) = &*expr.kind else {
+            panic!("expected macro call, got {:?}", expr);
+        };
+        assert_eq!(&*call.name, "vec");
+        assert_eq!(call.delimiter, MacroDelimiter::Bracket);
+        assert_eq!(call.tokens, vec![
+            MacroToken::Literal("1".to_string()),
+            MacroToken::Punctuation(','),
+            MacroToken::Literal("2".to_string()),
+            MacroToken::Punctuation(','),
+            MacroToken::Literal("3".to_string()),
+        ]);
+
+        let stmt = parse_statement("assert!(x == (y));", FileId::new(0)).unwrap();
+        let StmtKind::Expr(expr) = &stmt.kind else {
+            panic!("expected expression statement, got {:?}", stmt);
+        };
+        let ExprKind::MacroCall(call) = &*expr.kind else {
+            panic!("expected macro call, got {:?}", expr);
+        };
+        assert_eq!(&*call.name, "assert");
+        assert_eq!(call.delimiter, MacroDelimiter::Paren);
+        assert_eq!(call.tokens, vec![
+            MacroToken::Ident(Arc::from("x")),
+            MacroToken::Operator(Arc::from("==")),
+            MacroToken::Group(MacroDelimiter::Paren, vec![MacroToken::Ident(Arc::from("y"))]),
+        ]);
+
+        // `!` without a delimiter is still negation.
+        let expr = parse_expression("!x", FileId::new(0)).unwrap();
+        assert!(matches!(&*expr.kind, ExprKind::Unary(_)));
+
+        let program = parse("declare! { a } log!(b);", FileId::new(0)).unwrap();
+        let names: Vec<&str> = program.items.iter().map(|item| match item {
+            Item::MacroCall(call) => &*call.name,
+            other => panic!("expected macro call, got {:?}", other),
+        }).collect();
+        assert_eq!(names, ["declare", "log"]);
+    }
+
+    fn expand(macro_source: &str, call_source: &str) -> Result<Vec<MacroToken>, ChimError> {
+        let program = parse(macro_source, FileId::new(0)).unwrap();
+        let expr = parse_expression(call_source, FileId::new(0)).unwrap();
+        let ExprKind::MacroCall(call) = &*expr.kind else {
+            panic!("expected macro call, got {:?}", expr);
+        };
+        MacroExpander::from_program(&program).expand_tokens(call)
+    }
+
+    #[test]
+    fn test_expand_square_macro() {
+        let source = "macro square() { ($x:expr) => { $x * $x } };";
+        let ident = |name: &str| MacroToken::Ident(Arc::from(name));
+        assert_eq!(expand(source, "square!(x)").unwrap(), vec![ident("x"), MacroToken::Punctuation('*'), ident("x")]);
+
+        let program = parse(source, FileId::new(0)).unwrap();
+        let expr = parse_expression("square!(x)", FileId::new(0)).unwrap();
+        let ExprKind::MacroCall(call) = &*expr.kind else { unreachable!() };
+        let expanded = MacroExpander::from_program(&program).expand_expr(call).unwrap();
+        match &*expanded.kind {
+            ExprKind::Binary(binary) => {
+                assert_eq!(binary.op, BinOp::Mul);
+                assert!(matches!(&*binary.left.kind, ExprKind::Identifier(name) if &**name == "x"));
+                assert!(matches!(&*binary.right.kind, ExprKind::Identifier(name) if &**name == "x"));
+            }
+            other => panic!("expected multiplication, got {:?}", other),
+        }
+
+        // The argument stays one operand: `(1 + 2) * (1 + 2)`, not `1 + 2 * 1 + 2`.
+        let expr = parse_expression("square!(1 + 2)", FileId::new(0)).unwrap();
+        let ExprKind::MacroCall(call) = &*expr.kind else { unreachable!() };
+        let expanded = MacroExpander::from_program(&program).expand_expr(call).unwrap();
+        let ExprKind::Binary(binary) = &*expanded.kind else { panic!("expected multiplication, got {:?}", expanded) };
+        assert_eq!(binary.op, BinOp::Mul);
+        assert!(matches!(&*binary.left.kind, ExprKind::Binary(sum) if sum.op == BinOp::Add));
+        assert!(matches!(&*binary.right.kind, ExprKind::Binary(sum) if sum.op == BinOp::Add));
+        // Nothing in the expansion points into the synthetic text.
+        let mut spans = Vec::new();
+        expanded.clone().map_spans(&mut |span| spans.push(*span));
+        assert!(spans.iter().all(|span| *span == call.span), "{spans:?}");
+
+        let error = expand(source, "square!()").unwrap_err();
+        assert!(error.message().contains("no rule of `square!` matches"));
+        let error = expand(source, "cube!(x)").unwrap_err();
+        assert!(error.message().contains("cannot find macro `cube!`"));
+    }
+
+    #[test]
+    fn test_expand_macro_repetition() {
+        let source = "macro sum() { ($($x:expr),*) => { 0 $(+ $x)* } };";
+        let tokens = expand(source, "sum!(1, 2 * 3)").unwrap();
+        let literal = |text: &str| MacroToken::Literal(text.to_string());
+        let plus = MacroToken::Punctuation('+');
+        assert_eq!(tokens, vec![
+            literal("0"),
+            plus.clone(),
+            literal("1"),
+            plus,
+            MacroToken::Group(MacroDelimiter::Paren, vec![literal("2"), MacroToken::Punctuation('*'), literal("3")]),
+        ]);
+        assert_eq!(expand(source, "sum!()").unwrap(), vec![literal("0")]);
+    }
+
+    #[test]
+    fn test_parse_partial_keeps_good_items() {
+        let (program, errors) = parse_partial("fn a() { } fn b() { let = ; } fn c() { }", FileId::new(0));
+        let names: Vec<String> = program.unwrap().items.iter().map(|item| match item {
+            Item::Function(func) => func.name.to_string(),
+            other => panic!("expected function, got {:?}", other),
+        }).collect();
+        assert!(names.contains(&"a".to_string()));
+        assert!(names.contains(&"c".to_string()));
+        assert!(!errors.is_empty());
+
+        let (program, errors) = parse_partial("fn a() { }", FileId::new(0));
+        assert_eq!(program.unwrap().items.len(), 1);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn test_impl_item_visibility() {
+        let source = r#"
+            impl Point {
+                pub const X: int = 1;
+                const Y: int = 2;
+                pub type Output = int;
+                pub fn origin() { }
+                fn hidden() { }
+            }
+        "#;
+        let program = parse(source, FileId::new(0)).unwrap();
+        let Item::Impl(impl_def) = &program.items[0] else {
+            panic!("expected impl, got {:?}", program.items[0]);
+        };
+        let visibility: Vec<bool> = impl_def.items.iter().map(|item| match item {
+            ImplItem::Const(constant) => constant.is_pub,
+            ImplItem::Type(binding) => binding.is_pub,
+            ImplItem::Function(func) => func.is_pub,
+        }).collect();
+        assert_eq!(visibility, [true, false, true, true, false]);
+    }
+
+    #[test]
+    fn test_parse_variant_paths() {
+        let expr = parse_expression("Color::Red", FileId::new(0)).unwrap();
+        let ExprKind::Path(path) = &*expr.kind else {
+            panic!("expected path, got {:?}", expr.kind);
+        };
+        let names: Vec<&str> = path.segments.iter().map(|segment| &*segment.ident).collect();
+        assert_eq!(names, ["Color", "Red"]);
+
+        let expr = parse_expression("Option::Some(1)", FileId::new(0)).unwrap();
+        let ExprKind::Call(call) = &*expr.kind else {
+            panic!("expected call, got {:?}", expr.kind);
+        };
+        assert!(matches!(&*call.func.kind, ExprKind::Path(path) if path.segments.len() == 2));
+        assert_eq!(call.args.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_closure_captures() {
+        let expr = parse_expression("move |x| capture(a, ref b) x + a + b", FileId::new(0)).unwrap();
+        let ExprKind::Closure(closure) = &*expr.kind else {
+            panic!("expected closure, got {:?}", expr.kind);
+        };
+        assert!(closure.is_move);
+        assert_eq!(closure.params.len(), 1);
+        assert_eq!(closure.captures, [
+            Capture::CaptureValue(Arc::from("a")),
+            Capture::CaptureRef(Arc::from("b")),
+        ]);
+
+        let expr = parse_expression("|| capture(captureref total) { total }", FileId::new(0)).unwrap();
+        let ExprKind::Closure(closure) = &*expr.kind else {
+            panic!("expected closure, got {:?}", expr.kind);
+        };
+        assert!(!closure.is_move);
+        assert_eq!(closure.captures, [Capture::CaptureRef(Arc::from("total"))]);
+
+        let errors = parse_expression("|| capture(a, a) a", FileId::new(0)).unwrap_err();
+        assert!(errors.iter().any(|e| e.message().contains("`a` is captured more than once")));
+    }
+
+    #[test]
+    fn test_chinese_source_diagnostics_use_chinese_keywords() {
+        let source = "函数 主() { 返回 1; }";
+        let tokens = chim_lexer::tokenize(source, FileId::new(0)).tokens;
+        assert_eq!(Dialect::detect(source, &tokens), Dialect::Chinese);
+        assert_eq!(Dialect::detect("fn main() { return 1; }", &chim_lexer::tokenize("fn main() { return 1; }", FileId::new(0)).tokens), Dialect::English);
+
+        let tokens = chim_lexer::tokenize("返回", FileId::new(0)).tokens;
+        let mut interner = lasso::Rodeo::new();
+        let mut parser = Parser::new("返回", tokens, &mut interner, FileId::new(0)).with_dialect(Dialect::Chinese);
+        let error = parser.expect(Token::Func).unwrap_err();
+        assert!(error.message().starts_with("expected `函数`, found `返回`"), "{}", error.message());
+        assert!(!error.message().contains("Func"));
+
+        let tokens = chim_lexer::tokenize("return", FileId::new(0)).tokens;
+        let mut interner = lasso::Rodeo::new();
+        let mut parser = Parser::new("return", tokens, &mut interner, FileId::new(0));
+        assert!(parser.expect(Token::Func).unwrap_err().message().starts_with("expected Func, found Return"));
+    }
+
+    #[test]
+    fn test_block_like_statements_need_no_semicolon() {
+        let program = parse("fn f() { if c {} x + 1; match x { 1 => 2; } loop { break; } { y } x }", FileId::new(0)).unwrap();
+        let Item::Function(func) = &program.items[0] else {
+            panic!("expected function, got {:?}", program.items[0]);
+        };
+        assert_eq!(func.body.len(), 6);
+        assert!(matches!(&func.body[0].kind, StmtKind::Expr(expr) if matches!(&*expr.kind, ExprKind::If(_))));
+        assert!(matches!(&func.body[1].kind, StmtKind::Expr(expr) if matches!(&*expr.kind, ExprKind::Binary(_))));
+        assert!(matches!(&func.body[5].kind, StmtKind::Expr(expr) if matches!(&*expr.kind, ExprKind::Identifier(_))));
+
+        assert!(parse_statement("if c {}", FileId::new(0)).is_ok());
+        assert!(parse_statement("x + 1;", FileId::new(0)).is_ok());
+
+        let errors = parse("fn f() { x + 1 y }", FileId::new(0)).unwrap_err();
+        assert!(errors.iter().any(|e| e.message().contains("expected Semicolon")));
+    }
+
+    #[test]
+    fn test_match_block_arm_needs_no_separator() {
+        let expr = parse_expression("match n { 0 => { log(n); 1 } 1 => n * 2, _ => 0 }", FileId::new(0)).unwrap();
+        let ExprKind::Match(match_expr) = &*expr.kind else {
+            panic!("expected match, got {:?}", expr.kind);
+        };
+        assert_eq!(match_expr.arms.len(), 3);
+        assert!(matches!(&*match_expr.arms[0].body.kind, ExprKind::Block(_)));
+        assert!(matches!(&*match_expr.arms[1].body.kind, ExprKind::Binary(_)));
+
+        // The statement form takes the same separators.
+        assert!(parse("fn f() { match n { 0 => { g(); } 1 => h(); _ => i() } }", FileId::new(0)).is_ok());
+
+        // The block is the whole body: a tuple pattern after it starts the
+        // next arm instead of calling the block.
+        let expr = parse_expression("match p { (0, _) => { 1 } (a, b) => a + b }", FileId::new(0)).unwrap();
+        let ExprKind::Match(match_expr) = &*expr.kind else {
+            panic!("expected match, got {:?}", expr.kind);
+        };
+        assert_eq!(match_expr.arms.len(), 2);
+        assert!(matches!(&*match_expr.arms[0].body.kind, ExprKind::Block(_)));
+        assert!(matches!(&match_expr.arms[1].pattern.kind, PatternKind::Tuple(patterns) if patterns.len() == 2));
+
+        // An expression body still needs one before the next arm.
+        let errors = parse_expression("match n { 0 => 1 _ => 2 }", FileId::new(0)).unwrap_err();
+        assert!(errors.iter().any(|e| e.message() == "expected `,` after match arm"));
+    }
+
+    #[test]
+    fn test_parse_control_flow_expressions() {
+        let expr = parse_expression("x.unwrap_or(return)", FileId::new(0)).unwrap();
+        let ExprKind::MethodCall(call) = &*expr.kind else {
+            panic!("expected method call, got {:?}", expr.kind);
+        };
+        assert!(matches!(&*call.args[0].kind, ExprKind::Return(None)));
+
+        let expr = parse_expression("a || return 0", FileId::new(0)).unwrap();
+        let ExprKind::Binary(binary) = &*expr.kind else {
+            panic!("expected binary, got {:?}", expr.kind);
+        };
+        assert!(matches!(&*binary.right.kind, ExprKind::Return(Some(_))));
+
+        let expr = parse_expression("if c { break } else { 1 }", FileId::new(0)).unwrap();
+        let ExprKind::If(if_expr) = &*expr.kind else {
+            panic!("expected if, got {:?}", expr.kind);
+        };
+        assert!(matches!(
+            &if_expr.then_branch.stmts[0].kind,
+            StmtKind::Expr(expr) if matches!(&*expr.kind, ExprKind::Break(None, None))
+        ));
+
+        let stmt = parse_statement("let x = continue;", FileId::new(0)).unwrap();
+        assert!(matches!(
+            &stmt.kind,
+            StmtKind::Let(LetStmt { initializer: Some(init), .. }) if matches!(&*init.kind, ExprKind::Continue(None))
+        ));
+    }
+
+    #[test]
+    fn test_parser_spans_are_never_placeholders() {
+        let source = "trait Shape { const SIDES: i32; fn area() -> f64; }";
+        let program = parse(source, FileId::new(0)).unwrap();
+        assert_eq!((program.span.start, program.span.end), (0, source.len()));
+        let Item::Trait(shape) = &program.items[0] else { panic!("expected a trait") };
+        let starts: Vec<usize> = shape.items.iter().map(|item| match item {
+            TraitItem::Const(item) => item.span.start,
+            TraitItem::Function(sig) => sig.span.start,
+            TraitItem::Type(binding) => binding.span.start,
+        }).collect();
+        assert_eq!(starts, vec![source.find("const").unwrap(), source.find("fn").unwrap()]);
+
+        let errors = parse("struct Point { x: int;", FileId::new(0)).unwrap_err();
+        let eof = errors.iter().find(|e| e.kind() == &ErrorKind::UnexpectedEof).unwrap();
+        assert_eq!(eof.span.unwrap().start, "struct Point { x: int;".len());
+    }
+
+    #[test]
+    fn test_parse_relaxed_sized_bounds() {
+        let source = "fn f<T: ?Sized>(x: &T) { } fn g<T>(x: &T) where T: Clone + ?Sized { }";
+        let program = parse(source, FileId::new(0)).unwrap();
+        let Item::Function(f) = &program.items[0] else { panic!("expected a function") };
+        assert_eq!(f.generics.len(), 1);
+        assert!(f.lifetimes.is_empty());
+        assert!(f.generics[0].bounds[0].relaxed);
+
+        let Item::Function(g) = &program.items[1] else { panic!("expected a function") };
+        let bounds = g.where_clauses[0].predicates[0].bounds();
+        assert_eq!(bounds.iter().map(|bound| bound.relaxed).collect::<Vec<_>>(), vec![false, true]);
+
+        let errors = parse("fn h<T: ?Clone>() { }", FileId::new(0)).unwrap_err();
+        assert!(errors.iter().any(|e| e.message().contains("only `?Sized` is allowed")));
+        let errors = parse("trait Tr: ?Sized { }", FileId::new(0)).unwrap_err();
+        assert!(errors.iter().any(|e| e.message().contains("only allowed on type parameters")));
+    }
+
+    #[test]
+    fn test_parse_lifetime_bounds() {
+        let source = "fn f<'a, T: 'a>(x: &'a T) { } fn g<'a, T: Trait + 'a>(x: &'a T) { }";
+        let program = parse(source, FileId::new(0)).unwrap();
+        let Item::Function(f) = &program.items[0] else { panic!("expected a function") };
+        let bound = &f.generics[0].bounds[0];
+        assert!(bound.trait_ref.is_none());
+        assert_eq!(&*bound.lifetime_bounds[0].name, "a");
+
+        let Item::Function(g) = &program.items[1] else { panic!("expected a function") };
+        let bounds = &g.generics[0].bounds;
+        assert_eq!(bounds.len(), 1);
+        assert_eq!(bounds[0].trait_ref.as_ref().map(|t| t.to_string()), Some("Trait".to_string()));
+        assert_eq!(bounds[0].lifetime_bounds.iter().map(|l| &*l.name).collect::<Vec<_>>(), vec!["a"]);
+    }
+
+    #[test]
+    fn test_assignment_targets_must_be_places() {
+        let program = parse("fn f() { arr[i] += 1; obj.x = 5; *p -= 2; x <<= 1; }", FileId::new(0)).unwrap();
+        let Item::Function(f) = &program.items[0] else { panic!("expected a function") };
+        assert_eq!(f.body.len(), 4);
+        match &f.body[0].kind {
+            StmtKind::Expr(expr) => match &*expr.kind {
+                ExprKind::AssignOp(assign) => {
+                    assert_eq!(assign.op, BinOp::Add);
+                    assert!(matches!(&*assign.left.kind, ExprKind::Index(_)));
+                }
+                other => panic!("expected compound assignment, got {:?}", other),
+            },
+            other => panic!("expected expression statement, got {:?}", other),
+        }
+
+        for source in ["fn f() { (a + b) = 1; }", "fn f() { 1 += 2; }", "fn f() { g() = 3; }"] {
+            let errors = parse(source, FileId::new(0)).unwrap_err();
+            assert!(errors.iter().any(|e| e.message() == "invalid assignment target"), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_parse_self_type() {
+        let program = parse("impl Point { fn new() -> Self { Self::origin() } }", FileId::new(0)).unwrap();
+        let Item::Impl(imp) = &program.items[0] else { panic!("expected impl") };
+        let ImplItem::Function(func) = &imp.items[0] else { panic!("expected method") };
+        assert!(matches!(&*func.return_type.as_ref().unwrap().kind, TypeKind::SelfType));
+
+        let program = parse("impl Iter { fn next(self) -> Option<Self::Item> { } }", FileId::new(0)).unwrap();
+        let Item::Impl(imp) = &program.items[0] else { panic!("expected impl") };
+        let ImplItem::Function(func) = &imp.items[0] else { panic!("expected method") };
+        assert_eq!(func.return_type.as_ref().unwrap().to_string(), "Option<Self::Item>");
+    }
+
+    #[test]
+    fn test_parse_function_type_modifiers() {
+        let function_type = |source: &str| match *parse_const_type(source).kind {
+            TypeKind::Function(function) => function,
+            other => panic!("expected function type, got {:?}", other),
+        };
+        let future = function_type("const F: async fn() -> int = f;");
+        assert!(future.is_async && !future.is_unsafe);
+        assert_eq!(future.abi, None);
+
+        let foreign = function_type("const F: extern \"C\" fn(int) = f;");
+        assert_eq!(foreign.params.len(), 1);
+        assert!(!foreign.is_async);
+
+        let raw = function_type("const F: unsafe extern fn() = f;");
+        assert!(raw.is_unsafe);
+        assert!(raw.abi.is_some());
+    }
+
+    #[test]
+    fn test_error_recovery_always_terminates() {
+        let sources = [
+            "fn f() {",
+            "fn f() { let x = ",
+            "mod m {",
+            "mod m { } } fn g() {}",
+            "mod m { { ; fn",
+            "impl A { fn",
+            "trait T { ",
+            "{ { { } ; }",
+        ];
+        for source in sources {
+            assert!(parse(source, FileId::new(0)).is_err(), "`{}` should not parse", source);
+        }
+    }
+
+    #[test]
+    fn test_parse_impl_of_generic_trait() {
+        let source = "impl From<int> for Celsius { fn from(value: int) -> Celsius { Celsius::new(value) } }";
+        let program = parse(source, FileId::new(0)).unwrap();
+        let Item::Impl(imp) = &program.items[0] else {
+            panic!("expected impl, got {:?}", program.items[0]);
+        };
+        let trait_path = imp.trait_path().unwrap();
+        assert_eq!(&*trait_path.segments[0].ident, "From");
+        match trait_path.segments[0].args.as_slice() {
+            [GenericArg { kind: GenericArgKind::Type(arg), .. }] => assert_eq!(arg.to_string(), "int"),
+            other => panic!("expected one type argument, got {:?}", other),
+        }
+        assert_eq!(imp.type_name.to_string(), "Celsius");
+        assert_eq!(imp.items.len(), 1);
+
+        let errors = parse("impl &Shape for Circle {}", FileId::new(0)).unwrap_err();
+        assert!(errors[0].message().contains("expected a trait"));
+    }
+
+    #[test]
+    fn test_parse_defer_stmt() {
+        let program = parse("fn f() { defer { close(); log(); } work(); }", FileId::new(0)).unwrap();
+        let Item::Function(func) = &program.items[0] else {
+            panic!("expected function");
+        };
+        match &func.body[0].kind {
+            StmtKind::Defer(body) => assert_eq!(body.len(), 2),
+            other => panic!("expected defer, got {:?}", other),
+        }
+        assert!(matches!(func.body[1].kind, StmtKind::Expr(_)));
+
+        assert!(parse("fn f() { defer close(); }", FileId::new(0)).is_err());
+    }
+
+    #[test]
+    fn test_where_lifetime_predicates() {
+        let lifetime_names = |lifetimes: &[Lifetime]| lifetimes.iter().map(|l| l.name.to_string()).collect::<Vec<_>>();
+        let source = "fn f<'a, 'b, 'c>(x: &'a int) where 'a: 'b + 'c { } fn g<'a, 'b, T>(x: &'a T) where T: Clone, 'a: 'b { }";
+        let program = parse(source, FileId::new(0)).unwrap();
+
+        let Item::Function(f) = &program.items[0] else { panic!("expected a function") };
+        let WherePredicate::Lifetime(predicate) = &f.where_clauses[0].predicates[0] else {
+            panic!("expected a lifetime predicate, got {:?}", f.where_clauses[0].predicates[0]);
+        };
+        assert_eq!(&*predicate.lifetime.name, "a");
+        assert_eq!(lifetime_names(&predicate.bounds), vec!["b", "c"]);
+
+        let Item::Function(g) = &program.items[1] else { panic!("expected a function") };
+        let predicates = &g.where_clauses[0].predicates;
+        assert_eq!(predicates.len(), 2);
+        assert!(matches!(&predicates[0], WherePredicate::Type(p) if p.bounds.len() == 1));
+        let WherePredicate::Lifetime(predicate) = &predicates[1] else {
+            panic!("expected a lifetime predicate, got {:?}", predicates[1]);
+        };
+        assert_eq!(lifetime_names(&predicate.bounds), vec!["b"]);
+        assert!(predicates[1].bounds().is_empty());
+    }
+
+    #[test]
+    fn test_parse_struct_literal_update() {
+        let expr = parse_expression("P { x: 1, y, ..base }", FileId::new(0)).unwrap();
+        let ExprKind::Struct(literal) = &*expr.kind else { panic!("expected a struct literal, got {:?}", expr) };
+        assert_eq!(literal.path.to_string(), "P");
+        let names: Vec<&str> = literal.fields.iter().map(|field| &*field.name).collect();
+        assert_eq!(names, vec!["x", "y"]);
+        assert!(matches!(literal.base.as_deref().map(|base| &*base.kind), Some(ExprKind::Identifier(_))));
+
+        // A condition's `{` opens the body, not a literal.
+        let stmt = parse_statement("if ready { go(); }", FileId::new(0)).unwrap();
+        assert!(matches!(&stmt.kind, StmtKind::Expr(expr) if matches!(&*expr.kind, ExprKind::If(_))));
+
+        // Inside call arguments, indices and arrays a literal is unambiguous.
+        let expr = parse_expression("if contains(P { x: 1 }) { 1 } else { 0 }", FileId::new(0)).unwrap();
+        let ExprKind::If(if_expr) = &*expr.kind else { panic!("expected if, got {:?}", expr) };
+        let ExprKind::Call(call) = &*if_expr.condition.kind else { panic!("expected a call condition") };
+        assert!(matches!(&*call.args[0].kind, ExprKind::Struct(_)));
+        assert!(parse_statement("while ps[P { x: 1 }.x] { }", FileId::new(0)).is_ok());
+        assert!(parse_statement("for p in [P { x: 1 }] { }", FileId::new(0)).is_ok());
+
+        let source = "fn f() { let p = P { .. }; }";
+        let errors = parse(source, FileId::new(0)).unwrap_err();
+        let error = errors.iter()
+            .find(|e| e.message() == "expected base expression after `..`")
+            .expect("expected a missing-base error");
+        let dots = source.find("..").unwrap();
+        assert_eq!(error.span.map(|span| (span.start, span.end)), Some((dots, dots + 2)));
+    }
+
+    fn parse_const_type(source: &str) -> Type {
+        let file_id = FileId::new(0);
+        let program = parse(source, file_id).unwrap();
+        match &program.items[0] {
+            Item::Constant(constant) => constant.ty.clone().unwrap(),
+            other => panic!("expected constant, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_array_literal_length() {
+        let ty = parse_const_type("const A: [int; 16] = [];");
+        match &*ty.kind {
+            TypeKind::Array(_, len) => assert_eq!(len.as_literal(), Some(16)),
+            other => panic!("expected array type, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_array_const_param_length() {
+        let ty = parse_const_type("const A: [int; N] = [];");
+        match &*ty.kind {
+            TypeKind::Array(_, ArrayLen::Const(expr)) => {
+                assert!(matches!(&*expr.kind, ExprKind::Path(_) | ExprKind::Identifier(_)));
+            }
+            other => panic!("expected array type, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_array_expression_length() {
+        let ty = parse_const_type("const A: [int; 2 * 8] = [];");
+        match &*ty.kind {
+            TypeKind::Array(_, len @ ArrayLen::Const(expr)) => {
+                assert!(matches!(&*expr.kind, ExprKind::Binary(..)));
+                assert_eq!(len.as_literal(), None);
+            }
+            other => panic!("expected array type, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_const_generic_array_length() {
+        let program = parse("fn f<T, const N: usize>() -> [T; N + 1] {}", FileId::new(0)).unwrap();
+        let Item::Function(func) = &program.items[0] else {
+            panic!("expected function");
+        };
+        assert_eq!(func.generics.len(), 2);
+        assert!(func.generics[0].const_ty.is_none());
+        assert_eq!(func.generics[1].const_ty.as_ref().map(|ty| ty.to_string()).as_deref(), Some("usize"));
+
+        match &*func.return_type.as_ref().unwrap().kind {
+            TypeKind::Array(_, ArrayLen::Const(expr)) => {
+                assert!(matches!(&*expr.kind, ExprKind::Binary(BinaryExpr { op: BinOp::Add, .. })));
+            }
+            other => panic!("expected array type, got {:?}", other),
+        }
+    }
+
+    fn parse_match_pattern(source: &str) -> PatternKind {
+        let file_id = FileId::new(0);
+        let program = parse(source, file_id).unwrap();
+        let body = match &program.items[0] {
+            Item::Function(func) => func.body.clone(),
+            other => panic!("expected function, got {:?}", other),
+        };
+        let expr = body.iter().find_map(|stmt| match &stmt.kind {
+            StmtKind::Expr(expr) => Some(expr.clone()),
+            _ => None,
+        }).unwrap();
+        match *expr.kind {
+            ExprKind::Match(match_expr) => match_expr.arms[0].pattern.kind.clone(),
+            other => panic!("expected match, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_slice_pattern_trailing_rest() {
+        let kind = parse_match_pattern("fn f(xs: [int]) { match xs { [a, ..] => a, _ => 0 } }");
+        match kind {
+            PatternKind::Slice(before, Some(rest), after) => {
+                assert_eq!(before.len(), 1);
+                assert!(rest.binding.is_none());
+                assert!(after.is_empty());
+            }
+            other => panic!("expected slice pattern, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_slice_pattern_leading_rest() {
+        let kind = parse_match_pattern("fn f(xs: [int]) { match xs { [.., z] => z, _ => 0 } }");
+        match kind {
+            PatternKind::Slice(before, Some(_), after) => {
+                assert!(before.is_empty());
+                assert_eq!(after.len(), 1);
+            }
+            other => panic!("expected slice pattern, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_slice_pattern_named_rest() {
+        let kind = parse_match_pattern("fn f(xs: [int]) { match xs { [a, ..mid, z] => a, _ => 0 } }");
+        match kind {
+            PatternKind::Slice(before, Some(rest), after) => {
+                assert_eq!(before.len(), 1);
+                assert!(rest.binding.is_some());
+                assert_eq!(after.len(), 1);
+            }
+            other => panic!("expected slice pattern, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_program_test_functions() {
+        let source = r#"
+            @test
+            fn adds() { }
+
+            fn helper() { }
+
+            @test
+            fn subtracts() { }
+        "#;
+        let file_id = FileId::new(0);
+        let program = parse(source, file_id).unwrap();
+        let tests = program.test_functions();
+        assert_eq!(tests.len(), 2);
+        assert!(tests.iter().all(|func| func.has_attribute("test")));
+        assert!(tests.iter().all(|func| &*func.name != "helper"));
+    }
+
+    #[test]
+    fn test_static_requires_initializer() {
+        let file_id = FileId::new(0);
+        let errors = parse("static COUNTER: int;", file_id).unwrap_err();
+        assert!(errors.iter().any(|e| e.message() == "static `COUNTER` must have an initializer"));
+    }
+
+    #[test]
+    fn test_extern_static_without_initializer() {
+        let source = r#"
+            extern "C" {
+                static errno: int;
+                static mut environ: *u8;
+            }
+        "#;
+        let file_id = FileId::new(0);
+        let program = parse(source, file_id).unwrap();
+        match &program.items[0] {
+            Item::Extern(block) => {
+                assert_eq!(block.items.len(), 2);
+                assert!(!block.items[0].is_mut);
+                assert!(block.items[1].is_mut);
+            }
+            other => panic!("expected extern block, got {:?}", other),
+        }
+    }
+
+    fn parse_const_value(source: &str) -> Result<Expr, Vec<ChimError>> {
+        let file_id = FileId::new(0);
+        let program = parse(source, file_id)?;
+        match &program.items[0] {
+            Item::Constant(constant) => Ok(constant.value.clone()),
+            other => panic!("expected constant, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_float_literal() {
+        let value = parse_const_value("const RATE: float = 2.5;").unwrap();
+        match &*value.kind {
+            ExprKind::Literal(Literal { kind: LiteralKind::Float(f), .. }) => assert_eq!(*f, 2.5),
+            other => panic!("expected float literal, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_float_literal_out_of_range() {
+        let errors = parse_const_value("const BIG: float = 1e400;").unwrap_err();
+        assert!(errors.iter().any(|e| e.kind() == &ErrorKind::InvalidLiteral
+            && e.message().contains("out of range")));
+    }
+
+    #[test]
+    fn test_float_literal_multiple_decimal_points() {
+        let errors = parse_const_value("const V: float = 1.2.3;").unwrap_err();
+        assert!(errors.iter().any(|e| e.message() == "invalid float literal: multiple decimal points"));
+    }
+
+    #[test]
+    fn test_literal_values_come_from_source() {
+        let file_id = FileId::new(0);
+        let value = |source: &str| match *parse_expression(source, file_id).unwrap().kind {
+            ExprKind::Literal(Literal { kind: LiteralKind::String(text), .. }) => text.to_string(),
+            ExprKind::Literal(Literal { kind: LiteralKind::Char(c), .. }) => c.to_string(),
+            other => panic!("{other:?}"),
+        };
+        assert_eq!(value(r#""a\tb\n""#), "a\tb\n");
+        assert_eq!(value(r"'\''"), "'");
+    }
+
+    #[test]
+    fn test_parse_expression_entry_point() {
+        let file_id = FileId::new(0);
+        let expr = parse_expression("1 + 2", file_id).unwrap();
+        assert!(matches!(&*expr.kind, ExprKind::Binary(_)));
+        assert!(parse_expression("1 + 2 3", file_id).is_err());
+    }
+
+    #[test]
+    fn test_parse_statement_entry_point() {
+        let file_id = FileId::new(0);
+        let stmt = parse_statement("let x = 3;", file_id).unwrap();
+        assert!(matches!(stmt.kind, StmtKind::Let(_)));
+    }
+
+    #[test]
+    fn test_parse_closure_argument_stops_at_comma() {
+        let file_id = FileId::new(0);
+        let expr = parse_expression("apply(xs, |x| x + 1)", file_id).unwrap();
+        match &*expr.kind {
+            ExprKind::Call(call) => {
+                assert_eq!(call.args.len(), 2);
+                match &*call.args[1].kind {
+                    ExprKind::Closure(closure) => {
+                        assert_eq!(closure.params.len(), 1);
+                        assert!(matches!(&*closure.body.kind, ExprKind::Binary(_)));
+                    }
+                    other => panic!("expected closure, got {:?}", other),
+                }
+            }
+            other => panic!("expected call, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_tuple_index() {
+        let file_id = FileId::new(0);
+        let expr = parse_expression("t.0", file_id).unwrap();
+        match &*expr.kind {
+            ExprKind::FieldAccess(access) => {
+                assert_eq!(&*access.field, "0");
+                assert!(matches!(&*access.expr.kind, ExprKind::Identifier(_)));
+            }
+            other => panic!("expected field access, got {:?}", other),
+        }
+
+        let expr = parse_expression("t.0.1", file_id).unwrap();
+        match &*expr.kind {
+            ExprKind::FieldAccess(outer) => {
+                assert_eq!(&*outer.field, "1");
+                match &*outer.expr.kind {
+                    ExprKind::FieldAccess(inner) => assert_eq!(&*inner.field, "0"),
+                    other => panic!("expected inner field access, got {:?}", other),
+                }
+            }
+            other => panic!("expected field access, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_reference_lifetime() {
+        let file_id = FileId::new(0);
+        let program = parse("fn first<'a>(x: &'a int) -> &'a int { x }", file_id).unwrap();
+        match &program.items[0] {
+            Item::Function(func) => {
+                assert_eq!(func.lifetimes.len(), 1);
+                assert_eq!(&*func.lifetimes[0].name, "a");
+                match &*func.params[0].ty.kind {
+                    TypeKind::Reference(Some(lifetime), _, Mutability::Immutable) => {
+                        assert_eq!(&*lifetime.name, "a");
+                    }
+                    other => panic!("expected reference with lifetime, got {:?}", other),
+                }
+            }
+            other => panic!("expected function, got {:?}", other),
+        }
+
+        let expr = parse_expression("'a'", file_id).unwrap();
+        assert!(matches!(&*expr.kind, ExprKind::Literal(Literal { kind: LiteralKind::Char(_), .. })));
+    }
+
+    #[test]
+    fn test_parse_binding_modes() {
+        let mode_of = |source: &str| match parse_match_pattern(source) {
+            PatternKind::Identifier(_, mode) => mode,
+            other => panic!("expected binding, got {:?}", other),
+        };
+        assert_eq!(
+            mode_of("fn f() { match v { mut x => x } }"),
+            BindingMode { is_mut: true, by_ref: false },
+        );
+        assert_eq!(
+            mode_of("fn f() { match v { ref x => x } }"),
+            BindingMode { is_mut: false, by_ref: true },
+        );
+        assert_eq!(
+            mode_of("fn f() { match v { ref mut x => x } }"),
+            BindingMode { is_mut: true, by_ref: true },
+        );
+    }
+
+    #[test]
+    fn test_parse_mut_tuple_pattern_marks_all_bindings() {
+        let file_id = FileId::new(0);
+        let stmt = parse_statement("let mut (a, b) = pair;", file_id).unwrap();
+        match stmt.kind {
+            StmtKind::Let(let_stmt) => match let_stmt.pattern.kind {
+                PatternKind::Tuple(patterns) => {
+                    for pattern in patterns {
+                        assert!(matches!(pattern.kind, PatternKind::Identifier(_, BindingMode { is_mut: true, by_ref: false })));
+                    }
+                }
+                other => panic!("expected tuple pattern, got {:?}", other),
+            },
+            other => panic!("expected let, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_range_forms() {
+        let file_id = FileId::new(0);
+        let range = |source: &str| match *parse_expression(source, file_id).unwrap().kind {
+            ExprKind::Range(range) => (range.start.is_some(), range.end.is_some(), range.inclusive),
+            other => panic!("expected range for `{}`, got {:?}", source, other),
+        };
+        assert_eq!(range(".."), (false, false, false));
+        assert_eq!(range("..b"), (false, true, false));
+        assert_eq!(range("..=b"), (false, true, true));
+        assert_eq!(range("a.."), (true, false, false));
+        assert_eq!(range("a..b"), (true, true, false));
+        assert_eq!(range("a..=b"), (true, true, true));
+        assert_eq!(range("1..3"), (true, true, false));
+        assert!(parse_expression("a..=", file_id).is_err());
+    }
+
+    #[test]
+    fn test_parse_range_in_index() {
+        let file_id = FileId::new(0);
+        for (source, has_start) in [("arr[..2]", false), ("arr[1..3]", true)] {
+            match *parse_expression(source, file_id).unwrap().kind {
+                ExprKind::Index(index) => match &*index.index.kind {
+                    ExprKind::Range(range) => {
+                        assert_eq!(range.start.is_some(), has_start);
+                        assert!(range.end.is_some());
+                    }
+                    other => panic!("expected range index, got {:?}", other),
+                },
+                other => panic!("expected index, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_method_call_chain() {
+        let expr = parse_expression("xs.map(f).filter(g)", FileId::new(0)).unwrap();
+        let ExprKind::MethodCall(filter) = &*expr.kind else {
+            panic!("expected method call, got {:?}", expr);
+        };
+        assert_eq!(&*filter.method, "filter");
+        assert_eq!(filter.args.len(), 1);
+        match &*filter.expr.kind {
+            ExprKind::MethodCall(map) => {
+                assert_eq!(&*map.method, "map");
+                assert!(matches!(&*map.expr.kind, ExprKind::Identifier(_)));
+            }
+            other => panic!("expected method call, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_try_versus_ternary() {
+        let file_id = FileId::new(0);
+
+        let expr = parse_expression("f(a?)", file_id).unwrap();
+        match &*expr.kind {
+            ExprKind::Call(call) => assert!(matches!(&*call.args[0].kind, ExprKind::Try(_))),
+            other => panic!("expected call, got {:?}", other),
+        }
+
+        let expr = parse_expression("xs[i?]", file_id).unwrap();
+        match &*expr.kind {
+            ExprKind::Index(index) => assert!(matches!(&*index.index.kind, ExprKind::Try(_))),
+            other => panic!("expected index, got {:?}", other),
+        }
+
+        let expr = parse_expression("a ? b : c", file_id).unwrap();
+        assert!(matches!(&*expr.kind, ExprKind::Ternary(_)));
+
+        let expr = parse_expression("a?.len()", file_id).unwrap();
+        match &*expr.kind {
+            ExprKind::MethodCall(call) => assert!(matches!(&*call.expr.kind, ExprKind::Try(_))),
+            other => panic!("expected method call, got {:?}", other),
+        }
+
+        // The `:` of a later annotation does not make the `?` a ternary.
+        let program = parse("fn f() { if check()? { return; } let n: int = 1; }", file_id).unwrap();
+        let Item::Function(f) = &program.items[0] else { panic!("expected a function") };
+        assert_eq!(f.body.len(), 2);
+        match &f.body[0].kind {
+            StmtKind::Expr(expr) => match &*expr.kind {
+                ExprKind::If(if_expr) => assert!(matches!(&*if_expr.condition.kind, ExprKind::Try(_))),
+                other => panic!("expected if, got {:?}", other),
+            },
+            other => panic!("expected if statement, got {:?}", other),
+        }
+        assert!(matches!(&f.body[1].kind, StmtKind::Let(_)));
+    }
+
+    #[test]
+    fn test_top_level_let_is_skipped() {
+        let file_id = FileId::new(0);
+        let (program, errors) = parse_partial("let x = (1, 2);\nfn main() { }", file_id);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "items cannot start with variable declarations");
+        assert!(errors[0].notes.iter().any(|note| note.contains("function body")));
+        assert!(matches!(program.unwrap().items.as_slice(), [Item::Function(func)] if &*func.name == "main"));
+
+        // Without its `;`, the declaration ends at the next item.
+        let (program, errors) = parse_partial("let x = 1\nconst N: int = 2;\nfn main() { }", file_id);
+        assert_eq!(errors.len(), 1);
+        let items = program.unwrap().items;
+        assert!(matches!(items.as_slice(), [Item::Constant(_), Item::Function(func)] if &*func.name == "main"));
+    }
+
+    #[test]
+    fn test_parse_async_blocks() {
+        let file_id = FileId::new(0);
+
+        let expr = parse_expression("async { 1 }", file_id).unwrap();
+        match &*expr.kind {
+            ExprKind::AsyncBlock(block) => {
+                assert!(!block.is_move);
+                assert_eq!(block.body.stmts.len(), 1);
+            }
+            other => panic!("expected async block, got {:?}", other),
+        }
+
+        let expr = parse_expression("async move { x }", file_id).unwrap();
+        assert!(matches!(&*expr.kind, ExprKind::AsyncBlock(AsyncBlockExpr { is_move: true, .. })));
+    }
+
+    #[test]
+    fn test_labeled_break_with_value() {
+        let file_id = FileId::new(0);
+        let expr = parse_expression("'l: loop { loop { break 'l 5; } }", file_id).unwrap();
+        let body = match &*expr.kind {
+            ExprKind::Loop(LoopExpr { label: Some(label), body }) => {
+                assert_eq!(&**label, "l");
+                body
+            }
+            other => panic!("expected labeled loop, got {:?}", other),
+        };
+        let inner = match &body.stmts[0].kind {
+            StmtKind::Loop(inner) => inner,
+            other => panic!("expected inner loop, got {:?}", other),
+        };
+        match &inner.body[0].kind {
+            StmtKind::Expr(expr) => match &*expr.kind {
+                ExprKind::Break(Some(label), Some(value)) => {
+                    assert_eq!(&*label.name, "l");
+                    assert!(matches!(&*value.kind, ExprKind::Literal(Literal { kind: LiteralKind::Int(5), .. })));
+                }
+                other => panic!("expected labeled break, got {:?}", other),
+            },
+            other => panic!("expected break statement, got {:?}", other),
+        }
+
+        assert!(parse("fn main() { let v = 'l: loop { break 'l 5; }; }", file_id).is_ok());
+    }
+
+    #[test]
+    fn test_break_to_unknown_label() {
+        let file_id = FileId::new(0);
+        let errors = parse("fn main() { 'outer: loop { break 'inner; } }", file_id).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind(), &ErrorKind::UndefinedIdentifier);
+        assert!(errors[0].message().contains("'inner"));
+    }
+
+    #[test]
+    fn test_parse_guard_statement() {
+        let file_id = FileId::new(0);
+        let stmt = parse_statement("guard x > 0 else { return; }", file_id).unwrap();
+        match &stmt.kind {
+            StmtKind::Guard(guard) => {
+                assert!(matches!(&*guard.condition.kind, ExprKind::Binary(BinaryExpr { op: BinOp::Gt, .. })));
+                assert!(matches!(guard.else_branch.as_slice(), [Stmt { kind: StmtKind::Return(None), .. }]));
+            }
+            other => panic!("expected guard, got {:?}", other),
+        }
+
+        assert!(parse_statement("guard x > 0 { return; }", file_id).is_err());
+    }
+
+    #[test]
+    fn test_pattern_and_range_are_identifiers() {
+        let file_id = FileId::new(0);
+        assert!(parse("fn main() { let range = 1; let pattern = range + 2; }", file_id).is_ok());
+    }
 }
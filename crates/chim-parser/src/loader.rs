@@ -0,0 +1,137 @@
+use crate::parse;
+use chim_ast::{Item, Program};
+use chim_error::{ChimError, ErrorKind};
+use chim_span::{SourceMap, Span};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+const SOURCE_EXTENSION: &str = "chim";
+
+/// Loads a multi-file program by following `mod name;` declarations.
+///
+/// `mod foo;` declared in `dir/main.chim` or `dir/mod.chim` resolves to `dir/foo.chim`
+/// or `dir/foo/mod.chim`; declared in `dir/bar.chim` it resolves under `dir/bar/`.
+/// Every loaded file is registered in the `SourceMap` so spans can be rendered.
+pub struct ModuleLoader<'a> {
+    source_map: &'a mut SourceMap,
+    loading: Vec<PathBuf>,
+    errors: Vec<ChimError>,
+}
+
+impl<'a> ModuleLoader<'a> {
+    pub fn new(source_map: &'a mut SourceMap) -> Self {
+        ModuleLoader {
+            source_map,
+            loading: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn load(&mut self, root: &Path) -> Result<Program, Vec<ChimError>> {
+        let dir = root.parent().map(Path::to_path_buf).unwrap_or_default();
+        let program = self.load_file(root, &dir, None);
+
+        match program {
+            Some(program) if self.errors.is_empty() => Ok(program),
+            _ => Err(std::mem::take(&mut self.errors)),
+        }
+    }
+
+    fn load_file(&mut self, path: &Path, child_dir: &Path, mod_span: Option<Span>) -> Option<Program> {
+        let key = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        if self.loading.contains(&key) {
+            let mut error = ChimError::new(
+                ErrorKind::Io,
+                format!("cyclic module reference to `{}`", path.display()),
+            );
+            if let Some(span) = mod_span {
+                error = error.with_span(span);
+            }
+            self.errors.push(error);
+            return None;
+        }
+
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) => {
+                let mut error = ChimError::new(
+                    ErrorKind::Io,
+                    format!("failed to read `{}`: {}", path.display(), e),
+                );
+                if let Some(span) = mod_span {
+                    error = error.with_span(span);
+                }
+                self.errors.push(error);
+                return None;
+            }
+        };
+
+        let name: Arc<str> = Arc::from(path.to_string_lossy().as_ref());
+        let file_id = self.source_map.add_file(name, Arc::from(content.as_str()));
+
+        let mut program = match parse(&content, file_id) {
+            Ok(program) => program,
+            Err(errors) => {
+                self.errors.extend(errors);
+                return None;
+            }
+        };
+
+        self.loading.push(key);
+        self.load_items(&mut program.items, child_dir);
+        self.loading.pop();
+
+        Some(program)
+    }
+
+    fn load_items(&mut self, items: &mut [Item], dir: &Path) {
+        for item in items {
+            let module = match item {
+                Item::Mod(module) => module,
+                _ => continue,
+            };
+
+            let module_dir = dir.join(&*module.name);
+            if module.is_inline {
+                self.load_items(&mut module.items, &module_dir);
+                continue;
+            }
+
+            let path = match Self::resolve_module_path(dir, &module.name) {
+                Some(path) => path,
+                None => {
+                    self.errors.push(ChimError::new(
+                        ErrorKind::Io,
+                        format!("file not found for module `{}`", module.name),
+                    ).with_span(module.span).with_note(format!(
+                        "expected `{}.{ext}` or `{}/mod.{ext}` in `{}`",
+                        module.name,
+                        module.name,
+                        dir.display(),
+                        ext = SOURCE_EXTENSION,
+                    )));
+                    continue;
+                }
+            };
+
+            if let Some(loaded) = self.load_file(&path, &module_dir, Some(module.span)) {
+                module.items = loaded.items;
+                module.file_path = Some(path.to_string_lossy().into_owned());
+            }
+        }
+    }
+
+    pub fn resolve_module_path(dir: &Path, name: &str) -> Option<PathBuf> {
+        let file = dir.join(format!("{}.{}", name, SOURCE_EXTENSION));
+        if file.is_file() {
+            return Some(file);
+        }
+
+        let mod_file = dir.join(name).join(format!("mod.{}", SOURCE_EXTENSION));
+        if mod_file.is_file() {
+            return Some(mod_file);
+        }
+
+        None
+    }
+}
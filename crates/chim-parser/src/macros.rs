@@ -0,0 +1,310 @@
+use crate::parse_expression;
+use chim_ast::{
+    Expr, Ident, Item, Macro, MacroBody, MacroCall, MacroDelimiter, MacroExpansion, MacroFragment, MacroPattern,
+    MacroRepeatKind, MacroToken, MapSpans, Program,
+};
+use chim_error::{ChimError, ErrorKind};
+use std::collections::HashMap;
+
+/// Expands invocations of declarative macros by matching the call's tokens
+/// against each rule of the macro in order and substituting the bindings of
+/// the first rule that matches into its expansion.
+#[derive(Debug, Default)]
+pub struct MacroExpander {
+    macros: HashMap<Ident, Macro>,
+}
+
+/// What a matcher variable captured: its tokens, or one binding per
+/// repetition when it appears inside `$( ... )`.
+#[derive(Debug, Clone, PartialEq)]
+enum Binding {
+    Tokens(Vec<MacroToken>),
+    Repeated(Vec<Binding>),
+}
+
+type Bindings = HashMap<Ident, Binding>;
+
+impl MacroExpander {
+    pub fn new() -> Self {
+        MacroExpander::default()
+    }
+
+    /// An expander knowing every macro declared in `program`, including in
+    /// inline modules.
+    pub fn from_program(program: &Program) -> Self {
+        let mut expander = MacroExpander::new();
+        expander.collect(&program.items);
+        expander
+    }
+
+    fn collect(&mut self, items: &[Item]) {
+        for item in items {
+            match item {
+                Item::Macro(macro_def) => self.define(macro_def.clone()),
+                Item::Mod(module) => self.collect(&module.items),
+                _ => {}
+            }
+        }
+    }
+
+    /// Adds `macro_def`, replacing an earlier macro of the same name.
+    pub fn define(&mut self, macro_def: Macro) {
+        self.macros.insert(macro_def.name.clone(), macro_def);
+    }
+
+    /// The tokens `call` expands to.
+    pub fn expand_tokens(&self, call: &MacroCall) -> Result<Vec<MacroToken>, ChimError> {
+        let error = |message: String| ChimError::new(ErrorKind::UndefinedIdentifier, message).with_span(call.span);
+        let macro_def = self.macros.get(&call.name).ok_or_else(|| error(format!("cannot find macro `{}!`", call.name)))?;
+        let MacroBody::Rules(rules) = &macro_def.body else {
+            return Err(error(format!("`{}!` is a procedural macro and cannot be expanded here", call.name)));
+        };
+
+        for rule in rules {
+            let mut bindings = Bindings::new();
+            let mut position = 0;
+            if !match_pattern(&rule.pattern, &call.tokens, &mut position, &mut bindings)
+                || position != call.tokens.len()
+            {
+                continue;
+            }
+            return match &rule.expansion {
+                MacroExpansion::Template(template) => {
+                    let mut tokens = Vec::new();
+                    transcribe(template, &bindings, &mut tokens).map_err(|message| {
+                        ChimError::new(ErrorKind::Parser, message).with_span(call.span)
+                    })?;
+                    Ok(tokens)
+                }
+                MacroExpansion::Tokens(tokens) => Ok(tokens.clone()),
+                MacroExpansion::Expr(_) => Err(error(format!(
+                    "`{}!` has an expression body; write it as a `{{ ... }}` template to expand it",
+                    call.name
+                ))),
+            };
+        }
+
+        Err(ChimError::new(
+            ErrorKind::UnexpectedToken,
+            format!("no rule of `{}!` matches these arguments", call.name),
+        ).with_span(call.span))
+    }
+
+    /// Expands `call` and parses the result as an expression. The expanded
+    /// text exists nowhere in the source, so every span in the result, and in
+    /// any error, is the call's.
+    pub fn expand_expr(&self, call: &MacroCall) -> Result<Expr, Vec<ChimError>> {
+        let tokens = self.expand_tokens(call).map_err(|error| vec![error])?;
+        let mut expr = parse_expression(&render_tokens(&tokens), call.span.file_id)
+            .map_err(|errors| errors.into_iter().map(|error| error.with_span(call.span)).collect::<Vec<_>>())?;
+        expr.map_spans(&mut |span| *span = call.span);
+        Ok(expr)
+    }
+}
+
+fn match_pattern(pattern: &MacroPattern, tokens: &[MacroToken], position: &mut usize, bindings: &mut Bindings) -> bool {
+    match pattern {
+        MacroPattern::Token(expected) => {
+            if tokens.get(*position) == Some(expected) {
+                *position += 1;
+                true
+            } else {
+                false
+            }
+        }
+        MacroPattern::Sequence(patterns) => match_sequence(patterns, tokens, position, bindings),
+        MacroPattern::Group(delimiter, patterns) => match tokens.get(*position) {
+            Some(MacroToken::Group(found, inner)) if found == delimiter => {
+                let mut inner_position = 0;
+                if match_sequence(patterns, inner, &mut inner_position, bindings) && inner_position == inner.len() {
+                    *position += 1;
+                    true
+                } else {
+                    false
+                }
+            }
+            _ => false,
+        },
+        MacroPattern::Choice(alternatives) => alternatives.iter().any(|alternative| {
+            let mut attempt_position = *position;
+            let mut attempt = bindings.clone();
+            let matched = match_pattern(alternative, tokens, &mut attempt_position, &mut attempt);
+            if matched {
+                *position = attempt_position;
+                *bindings = attempt;
+            }
+            matched
+        }),
+        MacroPattern::Var(name, fragment) => {
+            let Some(end) = fragment_end(fragment.unwrap_or(MacroFragment::Tt), tokens, *position) else {
+                return false;
+            };
+            let mut captured = tokens[*position..end].to_vec();
+            // An expression keeps its own precedence wherever it is pasted:
+            // `$x * $x` with `1 + 2` must multiply the sums.
+            if *fragment == Some(MacroFragment::Expr) && captured.len() > 1 {
+                captured = vec![MacroToken::Group(MacroDelimiter::Paren, captured)];
+            }
+            bindings.insert(name.clone(), Binding::Tokens(captured));
+            *position = end;
+            true
+        }
+        MacroPattern::Repeat(patterns, separator, kind) => {
+            let mut iterations: Vec<Bindings> = Vec::new();
+            let mut end = *position;
+            loop {
+                if *kind == MacroRepeatKind::ZeroOrOne && !iterations.is_empty() {
+                    break;
+                }
+                let mut attempt_position = end;
+                if !iterations.is_empty() {
+                    if let Some(separator) = separator {
+                        if tokens.get(attempt_position) != Some(separator) {
+                            break;
+                        }
+                        attempt_position += 1;
+                    }
+                }
+                let mut iteration = Bindings::new();
+                if !match_sequence(patterns, tokens, &mut attempt_position, &mut iteration) || attempt_position == end {
+                    break;
+                }
+                end = attempt_position;
+                iterations.push(iteration);
+            }
+            if *kind == MacroRepeatKind::OneOrMore && iterations.is_empty() {
+                return false;
+            }
+
+            let mut names = Vec::new();
+            collect_vars(patterns, &mut names);
+            for name in names {
+                let repeated = iterations
+                    .iter_mut()
+                    .filter_map(|iteration| iteration.remove(&name))
+                    .collect();
+                bindings.insert(name, Binding::Repeated(repeated));
+            }
+            *position = end;
+            true
+        }
+    }
+}
+
+fn match_sequence(patterns: &[MacroPattern], tokens: &[MacroToken], position: &mut usize, bindings: &mut Bindings) -> bool {
+    patterns.iter().all(|pattern| match_pattern(pattern, tokens, position, bindings))
+}
+
+/// Where a fragment starting at `start` ends, or `None` if none starts there.
+fn fragment_end(fragment: MacroFragment, tokens: &[MacroToken], start: usize) -> Option<usize> {
+    let token = tokens.get(start)?;
+    match fragment {
+        MacroFragment::Tt => Some(start + 1),
+        MacroFragment::Ident => matches!(token, MacroToken::Ident(_)).then_some(start + 1),
+        MacroFragment::Literal => matches!(token, MacroToken::Literal(_)).then_some(start + 1),
+        MacroFragment::Expr => {
+            let end = tokens[start..]
+                .iter()
+                .position(|token| match token {
+                    MacroToken::Punctuation(c) => matches!(c, ',' | ';'),
+                    MacroToken::Operator(op) => &**op == "=>",
+                    _ => false,
+                })
+                .map_or(tokens.len(), |offset| start + offset);
+            (end > start).then_some(end)
+        }
+    }
+}
+
+fn collect_vars(patterns: &[MacroPattern], names: &mut Vec<Ident>) {
+    for pattern in patterns {
+        match pattern {
+            MacroPattern::Var(name, _) => names.push(name.clone()),
+            MacroPattern::Sequence(patterns)
+            | MacroPattern::Choice(patterns)
+            | MacroPattern::Group(_, patterns)
+            | MacroPattern::Repeat(patterns, _, _) => collect_vars(patterns, names),
+            MacroPattern::Token(_) => {}
+        }
+    }
+}
+
+fn transcribe(template: &[MacroPattern], bindings: &Bindings, out: &mut Vec<MacroToken>) -> Result<(), String> {
+    for pattern in template {
+        match pattern {
+            MacroPattern::Token(token) => out.push(token.clone()),
+            MacroPattern::Sequence(patterns) => transcribe(patterns, bindings, out)?,
+            MacroPattern::Group(delimiter, patterns) => {
+                let mut inner = Vec::new();
+                transcribe(patterns, bindings, &mut inner)?;
+                out.push(MacroToken::Group(*delimiter, inner));
+            }
+            MacroPattern::Var(name, _) => match bindings.get(name) {
+                Some(Binding::Tokens(tokens)) => out.extend(tokens.iter().cloned()),
+                Some(Binding::Repeated(_)) => {
+                    return Err(format!("variable `${}` is still repeating here; wrap it in `$( ... )*`", name));
+                }
+                None => return Err(format!("unknown macro variable `${}`", name)),
+            },
+            MacroPattern::Repeat(patterns, separator, _) => {
+                let mut names = Vec::new();
+                collect_vars(patterns, &mut names);
+                let mut count = None;
+                for name in &names {
+                    if let Some(Binding::Repeated(iterations)) = bindings.get(name) {
+                        match count {
+                            Some(count) if count != iterations.len() => {
+                                return Err(format!("variable `${}` repeats a different number of times", name));
+                            }
+                            _ => count = Some(iterations.len()),
+                        }
+                    }
+                }
+                let Some(count) = count else {
+                    return Err("repetition in expansion contains no repeating variable".to_string());
+                };
+                for index in 0..count {
+                    if index > 0 {
+                        out.extend(separator.clone());
+                    }
+                    let mut iteration = bindings.clone();
+                    for name in &names {
+                        if let Some(Binding::Repeated(iterations)) = bindings.get(name) {
+                            iteration.insert(name.clone(), iterations[index].clone());
+                        }
+                    }
+                    transcribe(patterns, &iteration, out)?;
+                }
+            }
+            MacroPattern::Choice(_) => return Err("alternatives are not allowed in a macro expansion".to_string()),
+        }
+    }
+    Ok(())
+}
+
+/// Source text for `tokens`, with a space between tokens so they lex back
+/// the same way.
+fn render_tokens(tokens: &[MacroToken]) -> String {
+    let mut text = String::new();
+    for token in tokens {
+        if !text.is_empty() {
+            text.push(' ');
+        }
+        match token {
+            MacroToken::Ident(name) | MacroToken::Operator(name) => text.push_str(name),
+            MacroToken::Literal(literal) => text.push_str(literal),
+            MacroToken::Punctuation(c) => text.push(*c),
+            MacroToken::Group(delimiter, inner) => {
+                let (open, close) = match delimiter {
+                    MacroDelimiter::Paren => ('(', ')'),
+                    MacroDelimiter::Bracket => ('[', ']'),
+                    MacroDelimiter::Brace => ('{', '}'),
+                };
+                text.push(open);
+                text.push_str(&render_tokens(inner));
+                text.push(close);
+            }
+        }
+    }
+    text
+}
@@ -0,0 +1,263 @@
+use chim_ast::{Attribute, Expr, ExprKind, Field, ImplItem, Item, LiteralKind, Program};
+use chim_error::{ChimError, ErrorKind};
+use std::collections::HashSet;
+
+/// The configuration `@cfg(...)` predicates are evaluated against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CfgOptions {
+    pub target_os: String,
+    pub target_arch: String,
+    pub features: HashSet<String>,
+    /// Bare names such as `test` that are simply set or not.
+    pub flags: HashSet<String>,
+}
+
+impl CfgOptions {
+    pub fn new(target_os: &str, target_arch: &str) -> Self {
+        CfgOptions {
+            target_os: target_os.to_string(),
+            target_arch: target_arch.to_string(),
+            features: HashSet::new(),
+            flags: HashSet::new(),
+        }
+    }
+
+    /// The platform the compiler itself runs on.
+    pub fn host() -> Self {
+        CfgOptions::new(std::env::consts::OS, std::env::consts::ARCH)
+    }
+
+    pub fn with_feature(mut self, feature: &str) -> Self {
+        self.features.insert(feature.to_string());
+        self
+    }
+
+    pub fn with_flag(mut self, flag: &str) -> Self {
+        self.flags.insert(flag.to_string());
+        self
+    }
+}
+
+/// Evaluates the predicate of a `@cfg(...)` attribute: `key = "value"` for
+/// `target_os`, `target_arch` and `feature`, a bare flag name, or
+/// `all(...)`, `any(...)` and `not(...)` over those.
+pub fn eval_cfg(attr: &Attribute, options: &CfgOptions) -> Result<bool, ChimError> {
+    match attr.args.as_slice() {
+        [arg] => eval_predicate(&arg.expr, options),
+        _ => Err(ChimError::new(
+            ErrorKind::InvalidAttribute,
+            "`cfg` takes exactly one predicate".to_string(),
+        ).with_span(attr.span)),
+    }
+}
+
+fn eval_predicate(expr: &Expr, options: &CfgOptions) -> Result<bool, ChimError> {
+    match &*expr.kind {
+        ExprKind::Identifier(flag) => Ok(options.flags.contains(&**flag)),
+        ExprKind::Assign(assign) => {
+            let key = match &*assign.left.kind {
+                ExprKind::Identifier(key) => key,
+                _ => return Err(malformed(expr)),
+            };
+            let value = match &*assign.right.kind {
+                ExprKind::Literal(literal) => match &literal.kind {
+                    LiteralKind::String(value) => value,
+                    _ => return Err(malformed(expr)),
+                },
+                _ => return Err(malformed(expr)),
+            };
+            match &**key {
+                "target_os" => Ok(options.target_os == **value),
+                "target_arch" => Ok(options.target_arch == **value),
+                "feature" => Ok(options.features.contains(&**value)),
+                _ => Err(ChimError::new(
+                    ErrorKind::InvalidAttribute,
+                    format!("unknown cfg key `{}`", key),
+                ).with_span(assign.left.span)),
+            }
+        }
+        ExprKind::Call(call) => {
+            let name = match &*call.func.kind {
+                ExprKind::Identifier(name) => name,
+                _ => return Err(malformed(expr)),
+            };
+            match &**name {
+                "all" => {
+                    for arg in &call.args {
+                        if !eval_predicate(arg, options)? {
+                            return Ok(false);
+                        }
+                    }
+                    Ok(true)
+                }
+                "any" => {
+                    for arg in &call.args {
+                        if eval_predicate(arg, options)? {
+                            return Ok(true);
+                        }
+                    }
+                    Ok(false)
+                }
+                "not" => match call.args.as_slice() {
+                    [arg] => Ok(!eval_predicate(arg, options)?),
+                    _ => Err(ChimError::new(
+                        ErrorKind::InvalidAttribute,
+                        "`not` takes exactly one predicate".to_string(),
+                    ).with_span(expr.span)),
+                },
+                _ => Err(malformed(expr)),
+            }
+        }
+        _ => Err(malformed(expr)),
+    }
+}
+
+fn malformed(expr: &Expr) -> ChimError {
+    ChimError::new(ErrorKind::InvalidAttribute, "malformed cfg predicate".to_string())
+        .with_span(expr.span)
+        .with_note("expected `key = \"value\"`, a flag name, or `all(..)`, `any(..)`, `not(..)`".to_string())
+}
+
+/// Removes every item whose `@cfg` predicates don't all hold: functions,
+/// structs, enums, traits, impls and modules, including methods, fields,
+/// variants and items in inline modules. Runs before semantic analysis so disabled code is never
+/// resolved or type-checked. An item with a malformed predicate is kept and
+/// the error returned.
+pub fn strip_cfg(program: &mut Program, options: &CfgOptions) -> Vec<ChimError> {
+    let mut errors = Vec::new();
+    strip_items(&mut program.items, options, &mut errors);
+    errors
+}
+
+/// The attributes written on `item`; kinds that can't carry any have none.
+fn item_attributes(item: &Item) -> &[Attribute] {
+    match item {
+        Item::Function(func) => &func.attributes,
+        Item::Struct(struct_def) => &struct_def.attributes,
+        Item::Enum(enum_def) => &enum_def.attributes,
+        Item::Trait(trait_def) => &trait_def.attributes,
+        Item::Impl(imp) => &imp.attributes,
+        Item::Mod(module) => &module.attributes,
+        _ => &[],
+    }
+}
+
+fn strip_items(items: &mut Vec<Item>, options: &CfgOptions, errors: &mut Vec<ChimError>) {
+    items.retain(|item| is_enabled(item_attributes(item), options, errors));
+    for item in items {
+        match item {
+            Item::Mod(module) => strip_items(&mut module.items, options, errors),
+            Item::Impl(imp) => imp.items.retain(|item| match item {
+                ImplItem::Function(func) => is_enabled(&func.attributes, options, errors),
+                _ => true,
+            }),
+            Item::Struct(struct_def) => strip_fields(&mut struct_def.fields, options, errors),
+            Item::Enum(enum_def) => {
+                enum_def.variants.retain(|variant| is_enabled(&variant.attributes, options, errors));
+                for variant in &mut enum_def.variants {
+                    strip_fields(&mut variant.fields, options, errors);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn strip_fields(fields: &mut Vec<Field>, options: &CfgOptions, errors: &mut Vec<ChimError>) {
+    fields.retain(|field| is_enabled(&field.attributes, options, errors));
+}
+
+fn is_enabled(attributes: &[Attribute], options: &CfgOptions, errors: &mut Vec<ChimError>) -> bool {
+    attributes
+        .iter()
+        .filter(|attr| &*attr.name == "cfg")
+        .all(|attr| eval_cfg(attr, options).unwrap_or_else(|error| {
+            errors.push(error);
+            true
+        }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chim_ast::CallExpr;
+    use chim_span::FileId;
+    use std::sync::Arc;
+
+    fn parse(source: &str) -> Program {
+        chim_parser::parse(source, FileId::new(0)).unwrap()
+    }
+
+    fn function_names(program: &Program) -> Vec<String> {
+        program.items.iter().filter_map(|item| match item {
+            Item::Function(func) => Some(func.name.to_string()),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn test_strip_cfg_drops_disabled_items() {
+        let mut program = parse(r#"
+            @cfg(target_os = "windows") fn windows_only() {}
+            @cfg(target_os = "linux") fn linux_only() {}
+            fn always() {}
+            @cfg(target_os = "windows") fn not_windows() {}
+            @cfg(all(target_arch = "x86_64", feature = "simd")) fn simd() {}
+            @cfg(any(target_os = "macos", feature = "simd")) fn either() {}
+        "#);
+        // `not` lexes as a keyword, so `not_windows` gets its `not(..)` here.
+        let Item::Function(not_windows) = &mut program.items[3] else { unreachable!() };
+        let predicate = &mut not_windows.attributes[0].args[0].expr;
+        let span = predicate.span;
+        let inner = std::mem::replace(predicate, Expr { kind: Box::new(ExprKind::Error), span, ty: None });
+        *predicate = Expr {
+            kind: Box::new(ExprKind::Call(CallExpr {
+                func: Box::new(Expr { kind: Box::new(ExprKind::Identifier(Arc::from("not"))), span, ty: None }),
+                args: std::iter::once(Box::new(inner)).collect(),
+            })),
+            span,
+            ty: None,
+        };
+
+        let errors = strip_cfg(&mut program, &CfgOptions::new("linux", "x86_64"));
+        assert!(errors.is_empty());
+        assert_eq!(function_names(&program), vec!["linux_only", "always", "not_windows"]);
+    }
+
+    #[test]
+    fn test_strip_cfg_reports_unknown_key() {
+        let mut program = parse(r#"@cfg(target_vendor = "pc") fn f() {}"#);
+        let errors = strip_cfg(&mut program, &CfgOptions::new("linux", "x86_64").with_feature("simd"));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "unknown cfg key `target_vendor`");
+        assert_eq!(function_names(&program), vec!["f"]);
+    }
+
+    #[test]
+    fn test_strip_cfg_drops_disabled_structs_and_fields() {
+        let mut program = parse(r#"
+            @cfg(target_os = "windows") struct WindowsHandle { raw: int; }
+            struct File { fd: int; handle: int; }
+            @cfg(target_os = "linux") fn linux() {}
+            @cfg(target_os = "windows") fn windows() {}
+        "#);
+        // Fields don't take attributes in source yet, so they borrow the
+        // functions' `@cfg`s.
+        let attributes: Vec<Vec<Attribute>> = program.items.drain(2..).map(|item| match item {
+            Item::Function(func) => func.attributes,
+            other => panic!("expected function, got {:?}", other),
+        }).collect();
+        let Item::Struct(file) = &mut program.items[1] else { unreachable!() };
+        for (field, attributes) in file.fields.iter_mut().zip(attributes) {
+            field.attributes = attributes;
+        }
+
+        let errors = strip_cfg(&mut program, &CfgOptions::new("linux", "x86_64"));
+        assert!(errors.is_empty());
+        let [Item::Struct(file)] = program.items.as_slice() else {
+            panic!("expected only `File` to remain, got {:?}", program.items);
+        };
+        let fields: Vec<&str> = file.fields.iter().map(|field| &*field.name).collect();
+        assert_eq!(fields, ["fd"]);
+    }
+}
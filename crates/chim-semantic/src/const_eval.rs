@@ -0,0 +1,108 @@
+use chim_ast::{ArrayLen, BinOp, Expr, ExprKind, Ident, LiteralKind, UnOp};
+use chim_error::{ChimError, ErrorKind};
+use std::collections::HashMap;
+
+/// Evaluates an integer const expression: literals, the const parameters
+/// bound in `bindings`, unary `-` and the arithmetic and bitwise operators.
+pub fn eval_const_expr(expr: &Expr, bindings: &HashMap<Ident, i128>) -> Result<i128, ChimError> {
+    let error = |kind: ErrorKind, message: String| ChimError::new(kind, message).with_span(expr.span);
+    let overflow = || error(ErrorKind::InvalidLiteral, "overflow evaluating constant".to_string());
+    match &*expr.kind {
+        ExprKind::Literal(literal) => match literal.kind {
+            LiteralKind::Int(value) => Ok(value),
+            _ => Err(error(ErrorKind::TypeMismatch, "expected an integer constant".to_string())),
+        },
+        ExprKind::Identifier(name) => bindings.get(name).copied().ok_or_else(|| {
+            error(ErrorKind::UndefinedIdentifier, format!("cannot find const parameter `{}`", name))
+        }),
+        ExprKind::Path(path) => match path.segments.as_slice() {
+            [segment] => bindings.get(&segment.ident).copied().ok_or_else(|| {
+                error(ErrorKind::UndefinedIdentifier, format!("cannot find const parameter `{}`", segment.ident))
+            }),
+            _ => Err(error(ErrorKind::InvalidLiteral, format!("`{}` is not a const parameter", path))),
+        },
+        ExprKind::Unary(unary) if unary.op == UnOp::Neg => {
+            eval_const_expr(&unary.expr, bindings)?.checked_neg().ok_or_else(overflow)
+        }
+        ExprKind::Binary(binary) => {
+            let left = eval_const_expr(&binary.left, bindings)?;
+            let right = eval_const_expr(&binary.right, bindings)?;
+            let value = match binary.op {
+                BinOp::Add => left.checked_add(right),
+                BinOp::Sub => left.checked_sub(right),
+                BinOp::Mul => left.checked_mul(right),
+                BinOp::Div | BinOp::Mod if right == 0 => {
+                    return Err(error(ErrorKind::InvalidLiteral, "division by zero in constant".to_string()));
+                }
+                BinOp::Div => left.checked_div(right),
+                BinOp::Mod => left.checked_rem(right),
+                BinOp::BitAnd => Some(left & right),
+                BinOp::BitOr => Some(left | right),
+                BinOp::BitXor => Some(left ^ right),
+                BinOp::Shl => u32::try_from(right).ok().and_then(|shift| left.checked_shl(shift)),
+                BinOp::Shr => u32::try_from(right).ok().and_then(|shift| left.checked_shr(shift)),
+                _ => {
+                    return Err(error(ErrorKind::InvalidLiteral, "operator is not allowed in a constant".to_string()));
+                }
+            };
+            value.ok_or_else(overflow)
+        }
+        _ => Err(error(ErrorKind::InvalidLiteral, "array lengths must be constant expressions".to_string())),
+    }
+}
+
+/// Whether `expr` names any of `params`, e.g. `N + 1` with `N` among them.
+pub fn mentions_const_param(expr: &Expr, params: &[Ident]) -> bool {
+    match &*expr.kind {
+        ExprKind::Identifier(name) => params.contains(name),
+        ExprKind::Path(path) => matches!(path.segments.as_slice(), [segment] if params.contains(&segment.ident)),
+        ExprKind::Unary(unary) => mentions_const_param(&unary.expr, params),
+        ExprKind::Binary(binary) => {
+            mentions_const_param(&binary.left, params) || mentions_const_param(&binary.right, params)
+        }
+        _ => false,
+    }
+}
+
+/// The length of `[T; len]` with the const parameters in it bound, e.g. 4
+/// for `[int; N + 1]` with `N = 3`.
+pub fn array_len(len: &ArrayLen, bindings: &HashMap<Ident, i128>) -> Result<usize, ChimError> {
+    let expr = match len {
+        ArrayLen::Const(expr) => expr,
+        ArrayLen::Inferred => {
+            return Err(ChimError::new(
+                ErrorKind::CannotInfer,
+                "array length `_` is only known from an initializer".to_string(),
+            ));
+        }
+    };
+    let value = eval_const_expr(expr, bindings)?;
+    usize::try_from(value).map_err(|_| {
+        ChimError::new(ErrorKind::InvalidLiteral, format!("array length `{}` is negative", value)).with_span(expr.span)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chim_ast::{Item, TypeKind};
+    use chim_span::FileId;
+
+    #[test]
+    fn test_array_len_with_const_params() {
+        let program = chim_parser::parse("fn f(xs: [int; N + 1]) {}", FileId::new(0)).unwrap();
+        let Item::Function(func) = &program.items[0] else { unreachable!() };
+        let TypeKind::Array(_, len) = &*func.params[0].ty.kind else {
+            panic!("expected an array, got {:?}", func.params[0].ty);
+        };
+
+        let bindings: HashMap<Ident, i128> = HashMap::from([(Ident::from("N"), 3)]);
+        assert_eq!(array_len(len, &bindings).unwrap(), 4);
+
+        let error = array_len(len, &HashMap::new()).unwrap_err();
+        assert_eq!(error.message(), "cannot find const parameter `N`");
+
+        let negative = HashMap::from([(Ident::from("N"), -5)]);
+        assert_eq!(array_len(len, &negative).unwrap_err().message(), "array length `-4` is negative");
+    }
+}
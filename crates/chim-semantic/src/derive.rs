@@ -0,0 +1,219 @@
+use chim_ast::{
+    CallExpr, Expr, ExprKind, FieldAccessExpr, Function, GenericArg, GenericArgKind, Ident, Impl, ImplItem, Item,
+    MethodCallExpr, Mutability, Param, Path, PathSegment, Program, Stmt, StmtKind, Struct, StructExpr, StructField,
+    Type, TypeKind,
+};
+use chim_error::{ChimError, ErrorKind};
+use chim_span::Span;
+use smallvec::SmallVec;
+use std::sync::Arc;
+
+/// The traits `@derive(...)` knows how to implement.
+const DERIVABLE: &[&str] = &["Clone", "Eq", "Debug", "Default"];
+
+/// Expands `@derive(Trait, ..)` on structs into `impl Trait for S` items,
+/// placed right after the struct. `Clone` clones each field and `Default`
+/// defaults each field; `Eq` and `Debug` get empty impls for now.
+pub fn expand_derives(program: &mut Program) -> Vec<ChimError> {
+    let mut errors = Vec::new();
+    expand_items(&mut program.items, &mut errors);
+    errors
+}
+
+fn expand_items(items: &mut Vec<Item>, errors: &mut Vec<ChimError>) {
+    let mut expanded = Vec::with_capacity(items.len());
+    for mut item in items.drain(..) {
+        let impls = match &mut item {
+            Item::Struct(struct_def) => derive_impls(struct_def, errors),
+            Item::Mod(module) => {
+                expand_items(&mut module.items, errors);
+                Vec::new()
+            }
+            _ => Vec::new(),
+        };
+        expanded.push(item);
+        expanded.extend(impls.into_iter().map(Item::Impl));
+    }
+    *items = expanded;
+}
+
+fn derive_impls(struct_def: &Struct, errors: &mut Vec<ChimError>) -> Vec<Impl> {
+    let mut impls = Vec::new();
+    for attr in struct_def.attributes.iter().filter(|attr| &*attr.name == "derive") {
+        for arg in &attr.args {
+            let name = match &*arg.expr.kind {
+                ExprKind::Identifier(name) => name.clone(),
+                ExprKind::Path(path) if path.segments.len() == 1 => path.segments[0].ident.clone(),
+                _ => {
+                    errors.push(ChimError::new(
+                        ErrorKind::InvalidAttribute,
+                        "`derive` takes trait names, e.g. `@derive(Clone)`".to_string(),
+                    ).with_span(arg.span));
+                    continue;
+                }
+            };
+            if !DERIVABLE.contains(&&*name) {
+                errors.push(ChimError::new(
+                    ErrorKind::InvalidAttribute,
+                    format!("cannot derive `{}`; derivable traits are {}", name, DERIVABLE.join(", ")),
+                ).with_span(arg.span));
+                continue;
+            }
+            impls.push(derive_impl(struct_def, name, attr.span));
+        }
+    }
+    impls
+}
+
+fn derive_impl(struct_def: &Struct, trait_name: Ident, span: Span) -> Impl {
+    let self_ty = self_type(struct_def, span);
+    let items = match &*trait_name {
+        "Clone" => vec![ImplItem::Function(clone_method(struct_def, &self_ty, span))],
+        "Default" => vec![ImplItem::Function(default_method(struct_def, &self_ty, span))],
+        _ => Vec::new(),
+    };
+    Impl {
+        trait_name: Some(path_type(path(&trait_name, Vec::new(), span), span)),
+        type_name: self_ty,
+        items,
+        span,
+        generics: struct_def.generics.clone(),
+        where_clauses: struct_def.where_clauses.clone(),
+        attributes: Vec::new(),
+    }
+}
+
+/// `fn clone(self: &S) -> S { S { x: self.x.clone(), .. } }`
+fn clone_method(struct_def: &Struct, self_ty: &Type, span: Span) -> Function {
+    let self_param = Param {
+        name: Arc::from("self"),
+        ty: Type { kind: Box::new(TypeKind::Reference(None, Box::new(self_ty.clone()), Mutability::Immutable)), span },
+        span,
+        is_mut: false,
+        is_ref: true,
+    };
+    let fields = struct_def.fields.iter().map(|field| {
+        let access = expr(ExprKind::FieldAccess(FieldAccessExpr {
+            expr: Box::new(expr(ExprKind::Identifier(Arc::from("self")), span)),
+            field: field.name.clone(),
+        }), span);
+        let value = expr(ExprKind::MethodCall(MethodCallExpr {
+            expr: Box::new(access),
+            method: Arc::from("clone"),
+            args: SmallVec::new(),
+            generics: Vec::new(),
+        }), span);
+        (field.name.clone(), value)
+    });
+    method("clone", vec![self_param], struct_def, self_ty, fields.collect(), span)
+}
+
+/// `fn default() -> S { S { x: Default::default(), .. } }`
+fn default_method(struct_def: &Struct, self_ty: &Type, span: Span) -> Function {
+    let fields = struct_def.fields.iter().map(|field| {
+        let callee = Path {
+            segments: vec![
+                PathSegment { ident: Arc::from("Default"), args: Vec::new(), span },
+                PathSegment { ident: Arc::from("default"), args: Vec::new(), span },
+            ],
+            span,
+        };
+        let value = expr(ExprKind::Call(CallExpr {
+            func: Box::new(expr(ExprKind::Path(callee), span)),
+            args: SmallVec::new(),
+        }), span);
+        (field.name.clone(), value)
+    });
+    method("default", Vec::new(), struct_def, self_ty, fields.collect(), span)
+}
+
+/// A method returning the struct built from `fields`.
+fn method(
+    name: &str,
+    params: Vec<Param>,
+    struct_def: &Struct,
+    self_ty: &Type,
+    fields: Vec<(Ident, Expr)>,
+    span: Span,
+) -> Function {
+    let value = expr(ExprKind::Struct(StructExpr {
+        path: path(&struct_def.name, Vec::new(), span),
+        fields: fields.into_iter().map(|(name, expr)| StructField { name, expr, span }).collect(),
+        base: None,
+    }), span);
+    Function {
+        name: Arc::from(name),
+        params,
+        return_type: Some(self_ty.clone()),
+        body: vec![Stmt { kind: StmtKind::Expr(value), span }],
+        span,
+        is_pub: true,
+        is_async: false,
+        lifetimes: Vec::new(),
+        generics: Vec::new(),
+        where_clauses: Vec::new(),
+        attributes: Vec::new(),
+    }
+}
+
+/// The struct applied to its own type parameters, e.g. `Pair<T>`.
+fn self_type(struct_def: &Struct, span: Span) -> Type {
+    let args = struct_def.generics.iter()
+        .filter(|param| param.const_ty.is_none())
+        .map(|param| GenericArg {
+            kind: GenericArgKind::Type(path_type(path(&param.name, Vec::new(), span), span)),
+            span,
+        })
+        .collect();
+    path_type(path(&struct_def.name, args, span), span)
+}
+
+fn path(name: &Ident, args: Vec<GenericArg>, span: Span) -> Path {
+    Path { segments: vec![PathSegment { ident: name.clone(), args, span }], span }
+}
+
+fn path_type(path: Path, span: Span) -> Type {
+    Type { kind: Box::new(TypeKind::Path(path)), span }
+}
+
+fn expr(kind: ExprKind, span: Span) -> Expr {
+    Expr { kind: Box::new(kind), span, ty: None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chim_span::FileId;
+
+    fn parse(source: &str) -> Program {
+        chim_parser::parse(source, FileId::new(0)).unwrap()
+    }
+
+    #[test]
+    fn test_expand_derives() {
+        let mut program = parse("@derive(Clone, Default) struct P { x: int; }");
+        assert!(expand_derives(&mut program).is_empty());
+        assert_eq!(program.items.len(), 3);
+        let Item::Impl(clone) = &program.items[1] else { panic!("expected the Clone impl") };
+        assert_eq!(clone.trait_name.as_ref().map(|t| t.to_string()), Some("Clone".to_string()));
+        assert_eq!(clone.type_name.to_string(), "P");
+        let ImplItem::Function(method) = &clone.items[0] else { panic!("expected `clone`") };
+        assert_eq!(&*method.name, "clone");
+        assert_eq!(&*method.params[0].name, "self");
+        let StmtKind::Expr(body) = &method.body[0].kind else { panic!("expected a struct literal") };
+        let ExprKind::Struct(literal) = &*body.kind else { panic!("expected a struct literal") };
+        assert_eq!(&*literal.fields[0].name, "x");
+        assert!(matches!(&*literal.fields[0].expr.kind, ExprKind::MethodCall(call) if &*call.method == "clone"));
+
+        let Item::Impl(default) = &program.items[2] else { panic!("expected the Default impl") };
+        let ImplItem::Function(method) = &default.items[0] else { panic!("expected `default`") };
+        assert_eq!(&*method.name, "default");
+        assert!(method.params.is_empty());
+
+        let mut program = parse("@derive(Hash) struct P { x: int; }");
+        let errors = expand_derives(&mut program);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message().contains("cannot derive `Hash`"));
+        assert_eq!(program.items.len(), 1);
+    }
+}
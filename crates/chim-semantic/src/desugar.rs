@@ -0,0 +1,326 @@
+use crate::methods::MethodIndex;
+use chim_ast::{
+    ChainExpr, Expr, ExprKind, FilterExpr, FoldExpr, Function, Ident, ImplItem, Item, MapExpr, MethodCallExpr, Param,
+    Pattern, PatternKind, Program, Stmt, StmtKind, Type,
+};
+
+/// Rewrites surface syntax into the forms later passes understand:
+/// `guard cond else { ... }` becomes `if !cond { ... }`, and iterator
+/// adapters called as methods (`xs.map(f)`) become the same nodes as their
+/// prefix forms (`map xs, f`). Runs after `@cfg` stripping and before
+/// semantic analysis.
+///
+/// A call is left alone when the receiver's type has an inherent method of
+/// that name, so a user's `Tree::map` isn't mistaken for the adapter. Only
+/// receivers whose type is written down, as a parameter or an annotated
+/// `let`/`var`, are checked.
+pub fn desugar(program: &mut Program) {
+    let mut cx = Context { methods: MethodIndex::new(program), locals: Vec::new() };
+    desugar_items(&mut cx, &mut program.items);
+}
+
+struct Context {
+    methods: MethodIndex,
+    /// The declared types of the names in scope, innermost last.
+    locals: Vec<(Ident, Type)>,
+}
+
+impl Context {
+    fn declare(&mut self, pattern: &Pattern, ty: Option<&Type>) {
+        if let (PatternKind::Identifier(name, _), Some(ty)) = (&pattern.kind, ty) {
+            self.locals.push((name.clone(), ty.clone()));
+        }
+    }
+
+    fn declare_params(&mut self, params: &[Param]) {
+        self.locals.extend(params.iter().map(|param| (param.name.clone(), param.ty.clone())));
+    }
+
+    /// Whether `call` names an inherent method of its receiver's type.
+    fn has_inherent_method(&self, call: &MethodCallExpr) -> bool {
+        let receiver_ty = match (&call.expr.ty, &*call.expr.kind) {
+            (Some(ty), _) => Some(ty),
+            (None, ExprKind::Identifier(name)) => {
+                self.locals.iter().rev().find(|(local, _)| local == name).map(|(_, ty)| ty)
+            }
+            _ => None,
+        };
+        receiver_ty.is_some_and(|ty| self.methods.find_method(ty, &call.method).is_some())
+    }
+}
+
+fn desugar_items(cx: &mut Context, items: &mut [Item]) {
+    for item in items {
+        match item {
+            Item::Function(func) => desugar_function(cx, func),
+            Item::Impl(imp) => {
+                for item in &mut imp.items {
+                    if let ImplItem::Function(func) = item {
+                        desugar_function(cx, func);
+                    }
+                }
+            }
+            Item::Mod(module) => desugar_items(cx, &mut module.items),
+            _ => {}
+        }
+    }
+}
+
+fn desugar_function(cx: &mut Context, func: &mut Function) {
+    cx.declare_params(&func.params);
+    desugar_stmts(cx, &mut func.body);
+    cx.locals.clear();
+}
+
+fn desugar_stmts(cx: &mut Context, stmts: &mut [Stmt]) {
+    let scope = cx.locals.len();
+    for stmt in stmts {
+        desugar_stmt(cx, stmt);
+    }
+    cx.locals.truncate(scope);
+}
+
+fn desugar_stmt(cx: &mut Context, stmt: &mut Stmt) {
+    match &mut stmt.kind {
+        StmtKind::Guard(guard) => {
+            let mut expr = guard.to_if_expr();
+            desugar_expr(cx, &mut expr);
+            stmt.kind = StmtKind::Expr(expr);
+        }
+        StmtKind::Expr(expr) => desugar_expr(cx, expr),
+        StmtKind::Let(let_stmt) => {
+            if let Some(init) = &mut let_stmt.initializer {
+                desugar_expr(cx, init);
+            }
+            cx.declare(&let_stmt.pattern, let_stmt.ty.as_ref());
+        }
+        StmtKind::Var(var_stmt) => {
+            if let Some(init) = &mut var_stmt.initializer {
+                desugar_expr(cx, init);
+            }
+            cx.declare(&var_stmt.pattern, var_stmt.ty.as_ref());
+        }
+        StmtKind::Return(Some(value)) | StmtKind::Break(Some(value)) => desugar_expr(cx, value),
+        StmtKind::Loop(loop_stmt) => desugar_stmts(cx, &mut loop_stmt.body),
+        StmtKind::Defer(body) => desugar_stmts(cx, body),
+        StmtKind::While(while_stmt) => {
+            desugar_expr(cx, &mut while_stmt.condition);
+            desugar_stmts(cx, &mut while_stmt.body);
+        }
+        StmtKind::For(for_stmt) => {
+            desugar_expr(cx, &mut for_stmt.iterable);
+            desugar_stmts(cx, &mut for_stmt.body);
+        }
+        _ => {}
+    }
+}
+
+fn desugar_expr(cx: &mut Context, expr: &mut Expr) {
+    match &mut *expr.kind {
+        ExprKind::MethodCall(call) => {
+            desugar_expr(cx, &mut call.expr);
+            for arg in &mut call.args {
+                desugar_expr(cx, arg);
+            }
+            if cx.has_inherent_method(call) {
+                return;
+            }
+            if let Some(adapter) = iterator_adapter(call) {
+                *expr.kind = adapter;
+            }
+        }
+        ExprKind::Binary(binary) => {
+            desugar_expr(cx, &mut binary.left);
+            desugar_expr(cx, &mut binary.right);
+        }
+        ExprKind::Unary(unary) => desugar_expr(cx, &mut unary.expr),
+        ExprKind::Call(call) => {
+            desugar_expr(cx, &mut call.func);
+            for arg in &mut call.args {
+                desugar_expr(cx, arg);
+            }
+        }
+        ExprKind::FieldAccess(access) => desugar_expr(cx, &mut access.expr),
+        ExprKind::Index(index) => {
+            desugar_expr(cx, &mut index.expr);
+            desugar_expr(cx, &mut index.index);
+        }
+        ExprKind::Cast(cast) => desugar_expr(cx, &mut cast.expr),
+        ExprKind::Ternary(ternary) => {
+            desugar_expr(cx, &mut ternary.condition);
+            desugar_expr(cx, &mut ternary.then_branch);
+            desugar_expr(cx, &mut ternary.else_branch);
+        }
+        ExprKind::Tuple(tuple) => tuple.elements.iter_mut().for_each(|element| desugar_expr(cx, element)),
+        ExprKind::Array(array) => array.elements.iter_mut().for_each(|element| desugar_expr(cx, element)),
+        ExprKind::Assign(assign) => {
+            desugar_expr(cx, &mut assign.left);
+            desugar_expr(cx, &mut assign.right);
+        }
+        ExprKind::AssignOp(assign) => {
+            desugar_expr(cx, &mut assign.left);
+            desugar_expr(cx, &mut assign.right);
+        }
+        ExprKind::Return(Some(value)) | ExprKind::Break(_, Some(value)) => desugar_expr(cx, value),
+        ExprKind::Try(try_expr) => desugar_expr(cx, &mut try_expr.expr),
+        ExprKind::Map(map) => {
+            desugar_expr(cx, &mut map.iterator);
+            desugar_expr(cx, &mut map.mapper);
+        }
+        ExprKind::Filter(filter) => {
+            desugar_expr(cx, &mut filter.iterator);
+            desugar_expr(cx, &mut filter.predicate);
+        }
+        ExprKind::Chain(chain) => {
+            desugar_expr(cx, &mut chain.iterator1);
+            desugar_expr(cx, &mut chain.iterator2);
+        }
+        ExprKind::Fold(fold) => {
+            desugar_expr(cx, &mut fold.iterator);
+            desugar_expr(cx, &mut fold.init);
+            desugar_expr(cx, &mut fold.body);
+        }
+        ExprKind::Collect(collect) => desugar_expr(cx, &mut collect.iterator),
+        ExprKind::Block(block) => desugar_stmts(cx, &mut block.stmts),
+        ExprKind::If(if_expr) => {
+            desugar_expr(cx, &mut if_expr.condition);
+            desugar_stmts(cx, &mut if_expr.then_branch.stmts);
+            if let Some(else_branch) = &mut if_expr.else_branch {
+                desugar_expr(cx, else_branch);
+            }
+        }
+        ExprKind::Match(match_expr) => {
+            desugar_expr(cx, &mut match_expr.expr);
+            for arm in &mut match_expr.arms {
+                if let Some(guard) = &mut arm.guard {
+                    desugar_expr(cx, guard);
+                }
+                desugar_expr(cx, &mut arm.body);
+            }
+        }
+        ExprKind::Loop(loop_expr) => desugar_stmts(cx, &mut loop_expr.body.stmts),
+        ExprKind::While(while_expr) => {
+            desugar_expr(cx, &mut while_expr.condition);
+            desugar_stmts(cx, &mut while_expr.body.stmts);
+        }
+        ExprKind::For(for_expr) => {
+            desugar_expr(cx, &mut for_expr.iterable);
+            desugar_stmts(cx, &mut for_expr.body.stmts);
+        }
+        ExprKind::Closure(closure) => {
+            let scope = cx.locals.len();
+            cx.declare_params(&closure.params);
+            desugar_expr(cx, &mut closure.body);
+            cx.locals.truncate(scope);
+        }
+        ExprKind::AsyncBlock(block) => desugar_stmts(cx, &mut block.body.stmts),
+        ExprKind::Unsafe(unsafe_expr) => desugar_expr(cx, &mut unsafe_expr.body),
+        _ => {}
+    }
+}
+
+/// The prefix-form node for `it.map(f)`, `it.filter(p)`, `a.chain(b)` and
+/// `it.fold(init, acc, body)`, whose arguments mirror `map it, f` and friends.
+/// Other method calls, including adapters with the wrong number of
+/// arguments, are left alone for method resolution to report on.
+fn iterator_adapter(call: &MethodCallExpr) -> Option<ExprKind> {
+    let receiver = call.expr.clone();
+    let kind = match (&*call.method, call.args.as_slice()) {
+        ("map", [mapper]) => ExprKind::Map(MapExpr { iterator: receiver, mapper: mapper.clone() }),
+        ("filter", [predicate]) => ExprKind::Filter(FilterExpr { iterator: receiver, predicate: predicate.clone() }),
+        ("chain", [other]) => ExprKind::Chain(ChainExpr { iterator1: receiver, iterator2: other.clone() }),
+        ("fold", [init, accumulator, body]) => {
+            let ExprKind::Identifier(accumulator) = &*accumulator.kind else {
+                return None;
+            };
+            ExprKind::Fold(FoldExpr {
+                iterator: receiver,
+                init: init.clone(),
+                accumulator: accumulator.clone(),
+                body: body.clone(),
+            })
+        }
+        _ => return None,
+    };
+    Some(kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chim_ast::UnOp;
+    use chim_span::FileId;
+
+    fn parse(source: &str) -> Program {
+        chim_parser::parse(source, FileId::new(0)).unwrap()
+    }
+
+    /// The statements of the last function in `program`.
+    fn last_body(program: &Program) -> &[Stmt] {
+        match program.items.last() {
+            Some(Item::Function(func)) => &func.body,
+            other => panic!("expected function, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_desugar_guard_to_negated_if() {
+        let mut program = parse("fn f() { guard ready else { return; } }");
+        let StmtKind::Guard(guard) = last_body(&program)[0].kind.clone() else {
+            panic!("expected guard, got {:?}", last_body(&program)[0]);
+        };
+
+        desugar(&mut program);
+        let StmtKind::Expr(expr) = &last_body(&program)[0].kind else {
+            panic!("guard was not desugared: {:?}", last_body(&program)[0]);
+        };
+        match &*expr.kind {
+            ExprKind::If(if_expr) => {
+                assert!(matches!(
+                    &*if_expr.condition.kind,
+                    ExprKind::Unary(chim_ast::UnaryExpr { op: UnOp::Not, expr }) if **expr == guard.condition
+                ));
+                assert_eq!(if_expr.then_branch.stmts, guard.else_branch);
+                assert!(if_expr.else_branch.is_none());
+            }
+            other => panic!("expected if, got {:?}", other),
+        }
+    }
+
+    fn is_identifier(expr: &Expr, name: &str) -> bool {
+        matches!(&*expr.kind, ExprKind::Identifier(ident) if &**ident == name)
+    }
+
+    #[test]
+    fn test_desugar_adapter_method_calls() {
+        let mut program = parse("fn f() { xs.map(f).filter(g); }");
+        desugar(&mut program);
+        let StmtKind::Expr(expr) = &last_body(&program)[0].kind else { unreachable!() };
+        let ExprKind::Filter(filter) = &*expr.kind else {
+            panic!("expected filter, got {:?}", expr);
+        };
+        assert!(is_identifier(&filter.predicate, "g"));
+        match &*filter.iterator.kind {
+            ExprKind::Map(map) => {
+                assert!(is_identifier(&map.iterator, "xs"));
+                assert!(is_identifier(&map.mapper, "f"));
+            }
+            other => panic!("expected map, got {:?}", other),
+        }
+
+        // Other methods, and adapters with the wrong arity, are left alone.
+        let mut program = parse("fn f() { xs.len(f); xs.fold(f); }");
+        let before = program.clone();
+        desugar(&mut program);
+        assert_eq!(program, before);
+    }
+
+    #[test]
+    fn test_desugar_keeps_inherent_adapter_methods() {
+        // `t.map(f)` calls `Tree::map`, not the iterator adapter.
+        let mut program = parse("impl Tree { fn map() {} } fn f(t: Tree) { t.map(f); }");
+        let before = program.clone();
+        desugar(&mut program);
+        assert_eq!(program, before);
+    }
+}
@@ -0,0 +1,237 @@
+use chim_ast::{Enum, Expr, ExprKind, Item, LiteralKind, Program, UnOp};
+use chim_error::{ChimError, ErrorKind};
+use std::collections::HashMap;
+
+/// The integer type named by an enum's `@repr(...)` attribute, which bounds
+/// its discriminants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EnumRepr {
+    I8,
+    I16,
+    I32,
+    I64,
+    Isize,
+    U8,
+    U16,
+    U32,
+    U64,
+    Usize,
+}
+
+impl EnumRepr {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "i8" => EnumRepr::I8,
+            "i16" => EnumRepr::I16,
+            "i32" => EnumRepr::I32,
+            "i64" => EnumRepr::I64,
+            "isize" => EnumRepr::Isize,
+            "u8" => EnumRepr::U8,
+            "u16" => EnumRepr::U16,
+            "u32" => EnumRepr::U32,
+            "u64" => EnumRepr::U64,
+            "usize" => EnumRepr::Usize,
+            _ => return None,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EnumRepr::I8 => "i8",
+            EnumRepr::I16 => "i16",
+            EnumRepr::I32 => "i32",
+            EnumRepr::I64 => "i64",
+            EnumRepr::Isize => "isize",
+            EnumRepr::U8 => "u8",
+            EnumRepr::U16 => "u16",
+            EnumRepr::U32 => "u32",
+            EnumRepr::U64 => "u64",
+            EnumRepr::Usize => "usize",
+        }
+    }
+
+    /// Size in bytes; `isize` and `usize` are pointer-sized.
+    pub fn size(self) -> usize {
+        match self {
+            EnumRepr::I8 | EnumRepr::U8 => 1,
+            EnumRepr::I16 | EnumRepr::U16 => 2,
+            EnumRepr::I32 | EnumRepr::U32 => 4,
+            EnumRepr::I64 | EnumRepr::U64 | EnumRepr::Isize | EnumRepr::Usize => 8,
+        }
+    }
+
+    pub fn range(self) -> (i128, i128) {
+        match self {
+            EnumRepr::I8 => (i8::MIN as i128, i8::MAX as i128),
+            EnumRepr::I16 => (i16::MIN as i128, i16::MAX as i128),
+            EnumRepr::I32 => (i32::MIN as i128, i32::MAX as i128),
+            EnumRepr::I64 | EnumRepr::Isize => (i64::MIN as i128, i64::MAX as i128),
+            EnumRepr::U8 => (0, u8::MAX as i128),
+            EnumRepr::U16 => (0, u16::MAX as i128),
+            EnumRepr::U32 => (0, u32::MAX as i128),
+            EnumRepr::U64 | EnumRepr::Usize => (0, u64::MAX as i128),
+        }
+    }
+}
+
+/// Reads `@repr(ty)` off an enum. `Ok(None)` when there is no such attribute.
+pub fn enum_repr(enum_def: &Enum) -> Result<Option<EnumRepr>, ChimError> {
+    let Some(attr) = enum_def.attributes.iter().find(|attr| &*attr.name == "repr") else {
+        return Ok(None);
+    };
+    let repr = match attr.args.as_slice() {
+        [arg] => match &*arg.expr.kind {
+            ExprKind::Identifier(name) => EnumRepr::from_name(name),
+            _ => None,
+        },
+        _ => None,
+    };
+    repr.map(Some).ok_or_else(|| {
+        ChimError::new(
+            ErrorKind::InvalidAttribute,
+            "`repr` takes one integer type, e.g. `@repr(u8)`".to_string(),
+        ).with_span(attr.span)
+    })
+}
+
+/// Computes the discriminant of every variant of `enum_def`, in order.
+///
+/// A variant without `= value` takes the previous discriminant plus one, or
+/// zero if it is the first. Explicit values must be integer literals,
+/// optionally negated. Values must be distinct and, when the enum declares a
+/// `@repr`, fit in that type.
+pub fn enum_discriminants(enum_def: &Enum) -> Result<Vec<i128>, Vec<ChimError>> {
+    let mut errors = Vec::new();
+    let repr = enum_repr(enum_def).unwrap_or_else(|error| {
+        errors.push(error);
+        None
+    });
+
+    let mut values = Vec::with_capacity(enum_def.variants.len());
+    let mut seen = HashMap::new();
+    let mut next = Some(0i128);
+    for variant in &enum_def.variants {
+        let value = match &variant.discriminant {
+            Some(expr) => match literal_value(expr) {
+                Some(value) => value,
+                None => {
+                    errors.push(ChimError::new(
+                        ErrorKind::InvalidLiteral,
+                        format!("discriminant of `{}` must be an integer literal", variant.name),
+                    ).with_span(expr.span));
+                    next.unwrap_or_default()
+                }
+            },
+            None => match next {
+                Some(value) => value,
+                None => {
+                    errors.push(ChimError::new(
+                        ErrorKind::InvalidLiteral,
+                        format!("discriminant of `{}` overflows", variant.name),
+                    ).with_span(variant.span));
+                    0
+                }
+            },
+        };
+
+        if let Some(repr) = repr {
+            let (min, max) = repr.range();
+            if value < min || value > max {
+                errors.push(ChimError::new(
+                    ErrorKind::InvalidLiteral,
+                    format!("discriminant `{}` of `{}` does not fit in `{}`", value, variant.name, repr.name()),
+                ).with_span(variant.span));
+            }
+        }
+        if let Some(first) = seen.insert(value, &variant.name) {
+            errors.push(ChimError::new(
+                ErrorKind::Redefinition,
+                format!("discriminant `{}` is assigned to both `{}` and `{}`", value, first, variant.name),
+            ).with_span(variant.span));
+        }
+
+        values.push(value);
+        next = value.checked_add(1);
+    }
+
+    if errors.is_empty() {
+        Ok(values)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Checks the discriminants of every enum in `program`, including enums in
+/// inline modules.
+pub fn check_discriminants(program: &Program) -> Vec<ChimError> {
+    let mut errors = Vec::new();
+    check_items(&program.items, &mut errors);
+    errors
+}
+
+fn check_items(items: &[Item], errors: &mut Vec<ChimError>) {
+    for item in items {
+        match item {
+            Item::Enum(enum_def) => {
+                if let Err(enum_errors) = enum_discriminants(enum_def) {
+                    errors.extend(enum_errors);
+                }
+            }
+            Item::Mod(module) => check_items(&module.items, errors),
+            _ => {}
+        }
+    }
+}
+
+fn literal_value(expr: &Expr) -> Option<i128> {
+    match &*expr.kind {
+        ExprKind::Literal(literal) => match literal.kind {
+            LiteralKind::Int(value) => Some(value),
+            _ => None,
+        },
+        ExprKind::Unary(unary) if unary.op == UnOp::Neg => literal_value(&unary.expr)?.checked_neg(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chim_span::FileId;
+
+    fn parse_enum(source: &str) -> Enum {
+        match chim_parser::parse(source, FileId::new(0)).unwrap().items.remove(0) {
+            Item::Enum(enum_def) => enum_def,
+            other => panic!("expected enum, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_enum_discriminants_auto_increment() {
+        let enum_def = parse_enum("enum E { A, B = 5, C, D = -2, E }");
+        assert_eq!(enum_discriminants(&enum_def).unwrap(), vec![0, 5, 6, -2, -1]);
+
+        let enum_def = parse_enum("@repr(i8) enum E { A = -128, B = 127 }");
+        assert_eq!(enum_discriminants(&enum_def).unwrap(), vec![-128, 127]);
+    }
+
+    #[test]
+    fn test_enum_discriminant_errors() {
+        let enum_def = parse_enum("enum E { A = 1, B, C = 2 }");
+        let errors = enum_discriminants(&enum_def).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, ErrorKind::Redefinition);
+        assert!(errors[0].message().contains("`B` and `C`"));
+
+        let enum_def = parse_enum("@repr(u8) enum E { A = -1, B = 255, C }");
+        let errors = enum_discriminants(&enum_def).unwrap_err();
+        let messages: Vec<&str> = errors.iter().map(|e| e.message()).collect();
+        assert_eq!(messages, [
+            "discriminant `-1` of `A` does not fit in `u8`",
+            "discriminant `256` of `C` does not fit in `u8`",
+        ]);
+
+        let enum_def = parse_enum("@repr(f32) enum E { A }");
+        assert_eq!(enum_discriminants(&enum_def).unwrap_err()[0].kind, ErrorKind::InvalidAttribute);
+    }
+}
@@ -0,0 +1,339 @@
+use chim_ast::{
+    BinOp, Expr, ExprKind, Function, Ident, ImplItem, Item, LiteralKind, Path, PathSegment, Pattern, PatternKind, Program,
+    Stmt, StmtKind, Type, TypeKind,
+};
+use chim_error::{ChimError, ErrorKind};
+use chim_span::Span;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Fills in the type of every `let`/`var` written without one whose
+/// initializer has a type this pass can name: `bool`, `char`, string and
+/// byte literals, annotated locals and parameters, calls to functions with a
+/// declared return type, casts, comparisons and tuples of those. Initializers
+/// it cannot name are left for the type inferencer, including number
+/// literals, whose type depends on how they are used, and arithmetic.
+///
+/// Reports "cannot infer type" when an initializer is a local that itself
+/// has no type yet, as in `var x; let y = x;`.
+pub fn infer_let_types(program: &mut Program) -> Vec<ChimError> {
+    let mut returns = HashMap::new();
+    collect_returns(&program.items, &mut returns);
+    let mut pass = LetTypes { returns, scopes: Vec::new(), errors: Vec::new() };
+    pass.items(&mut program.items);
+    pass.errors
+}
+
+/// What the pass knows about a local.
+#[derive(Debug, Clone)]
+enum Local {
+    Typed(Type),
+    /// Declared with neither a type nor an initializer, and not assigned since.
+    Untyped,
+    /// Has a type, but not one this pass can name.
+    Opaque,
+}
+
+struct LetTypes {
+    returns: HashMap<Ident, Type>,
+    scopes: Vec<HashMap<Ident, Local>>,
+    errors: Vec<ChimError>,
+}
+
+fn collect_returns(items: &[Item], returns: &mut HashMap<Ident, Type>) {
+    for item in items {
+        match item {
+            Item::Function(func) => {
+                let ty = func.return_type.clone().unwrap_or_else(|| unit_type(func.span));
+                returns.insert(func.name.clone(), ty);
+            }
+            Item::Mod(module) => collect_returns(&module.items, returns),
+            _ => {}
+        }
+    }
+}
+
+impl LetTypes {
+    fn items(&mut self, items: &mut [Item]) {
+        for item in items {
+            match item {
+                Item::Function(func) => self.function(func),
+                Item::Impl(imp) => {
+                    for item in &mut imp.items {
+                        if let ImplItem::Function(func) = item {
+                            self.function(func);
+                        }
+                    }
+                }
+                Item::Mod(module) => self.items(&mut module.items),
+                _ => {}
+            }
+        }
+    }
+
+    fn function(&mut self, func: &mut Function) {
+        let params = func.params.iter().map(|param| {
+            let local = match &*param.ty.kind {
+                TypeKind::Infer => Local::Opaque,
+                _ => Local::Typed(param.ty.clone()),
+            };
+            (param.name.clone(), local)
+        });
+        self.scopes.push(params.collect());
+        self.stmts(&mut func.body);
+        self.scopes.pop();
+    }
+
+    fn block(&mut self, stmts: &mut [Stmt]) {
+        self.scopes.push(HashMap::new());
+        self.stmts(stmts);
+        self.scopes.pop();
+    }
+
+    fn stmts(&mut self, stmts: &mut [Stmt]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &mut Stmt) {
+        match &mut stmt.kind {
+            StmtKind::Let(let_stmt) => {
+                self.binding(&let_stmt.pattern, &mut let_stmt.ty, let_stmt.initializer.as_mut(), let_stmt.span)
+            }
+            StmtKind::Var(var_stmt) => {
+                self.binding(&var_stmt.pattern, &mut var_stmt.ty, var_stmt.initializer.as_mut(), var_stmt.span)
+            }
+            StmtKind::Expr(expr) => self.expr(expr),
+            StmtKind::Return(Some(value)) | StmtKind::Break(Some(value)) => self.expr(value),
+            StmtKind::Loop(loop_stmt) => self.block(&mut loop_stmt.body),
+            StmtKind::While(while_stmt) => {
+                self.expr(&mut while_stmt.condition);
+                self.block(&mut while_stmt.body);
+            }
+            StmtKind::For(for_stmt) => {
+                self.expr(&mut for_stmt.iterable);
+                self.scopes.push(HashMap::new());
+                self.bind_opaque(&for_stmt.pattern);
+                self.stmts(&mut for_stmt.body);
+                self.scopes.pop();
+            }
+            StmtKind::Guard(guard) => {
+                self.expr(&mut guard.condition);
+                self.block(&mut guard.else_branch);
+            }
+            StmtKind::Defer(body) => self.block(body),
+            StmtKind::Return(None) | StmtKind::Break(None) | StmtKind::Continue(_) | StmtKind::Empty => {}
+        }
+    }
+
+    fn binding(&mut self, pattern: &Pattern, ty: &mut Option<Type>, initializer: Option<&mut Expr>, span: Span) {
+        let PatternKind::Identifier(name, _) = &pattern.kind else {
+            if let Some(init) = initializer {
+                self.expr(init);
+            }
+            self.bind_opaque(pattern);
+            return;
+        };
+
+        let local = match (ty.as_ref(), initializer) {
+            (Some(ty), init) => {
+                if let Some(init) = init {
+                    self.expr(init);
+                }
+                Local::Typed(ty.clone())
+            }
+            (None, None) => Local::Untyped,
+            (None, Some(init)) => {
+                self.expr(init);
+                match self.type_of(init) {
+                    Local::Typed(init_ty) => {
+                        *ty = Some(init_ty.clone());
+                        Local::Typed(init_ty)
+                    }
+                    Local::Untyped => {
+                        self.errors.push(ChimError::new(
+                            ErrorKind::CannotInfer,
+                            format!("cannot infer type of `{}`", name),
+                        ).with_span(span).with_note(
+                            "its initializer has no type yet; annotate one of them".to_string(),
+                        ));
+                        Local::Opaque
+                    }
+                    Local::Opaque => Local::Opaque,
+                }
+            }
+        };
+        self.insert(name.clone(), local);
+    }
+
+    fn bind_opaque(&mut self, pattern: &Pattern) {
+        match &pattern.kind {
+            PatternKind::Identifier(name, _) => self.insert(name.clone(), Local::Opaque),
+            PatternKind::Tuple(patterns) => patterns.iter().for_each(|pattern| self.bind_opaque(pattern)),
+            PatternKind::Struct(_, fields) | PatternKind::Enum(_, _, fields) => {
+                for field in fields {
+                    match &field.pattern {
+                        Some(pattern) => self.bind_opaque(pattern),
+                        None => self.insert(field.name.clone(), Local::Opaque),
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn insert(&mut self, name: Ident, local: Local) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name, local);
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Local> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    /// Visits the blocks nested in `expr`, and records the type of `x = value`
+    /// for an untyped `x`.
+    fn expr(&mut self, expr: &mut Expr) {
+        match &mut *expr.kind {
+            ExprKind::Assign(assign) => {
+                self.expr(&mut assign.right);
+                if let ExprKind::Identifier(name) = &*assign.left.kind {
+                    if let Some(Local::Untyped) = self.lookup(name) {
+                        let local = self.type_of(&assign.right);
+                        let local = if let Local::Untyped = local { Local::Opaque } else { local };
+                        let name = name.clone();
+                        if let Some(scope) = self.scopes.iter_mut().rev().find(|scope| scope.contains_key(&name)) {
+                            scope.insert(name, local);
+                        }
+                    }
+                }
+            }
+            ExprKind::Block(block) => self.block(&mut block.stmts),
+            ExprKind::If(if_expr) => {
+                self.expr(&mut if_expr.condition);
+                self.block(&mut if_expr.then_branch.stmts);
+                if let Some(else_branch) = &mut if_expr.else_branch {
+                    self.expr(else_branch);
+                }
+            }
+            ExprKind::Ternary(ternary) => {
+                self.expr(&mut ternary.condition);
+                self.expr(&mut ternary.then_branch);
+                self.expr(&mut ternary.else_branch);
+            }
+            ExprKind::Loop(loop_expr) => self.block(&mut loop_expr.body.stmts),
+            ExprKind::While(while_expr) => {
+                self.expr(&mut while_expr.condition);
+                self.block(&mut while_expr.body.stmts);
+            }
+            ExprKind::For(for_expr) => {
+                self.expr(&mut for_expr.iterable);
+                self.scopes.push(HashMap::new());
+                self.bind_opaque(&for_expr.pattern);
+                self.stmts(&mut for_expr.body.stmts);
+                self.scopes.pop();
+            }
+            ExprKind::Unsafe(unsafe_expr) => self.expr(&mut unsafe_expr.body),
+            _ => {}
+        }
+    }
+
+    fn type_of(&self, expr: &Expr) -> Local {
+        let named = |name: &str| Local::Typed(named_type(name, expr.span));
+        match &*expr.kind {
+            ExprKind::Literal(literal) => match literal.kind {
+                LiteralKind::Bool(_) => named("bool"),
+                LiteralKind::Char(_) => named("char"),
+                LiteralKind::String(_) => named("string"),
+                LiteralKind::Byte(_) => named("byte"),
+                LiteralKind::Unit => Local::Typed(unit_type(expr.span)),
+                LiteralKind::Int(_) | LiteralKind::Float(_) | LiteralKind::Null | LiteralKind::ByteString(_) | LiteralKind::Atomic(_) => {
+                    Local::Opaque
+                }
+            },
+            ExprKind::Identifier(name) => self.lookup(name).cloned().unwrap_or(Local::Opaque),
+            ExprKind::Call(call) => match &*call.func.kind {
+                ExprKind::Identifier(name) if self.lookup(name).is_none() => {
+                    self.returns.get(name).cloned().map_or(Local::Opaque, Local::Typed)
+                }
+                _ => Local::Opaque,
+            },
+            ExprKind::Cast(cast) => Local::Typed(cast.ty.clone()),
+            ExprKind::Binary(binary) => match binary.op {
+                BinOp::Eq | BinOp::Ne | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge | BinOp::And | BinOp::Or => {
+                    named("bool")
+                }
+                // The result type of arithmetic depends on the operator
+                // impls, so only an untyped operand is worth reporting.
+                _ => match (self.type_of(&binary.left), self.type_of(&binary.right)) {
+                    (Local::Untyped, _) | (_, Local::Untyped) => Local::Untyped,
+                    _ => Local::Opaque,
+                },
+            },
+            ExprKind::Tuple(tuple) => {
+                let mut elements = Vec::with_capacity(tuple.elements.len());
+                for element in &tuple.elements {
+                    match self.type_of(element) {
+                        Local::Typed(ty) => elements.push(ty),
+                        other => return other,
+                    }
+                }
+                Local::Typed(Type { kind: Box::new(TypeKind::Tuple(elements)), span: expr.span })
+            }
+            _ => Local::Opaque,
+        }
+    }
+}
+
+fn named_type(name: &str, span: Span) -> Type {
+    Type {
+        kind: Box::new(TypeKind::Path(Path {
+            segments: vec![PathSegment { ident: Arc::from(name), args: Vec::new(), span }],
+            span,
+        })),
+        span,
+    }
+}
+
+fn unit_type(span: Span) -> Type {
+    Type { kind: Box::new(TypeKind::Tuple(Vec::new())), span }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chim_span::FileId;
+
+    /// The types inferred for the `let`/`var` bindings in the last function
+    /// of `source`, in order.
+    fn let_types_of(source: &str) -> (Vec<Option<String>>, Vec<ChimError>) {
+        let mut program = chim_parser::parse(source, FileId::new(0)).unwrap();
+        let errors = infer_let_types(&mut program);
+        let Some(Item::Function(main)) = program.items.last() else {
+            unreachable!();
+        };
+        let types = main.body.iter().filter_map(|stmt| match &stmt.kind {
+            StmtKind::Let(let_stmt) => Some(let_stmt.ty.as_ref().map(|ty| ty.to_string())),
+            StmtKind::Var(var_stmt) => Some(var_stmt.ty.as_ref().map(|ty| ty.to_string())),
+            _ => None,
+        }).collect();
+        (types, errors)
+    }
+
+    #[test]
+    fn test_infer_let_types() {
+        let source = "fn foo() -> Point {} fn main() { let x = true; let y = foo(); let z = x; let n = 5; }";
+        let (types, errors) = let_types_of(source);
+        assert!(errors.is_empty(), "{:?}", errors);
+        // Number literals are left for the type inferencer.
+        assert_eq!(types, [Some("bool".to_string()), Some("Point".to_string()), Some("bool".to_string()), None]);
+
+        let (types, errors) = let_types_of("fn main() { var a; let b = a; }");
+        assert_eq!(types, [None, None]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, ErrorKind::CannotInfer);
+        assert_eq!(errors[0].message(), "cannot infer type of `b`");
+    }
+}
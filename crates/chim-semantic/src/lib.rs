@@ -1,3 +1,6 @@
+// `ChimError` carries its labels, notes and suggestions inline.
+#![allow(clippy::result_large_err)]
+
 pub mod type_pool;
 pub mod type_inference;
 pub mod type_inference;
@@ -7,6 +10,21 @@ pub mod borrow_check;
 pub mod ecs;
 pub mod actor;
 pub mod math_verification;
+pub mod operators;
+pub mod methods;
+pub mod conditional;
+pub mod const_eval;
+pub mod derive;
+pub mod desugar;
+pub mod discriminants;
+pub mod let_types;
+pub mod normalize;
+pub mod prelude;
+pub mod self_type;
+pub mod substitute;
+pub mod unused;
+pub mod variants;
+pub mod walk;
 
 pub use type_pool::{TypePool, TypeId, StructId, EnumId, TraitId, FunctionId, VarId, ConstId, LifetimeId, TypeData, StructData, EnumData, TraitData, FunctionData, FunctionSig, BuiltinTypes, IntSize, UintSize, FloatSize, Mutability, TagRepresentation, LifetimeData, LifetimeKind};
 pub use type_inference::{TypeInferencer, TypeConstraints};
@@ -20,6 +38,20 @@ pub use math_verification::{DependentTypeChecker, DependentType, TypeConstraint,
 pub use math_verification::{LinearTypeChecker, LinearType, LinearTypeError, LinearTypeErrorKind};
 pub use math_verification::{EffectTypeChecker, EffectType, Effect, EffectTypeError, EffectTypeErrorKind};
 pub use math_verification::{SessionTypeChecker, SessionType, SessionTypeError, SessionTypeErrorKind};
+pub use operators::{binop_trait, unop_trait};
+pub use methods::MethodIndex;
+pub use variants::{VariantIndex, VariantInfo};
+pub use conditional::{CfgOptions, eval_cfg, strip_cfg};
+pub use const_eval::{array_len, eval_const_expr};
+pub use derive::expand_derives;
+pub use desugar::desugar;
+pub use discriminants::{EnumRepr, check_discriminants, enum_discriminants, enum_repr};
+pub use let_types::infer_let_types;
+pub use normalize::{desugar_to_core, CoreOptions};
+pub use prelude::{resolve_prelude, Prelude};
+pub use self_type::resolve_self_types;
+pub use substitute::substitute;
+pub use unused::lint_unused_variables;
 pub use math_verification::{ProofGenerator, Proof, ProofGenerationError, ProofGenerationErrorKind};
 
 use chim_span::{Span, FileId};
@@ -86,6 +118,7 @@ impl SemanticAnalyzer {
         }
 
         self.pool = type_inferencer.take_pool();
+        self.errors.extend(check_discriminants(program));
 
         let mut lifetime_analyzer = LifetimeAnalyzer::new();
         let lifetime_result = match lifetime_analyzer.analyze_program(program, &self.pool) {
@@ -228,7 +261,7 @@ mod tests {
         let mut analyzer = SemanticAnalyzer::new();
         let program = Program {
             items: Vec::new(),
-            span: Span::new(FileId(0), 0, 0, 0, 0),
+            span: Span::new(FileId::new(0), 0, 0, 0, 0),
         };
 
         let result = analyzer.analyze(&program);
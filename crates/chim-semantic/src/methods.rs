@@ -0,0 +1,176 @@
+use chim_ast::{Function, Ident, Impl, ImplItem, Item, Path, Program, Type, TypeKind};
+use chim_error::{ChimError, ErrorKind};
+use std::collections::{HashMap, HashSet};
+
+/// The `impl` blocks of a program, keyed by the name of the type they
+/// implement. Method calls are resolved against the inherent impls before
+/// the trait impls. The index keeps its own copy of the impls, so it can be
+/// held while the program is rewritten.
+#[derive(Debug, Clone, Default)]
+pub struct MethodIndex {
+    impls: HashMap<Ident, Vec<Impl>>,
+    trait_impls: HashMap<Ident, Vec<Impl>>,
+    /// Traits declared or implemented in the program.
+    traits: HashSet<Ident>,
+}
+
+impl MethodIndex {
+    pub fn new(program: &Program) -> Self {
+        let mut index = MethodIndex { impls: HashMap::new(), trait_impls: HashMap::new(), traits: HashSet::new() };
+        index.collect(&program.items);
+        index
+    }
+
+    fn collect(&mut self, items: &[Item]) {
+        for item in items {
+            match item {
+                Item::Impl(imp) => {
+                    let Some(name) = Self::type_key(&imp.type_name) else { continue };
+                    match imp.trait_name.as_ref().and_then(Self::type_key) {
+                        Some(trait_name) => {
+                            self.traits.insert(trait_name);
+                            self.trait_impls.entry(name).or_default().push(imp.clone());
+                        }
+                        None => self.impls.entry(name).or_default().push(imp.clone()),
+                    }
+                }
+                Item::Trait(trait_def) => {
+                    self.traits.insert(trait_def.name.clone());
+                }
+                Item::Mod(module) => self.collect(&module.items),
+                _ => {}
+            }
+        }
+    }
+
+    /// The name a receiver type is indexed under; references are looked
+    /// through so `&Point` finds the methods of `Point`.
+    pub fn type_key(ty: &Type) -> Option<Ident> {
+        match &*ty.kind {
+            TypeKind::Path(path) => path.segments.last().map(|segment| segment.ident.clone()),
+            TypeKind::Reference(_, inner, _) | TypeKind::Pointer(inner, _) => Self::type_key(inner),
+            _ => None,
+        }
+    }
+
+    pub fn impls_for(&self, ty: &Type) -> &[Impl] {
+        Self::type_key(ty)
+            .and_then(|name| self.impls.get(&name))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Finds the inherent method `name` on `ty`, searching its `impl` blocks
+    /// in source order.
+    pub fn find_method(&self, ty: &Type, name: &str) -> Option<&Function> {
+        self.impls_for(ty).iter().find_map(|imp| Self::method_in(imp, name))
+    }
+
+    /// Finds the method `name` that `trait_name`'s impl for `ty` provides.
+    pub fn find_trait_method(&self, ty: &Type, trait_name: &str, name: &str) -> Option<&Function> {
+        let impls = Self::type_key(ty).and_then(|key| self.trait_impls.get(&key))?;
+        impls.iter()
+            .filter(|imp| imp.trait_name.as_ref().and_then(Self::type_key).is_some_and(|t| &*t == trait_name))
+            .find_map(|imp| Self::method_in(imp, name))
+    }
+
+    /// What `receiver.name(..)` calls for a receiver of type `ty`: an
+    /// inherent method, else a method of one of the traits `ty` implements.
+    /// An error when several of those traits provide `name`, since the call
+    /// could mean any of them.
+    pub fn resolve_method(&self, ty: &Type, name: &str) -> Result<Option<&Function>, ChimError> {
+        if let Some(method) = self.find_method(ty, name) {
+            return Ok(Some(method));
+        }
+        let impls = Self::type_key(ty).and_then(|key| self.trait_impls.get(&key)).map_or(&[][..], Vec::as_slice);
+        let candidates: Vec<(&Impl, &Function)> =
+            impls.iter().filter_map(|imp| Some((imp, Self::method_in(imp, name)?))).collect();
+        match candidates.as_slice() {
+            [] => Ok(None),
+            [(_, method)] => Ok(Some(method)),
+            _ => {
+                let paths: Vec<String> = candidates.iter()
+                    .filter_map(|(imp, _)| imp.trait_path())
+                    .map(|path| format!("`{}::{}`", path, name))
+                    .collect();
+                Err(ChimError::new(ErrorKind::CannotInfer, format!("multiple applicable methods named `{}`", name))
+                    .with_note(format!("candidates are {}; call one by its path instead", paths.join(", "))))
+            }
+        }
+    }
+
+    /// Resolves the callee of a fully qualified call `Trait::method(receiver,
+    /// ..)`, where `receiver_ty` is the type of the first argument. Only the
+    /// named trait's impl is searched, so this picks the trait method even
+    /// when the type has an inherent method of the same name.
+    ///
+    /// `None` when the path does not name a method of a known trait.
+    pub fn resolve_path_call(&self, path: &Path, receiver_ty: &Type) -> Option<&Function> {
+        let [.., trait_segment, method] = path.segments.as_slice() else { return None };
+        if !self.traits.contains(&trait_segment.ident) {
+            return None;
+        }
+        self.find_trait_method(receiver_ty, &trait_segment.ident, &method.ident)
+    }
+
+    fn method_in<'i>(imp: &'i Impl, name: &str) -> Option<&'i Function> {
+        imp.items.iter().find_map(|item| match item {
+            ImplItem::Function(func) if &*func.name == name => Some(func),
+            _ => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chim_ast::ExprKind;
+    use chim_span::FileId;
+
+    fn parse(source: &str) -> Program {
+        chim_parser::parse(source, FileId::new(0)).unwrap()
+    }
+
+    /// The type `source` names, as written in a parameter.
+    fn ty(source: &str) -> Type {
+        let program = parse(&format!("fn f(x: {}) {{}}", source));
+        let Item::Function(func) = &program.items[0] else { unreachable!() };
+        func.params[0].ty.clone()
+    }
+
+    /// The path `source` spells, as written in a call.
+    fn path(source: &str) -> Path {
+        match *chim_parser::parse_expression(source, FileId::new(0)).unwrap().kind {
+            ExprKind::Path(path) => path,
+            other => panic!("expected a path, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_method_index_finds_inherent_method() {
+        let index = MethodIndex::new(&parse("impl Point { fn x() {} }"));
+        let point = ty("Point");
+        assert_eq!(index.find_method(&point, "x").map(|f| &*f.name), Some("x"));
+        assert!(index.find_method(&point, "y").is_none());
+        assert!(index.find_method(&ty("Line"), "x").is_none());
+    }
+
+    #[test]
+    fn test_path_call_resolves_trait_method() {
+        let index = MethodIndex::new(&parse("impl Point { fn x() {} } impl Show for Point { fn show() {} }"));
+        let point = ty("Point");
+        // `x.show()` and `Show::show(x)` call the same function.
+        let by_method = index.resolve_method(&point, "show").unwrap().expect("`x.show()` should resolve");
+        let by_path = index.resolve_path_call(&path("Show::show"), &point).expect("`Show::show(x)` should resolve");
+        assert!(std::ptr::eq(by_method, by_path));
+        // Inherent methods aren't reachable through a trait path.
+        assert!(index.resolve_path_call(&path("Show::x"), &point).is_none());
+        assert!(index.resolve_path_call(&path("Point::x"), &point).is_none());
+        assert!(index.find_method(&point, "show").is_none());
+
+        // With two traits providing `show`, `x.show()` could mean either.
+        let index = MethodIndex::new(&parse("impl Show for Point { fn show() {} } impl Print for Point { fn show() {} }"));
+        let error = index.resolve_method(&point, "show").unwrap_err();
+        assert_eq!(error.message(), "multiple applicable methods named `show`");
+        assert!(index.resolve_path_call(&path("Print::show"), &point).is_some());
+    }
+}
@@ -0,0 +1,563 @@
+use chim_ast::{
+    Constant, Expr, ExprKind, Function, FunctionSig, GenericParam, Impl, ImplItem, Item, Literal, LiteralKind, Param,
+    Path, PathSegment, ProofExpr, Program, Stmt, StmtKind, Trait, TraitItem, Type, TypeKind, WhereBound,
+};
+use chim_error::{ChimError, ErrorKind};
+use chim_span::Span;
+
+/// What `desugar_to_core` does with forms no backend can lower.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CoreOptions {
+    /// Keep proof-only forms (`proof`, `refl`, `cong`, `rewrite`, ...) and
+    /// the constants `theorem`s become, for a verifier. Otherwise they carry
+    /// no runtime meaning and are erased.
+    pub keep_proofs: bool,
+}
+
+/// Lowers the declaration-like expression forms into core items:
+///
+/// - `class C(T) { fn .. }` becomes `trait C<T> { fn ..; }`
+/// - `instance C Ty { fn .. }` becomes `impl C for Ty { fn .. }`
+/// - `theorem`/`lemma name: prop = proof` becomes a `const` holding the
+///   proof term, or a function of its parameters when it has any
+///
+/// The items they become are hoisted to the enclosing module, after the item
+/// they were declared in; a declaration used as a value leaves `()` behind.
+/// Proof-only expressions are erased to `()` unless `options.keep_proofs` is
+/// set.
+pub fn desugar_to_core(program: &mut Program, options: &CoreOptions) -> Vec<ChimError> {
+    let mut errors = Vec::new();
+    lower_items(&mut program.items, options, &mut errors);
+    errors
+}
+
+fn lower_items(items: &mut Vec<Item>, options: &CoreOptions, errors: &mut Vec<ChimError>) {
+    let mut lowered = Vec::with_capacity(items.len());
+    for mut item in items.drain(..) {
+        let mut hoisted = Vec::new();
+        match &mut item {
+            Item::Function(func) => lower_stmts(&mut func.body, &mut hoisted, options, errors),
+            Item::Impl(imp) => {
+                for item in &mut imp.items {
+                    match item {
+                        ImplItem::Function(func) => lower_stmts(&mut func.body, &mut hoisted, options, errors),
+                        ImplItem::Const(constant) => lower_expr(&mut constant.value, &mut hoisted, options, errors),
+                        ImplItem::Type(_) => {}
+                    }
+                }
+            }
+            Item::Constant(constant) => lower_expr(&mut constant.value, &mut hoisted, options, errors),
+            Item::Static(static_def) => {
+                if let Some(value) = &mut static_def.value {
+                    lower_expr(value, &mut hoisted, options, errors);
+                }
+            }
+            Item::Mod(module) => lower_items(&mut module.items, options, errors),
+            _ => {}
+        }
+        lowered.push(item);
+        // An `instance`'s methods are lowered once it is an `impl`.
+        lower_items(&mut hoisted, options, errors);
+        lowered.append(&mut hoisted);
+    }
+    *items = lowered;
+}
+
+/// Lowers `stmts`, dropping the statements that only declared an item.
+fn lower_stmts(stmts: &mut Vec<Stmt>, hoisted: &mut Vec<Item>, options: &CoreOptions, errors: &mut Vec<ChimError>) {
+    let mut kept = Vec::with_capacity(stmts.len());
+    for mut stmt in stmts.drain(..) {
+        if let StmtKind::Expr(expr) = &stmt.kind {
+            if let Some(item) = lower_declaration(expr, options, errors) {
+                hoisted.extend(item);
+                continue;
+            }
+        }
+        lower_stmt(&mut stmt, hoisted, options, errors);
+        kept.push(stmt);
+    }
+    *stmts = kept;
+}
+
+fn lower_stmt(stmt: &mut Stmt, hoisted: &mut Vec<Item>, options: &CoreOptions, errors: &mut Vec<ChimError>) {
+    match &mut stmt.kind {
+        StmtKind::Expr(expr) => lower_expr(expr, hoisted, options, errors),
+        StmtKind::Let(let_stmt) => {
+            if let Some(init) = &mut let_stmt.initializer {
+                lower_expr(init, hoisted, options, errors);
+            }
+        }
+        StmtKind::Var(var_stmt) => {
+            if let Some(init) = &mut var_stmt.initializer {
+                lower_expr(init, hoisted, options, errors);
+            }
+        }
+        StmtKind::Return(Some(value)) | StmtKind::Break(Some(value)) => lower_expr(value, hoisted, options, errors),
+        StmtKind::Loop(loop_stmt) => lower_stmts(&mut loop_stmt.body, hoisted, options, errors),
+        StmtKind::While(while_stmt) => {
+            lower_expr(&mut while_stmt.condition, hoisted, options, errors);
+            lower_stmts(&mut while_stmt.body, hoisted, options, errors);
+        }
+        StmtKind::For(for_stmt) => {
+            lower_expr(&mut for_stmt.iterable, hoisted, options, errors);
+            lower_stmts(&mut for_stmt.body, hoisted, options, errors);
+        }
+        StmtKind::Guard(guard) => {
+            lower_expr(&mut guard.condition, hoisted, options, errors);
+            lower_stmts(&mut guard.else_branch, hoisted, options, errors);
+        }
+        StmtKind::Defer(body) => lower_stmts(body, hoisted, options, errors),
+        StmtKind::Return(None) | StmtKind::Break(None) | StmtKind::Continue(_) | StmtKind::Empty => {}
+    }
+}
+
+/// Lowers every expression nested in `expr`, then `expr` itself: a
+/// declaration is hoisted and leaves `()` in its place, and a proof-only
+/// expression is erased to `()`.
+fn lower_expr(expr: &mut Expr, hoisted: &mut Vec<Item>, options: &CoreOptions, errors: &mut Vec<ChimError>) {
+    match &mut *expr.kind {
+        ExprKind::If(if_expr) => {
+            lower_expr(&mut if_expr.condition, hoisted, options, errors);
+            lower_stmts(&mut if_expr.then_branch.stmts, hoisted, options, errors);
+            if let Some(else_branch) = &mut if_expr.else_branch {
+                lower_expr(else_branch, hoisted, options, errors);
+            }
+        }
+        ExprKind::Match(match_expr) => {
+            lower_expr(&mut match_expr.expr, hoisted, options, errors);
+            for arm in &mut match_expr.arms {
+                if let Some(guard) = &mut arm.guard {
+                    lower_expr(guard, hoisted, options, errors);
+                }
+                lower_expr(&mut arm.body, hoisted, options, errors);
+            }
+        }
+        ExprKind::Case(case) => {
+            lower_expr(&mut case.value, hoisted, options, errors);
+            for case in &mut case.cases {
+                lower_expr(&mut case.body, hoisted, options, errors);
+            }
+        }
+        ExprKind::Block(block) => lower_stmts(&mut block.stmts, hoisted, options, errors),
+        ExprKind::AsyncBlock(block) => lower_stmts(&mut block.body.stmts, hoisted, options, errors),
+        ExprKind::Loop(loop_expr) => lower_stmts(&mut loop_expr.body.stmts, hoisted, options, errors),
+        ExprKind::While(while_expr) => {
+            lower_expr(&mut while_expr.condition, hoisted, options, errors);
+            lower_stmts(&mut while_expr.body.stmts, hoisted, options, errors);
+        }
+        ExprKind::For(for_expr) => {
+            lower_expr(&mut for_expr.iterable, hoisted, options, errors);
+            lower_stmts(&mut for_expr.body.stmts, hoisted, options, errors);
+        }
+        kind => {
+            for operand in operands(kind) {
+                lower_expr(operand, hoisted, options, errors);
+            }
+        }
+    }
+    if let Some(item) = lower_declaration(expr, options, errors) {
+        hoisted.extend(item);
+        *expr.kind = ExprKind::Literal(Literal { kind: LiteralKind::Unit, span: expr.span });
+    } else if !options.keep_proofs {
+        erase_proofs(expr);
+    }
+}
+
+/// The expressions directly inside `kind`, for the kinds that hold no
+/// statement blocks. A `class` or `instance` is lowered once it is hoisted.
+fn operands(kind: &mut ExprKind) -> Vec<&mut Expr> {
+    match kind {
+        ExprKind::Binary(binary) => vec![&mut *binary.left, &mut *binary.right],
+        ExprKind::Unary(unary) => vec![&mut *unary.expr],
+        ExprKind::Call(call) => {
+            std::iter::once(&mut *call.func).chain(call.args.iter_mut().map(|arg| &mut **arg)).collect()
+        }
+        ExprKind::MethodCall(call) => {
+            std::iter::once(&mut *call.expr).chain(call.args.iter_mut().map(|arg| &mut **arg)).collect()
+        }
+        ExprKind::FieldAccess(access) => vec![&mut *access.expr],
+        ExprKind::Tuple(tuple) => tuple.elements.iter_mut().map(|element| &mut **element).collect(),
+        ExprKind::Array(array) => array.elements.iter_mut().map(|element| &mut **element).collect(),
+        ExprKind::Index(index) => vec![&mut *index.expr, &mut *index.index],
+        ExprKind::Slice(slice) => std::iter::once(&mut *slice.expr)
+            .chain(slice.start.as_deref_mut())
+            .chain(slice.end.as_deref_mut())
+            .collect(),
+        ExprKind::Cast(cast) => vec![&mut *cast.expr],
+        ExprKind::Ternary(ternary) => {
+            vec![&mut *ternary.condition, &mut *ternary.then_branch, &mut *ternary.else_branch]
+        }
+        ExprKind::Closure(closure) => vec![&mut *closure.body],
+        ExprKind::Break(_, value) | ExprKind::Return(value) => value.as_deref_mut().into_iter().collect(),
+        ExprKind::Assign(assign) => vec![&mut *assign.left, &mut *assign.right],
+        ExprKind::AssignOp(assign) => vec![&mut *assign.left, &mut *assign.right],
+        ExprKind::Range(range) => range.start.as_deref_mut().into_iter().chain(range.end.as_deref_mut()).collect(),
+        ExprKind::Struct(struct_expr) => struct_expr
+            .fields
+            .iter_mut()
+            .map(|field| &mut field.expr)
+            .chain(struct_expr.base.as_deref_mut())
+            .collect(),
+        ExprKind::Enum(enum_expr) => enum_expr.fields.iter_mut().map(|field| &mut field.expr).collect(),
+        ExprKind::Field(field) => vec![&mut field.expr],
+        ExprKind::AtomicLoad(load) => vec![&mut *load.atomic],
+        ExprKind::AtomicStore(store) => vec![&mut *store.atomic, &mut *store.value],
+        ExprKind::AtomicFetchAdd(fetch)
+        | ExprKind::AtomicFetchSub(fetch)
+        | ExprKind::AtomicFetchAnd(fetch)
+        | ExprKind::AtomicFetchOr(fetch)
+        | ExprKind::AtomicFetchXor(fetch) => vec![&mut *fetch.atomic, &mut *fetch.value],
+        ExprKind::AtomicCompareExchange(exchange) => {
+            vec![&mut *exchange.atomic, &mut *exchange.expected, &mut *exchange.desired]
+        }
+        ExprKind::AtomicExchange(exchange) => vec![&mut *exchange.atomic, &mut *exchange.value],
+        ExprKind::Wait(wait) => std::iter::once(&mut *wait.atomic).chain(wait.timeout.as_deref_mut()).collect(),
+        ExprKind::Notify(notify) => vec![&mut *notify.atomic],
+        ExprKind::NotifyAll(notify) => vec![&mut *notify.atomic],
+        ExprKind::EffectBlock(block) => vec![&mut *block.body],
+        ExprKind::AbilityBlock(block) => vec![&mut *block.body],
+        ExprKind::PushFront(push) => vec![&mut *push.list, &mut *push.value],
+        ExprKind::PushBack(push) => vec![&mut *push.list, &mut *push.value],
+        ExprKind::PopFront(op) => vec![&mut *op.list],
+        ExprKind::PopBack(op) => vec![&mut *op.list],
+        ExprKind::Front(op) => vec![&mut *op.list],
+        ExprKind::Back(op) => vec![&mut *op.list],
+        ExprKind::Clear(op) => vec![&mut *op.list],
+        ExprKind::Reverse(op) => vec![&mut *op.list],
+        ExprKind::Sort(op) => vec![&mut *op.list],
+        ExprKind::Unique(op) => vec![&mut *op.list],
+        ExprKind::Insert(insert) => vec![&mut *insert.list, &mut *insert.position, &mut *insert.value],
+        ExprKind::Erase(op) => vec![&mut *op.list, &mut *op.value],
+        ExprKind::Remove(op) => vec![&mut *op.list, &mut *op.value],
+        ExprKind::Splice(op) => vec![&mut *op.list1, &mut *op.list2],
+        ExprKind::Merge(op) => vec![&mut *op.list1, &mut *op.list2],
+        ExprKind::Iterator(iterator) => vec![&mut *iterator.iterable],
+        ExprKind::Next(op) => vec![&mut *op.iterator],
+        ExprKind::Item(op) => vec![&mut *op.iterator],
+        ExprKind::Collect(op) => vec![&mut *op.iterator],
+        ExprKind::Chain(chain) => vec![&mut *chain.iterator1, &mut *chain.iterator2],
+        ExprKind::Filter(filter) => vec![&mut *filter.iterator, &mut *filter.predicate],
+        ExprKind::Fold(fold) => vec![&mut *fold.iterator, &mut *fold.init, &mut *fold.body],
+        ExprKind::Map(map) => vec![&mut *map.iterator, &mut *map.mapper],
+        ExprKind::Ok(ok) => vec![&mut *ok.value],
+        ExprKind::Err(err) => vec![&mut *err.error],
+        ExprKind::Try(try_expr) => vec![&mut *try_expr.expr],
+        ExprKind::Catch(catch) => vec![&mut *catch.try_expr, &mut *catch.catch_expr],
+        ExprKind::ErrorExpr(error) => vec![&mut *error.message],
+        ExprKind::Context(context) => vec![&mut *context.context],
+        ExprKind::Throw(throw) => vec![&mut *throw.error],
+        ExprKind::Future(future) => vec![&mut *future.body],
+        ExprKind::Yield(yield_expr) => yield_expr.value.as_deref_mut().into_iter().collect(),
+        ExprKind::Stream(stream) => vec![&mut *stream.body],
+        ExprKind::Unsafe(unsafe_expr) => vec![&mut *unsafe_expr.body],
+        ExprKind::Alloc(alloc) => alloc.size.as_deref_mut().into_iter().collect(),
+        ExprKind::AllocAligned(alloc) => vec![&mut *alloc.size, &mut *alloc.alignment],
+        ExprKind::Free(free) => vec![&mut *free.ptr],
+        ExprKind::PtrAdd(add) => vec![&mut *add.ptr, &mut *add.offset],
+        ExprKind::PtrSub(sub) => vec![&mut *sub.ptr1, &mut *sub.ptr2],
+        ExprKind::PtrLoad(load) => vec![&mut *load.ptr],
+        ExprKind::PtrStore(store) => vec![&mut *store.ptr, &mut *store.value],
+        ExprKind::PtrCast(cast) => vec![&mut *cast.ptr],
+        ExprKind::PtrOffsetOf(offset) => vec![&mut *offset.ptr],
+        ExprKind::Proof(proof) => vec![&mut *proof.proposition, &mut *proof.proof],
+        ExprKind::Theorem(theorem) => vec![&mut *theorem.proposition, &mut *theorem.proof],
+        ExprKind::Lemma(lemma) => vec![&mut *lemma.proposition, &mut *lemma.proof],
+        ExprKind::Induction(induction) => vec![&mut *induction.base_case, &mut *induction.inductive_step],
+        ExprKind::Cong(cong) => vec![&mut *cong.expr1, &mut *cong.expr2],
+        ExprKind::Sym(sym) => vec![&mut *sym.expr],
+        ExprKind::Trans(trans) => vec![&mut *trans.expr1, &mut *trans.expr2, &mut *trans.expr3],
+        ExprKind::Rec(rec) => vec![&mut *rec.body],
+        ExprKind::Fix(fix) => vec![&mut *fix.body],
+        ExprKind::EqProp(eq) => vec![&mut *eq.left, &mut *eq.right],
+        ExprKind::ReflProp(refl) => vec![&mut *refl.expr],
+        ExprKind::JMeq(jmeq) => vec![&mut *jmeq.expr1, &mut *jmeq.expr2],
+        ExprKind::Rewrite(rewrite) => vec![&mut *rewrite.expr, &mut *rewrite.rule],
+        ExprKind::Where(where_expr) => {
+            std::iter::once(&mut *where_expr.expr).chain(&mut where_expr.constraints).collect()
+        }
+        ExprKind::With(with) => {
+            std::iter::once(&mut *with.expr).chain(with.bindings.iter_mut().map(|(_, value)| value)).collect()
+        }
+        // Leaves, and the kinds handled elsewhere.
+        ExprKind::Literal(_)
+        | ExprKind::Identifier(_)
+        | ExprKind::Path(_)
+        | ExprKind::Continue(_)
+        | ExprKind::MacroCall(_)
+        | ExprKind::Error
+        | ExprKind::AtomicFence(_)
+        | ExprKind::LinkedList(_)
+        | ExprKind::ListNode(_)
+        | ExprKind::Result(_)
+        | ExprKind::Ptr(_)
+        | ExprKind::PtrSizeOf(_)
+        | ExprKind::AlignOf(_)
+        | ExprKind::Refl(_)
+        | ExprKind::If(_)
+        | ExprKind::Match(_)
+        | ExprKind::Case(_)
+        | ExprKind::Block(_)
+        | ExprKind::AsyncBlock(_)
+        | ExprKind::Loop(_)
+        | ExprKind::While(_)
+        | ExprKind::For(_)
+        | ExprKind::Class(_)
+        | ExprKind::Instance(_) => Vec::new(),
+    }
+}
+
+/// The items a `class`, `instance`, `theorem` or `lemma` declares; `None`
+/// for any other expression. A `theorem` or `lemma` declares nothing once
+/// proofs are erased.
+fn lower_declaration(expr: &Expr, options: &CoreOptions, errors: &mut Vec<ChimError>) -> Option<Option<Item>> {
+    let span = expr.span;
+    let item = match &*expr.kind {
+        ExprKind::Class(class) => {
+            let items = class.methods.iter().map(|method| {
+                if !method.body.is_empty() {
+                    errors.push(ChimError::new(
+                        ErrorKind::TypeMismatch,
+                        format!("method `{}` of class `{}` cannot have a default body", method.name, class.name),
+                    ).with_span(method.span));
+                }
+                TraitItem::Function(signature(method))
+            }).collect();
+            Item::Trait(Trait {
+                name: class.name.clone(),
+                items,
+                span,
+                is_pub: false,
+                generics: class.params.iter().map(class_param).collect(),
+                super_traits: Vec::new(),
+                where_clauses: Vec::new(),
+                attributes: Vec::new(),
+            })
+        }
+        ExprKind::Instance(instance) => Item::Impl(Impl {
+            trait_name: Some(Type {
+                kind: Box::new(TypeKind::Path(Path {
+                    segments: vec![PathSegment { ident: instance.class_name.clone(), args: Vec::new(), span }],
+                    span,
+                })),
+                span,
+            }),
+            type_name: instance.ty.clone(),
+            items: instance.methods.iter().cloned().map(ImplItem::Function).collect(),
+            span,
+            generics: instance.generics.clone(),
+            where_clauses: Vec::new(),
+            attributes: Vec::new(),
+        }),
+        ExprKind::Theorem(theorem) if options.keep_proofs => {
+            proof_item(&theorem.name, &theorem.params, &theorem.proposition, &theorem.proof, span)
+        }
+        ExprKind::Lemma(lemma) if options.keep_proofs => {
+            proof_item(&lemma.name, &lemma.params, &lemma.proposition, &lemma.proof, span)
+        }
+        ExprKind::Theorem(_) | ExprKind::Lemma(_) => return Some(None),
+        _ => return None,
+    };
+    Some(Some(item))
+}
+
+/// The trait parameter for `T` in `class C(T)`; a written type, as in
+/// `class C(T: Show)`, is its bound.
+fn class_param(param: &Param) -> GenericParam {
+    let bounds = match &*param.ty.kind {
+        TypeKind::Infer => Vec::new(),
+        _ => vec![WhereBound {
+            trait_ref: Some(param.ty.clone()),
+            lifetime_bounds: Vec::new(),
+            relaxed: false,
+            span: param.ty.span,
+        }],
+    };
+    GenericParam { name: param.name.clone(), bounds, const_ty: None, span: param.span }
+}
+
+fn signature(method: &Function) -> FunctionSig {
+    FunctionSig {
+        name: method.name.clone(),
+        params: method.params.clone(),
+        return_type: method.return_type.clone(),
+        span: method.span,
+        is_variadic: false,
+    }
+}
+
+/// A `const` holding `proof : proposition`, or a function returning it when
+/// the theorem quantifies over `params`.
+fn proof_item(name: &chim_ast::Ident, params: &[chim_ast::Param], proposition: &Expr, proof: &Expr, span: Span) -> Item {
+    let value = Expr {
+        kind: Box::new(ExprKind::Proof(ProofExpr {
+            proposition: Box::new(proposition.clone()),
+            proof: Box::new(proof.clone()),
+        })),
+        span,
+        ty: None,
+    };
+    if params.is_empty() {
+        return Item::Constant(Constant { name: name.clone(), ty: None, value, span, is_pub: false });
+    }
+    Item::Function(Function {
+        name: name.clone(),
+        params: params.to_vec(),
+        return_type: None,
+        body: vec![Stmt { kind: StmtKind::Expr(value), span }],
+        span,
+        is_pub: false,
+        is_async: false,
+        lifetimes: Vec::new(),
+        generics: Vec::new(),
+        where_clauses: Vec::new(),
+        attributes: Vec::new(),
+    })
+}
+
+/// Replaces a proof-only expression with `()`.
+fn erase_proofs(expr: &mut Expr) {
+    let proof_only = matches!(
+        &*expr.kind,
+        ExprKind::Proof(_)
+            | ExprKind::Induction(_)
+            | ExprKind::Refl(_)
+            | ExprKind::Cong(_)
+            | ExprKind::Sym(_)
+            | ExprKind::Trans(_)
+            | ExprKind::EqProp(_)
+            | ExprKind::ReflProp(_)
+            | ExprKind::JMeq(_)
+            | ExprKind::Rewrite(_)
+    );
+    if proof_only {
+        *expr.kind = ExprKind::Literal(Literal { kind: LiteralKind::Unit, span: expr.span });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chim_ast::{ClassExpr, InstanceExpr, TheoremExpr};
+    use chim_span::FileId;
+    use std::sync::Arc;
+
+    // `class`, `instance` and `theorem` don't parse yet, so the tests build
+    // those nodes around parsed functions, types and expressions.
+
+    fn parse(source: &str) -> Program {
+        chim_parser::parse(source, FileId::new(0)).unwrap()
+    }
+
+    fn parse_expr(source: &str) -> Expr {
+        chim_parser::parse_expression(source, FileId::new(0)).unwrap()
+    }
+
+    fn parse_type(source: &str) -> Type {
+        match *parse_expr(&format!("x as {}", source)).kind {
+            ExprKind::Cast(cast) => cast.ty,
+            other => panic!("expected a cast, got {:?}", other),
+        }
+    }
+
+    fn into_function(item: Option<Item>) -> Function {
+        match item {
+            Some(Item::Function(func)) => func,
+            other => panic!("expected function, got {:?}", other),
+        }
+    }
+
+    fn expr(kind: ExprKind, span: Span) -> Expr {
+        Expr { kind: Box::new(kind), span, ty: None }
+    }
+
+    #[test]
+    fn test_desugar_class_and_instance_to_core() {
+        let program = || {
+            let mut program = parse("fn main() {} fn show() -> str {} fn show() -> str { s }");
+            let instance_show = into_function(program.items.pop());
+            let class_show = into_function(program.items.pop());
+            let Item::Function(main) = &mut program.items[0] else { unreachable!() };
+            let span = main.span;
+            let stmt = |kind| Stmt { kind: StmtKind::Expr(expr(kind, span)), span };
+            main.body = vec![
+                stmt(ExprKind::Class(ClassExpr {
+                    name: Arc::from("Show"),
+                    params: Vec::new(),
+                    methods: vec![class_show],
+                })),
+                stmt(ExprKind::Instance(InstanceExpr {
+                    generics: Vec::new(),
+                    class_name: Arc::from("Show"),
+                    ty: parse_type("int"),
+                    methods: vec![instance_show],
+                })),
+                stmt(ExprKind::Theorem(TheoremExpr {
+                    name: Arc::from("trivial"),
+                    params: Vec::new(),
+                    proposition: Box::new(parse_expr("p")),
+                    proof: Box::new(parse_expr("refl int")),
+                })),
+            ];
+            program
+        };
+
+        let mut erased = program();
+        assert!(desugar_to_core(&mut erased, &CoreOptions::default()).is_empty());
+        let Item::Function(main) = &erased.items[0] else { panic!("expected `main`") };
+        assert!(main.body.is_empty());
+        assert_eq!(erased.items.len(), 3);
+        let Item::Trait(show) = &erased.items[1] else { panic!("expected a trait, got {:?}", erased.items[1]) };
+        assert_eq!(&*show.name, "Show");
+        assert!(matches!(&show.items[..], [TraitItem::Function(sig)] if &*sig.name == "show"));
+        let Item::Impl(instance) = &erased.items[2] else { panic!("expected an impl, got {:?}", erased.items[2]) };
+        assert_eq!(instance.trait_name.as_ref().map(|t| t.to_string()), Some("Show".to_string()));
+        assert_eq!(instance.type_name.to_string(), "int");
+        assert_eq!(instance.items.len(), 1);
+
+        // With proofs kept, the theorem becomes a constant holding its proof.
+        let mut kept = program();
+        assert!(desugar_to_core(&mut kept, &CoreOptions { keep_proofs: true }).is_empty());
+        assert_eq!(kept.items.len(), 4);
+        assert!(matches!(&kept.items[3], Item::Constant(c) if &*c.name == "trivial"
+            && matches!(&*c.value.kind, ExprKind::Proof(_))));
+    }
+
+    #[test]
+    fn test_desugar_to_core_reaches_nested_declarations() {
+        let mut program = parse("fn main() { f(refl int); } fn show() { 1; } fn header<T>(T: Display) {}");
+        let header = into_function(program.items.pop());
+        let show = into_function(program.items.pop());
+        let Item::Function(main) = &mut program.items[0] else { unreachable!() };
+        let StmtKind::Expr(call) = &mut main.body[0].kind else { panic!("expected the call") };
+        let span = call.span;
+        let ExprKind::Call(call) = &mut *call.kind else { panic!("expected the call") };
+        // `f(class .., instance .., refl int)`
+        call.args.insert(0, Box::new(expr(ExprKind::Class(ClassExpr {
+            name: Arc::from("Show"),
+            params: header.params,
+            methods: vec![show],
+        }), span)));
+        call.args.insert(1, Box::new(expr(ExprKind::Instance(InstanceExpr {
+            generics: header.generics,
+            class_name: Arc::from("Show"),
+            ty: parse_type("T"),
+            methods: Vec::new(),
+        }), span)));
+
+        let errors = desugar_to_core(&mut program, &CoreOptions::default());
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, ErrorKind::TypeMismatch);
+        assert!(errors[0].message().contains("cannot have a default body"));
+
+        assert_eq!(program.items.len(), 3);
+        let Item::Function(main) = &program.items[0] else { panic!("expected `main`") };
+        let StmtKind::Expr(call) = &main.body[0].kind else { panic!("expected the call") };
+        let ExprKind::Call(call) = &*call.kind else { panic!("expected the call") };
+        assert!(call.args.iter().all(|arg| matches!(&*arg.kind, ExprKind::Literal(_))));
+
+        let Item::Trait(show) = &program.items[1] else { panic!("expected a trait") };
+        assert_eq!(show.generics[0].bounds[0].trait_ref.as_ref().map(|t| t.to_string()), Some("Display".to_string()));
+        let Item::Impl(instance) = &program.items[2] else { panic!("expected an impl") };
+        assert_eq!(&*instance.generics[0].name, "T");
+    }
+}
@@ -0,0 +1,55 @@
+use chim_ast::{BinOp, UnOp};
+
+/// The trait and method an overloaded binary operator resolves to, so that
+/// `a + b` can be rewritten to `a.add(b)` when `a` implements `Add`.
+///
+/// `&&` and `||` short-circuit and cannot be overloaded, so they map to `None`.
+pub fn binop_trait(op: BinOp) -> Option<(&'static str, &'static str)> {
+    let pair = match op {
+        BinOp::Add => ("Add", "add"),
+        BinOp::Sub => ("Sub", "sub"),
+        BinOp::Mul => ("Mul", "mul"),
+        BinOp::Div => ("Div", "div"),
+        BinOp::Mod => ("Rem", "rem"),
+        BinOp::BitAnd => ("BitAnd", "bitand"),
+        BinOp::BitOr => ("BitOr", "bitor"),
+        BinOp::BitXor => ("BitXor", "bitxor"),
+        BinOp::Shl => ("Shl", "shl"),
+        BinOp::Shr => ("Shr", "shr"),
+        BinOp::Eq => ("PartialEq", "eq"),
+        BinOp::Ne => ("PartialEq", "ne"),
+        BinOp::Lt => ("PartialOrd", "lt"),
+        BinOp::Le => ("PartialOrd", "le"),
+        BinOp::Gt => ("PartialOrd", "gt"),
+        BinOp::Ge => ("PartialOrd", "ge"),
+        BinOp::And | BinOp::Or => return None,
+    };
+    Some(pair)
+}
+
+/// The trait and method an overloaded unary operator resolves to. Taking a
+/// reference is built in and never overloaded.
+pub fn unop_trait(op: UnOp) -> Option<(&'static str, &'static str)> {
+    match op {
+        UnOp::Neg => Some(("Neg", "neg")),
+        UnOp::Not => Some(("Not", "not")),
+        UnOp::Deref => Some(("Deref", "deref")),
+        UnOp::Ref | UnOp::RefMut => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_operator_traits() {
+        assert_eq!(binop_trait(BinOp::Add), Some(("Add", "add")));
+        assert_eq!(binop_trait(BinOp::Mod), Some(("Rem", "rem")));
+        assert_eq!(binop_trait(BinOp::Ne), Some(("PartialEq", "ne")));
+        assert_eq!(binop_trait(BinOp::Ge), Some(("PartialOrd", "ge")));
+        assert_eq!(binop_trait(BinOp::And), None);
+        assert_eq!(unop_trait(UnOp::Neg), Some(("Neg", "neg")));
+        assert_eq!(unop_trait(UnOp::Ref), None);
+    }
+}
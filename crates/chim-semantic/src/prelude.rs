@@ -0,0 +1,268 @@
+use crate::walk::{walk_expr, walk_params, walk_stmts, TypeVisitor};
+use chim_ast::{
+    Function, FunctionSig, GenericArgKind, GenericParam, Ident, ImplItem, Item, Path, PathSegment, Program, TraitItem,
+    Type, TypeKind,
+};
+use chim_span::Span;
+use std::collections::HashSet;
+
+/// Names in scope in every module without a `use`. Each path brings in its
+/// last segment, e.g. `std::Option` makes `Option` available.
+#[derive(Debug, Clone, Default)]
+pub struct Prelude {
+    paths: Vec<Path>,
+}
+
+impl Prelude {
+    pub fn new(paths: Vec<Path>) -> Self {
+        Prelude { paths }
+    }
+
+    /// What `chim_std` provides everywhere: `Result` and `Option`, which it
+    /// re-exports at its root, and the `collections` types.
+    pub fn std() -> Self {
+        let path = |segments: &[&str]| Path {
+            segments: segments
+                .iter()
+                .map(|segment| PathSegment { ident: Ident::from(*segment), args: Vec::new(), span: Span::dummy() })
+                .collect(),
+            span: Span::dummy(),
+        };
+        Prelude::new(vec![
+            path(&["std", "Result"]),
+            path(&["std", "Option"]),
+            path(&["std", "collections", "Vec"]),
+            path(&["std", "collections", "List"]),
+            path(&["std", "collections", "Map"]),
+        ])
+    }
+
+    /// The prelude path for the bare name `name`.
+    pub fn get(&self, name: &str) -> Option<&Path> {
+        self.paths.iter().find(|path| path.segments.last().is_some_and(|segment| &*segment.ident == name))
+    }
+}
+
+/// Rewrites each bare name the prelude provides to its full path, so
+/// `Option<int>` becomes `std::Option<int>` and `Option::Some(1)` becomes
+/// `std::Option::Some(1)`. Covers every place a type or path is written:
+/// signatures, fields, impl headers, trait items, consts and statics, and
+/// the types and paths inside bodies.
+///
+/// A module's own items and `use` imports shadow the prelude, as do generic
+/// parameters: with an `enum Option` in the module, `Option` keeps naming it.
+pub fn resolve_prelude(program: &mut Program, prelude: &Prelude) {
+    resolve_items(&mut program.items, prelude);
+}
+
+/// The names a module declares or imports.
+fn module_names(items: &[Item]) -> HashSet<Ident> {
+    items.iter().filter_map(|item| match item {
+        Item::Struct(struct_def) => Some(struct_def.name.clone()),
+        Item::Enum(enum_def) => Some(enum_def.name.clone()),
+        Item::Trait(trait_def) => Some(trait_def.name.clone()),
+        Item::Mod(module) => Some(module.name.clone()),
+        Item::Use(use_item) => use_item.alias.clone().or_else(|| use_item.path.segments.last().map(|s| s.ident.clone())),
+        _ => None,
+    }).collect()
+}
+
+struct Scope<'p> {
+    prelude: &'p Prelude,
+    shadowed: HashSet<Ident>,
+}
+
+impl Scope<'_> {
+    fn with_generics(&self, generics: &[GenericParam]) -> Self {
+        let mut shadowed = self.shadowed.clone();
+        shadowed.extend(generics.iter().map(|param| param.name.clone()));
+        Scope { prelude: self.prelude, shadowed }
+    }
+}
+
+impl TypeVisitor for &Scope<'_> {
+    fn visit_type(&mut self, ty: &mut Type) {
+        resolve(ty, self);
+    }
+
+    fn visit_path(&mut self, path: &mut Path) {
+        resolve_path(path, self);
+    }
+}
+
+fn resolve_items(items: &mut [Item], prelude: &Prelude) {
+    let scope = Scope { prelude, shadowed: module_names(items) };
+    for item in items {
+        match item {
+            Item::Function(func) => resolve_function(func, &scope),
+            Item::Struct(struct_def) => {
+                let scope = scope.with_generics(&struct_def.generics);
+                for field in &mut struct_def.fields {
+                    resolve(&mut field.ty, &scope);
+                }
+            }
+            Item::Enum(enum_def) => {
+                let scope = scope.with_generics(&enum_def.generics);
+                for field in enum_def.variants.iter_mut().flat_map(|variant| &mut variant.fields) {
+                    resolve(&mut field.ty, &scope);
+                }
+            }
+            Item::Trait(trait_def) => {
+                let scope = scope.with_generics(&trait_def.generics);
+                for super_trait in &mut trait_def.super_traits {
+                    resolve(super_trait, &scope);
+                }
+                for item in &mut trait_def.items {
+                    match item {
+                        TraitItem::Function(sig) => resolve_sig(sig, &scope),
+                        TraitItem::Const(constant) => {
+                            resolve(&mut constant.ty, &scope);
+                            if let Some(default) = &mut constant.default {
+                                walk_expr(default, &mut &scope);
+                            }
+                        }
+                        TraitItem::Type(binding) => resolve(&mut binding.ty, &scope),
+                    }
+                }
+            }
+            Item::Impl(imp) => {
+                let scope = scope.with_generics(&imp.generics);
+                resolve(&mut imp.type_name, &scope);
+                if let Some(trait_name) = &mut imp.trait_name {
+                    resolve(trait_name, &scope);
+                }
+                for item in &mut imp.items {
+                    match item {
+                        ImplItem::Function(func) => resolve_function(func, &scope),
+                        ImplItem::Const(constant) => {
+                            if let Some(ty) = &mut constant.ty {
+                                resolve(ty, &scope);
+                            }
+                            walk_expr(&mut constant.value, &mut &scope);
+                        }
+                        ImplItem::Type(binding) => resolve(&mut binding.ty, &scope),
+                    }
+                }
+            }
+            Item::Constant(constant) => {
+                if let Some(ty) = &mut constant.ty {
+                    resolve(ty, &scope);
+                }
+                walk_expr(&mut constant.value, &mut &scope);
+            }
+            Item::Static(static_def) => {
+                resolve(&mut static_def.ty, &scope);
+                if let Some(value) = &mut static_def.value {
+                    walk_expr(value, &mut &scope);
+                }
+            }
+            Item::Extern(extern_block) => {
+                for extern_item in &mut extern_block.items {
+                    resolve(&mut extern_item.ty, &scope);
+                    if let Some(sig) = &mut extern_item.sig {
+                        resolve_sig(sig, &scope);
+                    }
+                }
+            }
+            Item::Mod(module) => resolve_items(&mut module.items, prelude),
+            _ => {}
+        }
+    }
+}
+
+fn resolve_function(func: &mut Function, scope: &Scope) {
+    let scope = scope.with_generics(&func.generics);
+    walk_params(&mut func.params, &mut &scope);
+    if let Some(ty) = &mut func.return_type {
+        resolve(ty, &scope);
+    }
+    walk_stmts(&mut func.body, &mut &scope);
+}
+
+fn resolve_sig(sig: &mut FunctionSig, scope: &Scope) {
+    walk_params(&mut sig.params, &mut &*scope);
+    if let Some(ty) = &mut sig.return_type {
+        resolve(ty, scope);
+    }
+}
+
+fn resolve(ty: &mut Type, scope: &Scope) {
+    match &mut *ty.kind {
+        TypeKind::Path(path) => resolve_path(path, scope),
+        TypeKind::Tuple(elements) => elements.iter_mut().for_each(|element| resolve(element, scope)),
+        TypeKind::Array(inner, _)
+        | TypeKind::Slice(inner)
+        | TypeKind::Pointer(inner, _)
+        | TypeKind::Reference(_, inner, _) => resolve(inner, scope),
+        TypeKind::Function(function) => {
+            function.params.iter_mut().for_each(|param| resolve(param, scope));
+            resolve(&mut function.return_type, scope);
+        }
+        TypeKind::SelfType | TypeKind::Never | TypeKind::Infer | TypeKind::Error => {}
+    }
+}
+
+/// Resolves the generic arguments in `path`, then its first segment if
+/// that's a prelude name: `Option<T>` as a type, or `Option` in
+/// `Option::Some(x)`.
+fn resolve_path(path: &mut Path, scope: &Scope) {
+    for segment in &mut path.segments {
+        for arg in &mut segment.args {
+            if let GenericArgKind::Type(arg) = &mut arg.kind {
+                resolve(arg, scope);
+            }
+        }
+    }
+    let Some(first) = path.segments.first() else { return };
+    if scope.shadowed.contains(&first.ident) {
+        return;
+    }
+    if let Some(full) = scope.prelude.get(&first.ident) {
+        // Keep the written segment, with its generic arguments and span, in
+        // place of the prelude path's last one.
+        let prefix = full.segments[..full.segments.len() - 1].to_vec();
+        path.segments.splice(0..0, prefix);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chim_ast::{ExprKind, StmtKind};
+    use chim_span::FileId;
+
+    fn parse(source: &str) -> Program {
+        chim_parser::parse(source, FileId::new(0)).unwrap()
+    }
+
+    /// `source` with the standard prelude resolved, and the last function in it.
+    fn resolve(source: &str) -> Function {
+        let mut program = parse(source);
+        resolve_prelude(&mut program, &Prelude::std());
+        match program.items.pop() {
+            Some(Item::Function(func)) => func,
+            other => panic!("expected function, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_resolve_prelude_names() {
+        // Without an import, `Option` is the prelude's.
+        let func = resolve("fn f(x: Option) -> int {}");
+        assert_eq!(func.params[0].ty.to_string(), "std::Option");
+
+        // A local `Option` shadows it; names the prelude lacks are left alone.
+        let func = resolve("enum Option { Some, None } fn f(x: Option) -> int {}");
+        assert_eq!(func.params[0].ty.to_string(), "Option");
+        assert_eq!(func.return_type.as_ref().map(|ty| ty.to_string()).as_deref(), Some("int"));
+
+        // Types and paths in bodies are resolved too.
+        let func = resolve("fn g() { let v: Vec = Option::None as Vec; }");
+        let StmtKind::Let(let_stmt) = &func.body[0].kind else { unreachable!() };
+        assert_eq!(let_stmt.ty.as_ref().map(|ty| ty.to_string()).as_deref(), Some("std::collections::Vec"));
+        let ExprKind::Cast(cast) = &*let_stmt.initializer.as_ref().unwrap().kind else { unreachable!() };
+        assert_eq!(cast.ty.to_string(), "std::collections::Vec");
+        let ExprKind::Path(path) = &*cast.expr.kind else { unreachable!() };
+        assert_eq!(path.to_string(), "std::Option::None");
+    }
+}
@@ -0,0 +1,145 @@
+use crate::walk::{walk_params, walk_stmts, TypeVisitor};
+use chim_ast::{Function, GenericArgKind, ImplItem, Item, Path, Program, Type, TypeKind};
+use chim_error::{ChimError, ErrorKind};
+use chim_span::Span;
+
+/// Replaces `Self` in the items of an `impl` with the type being
+/// implemented, so `impl Point { fn origin() -> Self }` returns a `Point`.
+/// Covers signatures and every type written in a body, and paths through
+/// `Self` such as `Self::Item` or `Self::new`. Inside a trait `Self`
+/// stays as it is, standing for whichever type implements the trait;
+/// anywhere else it is an error.
+pub fn resolve_self_types(program: &mut Program) -> Vec<ChimError> {
+    let mut errors = Vec::new();
+    resolve_items(&mut program.items, &mut errors);
+    errors
+}
+
+fn resolve_items(items: &mut [Item], errors: &mut Vec<ChimError>) {
+    for item in items {
+        match item {
+            Item::Function(func) => resolve_function(func, None, errors),
+            Item::Impl(imp) => {
+                // `impl Self { .. }` has nothing for `Self` to stand for.
+                resolve(&mut imp.type_name, None, errors);
+                let target = imp.type_name.clone();
+                for item in &mut imp.items {
+                    match item {
+                        ImplItem::Function(func) => resolve_function(func, Some(&target), errors),
+                        ImplItem::Const(constant) => {
+                            if let Some(ty) = &mut constant.ty {
+                                resolve(ty, Some(&target), errors);
+                            }
+                        }
+                        ImplItem::Type(binding) => resolve(&mut binding.ty, Some(&target), errors),
+                    }
+                }
+            }
+            Item::Mod(module) => resolve_items(&mut module.items, errors),
+            _ => {}
+        }
+    }
+}
+
+fn resolve_function(func: &mut Function, target: Option<&Type>, errors: &mut Vec<ChimError>) {
+    let mut resolver = Resolver { target, errors };
+    walk_params(&mut func.params, &mut resolver);
+    if let Some(ty) = &mut func.return_type {
+        resolver.visit_type(ty);
+    }
+    walk_stmts(&mut func.body, &mut resolver);
+}
+
+struct Resolver<'a> {
+    target: Option<&'a Type>,
+    errors: &'a mut Vec<ChimError>,
+}
+
+impl TypeVisitor for Resolver<'_> {
+    fn visit_type(&mut self, ty: &mut Type) {
+        resolve(ty, self.target, self.errors);
+    }
+
+    fn visit_path(&mut self, path: &mut Path) {
+        resolve_path(path, self.target, self.errors);
+    }
+}
+
+/// Rewrites every `Self` in `ty` to `target`, keeping the span it was
+/// written at; without a target each one is reported.
+fn resolve(ty: &mut Type, target: Option<&Type>, errors: &mut Vec<ChimError>) {
+    match &mut *ty.kind {
+        TypeKind::SelfType => match target {
+            Some(target) => *ty = Type { kind: target.kind.clone(), span: ty.span },
+            None => errors.push(outside_impl(ty.span)),
+        },
+        TypeKind::Path(path) => resolve_path(path, target, errors),
+        TypeKind::Tuple(elements) => elements.iter_mut().for_each(|element| resolve(element, target, errors)),
+        TypeKind::Array(inner, _)
+        | TypeKind::Slice(inner)
+        | TypeKind::Pointer(inner, _)
+        | TypeKind::Reference(_, inner, _) => resolve(inner, target, errors),
+        TypeKind::Function(function) => {
+            function.params.iter_mut().for_each(|param| resolve(param, target, errors));
+            resolve(&mut function.return_type, target, errors);
+        }
+        TypeKind::Never | TypeKind::Infer | TypeKind::Error => {}
+    }
+}
+
+/// Resolves the generic arguments of `path`, and a leading `Self` segment
+/// as in `Self::Item`: it becomes the target's own path, so inside
+/// `impl Iter` that names `Iter::Item`. A target that is not a path, such as
+/// a tuple, has no associated items to name and is left as written.
+fn resolve_path(path: &mut Path, target: Option<&Type>, errors: &mut Vec<ChimError>) {
+    for segment in &mut path.segments {
+        for arg in &mut segment.args {
+            if let GenericArgKind::Type(arg) = &mut arg.kind {
+                resolve(arg, target, errors);
+            }
+        }
+    }
+    if path.segments.len() < 2 || &*path.segments[0].ident != "Self" {
+        return;
+    }
+    match target.map(|target| &*target.kind) {
+        Some(TypeKind::Path(target)) => {
+            path.segments.splice(..1, target.segments.iter().cloned());
+        }
+        Some(_) => {}
+        None => errors.push(outside_impl(path.segments[0].span)),
+    }
+}
+
+fn outside_impl(span: Span) -> ChimError {
+    ChimError::new(
+        ErrorKind::UndefinedIdentifier,
+        "`Self` is only available inside an `impl` or a trait".to_string(),
+    ).with_span(span)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chim_span::FileId;
+
+    fn parse(source: &str) -> Program {
+        chim_parser::parse(source, FileId::new(0)).unwrap()
+    }
+
+    #[test]
+    fn test_resolve_self_types() {
+        let mut program = parse("impl Point { fn merge(self, other: &Self) -> Self {} }");
+        assert!(resolve_self_types(&mut program).is_empty());
+        let Item::Impl(imp) = &program.items[0] else { panic!("expected impl") };
+        let ImplItem::Function(func) = &imp.items[0] else { panic!("expected method") };
+        assert_eq!(func.return_type.as_ref().unwrap().to_string(), "Point");
+        assert_eq!(func.params[0].ty.to_string(), "Point");
+        assert_eq!(func.params[1].ty.to_string(), "&Point");
+
+        let mut program = parse("fn detached(other: &Self) -> Self {}");
+        let errors = resolve_self_types(&mut program);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].message(), "`Self` is only available inside an `impl` or a trait");
+    }
+}
@@ -0,0 +1,77 @@
+use chim_ast::{FunctionType, GenericArg, GenericArgKind, Ident, Path, PathSegment, Type, TypeKind};
+use std::collections::HashMap;
+
+/// Replaces the type parameters named in `subst` throughout `ty`, as when a
+/// field of `struct Vec<T>` is read through a `Vec<int>`. A parameter is a
+/// single-segment path without arguments; other paths keep their name and
+/// have their arguments substituted. Replacements keep the span of the
+/// parameter they stand in for.
+pub fn substitute(ty: &Type, subst: &HashMap<Ident, Type>) -> Type {
+    let kind = match &*ty.kind {
+        TypeKind::Path(path) => {
+            if let [segment] = path.segments.as_slice() {
+                if segment.args.is_empty() {
+                    if let Some(replacement) = subst.get(&segment.ident) {
+                        return Type { kind: replacement.kind.clone(), span: ty.span };
+                    }
+                }
+            }
+            TypeKind::Path(substitute_path(path, subst))
+        }
+        TypeKind::Tuple(elements) => TypeKind::Tuple(elements.iter().map(|element| substitute(element, subst)).collect()),
+        TypeKind::Array(element, len) => TypeKind::Array(Box::new(substitute(element, subst)), len.clone()),
+        TypeKind::Slice(element) => TypeKind::Slice(Box::new(substitute(element, subst))),
+        TypeKind::Pointer(pointee, mutability) => TypeKind::Pointer(Box::new(substitute(pointee, subst)), *mutability),
+        TypeKind::Reference(lifetime, referent, mutability) => {
+            TypeKind::Reference(lifetime.clone(), Box::new(substitute(referent, subst)), *mutability)
+        }
+        TypeKind::Function(function) => TypeKind::Function(FunctionType {
+            params: function.params.iter().map(|param| substitute(param, subst)).collect(),
+            return_type: Box::new(substitute(&function.return_type, subst)),
+            is_async: function.is_async,
+            is_unsafe: function.is_unsafe,
+            abi: function.abi.clone(),
+        }),
+        TypeKind::SelfType | TypeKind::Never | TypeKind::Infer | TypeKind::Error => return ty.clone(),
+    };
+    Type { kind: Box::new(kind), span: ty.span }
+}
+
+fn substitute_path(path: &Path, subst: &HashMap<Ident, Type>) -> Path {
+    let segments = path
+        .segments
+        .iter()
+        .map(|segment| PathSegment {
+            ident: segment.ident.clone(),
+            args: segment.args.iter().map(|arg| substitute_arg(arg, subst)).collect(),
+            span: segment.span,
+        })
+        .collect();
+    Path { segments, span: path.span }
+}
+
+fn substitute_arg(arg: &GenericArg, subst: &HashMap<Ident, Type>) -> GenericArg {
+    match &arg.kind {
+        GenericArgKind::Type(ty) => GenericArg { kind: GenericArgKind::Type(substitute(ty, subst)), span: arg.span },
+        GenericArgKind::Lifetime(_) => arg.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chim_ast::Item;
+    use chim_span::FileId;
+
+    #[test]
+    fn test_substitute_type_parameters() {
+        let program = chim_parser::parse("fn f(a: Vec<T>, b: (T, [T; 4]), c: U, d: int) {}", FileId::new(0)).unwrap();
+        let Item::Function(func) = &program.items[0] else { unreachable!() };
+        let [vec, tuple, u, int] = [0, 1, 2, 3].map(|i| &func.params[i].ty);
+        let subst = HashMap::from([(Ident::from("T"), int.clone())]);
+
+        assert_eq!(substitute(vec, &subst).to_string(), "Vec<int>");
+        assert_eq!(substitute(tuple, &subst).to_string(), "(int, [int; 4])");
+        assert_eq!(substitute(u, &subst).to_string(), "U");
+    }
+}
@@ -1,5 +1,7 @@
 use crate::type_pool::{TypeId, TypeData, TypePool, IntSize, UintSize, FloatSize, Mutability};
 use crate::ChimError;
+use crate::methods::MethodIndex;
+use crate::variants::VariantIndex;
 use chim_ast::*;
 use chim_span::Span;
 use chim_error::ErrorKind;
@@ -16,6 +18,16 @@ pub enum TypeConstraint {
     SubType(TypeId, TypeId),
 }
 
+/// A loop `break` may target while its body is being inferred.
+#[derive(Debug)]
+struct LoopContext {
+    label: Option<Ident>,
+    /// Only `loop` can produce a value; `while` and `for` always have type unit.
+    accepts_value: bool,
+    /// The unified type of every `break` seen so far that targets this loop.
+    break_ty: Option<TypeId>,
+}
+
 #[derive(Debug)]
 pub struct TypeInferencer {
     pool: TypePool,
@@ -24,6 +36,9 @@ pub struct TypeInferencer {
     errors: Vec<ChimError>,
     scope_stack: Vec<HashMap<Ident, TypeId>>,
     current_function: Option<Function>,
+    loop_stack: Vec<LoopContext>,
+    variants: VariantIndex,
+    methods: MethodIndex,
 }
 
 impl TypeInferencer {
@@ -35,6 +50,9 @@ impl TypeInferencer {
             errors: Vec::new(),
             scope_stack: Vec::new(),
             current_function: None,
+            loop_stack: Vec::new(),
+            variants: VariantIndex::default(),
+            methods: MethodIndex::default(),
         }
     }
 
@@ -127,6 +145,8 @@ impl TypeInferencer {
     }
 
     pub fn infer_program(&mut self, program: &Program) -> Result<(), Vec<ChimError>> {
+        self.variants = VariantIndex::new(program);
+        self.methods = MethodIndex::new(program);
         self.enter_scope();
 
         for item in &program.items {
@@ -156,6 +176,7 @@ impl TypeInferencer {
             Item::Constant(const_def) => self.infer_constant(const_def),
             Item::Static(static_def) => self.infer_static(static_def),
             Item::Macro(macro_def) => self.infer_macro(macro_def),
+            Item::MacroCall(call) => Err(vec![Self::unexpanded_macro(call)]),
             Item::ForAll(forall_def) => self.infer_forall(forall_def),
             Item::Default(default_def) => self.infer_default(default_def),
             Item::Sync(sync_def) => self.infer_sync(sync_def),
@@ -182,6 +203,15 @@ impl TypeInferencer {
         Ok(())
     }
 
+    /// The `const N: usize` parameters of the function being inferred.
+    fn const_params(&self) -> Vec<Ident> {
+        self.current_function.iter()
+            .flat_map(|func| &func.generics)
+            .filter(|param| param.const_ty.is_some())
+            .map(|param| param.name.clone())
+            .collect()
+    }
+
     pub fn infer_struct(&mut self, struct_def: &Struct) -> Result<(), Vec<ChimError>> {
         let _ = struct_def;
         Ok(())
@@ -231,6 +261,14 @@ impl TypeInferencer {
         Ok(())
     }
 
+    /// Macro invocations must be expanded away before inference.
+    fn unexpanded_macro(call: &MacroCall) -> ChimError {
+        ChimError::new(
+            ErrorKind::UndefinedIdentifier,
+            format!("cannot find macro `{}!`", call.name),
+        ).with_span(call.span)
+    }
+
     pub fn infer_forall(&mut self, forall_def: &ForAll) -> Result<(), Vec<ChimError>> {
         let _ = forall_def;
         Ok(())
@@ -306,9 +344,88 @@ impl TypeInferencer {
             ExprKind::Future(future_expr) => self.infer_future_expr(future_expr, expr.span),
             ExprKind::Yield(yield_expr) => self.infer_yield_expr(yield_expr, expr.span),
             ExprKind::Stream(stream_expr) => self.infer_stream_expr(stream_expr, expr.span),
+            ExprKind::Loop(loop_expr) => self.infer_loop_expr(loop_expr),
+            ExprKind::Break(label, value) => {
+                self.infer_break(label.as_ref().map(|l| &l.name), value.as_deref(), expr.span)
+            }
+            ExprKind::Return(value) => {
+                self.infer_return_stmt(value, expr.span)?;
+                Ok(self.pool.builtin_types.never)
+            }
+            ExprKind::Continue(label) => {
+                self.loop_target(label.as_ref().map(|l| &l.name), "continue", expr.span)?;
+                Ok(self.pool.builtin_types.never)
+            }
+            ExprKind::MacroCall(call) => Err(vec![Self::unexpanded_macro(call)]),
+            ExprKind::Path(path) => match self.variants.resolve_value(path, None, expr.span) {
+                Some(Ok(_)) | None => Ok(self.fresh_type_var()),
+                Some(Err(error)) => Err(vec![error]),
+            },
         }
     }
 
+    /// A `loop` expression has the unified type of the values its `break`s
+    /// carry, or `!` if nothing breaks out of it.
+    pub fn infer_loop_expr(&mut self, loop_expr: &LoopExpr) -> Result<TypeId, Vec<ChimError>> {
+        self.loop_stack.push(LoopContext {
+            label: loop_expr.label.clone(),
+            accepts_value: true,
+            break_ty: None,
+        });
+        let body = self.infer_block_expr(&loop_expr.body);
+        let context = self.loop_stack.pop().expect("loop context pushed above");
+        body?;
+        Ok(context.break_ty.unwrap_or(self.pool.builtin_types.never))
+    }
+
+    /// Records a `break` against the loop it targets and yields `!`, since
+    /// control never continues past it.
+    fn infer_break(&mut self, label: Option<&Ident>, value: Option<&Expr>, span: Span) -> Result<TypeId, Vec<ChimError>> {
+        let value_ty = match value {
+            Some(value) => self.infer_expr(value)?,
+            None => self.pool.builtin_types.unit,
+        };
+
+        let target = self.loop_target(label, "break", span)?;
+
+        if value.is_some() && !self.loop_stack[target].accepts_value {
+            return Err(vec![ChimError::new(
+                ErrorKind::TypeMismatch,
+                "`break` with a value is only allowed inside `loop`".to_string(),
+            ).with_span(span)]);
+        }
+
+        match self.loop_stack[target].break_ty {
+            Some(previous) => {
+                if self.unify(previous, value_ty).is_err() {
+                    return Err(vec![ChimError::new(
+                        ErrorKind::TypeMismatch,
+                        "break values have incompatible types".to_string(),
+                    ).with_span(span)]);
+                }
+            }
+            None => self.loop_stack[target].break_ty = Some(value_ty),
+        }
+
+        Ok(self.pool.builtin_types.never)
+    }
+
+    /// The index in `loop_stack` of the loop a `break` or `continue` (the
+    /// `keyword`) with `label` leaves.
+    fn loop_target(&self, label: Option<&Ident>, keyword: &str, span: Span) -> Result<usize, Vec<ChimError>> {
+        let target = match label {
+            Some(label) => self.loop_stack.iter().rposition(|ctx| ctx.label.as_ref() == Some(label)),
+            None => self.loop_stack.len().checked_sub(1),
+        };
+        target.ok_or_else(|| {
+            let message = match label {
+                Some(label) => format!("use of undeclared label `{}`", label),
+                None => format!("`{}` outside of a loop", keyword),
+            };
+            vec![ChimError::new(ErrorKind::TypeMismatch, message).with_span(span)]
+        })
+    }
+
     pub fn infer_literal(&mut self, lit: &Literal) -> Result<TypeId, Vec<ChimError>> {
         match lit.kind {
             LiteralKind::Int(_) => Ok(self.pool.builtin_types.i32),
@@ -378,6 +495,22 @@ impl TypeInferencer {
     }
 
     pub fn infer_call_expr(&mut self, call_expr: &CallExpr, span: Span) -> Result<TypeId, Vec<ChimError>> {
+        if let ExprKind::Path(path) = &*call_expr.func.kind {
+            if let Some(variant) = self.variants.resolve_value(path, Some(call_expr.args.len()), span) {
+                variant.map_err(|error| vec![error])?;
+                for arg in &call_expr.args {
+                    self.infer_expr(arg)?;
+                }
+                return Ok(self.fresh_type_var());
+            }
+            // `Trait::method(x, ..)` calls the method `x`'s type implements.
+            let receiver_ty = call_expr.args.first().and_then(|receiver| receiver.ty.as_ref());
+            if let Some(method) = receiver_ty.and_then(|ty| self.methods.resolve_path_call(path, ty)) {
+                let method = method.clone();
+                return self.infer_method_call(&method, &call_expr.args, span);
+            }
+        }
+
         let func_ty = self.infer_expr(&call_expr.func)?;
         let arg_tys: Result<Vec<_>, _> = call_expr.args.iter()
             .map(|arg| self.infer_expr(arg))
@@ -406,6 +539,25 @@ impl TypeInferencer {
         }
     }
 
+    /// A call to `method` with `args`, the receiver first.
+    fn infer_method_call(&mut self, method: &Function, args: &[Box<Expr>], span: Span) -> Result<TypeId, Vec<ChimError>> {
+        if method.params.len() != args.len() {
+            return Err(vec![ChimError::new(
+                ErrorKind::TypeMismatch,
+                format!("expected {} arguments, got {}", method.params.len(), args.len()),
+            ).with_span(span)]);
+        }
+        for (param, arg) in method.params.iter().zip(args) {
+            let param_ty = self.infer_type(&param.ty)?;
+            let arg_ty = self.infer_expr(arg)?;
+            self.unify(param_ty, arg_ty)?;
+        }
+        match &method.return_type {
+            Some(ty) => self.infer_type(ty),
+            None => Ok(self.pool.builtin_types.unit),
+        }
+    }
+
     pub fn infer_block_expr(&mut self, block_expr: &BlockExpr) -> Result<TypeId, Vec<ChimError>> {
         self.enter_scope();
 
@@ -456,6 +608,19 @@ impl TypeInferencer {
     }
 
     pub fn infer_closure_expr(&mut self, closure_expr: &ClosureExpr, span: Span) -> Result<TypeId, Vec<ChimError>> {
+        let unresolved: Vec<ChimError> = closure_expr.captures.iter()
+            .filter_map(|capture| {
+                let (Capture::CaptureRef(name) | Capture::CaptureValue(name)) = capture;
+                self.lookup_var(name).is_none().then(|| ChimError::new(
+                    ErrorKind::UndefinedIdentifier,
+                    format!("cannot capture `{}`: no variable of that name is in scope", name),
+                ).with_span(span))
+            })
+            .collect();
+        if !unresolved.is_empty() {
+            return Err(unresolved);
+        }
+
         self.enter_scope();
 
         let param_tys: Result<Vec<_>, _> = closure_expr.params.iter()
@@ -662,10 +827,27 @@ impl TypeInferencer {
     }
 
     pub fn infer_try_expr(&mut self, try_expr: &TryExpr, span: Span) -> Result<TypeId, Vec<ChimError>> {
+        // `?` returns early with the error or `None`, so the enclosing
+        // function must be able to return one.
+        if !self.current_function.as_ref().map_or(false, Self::returns_try_type) {
+            return Err(vec![ChimError::new(
+                ErrorKind::TypeMismatch,
+                "the `?` operator can only be used in a function returning Result/Option".to_string(),
+            ).with_span(span)]);
+        }
         let _ = self.infer_expr(&try_expr.expr)?;
         Ok(self.fresh_type_var())
     }
 
+    /// Whether `func` is declared to return a `Result` or an `Option`.
+    fn returns_try_type(func: &Function) -> bool {
+        match func.return_type.as_ref().map(|ty| &*ty.kind) {
+            Some(TypeKind::Path(path)) => path.segments.last()
+                .map_or(false, |segment| matches!(&*segment.ident, "Result" | "Option")),
+            _ => false,
+        }
+    }
+
     pub fn infer_catch_expr(&mut self, catch_expr: &CatchExpr, span: Span) -> Result<TypeId, Vec<ChimError>> {
         let _ = self.infer_expr(&catch_expr.try_expr)?;
         let _ = self.infer_expr(&catch_expr.catch_expr)?;
@@ -711,10 +893,24 @@ impl TypeInferencer {
             StmtKind::Expr(expr) => self.infer_expr(expr),
             StmtKind::Return(return_stmt) => self.infer_return_stmt(return_stmt, stmt.span),
             StmtKind::Break(break_stmt) => self.infer_break_stmt(break_stmt, stmt.span),
-            StmtKind::Continue => Ok(self.pool.builtin_types.unit),
+            StmtKind::Continue(label) => {
+                self.loop_target(label.as_ref().map(|l| &l.name), "continue", stmt.span)?;
+                Ok(self.pool.builtin_types.unit)
+            }
             StmtKind::Loop(loop_stmt) => self.infer_loop_stmt(loop_stmt, stmt.span),
             StmtKind::While(while_stmt) => self.infer_while_stmt(while_stmt, stmt.span),
             StmtKind::For(for_stmt) => self.infer_for_stmt(for_stmt, stmt.span),
+            StmtKind::Guard(guard) => {
+                let _ = self.infer_expr(&guard.to_if_expr())?;
+                Ok(self.pool.builtin_types.unit)
+            }
+            StmtKind::Defer(body) => {
+                self.enter_scope();
+                let result = body.iter().try_for_each(|stmt| self.infer_stmt(stmt).map(|_| ()));
+                self.exit_scope();
+                result?;
+                Ok(self.pool.builtin_types.unit)
+            }
             StmtKind::Match(match_stmt) => {
                 let _ = self.infer_match_expr(&MatchExpr {
                     expr: match_stmt.expr.clone(),
@@ -774,32 +970,33 @@ impl TypeInferencer {
         }
     }
 
-    pub fn infer_break_stmt(&mut self, break_stmt: &Option<Box<Expr>>, span: Span) -> Result<TypeId, Vec<ChimError>> {
-        if let Some(value) = break_stmt {
-            self.infer_expr(value)
-        } else {
-            Ok(self.pool.builtin_types.unit)
-        }
+    pub fn infer_break_stmt(&mut self, break_stmt: &Option<Expr>, span: Span) -> Result<TypeId, Vec<ChimError>> {
+        self.infer_break(None, break_stmt.as_ref(), span)
     }
 
     pub fn infer_loop_stmt(&mut self, loop_stmt: &LoopStmt, span: Span) -> Result<TypeId, Vec<ChimError>> {
+        self.infer_loop_body(loop_stmt.label.clone(), true, &loop_stmt.body)?;
+        Ok(self.pool.builtin_types.unit)
+    }
+
+    fn infer_loop_body(&mut self, label: Option<Ident>, accepts_value: bool, body: &[Stmt]) -> Result<(), Vec<ChimError>> {
+        self.loop_stack.push(LoopContext {
+            label,
+            accepts_value,
+            break_ty: None,
+        });
         self.enter_scope();
-        for stmt in &loop_stmt.body {
-            self.infer_stmt(stmt)?;
-        }
+        let result = body.iter().try_for_each(|stmt| self.infer_stmt(stmt).map(|_| ()));
         self.exit_scope();
-        Ok(self.pool.builtin_types.unit)
+        self.loop_stack.pop();
+        result
     }
 
     pub fn infer_while_stmt(&mut self, while_stmt: &WhileStmt, span: Span) -> Result<TypeId, Vec<ChimError>> {
         let cond_ty = self.infer_expr(&while_stmt.condition)?;
         self.unify(cond_ty, self.pool.builtin_types.bool)?;
 
-        self.enter_scope();
-        for stmt in &while_stmt.body {
-            self.infer_stmt(stmt)?;
-        }
-        self.exit_scope();
+        self.infer_loop_body(while_stmt.label.clone(), false, &while_stmt.body)?;
 
         Ok(self.pool.builtin_types.unit)
     }
@@ -825,17 +1022,16 @@ impl TypeInferencer {
 
         self.enter_scope();
         self.insert_var_from_pattern(&for_stmt.pattern, elem_ty);
-        for stmt in &for_stmt.body {
-            self.infer_stmt(stmt)?;
-        }
+        let body = self.infer_loop_body(for_stmt.label.clone(), false, &for_stmt.body);
         self.exit_scope();
+        body?;
 
         Ok(self.pool.builtin_types.unit)
     }
 
     pub fn infer_pattern(&mut self, pattern: &Pattern, expected_ty: TypeId) -> Result<TypeId, Vec<ChimError>> {
         match &pattern.kind {
-            PatternKind::Identifier(name) => {
+            PatternKind::Identifier(name, _) => {
                 self.unify(expected_ty, self.fresh_type_var())?;
                 Ok(expected_ty)
             }
@@ -847,7 +1043,7 @@ impl TypeInferencer {
                 self.unify(tuple_ty, expected_ty)?;
                 Ok(expected_ty)
             }
-            PatternKind::Slice(patterns) => {
+            PatternKind::Slice(..) => {
                 let slice_ty = self.pool.add_type(TypeData::Slice(expected_ty));
                 self.unify(slice_ty, expected_ty)?;
                 Ok(expected_ty)
@@ -857,7 +1053,7 @@ impl TypeInferencer {
 
     fn insert_var_from_pattern(&mut self, pattern: &Pattern, ty: TypeId) {
         match &pattern.kind {
-            PatternKind::Identifier(name) => {
+            PatternKind::Identifier(name, _) => {
                 self.insert_var(name.clone(), ty);
             }
             PatternKind::Tuple(patterns) => {
@@ -865,10 +1061,14 @@ impl TypeInferencer {
                     self.insert_var_from_pattern(sub_pattern, ty);
                 }
             }
-            PatternKind::Slice(patterns) => {
-                for sub_pattern in patterns {
+            PatternKind::Slice(before, rest, after) => {
+                for sub_pattern in before.iter().chain(after) {
                     self.insert_var_from_pattern(sub_pattern, ty);
                 }
+                if let Some(name) = rest.as_ref().and_then(|rest| rest.binding.clone()) {
+                    let rest_ty = self.pool.add_type(TypeData::Slice(ty));
+                    self.insert_var(name, rest_ty);
+                }
             }
             PatternKind::Wildcard => {}
         }
@@ -877,6 +1077,9 @@ impl TypeInferencer {
     pub fn infer_type(&mut self, ty: &Type) -> Result<TypeId, Vec<ChimError>> {
         match &*ty.kind {
             TypeKind::Infer => Ok(self.fresh_type_var()),
+            // Only a trait's `Self` survives `resolve_self_types`, and it
+            // stands for whichever type implements the trait.
+            TypeKind::SelfType => Ok(self.fresh_type_var()),
             TypeKind::Path(path) => {
                 if let Some(segment) = path.segments.first() {
                     match segment.ident.as_ref() {
@@ -908,9 +1111,22 @@ impl TypeInferencer {
                 let elem_tys = elem_tys?;
                 Ok(self.pool.add_type(TypeData::Tuple(elem_tys)))
             }
-            TypeKind::Array(inner, size) => {
+            TypeKind::Array(inner, len) => {
                 let inner_ty = self.infer_type(inner)?;
-                Ok(self.pool.add_type(TypeData::Array(inner_ty, *size)))
+                let expr = match len {
+                    ArrayLen::Const(expr) => expr,
+                    // `[T; _]` takes its length from the initializer.
+                    ArrayLen::Inferred => return Ok(self.fresh_type_var()),
+                };
+                match crate::array_len(len, &HashMap::new()) {
+                    Ok(size) => Ok(self.pool.add_type(TypeData::Array(inner_ty, size))),
+                    // Lengths naming const parameters stay unsized until the
+                    // parameters are bound.
+                    Err(_) if crate::const_eval::mentions_const_param(expr, &self.const_params()) => {
+                        Ok(self.pool.add_type(TypeData::Slice(inner_ty)))
+                    }
+                    Err(error) => Err(vec![error]),
+                }
             }
             TypeKind::Slice(inner) => {
                 let inner_ty = self.infer_type(inner)?;
@@ -988,9 +1204,9 @@ mod tests {
         let expr = crate::ast::Expr {
             kind: Box::new(crate::ast::ExprKind::Literal(crate::ast::Literal {
                 kind: crate::ast::LiteralKind::Int(42),
-                span: Span::new(chim_span::FileId(0), 0, 2, 0, 0),
+                span: Span::new(chim_span::FileId::new(0), 0, 2, 0, 0),
             })),
-            span: Span::new(chim_span::FileId(0), 0, 2, 0, 0),
+            span: Span::new(chim_span::FileId::new(0), 0, 2, 0, 0),
             ty: None,
         };
 
@@ -1005,22 +1221,134 @@ mod tests {
         let left = crate::ast::Expr {
             kind: Box::new(crate::ast::ExprKind::Literal(crate::ast::Literal {
                 kind: crate::ast::LiteralKind::Int(1),
-                span: Span::new(chim_span::FileId(0), 0, 1, 0, 0),
+                span: Span::new(chim_span::FileId::new(0), 0, 1, 0, 0),
             })),
-            span: Span::new(chim_span::FileId(0), 0, 1, 0, 0),
+            span: Span::new(chim_span::FileId::new(0), 0, 1, 0, 0),
             ty: None,
         };
 
         let right = crate::ast::Expr {
             kind: Box::new(crate::ast::ExprKind::Literal(crate::ast::Literal {
                 kind: crate::ast::LiteralKind::Int(2),
-                span: Span::new(chim_span::FileId(0), 4, 5, 0, 4),
+                span: Span::new(chim_span::FileId::new(0), 4, 5, 0, 4),
             })),
-            span: Span::new(chim_span::FileId(0), 4, 5, 0, 4),
+            span: Span::new(chim_span::FileId::new(0), 4, 5, 0, 4),
             ty: None,
         };
 
         let ty = inferencer.infer_expr(&left);
         assert!(ty.is_ok());
     }
+
+    fn test_expr(kind: ExprKind) -> Expr {
+        Expr {
+            kind: Box::new(kind),
+            span: Span::new(chim_span::FileId::new(0), 0, 0, 0, 0),
+            ty: None,
+        }
+    }
+
+    fn break_with(kind: LiteralKind) -> Stmt {
+        let value = test_expr(ExprKind::Literal(Literal {
+            kind,
+            span: Span::new(chim_span::FileId::new(0), 0, 0, 0, 0),
+        }));
+        Stmt {
+            kind: StmtKind::Expr(test_expr(ExprKind::Break(None, Some(Box::new(value))))),
+            span: Span::new(chim_span::FileId::new(0), 0, 0, 0, 0),
+        }
+    }
+
+    fn loop_of(stmts: Vec<Stmt>) -> Expr {
+        test_expr(ExprKind::Loop(LoopExpr {
+            label: None,
+            body: BlockExpr {
+                label: None,
+                stmts,
+                ty: None,
+            },
+        }))
+    }
+
+    #[test]
+    fn test_loop_break_values_unify() {
+        let mut inferencer = TypeInferencer::new();
+        let expr = loop_of(vec![break_with(LiteralKind::Int(1)), break_with(LiteralKind::Int(2))]);
+        let ty = inferencer.infer_expr(&expr).unwrap();
+        assert_eq!(ty, inferencer.pool.builtin_types.i32);
+    }
+
+    #[test]
+    fn test_loop_break_values_conflict() {
+        let mut inferencer = TypeInferencer::new();
+        let expr = loop_of(vec![break_with(LiteralKind::Int(1)), break_with(LiteralKind::Bool(true))]);
+        let errors = inferencer.infer_expr(&expr).unwrap_err();
+        assert!(errors[0].message().contains("break values have incompatible types"));
+    }
+
+    /// Infers `fn f(x: Result) -> return_type { x? }`.
+    fn infer_try_in(return_type: &str) -> Result<(), Vec<ChimError>> {
+        let span = Span::new(chim_span::FileId::new(0), 0, 0, 0, 0);
+        let path_type = |name: &str| Type {
+            kind: Box::new(TypeKind::Path(Path {
+                segments: vec![PathSegment { ident: Ident::from(name), args: Vec::new(), span }],
+                span,
+            })),
+            span,
+        };
+        let try_expr = test_expr(ExprKind::Try(TryExpr {
+            expr: Box::new(test_expr(ExprKind::Identifier(Ident::from("x")))),
+        }));
+        let func = Function {
+            name: Ident::from("f"),
+            params: vec![Param { name: Ident::from("x"), ty: path_type("Result"), span, is_mut: false, is_ref: false }],
+            return_type: Some(path_type(return_type)),
+            body: vec![Stmt { kind: StmtKind::Expr(try_expr), span }],
+            span,
+            is_pub: false,
+            is_async: false,
+            lifetimes: Vec::new(),
+            generics: Vec::new(),
+            where_clauses: Vec::new(),
+            attributes: Vec::new(),
+        };
+        TypeInferencer::new().infer_function(&func)
+    }
+
+    #[test]
+    fn test_try_requires_result_or_option_return() {
+        assert!(infer_try_in("Result").is_ok());
+        assert!(infer_try_in("Option").is_ok());
+
+        let errors = infer_try_in("int").unwrap_err();
+        assert_eq!(
+            errors[0].message(),
+            "the `?` operator can only be used in a function returning Result/Option"
+        );
+    }
+
+    #[test]
+    fn test_closure_capture_must_resolve() {
+        let expr = chim_parser::parse_expression("move || capture(ref missing) 1", chim_span::FileId::new(0)).unwrap();
+        let ExprKind::Closure(closure) = &*expr.kind else {
+            panic!("expected closure, got {:?}", expr);
+        };
+        let errors = TypeInferencer::new().infer_closure_expr(closure, expr.span).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, ErrorKind::UndefinedIdentifier);
+        assert!(errors[0].message().contains("cannot capture `missing`"));
+    }
+
+    #[test]
+    fn test_array_len_outside_const_params_is_reported() {
+        let source = "fn sum<const N: int>(xs: [int; N]) {} fn bad<const N: int>(xs: [int; M]) {}";
+        let program = chim_parser::parse(source, chim_span::FileId::new(0)).unwrap();
+        let [Item::Function(sum), Item::Function(bad)] = program.items.as_slice() else {
+            panic!("expected two functions, got {:?}", program.items);
+        };
+
+        assert!(TypeInferencer::new().infer_function(sum).is_ok());
+        let errors = TypeInferencer::new().infer_function(bad).unwrap_err();
+        assert_eq!(errors[0].message(), "cannot find const parameter `M`");
+    }
 }
@@ -3,7 +3,7 @@ use std::fmt;
 use std::hash::Hash;
 use std::sync::Arc;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct TypeId(usize);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -260,6 +260,16 @@ impl TypePool {
         id
     }
 
+    /// The type of the struct or enum declared as `name`, if one has been
+    /// interned.
+    pub fn named_type(&self, name: &str) -> Option<TypeId> {
+        let data = match self.structs.iter().position(|data| &*data.name == name) {
+            Some(index) => TypeData::Struct(StructId(index)),
+            None => TypeData::Enum(EnumId(self.enums.iter().position(|data| &*data.name == name)?)),
+        };
+        self.types.iter().position(|ty| ty == &data).map(TypeId)
+    }
+
     pub fn get_type(&self, id: TypeId) -> &TypeData {
         self.types.get(id.0).unwrap_or(&TypeData::Error)
     }
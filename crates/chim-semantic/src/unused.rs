@@ -0,0 +1,321 @@
+use chim_ast::{
+    Expr, ExprKind, Function, Ident, ImplItem, Item, Param, Pattern, PatternKind, Program, Stmt, StmtKind,
+};
+use chim_error::{ChimError, ErrorKind};
+use chim_span::Span;
+
+/// Warns about `let`/`var` bindings and parameters that are never read.
+///
+/// A name is read when it appears in an expression other than as the target
+/// of a plain `=` assignment; reads resolve to the innermost binding of that
+/// name, so a shadowed binding is reported on its own. Names starting with
+/// `_` are never reported, nor is `self`. The returned diagnostics are
+/// warnings: report them with `ErrorReporter::report_warning`.
+pub fn lint_unused_variables(program: &Program) -> Vec<ChimError> {
+    let mut pass = UnusedVariables { scopes: Vec::new(), warnings: Vec::new() };
+    pass.items(&program.items);
+    pass.warnings
+}
+
+struct Binding {
+    name: Ident,
+    span: Span,
+    used: bool,
+    /// Only `let`/`var` bindings and parameters are reported; names bound by
+    /// `for` and `match` patterns are tracked so reads resolve past them.
+    reported: bool,
+}
+
+struct UnusedVariables {
+    scopes: Vec<Vec<Binding>>,
+    warnings: Vec<ChimError>,
+}
+
+impl UnusedVariables {
+    fn items(&mut self, items: &[Item]) {
+        for item in items {
+            match item {
+                Item::Function(func) => self.function(func),
+                Item::Impl(imp) => {
+                    for item in &imp.items {
+                        if let ImplItem::Function(func) = item {
+                            self.function(func);
+                        }
+                    }
+                }
+                Item::Mod(module) => self.items(&module.items),
+                _ => {}
+            }
+        }
+    }
+
+    fn function(&mut self, func: &Function) {
+        self.scopes.push(Vec::new());
+        self.params(&func.params);
+        self.stmts(&func.body);
+        self.pop_scope();
+    }
+
+    fn params(&mut self, params: &[Param]) {
+        for param in params.iter().filter(|param| &*param.name != "self") {
+            self.declare(param.name.clone(), param.span, true);
+        }
+    }
+
+    fn declare(&mut self, name: Ident, span: Span, reported: bool) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(Binding { name, span, used: false, reported });
+        }
+    }
+
+    fn bind_pattern(&mut self, pattern: &Pattern, reported: bool) {
+        match &pattern.kind {
+            PatternKind::Identifier(name, _) => self.declare(name.clone(), pattern.span, reported),
+            PatternKind::Tuple(patterns) | PatternKind::Or(patterns) => {
+                for pattern in patterns {
+                    self.bind_pattern(pattern, reported);
+                }
+            }
+            PatternKind::Struct(_, fields) | PatternKind::Enum(_, _, fields) => {
+                for field in fields {
+                    match &field.pattern {
+                        Some(pattern) => self.bind_pattern(pattern, reported),
+                        None => self.declare(field.name.clone(), pattern.span, reported),
+                    }
+                }
+            }
+            PatternKind::Slice(before, rest, after) => {
+                for pattern in before.iter().chain(after) {
+                    self.bind_pattern(pattern, reported);
+                }
+                if let Some(rest) = rest {
+                    if let Some(name) = &rest.binding {
+                        self.declare(name.clone(), rest.span, reported);
+                    }
+                }
+            }
+            PatternKind::Range(..) | PatternKind::Wildcard | PatternKind::Literal(_) | PatternKind::Error => {}
+        }
+    }
+
+    /// Marks the innermost binding of `name` as read.
+    fn read(&mut self, name: &str) {
+        let binding = self.scopes.iter_mut().rev().flat_map(|scope| scope.iter_mut().rev()).find(|b| &*b.name == name);
+        if let Some(binding) = binding {
+            binding.used = true;
+        }
+    }
+
+    /// Marks every binding in scope as read, for expressions this pass does
+    /// not look inside: better to miss a warning than to give a wrong one.
+    fn read_all(&mut self) {
+        for binding in self.scopes.iter_mut().flatten() {
+            binding.used = true;
+        }
+    }
+
+    fn pop_scope(&mut self) {
+        for binding in self.scopes.pop().unwrap_or_default() {
+            if binding.used || !binding.reported || binding.name.starts_with('_') {
+                continue;
+            }
+            self.warnings.push(
+                ChimError::new(ErrorKind::Lint, format!("unused variable: `{}`", binding.name))
+                    .with_span(binding.span)
+                    .with_note(format!("if this is intentional, prefix it with an underscore: `_{}`", binding.name)),
+            );
+        }
+    }
+
+    fn block(&mut self, stmts: &[Stmt]) {
+        self.scopes.push(Vec::new());
+        self.stmts(stmts);
+        self.pop_scope();
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Expr(expr) => self.expr(expr),
+            StmtKind::Let(let_stmt) => {
+                if let Some(init) = &let_stmt.initializer {
+                    self.expr(init);
+                }
+                self.bind_pattern(&let_stmt.pattern, true);
+            }
+            StmtKind::Var(var_stmt) => {
+                if let Some(init) = &var_stmt.initializer {
+                    self.expr(init);
+                }
+                self.bind_pattern(&var_stmt.pattern, true);
+            }
+            StmtKind::Return(value) | StmtKind::Break(value) => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+            StmtKind::Loop(loop_stmt) => self.block(&loop_stmt.body),
+            StmtKind::While(while_stmt) => {
+                self.expr(&while_stmt.condition);
+                self.block(&while_stmt.body);
+            }
+            StmtKind::For(for_stmt) => {
+                self.expr(&for_stmt.iterable);
+                self.scopes.push(Vec::new());
+                self.bind_pattern(&for_stmt.pattern, false);
+                self.stmts(&for_stmt.body);
+                self.pop_scope();
+            }
+            StmtKind::Guard(guard) => {
+                self.expr(&guard.condition);
+                self.block(&guard.else_branch);
+            }
+            StmtKind::Defer(body) => self.block(body),
+            StmtKind::Continue(_) | StmtKind::Empty => {}
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &*expr.kind {
+            ExprKind::Identifier(name) => self.read(name),
+            ExprKind::Path(path) => {
+                if let [segment] = path.segments.as_slice() {
+                    self.read(&segment.ident);
+                }
+            }
+            ExprKind::Literal(_) | ExprKind::Continue(_) | ExprKind::Error => {}
+            ExprKind::Binary(binary) => {
+                self.expr(&binary.left);
+                self.expr(&binary.right);
+            }
+            ExprKind::Unary(unary) => self.expr(&unary.expr),
+            ExprKind::Call(call) => {
+                self.expr(&call.func);
+                for arg in &call.args {
+                    self.expr(arg);
+                }
+            }
+            ExprKind::MethodCall(call) => {
+                self.expr(&call.expr);
+                for arg in &call.args {
+                    self.expr(arg);
+                }
+            }
+            ExprKind::FieldAccess(access) => self.expr(&access.expr),
+            ExprKind::Tuple(tuple) => {
+                for element in &tuple.elements {
+                    self.expr(element);
+                }
+            }
+            ExprKind::Array(array) => {
+                for element in &array.elements {
+                    self.expr(element);
+                }
+            }
+            ExprKind::Index(index) => {
+                self.expr(&index.expr);
+                self.expr(&index.index);
+            }
+            ExprKind::Cast(cast) => self.expr(&cast.expr),
+            ExprKind::Ternary(ternary) => {
+                self.expr(&ternary.condition);
+                self.expr(&ternary.then_branch);
+                self.expr(&ternary.else_branch);
+            }
+            ExprKind::If(if_expr) => {
+                self.expr(&if_expr.condition);
+                self.block(&if_expr.then_branch.stmts);
+                if let Some(else_branch) = &if_expr.else_branch {
+                    self.expr(else_branch);
+                }
+            }
+            ExprKind::Match(match_expr) => {
+                self.expr(&match_expr.expr);
+                for arm in &match_expr.arms {
+                    self.scopes.push(Vec::new());
+                    self.bind_pattern(&arm.pattern, false);
+                    if let Some(guard) = &arm.guard {
+                        self.expr(guard);
+                    }
+                    self.expr(&arm.body);
+                    self.pop_scope();
+                }
+            }
+            ExprKind::Block(block) => self.block(&block.stmts),
+            ExprKind::AsyncBlock(block) => self.block(&block.body.stmts),
+            ExprKind::Closure(closure) => {
+                self.scopes.push(Vec::new());
+                self.params(&closure.params);
+                self.expr(&closure.body);
+                self.pop_scope();
+            }
+            ExprKind::Break(_, value) | ExprKind::Return(value) => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+            ExprKind::Loop(loop_expr) => self.block(&loop_expr.body.stmts),
+            ExprKind::While(while_expr) => {
+                self.expr(&while_expr.condition);
+                self.block(&while_expr.body.stmts);
+            }
+            ExprKind::For(for_expr) => {
+                self.expr(&for_expr.iterable);
+                self.scopes.push(Vec::new());
+                self.bind_pattern(&for_expr.pattern, false);
+                self.stmts(&for_expr.body.stmts);
+                self.pop_scope();
+            }
+            ExprKind::Assign(assign) => {
+                // `x = ..` writes `x` without reading it.
+                if !matches!(&*assign.left.kind, ExprKind::Identifier(_)) {
+                    self.expr(&assign.left);
+                }
+                self.expr(&assign.right);
+            }
+            ExprKind::AssignOp(assign) => {
+                self.expr(&assign.left);
+                self.expr(&assign.right);
+            }
+            ExprKind::Range(range) => {
+                for bound in range.start.iter().chain(&range.end) {
+                    self.expr(bound);
+                }
+            }
+            ExprKind::Struct(struct_expr) => {
+                for field in &struct_expr.fields {
+                    self.expr(&field.expr);
+                }
+                if let Some(base) = &struct_expr.base {
+                    self.expr(base);
+                }
+            }
+            ExprKind::Try(try_expr) => self.expr(&try_expr.expr),
+            ExprKind::Unsafe(unsafe_expr) => self.expr(&unsafe_expr.body),
+            _ => self.read_all(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chim_span::FileId;
+
+    #[test]
+    fn test_lint_unused_variables() {
+        let source = "fn main() { let unused = 1; var used = 1; let _unused = 1; return used; }";
+        let program = chim_parser::parse(source, FileId::new(0)).unwrap();
+
+        let warnings = lint_unused_variables(&program);
+        assert_eq!(warnings.len(), 1, "{:?}", warnings);
+        assert_eq!(warnings[0].kind, ErrorKind::Lint);
+        assert_eq!(warnings[0].message(), "unused variable: `unused`");
+        assert!(warnings[0].notes().iter().any(|note| note.contains("`_unused`")));
+    }
+}
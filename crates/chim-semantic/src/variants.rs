@@ -0,0 +1,122 @@
+use crate::discriminants::enum_discriminants;
+use chim_ast::{Ident, Item, Path, Program};
+use chim_error::{ChimError, ErrorKind};
+use chim_span::Span;
+use std::collections::HashMap;
+
+/// One variant of an enum, as seen from a path expression naming it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariantInfo {
+    pub enum_name: Ident,
+    pub name: Ident,
+    /// Position among the enum's variants.
+    pub index: usize,
+    pub discriminant: i128,
+    pub field_count: usize,
+}
+
+/// Enum variants of a program keyed by `(enum, variant)` name, so that path
+/// expressions like `Color::Red` and `Option::Some` resolve to values.
+#[derive(Debug, Clone, Default)]
+pub struct VariantIndex {
+    variants: HashMap<(Ident, Ident), VariantInfo>,
+}
+
+impl VariantIndex {
+    pub fn new(program: &Program) -> Self {
+        let mut index = VariantIndex::default();
+        index.collect(&program.items);
+        index
+    }
+
+    fn collect(&mut self, items: &[Item]) {
+        for item in items {
+            match item {
+                Item::Enum(enum_def) => {
+                    // Bad discriminants are reported by `check_discriminants`.
+                    let discriminants = enum_discriminants(enum_def)
+                        .unwrap_or_else(|_| (0..enum_def.variants.len() as i128).collect());
+                    for (index, (variant, discriminant)) in enum_def.variants.iter().zip(discriminants).enumerate() {
+                        self.variants.insert((enum_def.name.clone(), variant.name.clone()), VariantInfo {
+                            enum_name: enum_def.name.clone(),
+                            name: variant.name.clone(),
+                            index,
+                            discriminant,
+                            field_count: variant.fields.len(),
+                        });
+                    }
+                }
+                Item::Mod(module) => self.collect(&module.items),
+                _ => {}
+            }
+        }
+    }
+
+    /// The variant a path ends in: `Enum::Variant`, possibly behind module
+    /// segments.
+    pub fn resolve(&self, path: &Path) -> Option<&VariantInfo> {
+        match path.segments.as_slice() {
+            [.., enum_name, variant] => self.variants.get(&(enum_name.ident.clone(), variant.ident.clone())),
+            _ => None,
+        }
+    }
+
+    /// Resolves `path` used as a value: bare (`args` is `None`) it must name
+    /// a unit variant, called with `args` arguments it must name a variant
+    /// with that many fields. `None` if the path is not a variant at all.
+    pub fn resolve_value(&self, path: &Path, args: Option<usize>, span: Span) -> Option<Result<&VariantInfo, ChimError>> {
+        let variant = self.resolve(path)?;
+        let message = match args {
+            None if variant.field_count > 0 => format!(
+                "`{}::{}` has {} field(s) and must be constructed with `(...)`",
+                variant.enum_name, variant.name, variant.field_count
+            ),
+            Some(count) if count != variant.field_count => format!(
+                "`{}::{}` takes {} argument(s) but {} were given",
+                variant.enum_name, variant.name, variant.field_count, count
+            ),
+            _ => return Some(Ok(variant)),
+        };
+        Some(Err(ChimError::new(ErrorKind::TypeMismatch, message).with_span(span)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chim_ast::TypeKind;
+    use chim_span::FileId;
+
+    fn parse(source: &str) -> Program {
+        chim_parser::parse(source, FileId::new(0)).unwrap()
+    }
+
+    /// The path `source` spells, written as a parameter's type.
+    fn path(source: &str) -> Path {
+        let program = parse(&format!("fn f(x: {}) {{}}", source));
+        let Item::Function(func) = &program.items[0] else { unreachable!() };
+        match &*func.params[0].ty.kind {
+            TypeKind::Path(path) => path.clone(),
+            other => panic!("expected a path, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_resolve_variant_values() {
+        let program = parse("enum Color { Red, Green = 4 } enum Option { None, Some(value: T) }");
+        let variants = VariantIndex::new(&program);
+        let span = program.span;
+
+        let green = variants.resolve_value(&path("Color::Green"), None, span).unwrap().unwrap();
+        assert_eq!((green.index, green.discriminant, green.field_count), (1, 4, 0));
+        let some = variants.resolve_value(&path("Option::Some"), Some(1), span).unwrap().unwrap();
+        assert_eq!((some.index, some.discriminant, some.field_count), (1, 1, 1));
+        assert!(variants.resolve_value(&path("Color::Blue"), None, span).is_none());
+        assert!(variants.resolve_value(&path("Red"), None, span).is_none());
+
+        let error = variants.resolve_value(&path("Option::Some"), None, span).unwrap().unwrap_err();
+        assert_eq!(error.message(), "`Option::Some` has 1 field(s) and must be constructed with `(...)`");
+        let error = variants.resolve_value(&path("Color::Red"), Some(1), span).unwrap().unwrap_err();
+        assert_eq!(error.message(), "`Color::Red` takes 0 argument(s) but 1 were given");
+    }
+}
@@ -0,0 +1,236 @@
+use chim_ast::{Expr, ExprKind, Param, Path, Pattern, PatternKind, Stmt, StmtKind, Type};
+
+/// What a pass does with the types and paths written inside a function
+/// body. Used by the passes that rewrite names in every type position, such
+/// as `Self` and prelude resolution.
+pub trait TypeVisitor {
+    fn visit_type(&mut self, ty: &mut Type);
+
+    /// A path in an expression or pattern, such as `Option::Some` in
+    /// `Option::Some(1)` or `P` in `P { x: 1 }`.
+    fn visit_path(&mut self, _path: &mut Path) {}
+}
+
+/// Visits every type and path written in `stmts`: `let`/`var` annotations,
+/// casts, closure parameters, turbofish arguments, and those in nested
+/// blocks, branches, arms and loops.
+pub fn walk_stmts(stmts: &mut [Stmt], visitor: &mut impl TypeVisitor) {
+    for stmt in stmts {
+        walk_stmt(stmt, visitor);
+    }
+}
+
+fn walk_stmt(stmt: &mut Stmt, visitor: &mut impl TypeVisitor) {
+    match &mut stmt.kind {
+        StmtKind::Expr(expr) => walk_expr(expr, visitor),
+        StmtKind::Let(let_stmt) => {
+            walk_pattern(&mut let_stmt.pattern, visitor);
+            if let Some(ty) = &mut let_stmt.ty {
+                visitor.visit_type(ty);
+            }
+            if let Some(init) = &mut let_stmt.initializer {
+                walk_expr(init, visitor);
+            }
+        }
+        StmtKind::Var(var_stmt) => {
+            walk_pattern(&mut var_stmt.pattern, visitor);
+            if let Some(ty) = &mut var_stmt.ty {
+                visitor.visit_type(ty);
+            }
+            if let Some(init) = &mut var_stmt.initializer {
+                walk_expr(init, visitor);
+            }
+        }
+        StmtKind::Return(value) | StmtKind::Break(value) => {
+            if let Some(value) = value {
+                walk_expr(value, visitor);
+            }
+        }
+        StmtKind::Loop(loop_stmt) => walk_stmts(&mut loop_stmt.body, visitor),
+        StmtKind::While(while_stmt) => {
+            walk_expr(&mut while_stmt.condition, visitor);
+            walk_stmts(&mut while_stmt.body, visitor);
+        }
+        StmtKind::For(for_stmt) => {
+            walk_pattern(&mut for_stmt.pattern, visitor);
+            walk_expr(&mut for_stmt.iterable, visitor);
+            walk_stmts(&mut for_stmt.body, visitor);
+        }
+        StmtKind::Guard(guard) => {
+            walk_expr(&mut guard.condition, visitor);
+            walk_stmts(&mut guard.else_branch, visitor);
+        }
+        StmtKind::Defer(body) => walk_stmts(body, visitor),
+        StmtKind::Continue(_) | StmtKind::Empty => {}
+    }
+}
+
+pub fn walk_params(params: &mut [Param], visitor: &mut impl TypeVisitor) {
+    for param in params {
+        visitor.visit_type(&mut param.ty);
+    }
+}
+
+/// Visits every type and path written in `expr`, as `walk_stmts` does.
+pub fn walk_expr(expr: &mut Expr, visitor: &mut impl TypeVisitor) {
+    match &mut *expr.kind {
+        ExprKind::Path(path) => visitor.visit_path(path),
+        ExprKind::Binary(binary) => {
+            walk_expr(&mut binary.left, visitor);
+            walk_expr(&mut binary.right, visitor);
+        }
+        ExprKind::Unary(unary) => walk_expr(&mut unary.expr, visitor),
+        ExprKind::Call(call) => {
+            walk_expr(&mut call.func, visitor);
+            for arg in &mut call.args {
+                walk_expr(arg, visitor);
+            }
+        }
+        ExprKind::MethodCall(call) => {
+            walk_expr(&mut call.expr, visitor);
+            for ty in &mut call.generics {
+                visitor.visit_type(ty);
+            }
+            for arg in &mut call.args {
+                walk_expr(arg, visitor);
+            }
+        }
+        ExprKind::FieldAccess(access) => walk_expr(&mut access.expr, visitor),
+        ExprKind::Tuple(tuple) => tuple.elements.iter_mut().for_each(|element| walk_expr(element, visitor)),
+        ExprKind::Array(array) => array.elements.iter_mut().for_each(|element| walk_expr(element, visitor)),
+        ExprKind::Index(index) => {
+            walk_expr(&mut index.expr, visitor);
+            walk_expr(&mut index.index, visitor);
+        }
+        ExprKind::Slice(slice) => {
+            walk_expr(&mut slice.expr, visitor);
+            for bound in slice.start.iter_mut().chain(&mut slice.end) {
+                walk_expr(bound, visitor);
+            }
+        }
+        ExprKind::Cast(cast) => {
+            walk_expr(&mut cast.expr, visitor);
+            visitor.visit_type(&mut cast.ty);
+        }
+        ExprKind::Ternary(ternary) => {
+            walk_expr(&mut ternary.condition, visitor);
+            walk_expr(&mut ternary.then_branch, visitor);
+            walk_expr(&mut ternary.else_branch, visitor);
+        }
+        ExprKind::If(if_expr) => {
+            walk_expr(&mut if_expr.condition, visitor);
+            walk_stmts(&mut if_expr.then_branch.stmts, visitor);
+            if let Some(else_branch) = &mut if_expr.else_branch {
+                walk_expr(else_branch, visitor);
+            }
+        }
+        ExprKind::Match(match_expr) => {
+            walk_expr(&mut match_expr.expr, visitor);
+            for arm in &mut match_expr.arms {
+                walk_pattern(&mut arm.pattern, visitor);
+                if let Some(guard) = &mut arm.guard {
+                    walk_expr(guard, visitor);
+                }
+                walk_expr(&mut arm.body, visitor);
+            }
+        }
+        ExprKind::Block(block) => walk_stmts(&mut block.stmts, visitor),
+        ExprKind::AsyncBlock(block) => walk_stmts(&mut block.body.stmts, visitor),
+        ExprKind::Closure(closure) => {
+            walk_params(&mut closure.params, visitor);
+            walk_expr(&mut closure.body, visitor);
+        }
+        ExprKind::Break(_, value) | ExprKind::Return(value) => {
+            if let Some(value) = value {
+                walk_expr(value, visitor);
+            }
+        }
+        ExprKind::Loop(loop_expr) => walk_stmts(&mut loop_expr.body.stmts, visitor),
+        ExprKind::While(while_expr) => {
+            walk_expr(&mut while_expr.condition, visitor);
+            walk_stmts(&mut while_expr.body.stmts, visitor);
+        }
+        ExprKind::For(for_expr) => {
+            walk_pattern(&mut for_expr.pattern, visitor);
+            walk_expr(&mut for_expr.iterable, visitor);
+            walk_stmts(&mut for_expr.body.stmts, visitor);
+        }
+        ExprKind::Assign(assign) => {
+            walk_expr(&mut assign.left, visitor);
+            walk_expr(&mut assign.right, visitor);
+        }
+        ExprKind::AssignOp(assign) => {
+            walk_expr(&mut assign.left, visitor);
+            walk_expr(&mut assign.right, visitor);
+        }
+        ExprKind::Range(range) => {
+            for bound in range.start.iter_mut().chain(&mut range.end) {
+                walk_expr(bound, visitor);
+            }
+        }
+        ExprKind::Struct(struct_expr) => {
+            visitor.visit_path(&mut struct_expr.path);
+            for field in &mut struct_expr.fields {
+                walk_expr(&mut field.expr, visitor);
+            }
+            if let Some(base) = &mut struct_expr.base {
+                walk_expr(base, visitor);
+            }
+        }
+        ExprKind::Enum(enum_expr) => {
+            visitor.visit_path(&mut enum_expr.path);
+            for field in &mut enum_expr.fields {
+                walk_expr(&mut field.expr, visitor);
+            }
+        }
+        ExprKind::Try(try_expr) => walk_expr(&mut try_expr.expr, visitor),
+        ExprKind::Unsafe(unsafe_expr) => walk_expr(&mut unsafe_expr.body, visitor),
+        ExprKind::Alloc(alloc) => {
+            visitor.visit_type(&mut alloc.ty);
+            if let Some(size) = &mut alloc.size {
+                walk_expr(size, visitor);
+            }
+        }
+        ExprKind::AllocAligned(alloc) => {
+            visitor.visit_type(&mut alloc.ty);
+            walk_expr(&mut alloc.size, visitor);
+            walk_expr(&mut alloc.alignment, visitor);
+        }
+        ExprKind::Ptr(ptr) => visitor.visit_type(&mut ptr.ty),
+        ExprKind::PtrLoad(load) => {
+            walk_expr(&mut load.ptr, visitor);
+            visitor.visit_type(&mut load.ty);
+        }
+        ExprKind::PtrCast(cast) => {
+            walk_expr(&mut cast.ptr, visitor);
+            visitor.visit_type(&mut cast.target_ty);
+        }
+        ExprKind::PtrSizeOf(size_of) => visitor.visit_type(&mut size_of.ty),
+        ExprKind::AlignOf(align_of) => visitor.visit_type(&mut align_of.ty),
+        // The remaining kinds hold no types written in the source.
+        _ => {}
+    }
+}
+
+fn walk_pattern(pattern: &mut Pattern, visitor: &mut impl TypeVisitor) {
+    match &mut pattern.kind {
+        PatternKind::Tuple(patterns) | PatternKind::Or(patterns) => {
+            patterns.iter_mut().for_each(|pattern| walk_pattern(pattern, visitor))
+        }
+        PatternKind::Struct(path, fields) | PatternKind::Enum(path, _, fields) => {
+            visitor.visit_path(path);
+            for pattern in fields.iter_mut().filter_map(|field| field.pattern.as_mut()) {
+                walk_pattern(pattern, visitor);
+            }
+        }
+        PatternKind::Slice(before, _, after) => {
+            before.iter_mut().chain(after).for_each(|pattern| walk_pattern(pattern, visitor))
+        }
+        PatternKind::Range(start, end) => {
+            for bound in start.iter_mut().chain(end) {
+                walk_pattern(bound, visitor);
+            }
+        }
+        PatternKind::Identifier(..) | PatternKind::Wildcard | PatternKind::Literal(_) | PatternKind::Error => {}
+    }
+}
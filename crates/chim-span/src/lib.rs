@@ -2,9 +2,20 @@ use std::collections::HashMap;
 use std::fmt;
 use std::sync::Arc;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct FileId(usize);
 
+impl FileId {
+    pub fn new(index: usize) -> Self {
+        FileId(index)
+    }
+
+    /// The position of the file in the order files were added.
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct SpanId(usize);
 
@@ -22,6 +33,22 @@ impl Span {
         Span { file_id, start, end, line, column }
     }
 
+    /// Placeholder for nodes that have no source location, such as ones
+    /// synthesized by the compiler. Unlike an all-zero span it cannot be
+    /// mistaken for the start of a file.
+    pub fn dummy() -> Self {
+        Span { file_id: FileId(usize::MAX), start: usize::MAX, end: usize::MAX, line: 0, column: 0 }
+    }
+
+    pub fn is_dummy(&self) -> bool {
+        *self == Span::dummy()
+    }
+
+    /// `self`, or `fallback` if `self` is a placeholder.
+    pub fn or(self, fallback: Span) -> Span {
+        if self.is_dummy() { fallback } else { self }
+    }
+
     pub fn is_valid(&self) -> bool {
         self.start <= self.end && self.file_id.0 != usize::MAX
     }
@@ -30,7 +57,14 @@ impl Span {
         pos >= self.start && pos <= self.end
     }
 
+    /// The smallest span covering both. A dummy side contributes nothing.
     pub fn merge(&self, other: &Span) -> Span {
+        if self.is_dummy() {
+            return *other;
+        }
+        if other.is_dummy() {
+            return *self;
+        }
         let start = self.start.min(other.start);
         let end = self.end.max(other.end);
         let line = if self.start <= other.start { self.line } else { other.line };
@@ -39,12 +73,71 @@ impl Span {
     }
 }
 
+/// Spans order by source position: file first, then start and end offset.
+impl Ord for Span {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.file_id, self.start, self.end, self.line, self.column)
+            .cmp(&(other.file_id, other.start, other.end, other.line, other.column))
+    }
+}
+
+impl PartialOrd for Span {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl fmt::Display for Span {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_dummy() {
+            return write!(f, "<unknown>");
+        }
         write!(f, "{}:{}:{}", self.file_id.0, self.line + 1, self.column + 1)
     }
 }
 
+/// Anything that knows where in the source it came from.
+pub trait HasSpan {
+    fn span(&self) -> Span;
+}
+
+impl HasSpan for Span {
+    fn span(&self) -> Span {
+        *self
+    }
+}
+
+/// Attaches a span to an arbitrary value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(node: T, span: Span) -> Self {
+        Spanned { node, span }
+    }
+
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Spanned<U> {
+        Spanned { node: f(self.node), span: self.span }
+    }
+}
+
+impl<T> HasSpan for Spanned<T> {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+impl<T> std::ops::Deref for Spanned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.node
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SourceFile {
     id: FileId,
@@ -66,7 +159,9 @@ impl SourceFile {
                 offsets.push(idx + 1);
             }
         }
-        offsets.push(content.len());
+        if offsets.last() != Some(&content.len()) {
+            offsets.push(content.len());
+        }
         offsets
     }
 
@@ -186,6 +281,19 @@ mod tests {
         assert_eq!(merged.end, 30);
     }
 
+    #[test]
+    fn test_dummy_span() {
+        let dummy = Span::dummy();
+        let real = Span::new(FileId(0), 0, 0, 0, 0);
+        assert!(dummy.is_dummy());
+        assert!(!real.is_dummy());
+        assert!(!dummy.is_valid());
+        assert_eq!(dummy.merge(&real), real);
+        assert_eq!(real.merge(&dummy), real);
+        assert_eq!(dummy.or(real), real);
+        assert_eq!(dummy.to_string(), "<unknown>");
+    }
+
     #[test]
     fn test_source_file_line_count() {
         let content = "line1\nline2\nline3\n";
@@ -201,4 +309,13 @@ mod tests {
         assert_eq!(file.location(6), Some((2, 1)));
         assert_eq!(file.location(12), Some((3, 1)));
     }
+
+    #[test]
+    fn test_spanned_wrapper() {
+        let span = Span::new(FileId(0), 3, 7, 0, 3);
+        let spanned = Spanned::new(5, span);
+        assert_eq!(*spanned, 5);
+        assert_eq!(spanned.span(), span);
+        assert_eq!(spanned.map(|n| n * 2).node, 10);
+    }
 }
@@ -5,7 +5,10 @@ use std::sync::Arc;
 use clap::{Parser, Subcommand};
 use chim_lexer::{tokenize, TokenStream};
 use chim_parser::parse;
-use chim_semantic::SemanticAnalyzer;
+use chim_semantic::{
+    desugar, expand_derives, infer_let_types, lint_unused_variables, resolve_prelude, resolve_self_types, CfgOptions,
+    Prelude, SemanticAnalyzer, strip_cfg,
+};
 use chim_codegen::{CodeGen, CodegenTarget, GeneratedCode};
 use chim_span::{SourceMap, FileId, Span};
 use chim_error::ErrorReporter;
@@ -39,6 +42,9 @@ struct Args {
 
     #[arg(long)]
     dump_ir: bool,
+
+    #[arg(long)]
+    no_std: bool,
 }
 
 #[derive(Subcommand, Debug)]
@@ -64,14 +70,14 @@ fn main() {
     if let Some(command) = args.command {
         match command {
             Commands::Compile { input } => {
-                compile_file(&input, &args.target, args.opt_level, args.output.as_deref(), args.verbose);
+                compile_file(&input, &args.target, args.opt_level, args.output.as_deref(), args.verbose, args.no_std);
             }
             Commands::Run { input } => {
                 eprintln!("Run command not yet implemented");
                 std::process::exit(1);
             }
             Commands::Check { input } => {
-                check_file(&input, args.verbose);
+                check_file(&input, args.verbose, args.no_std);
             }
             Commands::Lsp => {
                 eprintln!("LSP server not yet implemented");
@@ -79,7 +85,7 @@ fn main() {
             }
         }
     } else if let Some(input) = args.input {
-        compile_file(&input, &args.target, args.opt_level, args.output.as_deref(), args.verbose);
+        compile_file(&input, &args.target, args.opt_level, args.output.as_deref(), args.verbose, args.no_std);
     } else {
         print_usage();
     }
@@ -100,6 +106,7 @@ fn print_usage() {
     println!("  -v, --verbose            Verbose output");
     println!("  --dump-ast               Print AST");
     println!("  --dump-ir                Print IR");
+    println!("  --no-std                 Don't bring the standard prelude into scope");
     println!();
     println!("Number Literals:");
     println!("  Decimal: 42, 1_000_000");
@@ -128,7 +135,7 @@ fn print_usage() {
     println!("  Total: 61+ targets!");
 }
 
-fn compile_file(input_file: &str, target: &str, opt_level: u32, output_file: Option<&str>, verbose: bool) {
+fn compile_file(input_file: &str, target: &str, opt_level: u32, output_file: Option<&str>, verbose: bool, no_std: bool) {
     if verbose {
         println!("Chim Compiler v0.1.0");
         println!("Input: {}", input_file);
@@ -147,20 +154,20 @@ fn compile_file(input_file: &str, target: &str, opt_level: u32, output_file: Opt
 
     let mut source_map = SourceMap::new();
     let file_id = source_map.add_file(Arc::from(input_file), Arc::from(&source));
+    let source_map = Arc::new(source_map);
 
     if verbose {
         println!("Source file: {} ({} bytes)", input_file, source.len());
         println!();
     }
 
-    let mut interner = lasso::Rodeo::new();
-    let tokens = chim_lexer::tokenize(&source, &mut interner, file_id);
+    let tokens = chim_lexer::tokenize(&source, file_id).tokens;
 
     if verbose {
         println!("Lexing: {} tokens", tokens.len());
     }
 
-    let ast = match parse(&source, file_id) {
+    let mut ast = match parse(&source, file_id) {
         Ok(ast) => {
             if verbose {
                 println!("Parsing: OK ({} items)", ast.items.len());
@@ -179,6 +186,13 @@ fn compile_file(input_file: &str, target: &str, opt_level: u32, output_file: Opt
             std::process::exit(1);
         }
     };
+    apply_cfg(&mut ast);
+    desugar(&mut ast);
+    derive_impls(&mut ast);
+    apply_prelude(&mut ast, no_std);
+    resolve_self(&mut ast);
+    annotate_lets(&mut ast);
+    warn_unused(&ast, &source_map);
 
     let mut analyzer = SemanticAnalyzer::new();
     let analyzed_program = match analyzer.analyze(&ast) {
@@ -200,7 +214,17 @@ fn compile_file(input_file: &str, target: &str, opt_level: u32, output_file: Opt
         }
     };
 
-    let ir_module = chim_ir::generate_ir(&ast, &analyzed_program);
+    let overflow = chim_ir::OverflowMode::for_build(opt_level == 0);
+    let mut generator = chim_ir::IRGenerator::new(&analyzed_program).with_overflow_mode(overflow);
+    let ir_module = generator.generate_module(&ast);
+    let errors = generator.take_errors();
+    if !errors.is_empty() {
+        eprintln!("IR generation failed with {} errors:", errors.len());
+        for error in errors {
+            eprintln!("  {}", error);
+        }
+        std::process::exit(1);
+    }
 
     let codegen = CodeGen::new();
     let generated_code = match codegen.generate(&ir_module, &analyzed_program, target) {
@@ -244,7 +268,7 @@ fn compile_file(input_file: &str, target: &str, opt_level: u32, output_file: Opt
     }
 }
 
-fn check_file(input_file: &str, verbose: bool) {
+fn check_file(input_file: &str, verbose: bool, no_std: bool) {
     let source = match fs::read_to_string(input_file) {
         Ok(s) => s,
         Err(e) => {
@@ -255,8 +279,9 @@ fn check_file(input_file: &str, verbose: bool) {
 
     let mut source_map = SourceMap::new();
     let file_id = source_map.add_file(Arc::from(input_file), Arc::from(&source));
+    let source_map = Arc::new(source_map);
 
-    let ast = match parse(&source, file_id) {
+    let mut ast = match parse(&source, file_id) {
         Ok(ast) => ast,
         Err(errors) => {
             eprintln!("Parsing failed with {} errors:", errors.len());
@@ -266,6 +291,13 @@ fn check_file(input_file: &str, verbose: bool) {
             std::process::exit(1);
         }
     };
+    apply_cfg(&mut ast);
+    desugar(&mut ast);
+    derive_impls(&mut ast);
+    apply_prelude(&mut ast, no_std);
+    resolve_self(&mut ast);
+    annotate_lets(&mut ast);
+    warn_unused(&ast, &source_map);
 
     let mut analyzer = SemanticAnalyzer::new();
     match analyzer.analyze(&ast) {
@@ -286,6 +318,67 @@ fn check_file(input_file: &str, verbose: bool) {
     }
 }
 
-fn args() -> Args {
-    Args::parse()
+/// Drops items disabled by `@cfg` for the host platform before analysis.
+fn apply_cfg(ast: &mut chim_ast::Program) {
+    let errors = strip_cfg(ast, &CfgOptions::host());
+    if !errors.is_empty() {
+        eprintln!("Configuration failed with {} errors:", errors.len());
+        for error in errors {
+            eprintln!("  {}", error);
+        }
+        std::process::exit(1);
+    }
+}
+
+/// Adds the `impl` items requested by `@derive(...)` on structs.
+fn derive_impls(ast: &mut chim_ast::Program) {
+    let errors = expand_derives(ast);
+    if !errors.is_empty() {
+        eprintln!("Derive expansion failed with {} errors:", errors.len());
+        for error in errors {
+            eprintln!("  {}", error);
+        }
+        std::process::exit(1);
+    }
+}
+
+/// Resolves the prelude's names, such as `Option`, to their `std` paths,
+/// unless `--no-std` was given.
+fn apply_prelude(ast: &mut chim_ast::Program, no_std: bool) {
+    if !no_std {
+        resolve_prelude(ast, &Prelude::std());
+    }
+}
+
+/// Rewrites `Self` in impls to the implemented type.
+fn resolve_self(ast: &mut chim_ast::Program) {
+    let errors = resolve_self_types(ast);
+    if !errors.is_empty() {
+        eprintln!("Name resolution failed with {} errors:", errors.len());
+        for error in errors {
+            eprintln!("  {}", error);
+        }
+        std::process::exit(1);
+    }
+}
+
+/// Prints a warning for each local or parameter that is never read.
+fn warn_unused(ast: &chim_ast::Program, source_map: &Arc<SourceMap>) {
+    let mut reporter = ErrorReporter::new().with_source_map(Arc::clone(source_map));
+    for warning in lint_unused_variables(ast) {
+        reporter.report_warning(warning);
+    }
+    eprint!("{}", reporter.format());
+}
+
+/// Gives un-annotated `let`/`var` bindings the type of their initializer.
+fn annotate_lets(ast: &mut chim_ast::Program) {
+    let errors = infer_let_types(ast);
+    if !errors.is_empty() {
+        eprintln!("Type inference failed with {} errors:", errors.len());
+        for error in errors {
+            eprintln!("  {}", error);
+        }
+        std::process::exit(1);
+    }
 }